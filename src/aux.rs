@@ -1,13 +1,22 @@
+use core::{
+    mem::MaybeUninit,
+    ops::{Bound, Deref, DerefMut, RangeBounds},
+    ptr, slice,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
+use crate::*;
 
+
+/// `$d` is the `$` token passed in, for the repetitions of the generated macro.
 macro_rules! def_coll_init {
-    (seq | $name:ident, $new:expr, $push:ident) => {
+    ($d:tt seq | $name:ident, $new:expr, $push:ident) => {
         macro_rules! $name {
-            ( $$($value:expr),* ) => {{
+            ( $d($value:expr),* ) => {{
                 #[allow(unused_mut)]
                 let mut _coll = $new;
 
-                $$(
+                $d(
                     _coll.$push($value);
                 )*
 
@@ -17,13 +26,13 @@ macro_rules! def_coll_init {
         #[allow(unused)]
         pub(crate) use $name;
     };
-    (map | $name:ident, $new:expr) => {
+    ($d:tt map | $name:ident, $new:expr) => {
         #[allow(unused)]
         macro_rules! $name {
-            ( $$($k:expr => $v:expr),* $$(,)? ) => {{
+            ( $d($k:expr => $v:expr),* $d(,)? ) => {{
                 let mut _coll = $new;
 
-                $$(
+                $d(
                     _coll.insert($k, $v);
                 )*
 
@@ -36,8 +45,256 @@ macro_rules! def_coll_init {
 }
 
 
-def_coll_init!(seq | vecdeq, std::collections::VecDeque::new(), push_back);
-def_coll_init!(map | hashmap, std::collections::HashMap::new());
+def_coll_init!($ seq | vecdeq, alloc::collections::VecDeque::new(), push_back);
+#[cfg(feature = "std")]
+def_coll_init!($ map | hashmap, std::collections::HashMap::new());
+def_coll_init!($ seq | array, crate::aux::Array::new(), push_back);
+
+
+/// Fixed capacity inline array for B-Tree node,
+/// with the part of VecDeque interface node needs.
+///
+/// Full array would panic on insert.
+pub(crate) struct Array<T, const N: usize> {
+    len: usize,
+    items: [MaybeUninit<T>; N],
+}
+
+impl<T, const N: usize> Array<T, N> {
+    pub(crate) fn new() -> Self {
+        Self {
+            len: 0,
+            // An uninitialized `[MaybeUninit<_>; N]` is valid.
+            items: unsafe { MaybeUninit::uninit().assume_init() },
+        }
+    }
+
+    pub(crate) fn insert(&mut self, idx: usize, item: T) {
+        assert!(self.len < N, "array overflow {}", N);
+        assert!(idx <= self.len);
+
+        unsafe {
+            let p = self.items.as_mut_ptr() as *mut T;
+
+            ptr::copy(p.add(idx), p.add(idx + 1), self.len - idx);
+            ptr::write(p.add(idx), item);
+        }
+
+        self.len += 1;
+    }
+
+    pub(crate) fn remove(&mut self, idx: usize) -> Option<T> {
+        if idx >= self.len {
+            return None;
+        }
+
+        self.len -= 1;
+
+        unsafe {
+            let p = self.items.as_mut_ptr() as *mut T;
+            let item = ptr::read(p.add(idx));
+
+            ptr::copy(p.add(idx + 1), p.add(idx), self.len - idx);
+
+            Some(item)
+        }
+    }
+
+    /// Inherent, so that it works on a node behind raw pointer without a `Deref` to slice.
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub(crate) fn push_back(&mut self, item: T) {
+        self.insert(self.len, item)
+    }
+
+    pub(crate) fn push_front(&mut self, item: T) {
+        self.insert(0, item)
+    }
+
+    pub(crate) fn pop_back(&mut self) -> Option<T> {
+        if self.len == 0 {
+            None
+        } else {
+            self.remove(self.len - 1)
+        }
+    }
+
+    pub(crate) fn pop_front(&mut self) -> Option<T> {
+        self.remove(0)
+    }
+}
+
+impl<T, const N: usize> Deref for Array<T, N> {
+    type Target = [T];
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { slice::from_raw_parts(self.items.as_ptr() as *const T, self.len) }
+    }
+}
+
+impl<T, const N: usize> DerefMut for Array<T, N> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { slice::from_raw_parts_mut(self.items.as_mut_ptr() as *mut T, self.len) }
+    }
+}
+
+impl<T: Clone, const N: usize> Clone for Array<T, N> {
+    fn clone(&self) -> Self {
+        let mut res = Self::new();
+
+        for item in self.iter() {
+            res.push_back(item.clone());
+        }
+
+        res
+    }
+}
+
+impl<T, const N: usize> Drop for Array<T, N> {
+    fn drop(&mut self) {
+        unsafe { ptr::drop_in_place(&mut **self as *mut [T]) }
+    }
+}
+
+
+/// Reader/writer spin latch, held shortly, e.g. per node as a thread goes down a tree.
+pub(crate) struct Latch {
+    state: AtomicUsize,
+}
+
+const WRITER: usize = usize::MAX;
+
+fn relax() {
+    #[cfg(any(feature = "std", test))]
+    std::thread::yield_now();
+    #[cfg(not(any(feature = "std", test)))]
+    core::hint::spin_loop();
+}
+
+impl Latch {
+    pub(crate) const fn new() -> Self {
+        Self { state: AtomicUsize::new(0) }
+    }
+
+    pub(crate) fn read(&self) {
+        loop {
+            let state = self.state.load(Ordering::Relaxed);
+
+            if state != WRITER
+                && self
+                    .state
+                    .compare_exchange_weak(state, state + 1, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+            {
+                return;
+            }
+
+            relax();
+        }
+    }
+
+    pub(crate) fn read_unlock(&self) {
+        self.state.fetch_sub(1, Ordering::Release);
+    }
+
+    pub(crate) fn write(&self) {
+        while self
+            .state
+            .compare_exchange_weak(0, WRITER, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            relax();
+        }
+    }
+
+    pub(crate) fn write_unlock(&self) {
+        self.state.store(0, Ordering::Release);
+    }
+
+    pub(crate) fn is_free(&self) -> bool {
+        self.state.load(Ordering::Relaxed) == 0
+    }
+}
+
+
+/// key < range
+pub(crate) fn key_before_range<K: Ord, R: RangeBounds<K>>(key: &K, range: &R) -> bool {
+    match range.start_bound() {
+        Bound::Included(start) => key < start,
+        Bound::Excluded(start) => key <= start,
+        Bound::Unbounded => false,
+    }
+}
+
+
+/// range < key
+pub(crate) fn key_after_range<K: Ord, R: RangeBounds<K>>(key: &K, range: &R) -> bool {
+    match range.end_bound() {
+        Bound::Included(end) => key > end,
+        Bound::Excluded(end) => key >= end,
+        Bound::Unbounded => false,
+    }
+}
+
+
+/// Collect the key-value sequence, Err if keys are not strictly ascending.
+pub(crate) fn collect_sorted<K: Ord, V, I: IntoIterator<Item = (K, V)>>(
+    iter: I,
+) -> Result<Vec<(K, V)>, ()> {
+    let mut res: Vec<(K, V)> = vec![];
+
+    for (key, value) in iter {
+        if let Some((last, _)) = res.last() {
+            if *last >= key {
+                return Err(());
+            }
+        }
+
+        res.push((key, value));
+    }
+
+    Ok(res)
+}
+
+
+/// Least height of B-Tree of `order` which holds n keys, (order^h - 1 keys at most)
+pub(crate) fn btree_height(n: usize, order: usize) -> u32 {
+    let mut h = 0;
+
+    while order.saturating_pow(h) - 1 < n {
+        h += 1;
+    }
+
+    h
+}
+
+
+/// Distribute n keys of a B-Tree (sub)tree of height h (h > 1) among its children,
+/// return the key number of each child subtree.
+///
+/// Use as few children as possible, so the nodes are as full as possible.
+pub(crate) fn btree_partition(n: usize, h: u32, order: usize) -> Vec<usize> {
+    debug_assert!(h > 1);
+
+    // most keys of child subtree
+    let child_max = order.saturating_pow(h - 1) - 1;
+
+    for c in 2..=order {
+        let rest = n - (c - 1);
+
+        if rest <= c * child_max {
+            return (0..c).map(|i| rest / c + (i < rest % c) as usize).collect();
+        }
+    }
+
+    unreachable!("{} keys overflow height {}", n, h)
+}
 
 
 pub trait Reverse {
@@ -55,20 +312,35 @@ impl Reverse for either::Either<(), ()> {
 }
 
 
-#[cfg(test)]
-pub fn gen_unique() -> impl FnMut() -> usize {
-    let mut set = std::collections::HashSet::new();
+/// Random priority, e.g. for Treap.
+#[cfg(feature = "std")]
+pub(crate) fn random_usize() -> usize {
+    rand::random()
+}
 
-    move || {
-        let rand = || rand::random::<usize>();
-        let mut v = rand();
+/// Random priority, e.g. for Treap.
+///
+/// Xorshift on a global state without `rand`, it only needs atomic load and store,
+/// a lost update under race just repeats a number.
+#[cfg(not(feature = "std"))]
+pub(crate) fn random_usize() -> usize {
+    use core::sync::atomic::{AtomicUsize, Ordering};
 
-        while set.contains(&v) {
-            v = rand();
-        }
+    static STATE: AtomicUsize = AtomicUsize::new(0x2545_F491);
 
-        set.insert(v);
+    let mut x = STATE.load(Ordering::Relaxed);
 
-        v
+    if cfg!(target_pointer_width = "64") {
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+    } else {
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
     }
+
+    STATE.store(x, Ordering::Relaxed);
+
+    x
 }
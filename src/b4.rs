@@ -2,8 +2,10 @@
 //! reference: https://en.wikipedia.org/wiki/2%E2%80%933%E2%80%934_tree
 //! (B3 B4), (B5, B6), (B7, B8) ... = Algs(x), x = div_ceil(order, 2), order > 2
 
-use std::{
-    collections::VecDeque,
+use core::{
+    cmp::min,
+    mem::{self, ManuallyDrop},
+    ops::{Bound, RangeBounds},
     ptr::{null, null_mut},
 };
 
@@ -16,50 +18,213 @@ use crate::*;
 //// Structs
 
 /// 2-3-4 Tree
-pub struct B4<K, V> {
+pub struct B4<K, V, O = ()> {
     root: *mut B4Node<K, V>,
+    /// notified of the structural changes, `()` for none
+    observer: O,
 }
 
+/// B4 allows duplicate keys.
+///
+/// Each one is an item of its own, inserted as the successor of the equal ones,
+/// and the splits, borrows and merges keep the infix order, so they stay in insertion order.
+pub struct B4Multi<K, V> {
+    b4: B4<K, V>,
+}
+
+/// A detached subtree as (root, levels), for the splits and the joins
+type Subtree<K, V> = (*mut B4Node<K, V>, usize);
+
+/// One more slot than a full node, for the overfilled node before promotion.
 pub struct B4Node<K, V> {
-    keys: VecDeque<*mut K>,
-    values: VecDeque<*mut V>,
+    keys: Array<K, 4>,
+    values: Array<V, 4>,
 
-    children: VecDeque<*mut Self>,
+    children: Array<*mut Self, 5>,
     paren: *mut Self,
+    itself: *mut Self,
 }
 
 
 
 ////////////////////////////////////////////////////////////////////////////////
 //// Implement
+
+/// The node operations shared with `CB4`, which latches each node and has no paren link.
 ///
+/// They go by the node pointers and borrow the arrays one by one, never a whole node.
+pub(crate) trait B4Arrays<K, V>: Sized {
+    /// Result is Invalid B4 Node until an item goes in.
+    fn new_empty() -> *mut Self;
 
-impl<'a, K: CollKey + 'a, V: 'a> B4Node<K, V> {
-    pub fn new_value(key: K, value: V) -> *mut Self {
-        let key = Box::into_raw(box key);
-        let value = Box::into_raw(box value);
+    unsafe fn keys<'n>(x: *mut Self) -> &'n mut Array<K, 4>;
+
+    unsafe fn values<'n>(x: *mut Self) -> &'n mut Array<V, 4>;
+
+    unsafe fn children<'n>(x: *mut Self) -> &'n mut Array<*mut Self, 5>;
+
+    /// Link the child moved under x, no-op without paren links
+    unsafe fn link(x: *mut Self, child: *mut Self);
+
+    /// Move the items from idx on, and the children after them, into a new node.
+    unsafe fn split_off(x: *mut Self, idx: usize) -> *mut Self {
+        let y = Self::new_empty();
+        let is_leaf = Self::children(x).is_empty();
 
-        Self::new_ptr(key, value)
+        while Self::keys(x).len() > idx {
+            Self::keys(y).push_front(Self::keys(x).pop_back().unwrap());
+            Self::values(y).push_front(Self::values(x).pop_back().unwrap());
+
+            if !is_leaf {
+                let child = Self::children(x).pop_back().unwrap();
+
+                Self::link(y, child);
+                Self::children(y).push_front(child);
+            }
+        }
+
+        y
     }
 
-    pub fn new_ptr(key: *mut K, value: *mut V) -> *mut Self {
-        Box::into_raw(box Self {
-            keys: vecdeq![key],
-            values: vecdeq![value],
+    /// Rotate an item from the left sibling through x to the child at idx
+    unsafe fn borrow_left(x: *mut Self, idx: usize) {
+        let c = Self::children(x)[idx];
+        let sibling = Self::children(x)[idx - 1];
+
+        let key = mem::replace(&mut Self::keys(x)[idx - 1], Self::keys(sibling).pop_back().unwrap());
+        let value = mem::replace(&mut Self::values(x)[idx - 1], Self::values(sibling).pop_back().unwrap());
+
+        Self::keys(c).push_front(key);
+        Self::values(c).push_front(value);
+
+        if let Some(child) = Self::children(sibling).pop_back() {
+            Self::link(c, child);
+            Self::children(c).push_front(child);
+        }
+    }
+
+    /// Rotate an item from the right sibling through x to the child at idx
+    unsafe fn borrow_right(x: *mut Self, idx: usize) {
+        let c = Self::children(x)[idx];
+        let sibling = Self::children(x)[idx + 1];
+
+        let key = mem::replace(&mut Self::keys(x)[idx], Self::keys(sibling).pop_front().unwrap());
+        let value = mem::replace(&mut Self::values(x)[idx], Self::values(sibling).pop_front().unwrap());
+
+        Self::keys(c).push_back(key);
+        Self::values(c).push_back(value);
+
+        if let Some(child) = Self::children(sibling).pop_front() {
+            Self::link(c, child);
+            Self::children(c).push_back(child);
+        }
+    }
+
+    /// Merge the child at idx + 1 and the item at idx down to the child at idx,
+    /// and free the right one.
+    unsafe fn merge_children(x: *mut Self, idx: usize) {
+        let y = Self::children(x)[idx];
+        let z = Self::children(x).remove(idx + 1).unwrap();
+
+        Self::keys(y).push_back(Self::keys(x).remove(idx).unwrap());
+        Self::values(y).push_back(Self::values(x).remove(idx).unwrap());
+
+        while let Some(key) = Self::keys(z).pop_front() {
+            Self::keys(y).push_back(key);
+            Self::values(y).push_back(Self::values(z).pop_front().unwrap());
+        }
+        while let Some(child) = Self::children(z).pop_front() {
+            Self::link(y, child);
+            Self::children(y).push_back(child);
+        }
+
+        drop(Box::from_raw(z));
+    }
+}
+
+impl<K, V> B4Arrays<K, V> for B4Node<K, V> {
+    fn new_empty() -> *mut Self {
+        let x = Box::into_raw(Box::new(Self {
+            keys: array![],
+            values: array![],
             paren: null_mut(),
-            children: vecdeq![],
-        })
+            itself: null_mut(),
+            children: array![],
+        }));
+        unsafe { (*x).itself = x };
+
+        x
+    }
+
+    unsafe fn keys<'n>(x: *mut Self) -> &'n mut Array<K, 4> {
+        &mut (*x).keys
+    }
+
+    unsafe fn values<'n>(x: *mut Self) -> &'n mut Array<V, 4> {
+        &mut (*x).values
+    }
+
+    unsafe fn children<'n>(x: *mut Self) -> &'n mut Array<*mut Self, 5> {
+        &mut (*x).children
+    }
+
+    unsafe fn link(x: *mut Self, child: *mut Self) {
+        if !child.is_null() {
+            (*child).paren = x;
+        }
+    }
+}
+
+impl<K, V> B4Node<K, V> {
+    /// Free the whole subtree, return the number of freed items.
+    unsafe fn drop_subtree(x: *mut Self) -> usize {
+        let mut stack = vec![x];
+        let mut cnt = 0;
+
+        while let Some(x) = stack.pop() {
+            if x.is_null() {
+                continue;
+            }
+
+            let node = Box::from_raw(x);
+
+            stack.extend(node.children.iter().cloned());
+
+            cnt += node.keys.len();
+        }
+
+        cnt
+    }
+
+    /// Free a node unlinked from the tree (no-op on null), leaving its children alone.
+    unsafe fn free(x: *mut Self) {
+        if !x.is_null() {
+            drop(Box::from_raw(x));
+        }
+    }
+}
+
+impl<'a, K: CollKey + 'a, V: 'a> B4Node<K, V> {
+    pub fn new_value(key: K, value: V) -> *mut Self {
+        let x = Self::new_empty();
+
+        unsafe {
+            (*x).keys.push_back(key);
+            (*x).values.push_back(value);
+        }
+
+        x
     }
 
     fn is_leaf(&self) -> bool {
         self.children.is_empty()
     }
 
-    fn node_insert(&mut self, key: *mut K, value: *mut V) {
+    fn node_insert(&mut self, key: K, value: V) {
         let insert_idx = if let Some((i, _)) = self
             .keys
             .iter()
-            .find_position(|&&here_k| unsafe { *key < *here_k })
+            .find_position(|&here_k| key < *here_k)
         {
             i
         } else {
@@ -72,7 +237,7 @@ impl<'a, K: CollKey + 'a, V: 'a> B4Node<K, V> {
 
     unsafe fn connect_child_append(&mut self, child: *mut B4Node<K, V>) {
         if !child.is_null() {
-            (*child).paren = self as *mut Self;
+            (*child).paren = self.itself;
         }
 
         self.children.push_back(child);
@@ -84,7 +249,7 @@ impl<'a, K: CollKey + 'a, V: 'a> B4Node<K, V> {
         idx: usize,
     ) {
         if !child.is_null() {
-            (*child).paren = self as *mut Self;
+            (*child).paren = self.itself;
         }
 
         self.children.insert(idx, child);
@@ -97,25 +262,55 @@ impl<'a, K: CollKey + 'a, V: 'a> B4Node<K, V> {
             self.values.remove(remove_idx).unwrap(),
         );
 
-        B4Node::new_ptr(key, val)
+        B4Node::new_value(key, val)
+    }
+
+    fn pop_front_item(&mut self) -> (K, V) {
+        (self.keys.pop_front().unwrap(), self.values.pop_front().unwrap())
+    }
+
+    fn pop_back_item(&mut self) -> (K, V) {
+        (self.keys.pop_back().unwrap(), self.values.pop_back().unwrap())
     }
 
-    unsafe fn merge_node(&mut self, income_node: *mut B4Node<K, V>) {
-        let income_item_len = (*income_node).node_size();
+    /// A detached node left without item is replaced by its only child.
+    unsafe fn shrink((x, h): Subtree<K, V>) -> Subtree<K, V> {
+        if !(*x).keys.is_empty() {
+            return (x, h);
+        }
+
+        let c = (*x).children.pop_front().unwrap_or(null_mut());
 
-        for _ in 0..income_item_len {
-            self.node_insert(
-                (*income_node).keys.pop_front().unwrap(),
-                (*income_node).values.pop_front().unwrap()
-            )
+        if !c.is_null() {
+            (*c).paren = null_mut();
         }
+
+        Self::free(x);
+
+        (c, h - 1)
     }
 }
 
 
+impl<'a, K: CollKey + Clone + 'a, V: Clone + 'a> B4Node<K, V> {
+    /// Copy without links
+    fn clone_node(&self) -> *mut Self {
+        let x = Box::into_raw(Box::new(Self {
+            keys: self.keys.clone(),
+            values: self.values.clone(),
+            paren: null_mut(),
+            itself: null_mut(),
+            children: array![],
+        }));
+        unsafe { (*x).itself = x };
+
+        x
+    }
+}
+
 impl<'a, K: CollKey + 'a, V: 'a> BTNode<'a, K, V> for B4Node<K, V> {
     fn itself(&self) -> *const (dyn BTNode<'a, K, V> + 'a) {
-        self as *const Self
+        self.itself
     }
 
     fn null(&self) -> *const (dyn BTNode<'a, K, V> + 'a) {
@@ -124,7 +319,14 @@ impl<'a, K: CollKey + 'a, V: 'a> BTNode<'a, K, V> for B4Node<K, V> {
 
     fn try_as_bst(
         &self,
-    ) -> Result<*const (dyn super::bst::BSTNode<'a, K, V> + 'a), ()> {
+    ) -> Result<*const (dyn BSTNode<'a, K, V> + 'a), ()> {
+        Err(())
+    }
+
+    fn downcast(
+        &self,
+        _x: *mut (dyn BTNode<'a, K, V> + 'a),
+    ) -> Result<*mut (dyn BSTNode<'a, K, V> + 'a), ()> {
         Err(())
     }
 
@@ -154,10 +356,6 @@ impl<'a, K: CollKey + 'a, V: 'a> BTNode<'a, K, V> for B4Node<K, V> {
         }
     }
 
-    fn assign_value(&mut self, value: V, idx: usize) {
-        self.values[idx] = Box::into_raw(box value)
-    }
-
     fn assign_paren(&mut self, paren: *mut (dyn BTNode<'a, K, V> + 'a)) {
         self.paren = paren as *mut Self;
     }
@@ -166,32 +364,20 @@ impl<'a, K: CollKey + 'a, V: 'a> BTNode<'a, K, V> for B4Node<K, V> {
         self.paren
     }
 
-    fn key_ptr(&self, idx: usize) -> *mut K {
-        if idx < self.keys.len() {
-            self.keys[idx]
-        } else {
-            null_mut::<K>()
-        }
+    fn key(&self, idx: usize) -> Option<&K> {
+        self.keys.get(idx)
     }
 
-    fn assign_key_ptr(&mut self, idx: usize, key_ptr: *mut K) {
-        if idx < self.keys.len() {
-            self.keys[idx] = key_ptr;
-        }
+    fn key_mut(&mut self, idx: usize) -> Option<&mut K> {
+        self.keys.get_mut(idx)
     }
 
-    fn val_ptr(&self, idx: usize) -> *mut V {
-        if idx < self.values.len() {
-            self.values[idx]
-        } else {
-            null_mut::<V>()
-        }
+    fn value(&self, idx: usize) -> Option<&V> {
+        self.values.get(idx)
     }
 
-    fn assign_val_ptr(&mut self, idx: usize, val_ptr: *mut V) {
-        if idx < self.values.len() {
-            self.values[idx] = val_ptr;
-        }
+    fn value_mut(&mut self, idx: usize) -> Option<&mut V> {
+        self.values.get_mut(idx)
     }
 
     fn height(&self) -> i32 {
@@ -202,32 +388,235 @@ impl<'a, K: CollKey + 'a, V: 'a> BTNode<'a, K, V> for B4Node<K, V> {
 
 impl<'a, K: CollKey + 'a, V: 'a> B4<K, V> {
     pub fn new() -> Self {
-        Self { root: null_mut() }
+        Self::with_observer(())
     }
 
     /// Ordered Sequence
     pub fn bulk_load(seq: &mut dyn Iterator<Item = (K, V)>) -> Self {
         let mut b4 = Self::new();
 
-        let mut seq =
-            seq.map(|(k, v)| (Box::into_raw(box k), Box::into_raw(box v)));
+        b4.load(seq);
+
+        b4
+    }
+}
+
+impl<'a, K: CollKey + 'a, V: 'a, O: Observer<K>> B4<K, V, O> {
+    pub fn with_observer(observer: O) -> Self {
+        Self { root: null_mut(), observer }
+    }
+
+    pub fn observer(&self) -> &O {
+        &self.observer
+    }
+
+    pub fn observer_mut(&mut self) -> &mut O {
+        &mut self.observer
+    }
+
+    /// Load the ordered sequence into the empty tree
+    fn load(&mut self, seq: &mut dyn Iterator<Item = (K, V)>) {
+        debug_assert!(self.root.is_null());
 
         if let Some((k, v)) = seq.next() {
-            b4.root = B4Node::new_ptr(k, v);
+            self.root = B4Node::new_value(k, v);
         }
 
-        for (k, v) in seq.into_iter() {
+        for (k, v) in seq {
             unsafe {
-                let target_node = b4.maximum() as *mut B4Node<K, V>;
+                let target_node = self.maximum() as *mut B4Node<K, V>;
 
                 (*target_node).node_insert(k, v);
 
                 // There are soem other optimization include keeping rightmost node's size zero and one child instead of split from middle when promotion
-                b4.promote(target_node);
+                self.promote(target_node);
             }
         }
+    }
 
-        b4
+    /// Remove all entries whose key is in the range, return the number of them.
+    ///
+    /// Two splits and one join, O(log n + k)
+    pub fn remove_range<R: RangeBounds<K>>(&mut self, range: R) -> usize {
+        unsafe {
+            let mut h = 0;
+            let mut x = self.root;
+
+            while !x.is_null() {
+                x = (*x).child(0) as *mut B4Node<K, V>;
+                h += 1;
+            }
+
+            let observer = &mut self.observer;
+            let (lf, rest) = Self::split_by(
+                (self.root, h), &|key| key_before_range(key, &range), observer
+            );
+            let (mid, rh) = Self::split_by(
+                rest, &|key| !key_after_range(key, &range), observer
+            );
+
+            self.root = Self::join2(lf, rh, observer).0;
+
+            self.observer.on_tree_split();
+            self.observer.on_tree_split();
+            self.observer.on_tree_join();
+
+            B4Node::drop_subtree(mid.0)
+        }
+    }
+
+    /// Split the tree into two by predicate.
+    ///
+    /// The left satisfy `is_lf`, the right don't.
+    ///
+    /// **MUST:** `is_lf` is monotone (true ... true false ... false) on infix order
+    ///
+    /// O(h), the joins on the way up cost the differences of the levels, which add up to h.
+    unsafe fn split_by(
+        (t, h): Subtree<K, V>,
+        is_lf: &dyn Fn(&K) -> bool,
+        observer: &mut O,
+    ) -> (Subtree<K, V>, Subtree<K, V>) {
+        if t.is_null() {
+            return ((null_mut(), 0), (null_mut(), 0));
+        }
+
+        // t keeps the items before idx and the children up to idx, tr takes the rest,
+        // and the child at idx between them is split down
+        let idx = (*t)
+            .keys
+            .iter()
+            .position(|key| !is_lf(key))
+            .unwrap_or((*t).keys.len());
+
+        let tr = B4Node::split_off(t, idx);
+        let c = (*t).children.pop_back().unwrap_or(null_mut());
+
+        if !c.is_null() {
+            (*c).paren = null_mut();
+        }
+
+        let (part_lf, part_rh) = Self::split_by((c, h - 1), is_lf, observer);
+
+        let lf = if (*t).keys.is_empty() {
+            B4Node::free(t);
+            part_lf
+        } else {
+            let (key, value) = (*t).pop_back_item();
+            Self::join(B4Node::shrink((t, h)), key, value, part_lf, observer)
+        };
+
+        let rh = if (*tr).keys.is_empty() {
+            B4Node::free(tr);
+            part_rh
+        } else {
+            let (key, value) = (*tr).pop_front_item();
+            Self::join(part_rh, key, value, B4Node::shrink((tr, h)), observer)
+        };
+
+        (lf, rh)
+    }
+
+    /// Join two trees with a middle item.
+    ///
+    /// **MUST:** keys of tl < key < keys of tr
+    ///
+    /// O(|h(tl) - h(tr)|)
+    unsafe fn join(
+        (tl, hl): Subtree<K, V>,
+        key: K,
+        value: V,
+        (tr, hr): Subtree<K, V>,
+        observer: &mut O,
+    ) -> Subtree<K, V> {
+        if hl == hr {
+            let x = B4Node::new_value(key, value);
+
+            if hl > 0 {
+                (*x).connect_child_append(tl);
+                (*x).connect_child_append(tr);
+            }
+
+            return (x, hl + 1);
+        }
+
+        // Descend along the spine of the higher tree to the node x one level above the lower tree,
+        // put the item (and the lower tree) at the end of x facing it, then promote x as an insert does.
+        let (higher, h) = if hl > hr { (tl, hl) } else { (tr, hr) };
+        let mut x = higher;
+
+        for _ in 0..h - min(hl, hr) - 1 {
+            let idx = if hl > hr { (*x).children.len() - 1 } else { 0 };
+            x = (*x).child(idx) as *mut B4Node<K, V>;
+        }
+
+        if hl > hr {
+            (*x).keys.push_back(key);
+            (*x).values.push_back(value);
+
+            if hr > 0 {
+                (*x).connect_child_append(tr);
+            }
+        } else {
+            (*x).keys.push_front(key);
+            (*x).values.push_front(value);
+
+            if hl > 0 {
+                (*x).connect_child_insert(tl, 0);
+            }
+        }
+
+        // a view on the nodes, which are owned by the caller
+        let mut b4 = ManuallyDrop::new(B4 { root: higher, observer });
+        b4.promote(x);
+
+        (b4.root, if b4.root == higher { h } else { h + 1 })
+    }
+
+    /// Join two trees.
+    ///
+    /// **MUST:** keys of tl < keys of tr
+    unsafe fn join2(
+        tl: Subtree<K, V>,
+        tr: Subtree<K, V>,
+        observer: &mut O,
+    ) -> Subtree<K, V> {
+        if tl.0.is_null() {
+            return tr;
+        }
+
+        if tr.0.is_null() {
+            return tl;
+        }
+
+        // the minimum of tr is the middle item
+        let mut b4 = ManuallyDrop::new(B4 { root: tr.0, observer: &mut *observer });
+        let mut x = tr.0;
+
+        while !(*x).is_leaf() {
+            x = (*x).child(0) as *mut B4Node<K, V>;
+        }
+
+        let (key, value) = (*x).pop_front_item();
+        b4.unpromote(x);
+
+        // the root changes only as the tree gets one level lower
+        let tr = (b4.root, if b4.root == tr.0 { tr.1 } else { tr.1 - 1 });
+
+        Self::join(tl, key, value, tr, observer)
+    }
+
+    /// Remove the item at (x, idx)
+    unsafe fn remove_at(&mut self, x: *mut B4Node<K, V>, idx: usize) -> V {
+        let leaf_item = BTItem::new(x, idx).swap_to_leaf();
+        let leaf = leaf_item.node as *mut B4Node<K, V>;
+
+        let _key = (*leaf).keys.remove(leaf_item.idx).unwrap();
+        let val = (*leaf).values.remove(leaf_item.idx).unwrap();
+
+        self.unpromote(leaf);
+
+        val
     }
 
 
@@ -240,6 +629,7 @@ impl<'a, K: CollKey + 'a, V: 'a> B4<K, V> {
         // So the node is 2/3 instead 1/2.
 
         let x_mid_key = (*x).keys.remove(1).unwrap();
+        self.observer.on_split(&x_mid_key);
         let x_mid_val = (*x).values.remove(1).unwrap();
         let left_sibling = (*x).remove_node(0);
 
@@ -249,7 +639,7 @@ impl<'a, K: CollKey + 'a, V: 'a> B4<K, V> {
         }
 
         if (*x).paren.is_null() {
-            self.root = B4Node::new_ptr(x_mid_key, x_mid_val);
+            self.root = B4Node::new_value(x_mid_key, x_mid_val);
 
             (*self.root).connect_child_append(left_sibling);
             (*self.root).connect_child_append(x);
@@ -264,129 +654,88 @@ impl<'a, K: CollKey + 'a, V: 'a> B4<K, V> {
 
     }
 
-    unsafe fn unpromote(&mut self, leaf: *mut B4Node<K, V>) {
-        debug_assert!(!leaf.is_null());
-
-        if (*leaf).node_size() == 0 {
-            let paren = (*leaf).paren;
+    /// Fix the node left without key, borrow an item from a sibling through the paren,
+    /// or else merge with a sibling and go on with the paren (it lost a key).
+    unsafe fn unpromote(&mut self, x: *mut B4Node<K, V>) {
+        debug_assert!(!x.is_null());
 
-            if paren.is_null() {
-                self.root = null_mut();
-            } else {
-                let leaf_idx = (*paren).index_of_child(leaf);
-                self.unpromote_(paren, leaf_idx);
-            }
+        if (*x).node_size() > 0 {
+            return;
         }
-    }
-
-    unsafe fn unpromote_(&mut self, paren: *mut B4Node<K, V>, leaf_idx: usize) {
-        debug_assert!(!paren.is_null());
 
-        // First check 2-key-val sibling
-        // Split && Redistribute
-        if !(*paren).child(leaf_idx + 1).is_null() {
-            if (*(*paren).child(leaf_idx + 1)).node_size() > 1 {
-                // split
-                let sibling = (*paren).child(leaf_idx + 1) as *mut B4Node<K, V>;
-                let split_sibling = (*sibling).remove_node(0);
-                (*paren).children.remove(leaf_idx);
-                (*paren).connect_child_insert(split_sibling, leaf_idx);
+        let paren = (*x).paren;
 
-                // redistribute
-                let mut paren_item = BTItem::new(paren, leaf_idx);
-                let mut split_sibling_item = BTItem::new(split_sibling, 0);
-                BTItem::swap(&mut paren_item, &mut split_sibling_item);
+        if paren.is_null() {
+            // one level lower (or empty)
+            self.root = (*x).children.pop_front().unwrap_or(null_mut());
 
-                if !(*sibling).is_leaf() {
-                    let sibling_child =  (*sibling).children.pop_front().unwrap();
-                    (*split_sibling).children.push_back(null_mut());
-                    (*split_sibling).connect_child_append(sibling_child);
-
-                    // handle 1-key-val, 1 br with child of split sibling, the other br is regarded removed
-                    self.unpromote_(split_sibling, 0)
-                }
-
-                return;
+            if !self.root.is_null() {
+                (*self.root).paren = null_mut();
             }
-        }
-
-        if leaf_idx > 0 && !(*paren).child(leaf_idx - 1).is_null() {
-            if (*(*paren).child(leaf_idx - 1)).node_size() > 1 {
-                // split
-                let sibing = (*paren).child(leaf_idx - 1) as *mut B4Node<K, V>;
-                let split_sibling = (*sibing).remove_node((*sibing).node_size() - 1);
-                (*paren).children.remove(leaf_idx);
-                (*paren).connect_child_insert(split_sibling, leaf_idx);
 
-                // redistribute (including subtree)
-                let mut paren_item = BTItem::new(paren, leaf_idx - 1);
-                let mut split_sibling_item = BTItem::new(split_sibling, 0);
-                BTItem::swap(&mut paren_item, &mut split_sibling_item);
-
-                if !(*sibing).is_leaf() {
-                    let sibling_child = (*sibing).children.pop_back().unwrap();
-                    (*split_sibling).connect_child_append(sibling_child);
-                    (*split_sibling).children.push_back(null_mut());
-
-                    self.unpromote_(split_sibling, 1)
-                }
-
-                return;
-            }
+            B4Node::free(x);
+            return;
         }
 
+        let idx = (*paren).index_of_child(x);
+        let left = if idx > 0 { (*paren).child(idx - 1) as *mut B4Node<K, V> } else { null_mut() };
+        let right = (*paren).child(idx + 1) as *mut B4Node<K, V>;
 
-        // For 1-key-val node
-        // Move down && Merge (Recursive)
-        let sibling;
-        if !(*paren).child(leaf_idx + 1).is_null() {
-            // move down
-            sibling = (*paren).child(leaf_idx + 1) as *mut B4Node<K, V>;
-            (*paren).children.remove(leaf_idx);
-            let mvd_sibling = (*paren).remove_node(leaf_idx);
+        if !left.is_null() && (*left).node_size() > 1 {
+            self.observer.on_borrow(&(&(*paren).keys)[idx - 1]);
+            B4Node::borrow_left(paren, idx);
+        } else if !right.is_null() && (*right).node_size() > 1 {
+            self.observer.on_borrow(&(&(*paren).keys)[idx]);
+            B4Node::borrow_right(paren, idx);
+        } else {
+            // merge (left, x) or (x, right) into the left one
+            let idx = if !left.is_null() { idx - 1 } else { idx };
 
-            // merge
-            (*sibling).merge_node(mvd_sibling);
+            self.observer.on_merge(&(&(*paren).keys)[idx]);
+            B4Node::merge_children(paren, idx);
 
-            if (*paren).node_size() == 0 {
-                self.subtree_shift(paren, sibling);
-            }
-
-            if !(*sibling).is_leaf() {
-                (*sibling).children.insert(0, null_mut());
-                self.unpromote_(sibling, 0 );
-            }
-
-            return;
+            self.unpromote(paren);
         }
+    }
+}
 
-        if leaf_idx > 0 && !(*paren).child(leaf_idx - 1).is_null() {
-            // move down
-            sibling = (*paren).child(leaf_idx - 1) as *mut B4Node<K, V>;
-            (*paren).children.remove(leaf_idx);
-            let mvd_sibling = (*paren).remove_node(leaf_idx - 1);
 
-            // merge
-            (*sibling).merge_node(mvd_sibling);
+impl<'a, K: CollKey + 'a, V: 'a, O: Observer<K> + Default> Default for B4<K, V, O> {
+    fn default() -> Self {
+        Self::with_observer(O::default())
+    }
+}
 
-            if (*paren).node_size() == 0 {
-                self.subtree_shift(paren, sibling);
-            }
+impl<'a, K: CollKey + 'a, V: 'a> FromSortedIter<K, V> for B4<K, V> {
+    fn from_sorted_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Result<Self, ()> {
+        Ok(B4::bulk_load(&mut collect_sorted(iter)?.into_iter()))
+    }
+}
 
-            if !(*sibling).is_leaf() {
-                (*sibling).children.push_back(null_mut());
-                self.unpromote_(sibling, (*sibling).children.len() - 1);
-            }
+unsafe impl<K: Send, V: Send, O: Send> Send for B4<K, V, O> {}
+unsafe impl<K: Sync, V: Sync, O: Sync> Sync for B4<K, V, O> {}
 
-            return;
+impl<'a, K: CollKey + Clone + 'a, V: Clone + 'a, O: Observer<K> + Clone> Clone for B4<K, V, O> {
+    fn clone(&self) -> Self {
+        Self {
+            root: unsafe { clone_subtree(self.root, B4Node::clone_node) },
+            observer: self.observer.clone(),
         }
+    }
+}
 
-        unreachable!()
+impl<K, V, O> Drop for B4<K, V, O> {
+    fn drop(&mut self) {
+        unsafe {
+            B4Node::drop_subtree(self.root);
+        }
     }
 }
 
+impl_std_traits!(B4<K, V, O>);
+impl_stats!(B4<K, V, StatsObserver>);
 
-impl<'a, K: CollKey + 'a, V: 'a> Dictionary<K, V> for B4<K, V> {
+impl<'a, K: CollKey + 'a, V: 'a, O: Observer<K>> Dictionary<K, V> for B4<K, V, O> {
     fn insert(&mut self, key: K, value: V) -> bool {
         if self.root().is_null() {
             self.assign_root(B4Node::new_value(key, value));
@@ -394,16 +743,12 @@ impl<'a, K: CollKey + 'a, V: 'a> Dictionary<K, V> for B4<K, V> {
         }
 
         unsafe {
-            let x = (*self.root()).search_approximately(&key);
+            let x = self.search_approximately(&key);
 
             if (*x).node_contains(&key) {
                 return false;
             }
 
-            // box key and value
-            let key = Box::into_raw(box key);
-            let value = Box::into_raw(box value);
-
             let x_self = x as *mut B4Node<K, V>;
             (*x_self).node_insert(key, value);
 
@@ -421,98 +766,35 @@ impl<'a, K: CollKey + 'a, V: 'a> Dictionary<K, V> for B4<K, V> {
         }
 
         unsafe {
-            if let Some(idx) = (*res).find_pos_of_key(key) {
-                // if !(*res).paren.is_null() {
-                //     println!("REMOVE RES: {}", (*res).format_keys());
-
-                //     println!("REMOVE RES PAREN:", );
-                //     (*(*res).paren).just_echo_stdout();
-                // }
-
-                let leaf_item = (*res).swap_to_leaf(idx);
-                let leaf = leaf_item.node as *mut B4Node<K, V>;
-
-                let _key = (*leaf).keys.remove(leaf_item.idx).unwrap();
-                let val = (*leaf).values.remove(leaf_item.idx).unwrap();
-
-                self.unpromote(leaf);
-
-                Some(*Box::from_raw(val))
-            } else {
-                None
-            }
+            (*res).find_pos_of_key(key).map(|idx| self.remove_at(res, idx))
         }
     }
 
     fn modify(&mut self, key: &K, value: V) -> bool {
-        let res = self.search_approximately(key) as *mut B4Node<K, V>;
-
-        if res.is_null() {
-            false
+        if let Some(here) = self.get_mut(key) {
+            *here = value;
+            true
         } else {
-            unsafe {
-                if let Some((idx, _)) = (*res)
-                    .keys
-                    .iter_mut()
-                    .find_position(|&&mut here_key| *here_key == *key)
-                {
-                    (*res).values[idx] = Box::into_raw(box value);
-                    true
-                } else {
-                    false
-                }
-            }
+            false
         }
     }
 
     fn get(&self, key: &K) -> Option<&V> {
-        let res = self.search_approximately(key) as *const B4Node<K, V>;
-
-        if res.is_null() {
-            None
-        } else {
-            unsafe {
-                if let Some((idx, _)) = (*res)
-                    .keys
-                    .iter()
-                    .find_position(|&&here_key| *here_key == *key)
-                {
-                    Some(&*(*res).values[idx])
-                } else {
-                    None
-                }
-            }
-        }
+        self.basic_lookup(key)
     }
 
     fn get_mut(&mut self, key: &K) -> Option<&mut V> {
-        let res = self.search_approximately(key) as *mut B4Node<K, V>;
-
-        if res.is_null() {
-            None
-        } else {
-            unsafe {
-                if let Some((idx, _)) = (*res)
-                    .keys
-                    .iter_mut()
-                    .find_position(|&&mut here_key| *here_key == *key)
-                {
-                    Some(&mut *(*res).values[idx])
-                } else {
-                    None
-                }
-            }
-        }
+        self.basic_lookup_mut(key)
     }
 
-    fn self_validate(&self) -> Result<(), Box<dyn std::error::Error>> {
+    fn self_validate(&self) -> Result<(), Box<dyn core::error::Error>> {
         self.basic_self_validate()
     }
 }
 
 
 
-impl<'a, K: CollKey + 'a, V: 'a> BT<'a, K, V> for B4<K, V> {
+impl<'a, K: CollKey + 'a, V: 'a, O: Observer<K>> BT<'a, K, V> for B4<K, V, O> {
     fn order(&self) -> usize {
         4
     }
@@ -524,8 +806,144 @@ impl<'a, K: CollKey + 'a, V: 'a> BT<'a, K, V> for B4<K, V> {
     fn assign_root(&mut self, root: *mut (dyn BTNode<'a, K, V> + 'a)) {
         self.root = root as *mut B4Node<K, V>;
     }
+
+    fn on_search(&self, visits: usize, comparisons: usize) {
+        self.observer.on_search(visits, comparisons)
+    }
+}
+
+
+impl<'a, K: CollKey + 'a, V: 'a> B4Multi<K, V> {
+    pub fn new() -> Self {
+        Self { b4: B4::new() }
+    }
+
+    /// The first item on infix order whose key is equal to the key
+    unsafe fn search_first(&self, key: &K) -> Option<(*mut B4Node<K, V>, usize)> {
+        let mut res = None;
+        let mut x = self.b4.root;
+
+        while !x.is_null() {
+            let idx = (*x)
+                .keys
+                .iter()
+                .position(|here| key <= here)
+                .unwrap_or((*x).keys.len());
+
+            if idx < (*x).keys.len() {
+                res = Some((x, idx));
+            }
+
+            x = (*x).child(idx) as *mut B4Node<K, V>;
+        }
+
+        res.filter(|&(x, idx)| (&(*x).keys)[idx] == *key)
+    }
+}
+
+impl<'a, K: CollKey + 'a, V: 'a> Default for B4Multi<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
+impl<'a, K: CollKey + 'a, V: 'a> MultiDictionary<K, V> for B4Multi<K, V> {
+    fn insert(&mut self, key: K, value: V) {
+        let b4 = &mut self.b4;
+
+        if b4.root.is_null() {
+            b4.root = B4Node::new_value(key, value);
+            return;
+        }
+
+        unsafe {
+            // equal key goes right
+            let mut x = b4.root;
+
+            while !(*x).is_leaf() {
+                let idx = (*x)
+                    .keys
+                    .iter()
+                    .position(|here| key < *here)
+                    .unwrap_or((*x).keys.len());
+
+                x = (*x).child(idx) as *mut B4Node<K, V>;
+            }
+
+            (*x).node_insert(key, value);
+            b4.promote(x);
+        }
+    }
+
+    fn remove_one(&mut self, key: &K) -> Option<V> {
+        unsafe {
+            let (x, idx) = self.search_first(key)?;
+
+            Some(self.b4.remove_at(x, idx))
+        }
+    }
+
+    fn get_all<'b>(&'b self, key: &K) -> Box<dyn Iterator<Item = &'b V> + 'b> {
+        unsafe {
+            let Some((x, idx)) = self.search_first(key) else {
+                return Box::new(core::iter::empty());
+            };
+
+            let key = &(&(*x).keys)[idx];
+            let iter = self.b4.range((Bound::Included(key), Bound::Included(key)));
+
+            Box::new(iter.map(|(_, value)| value))
+        }
+    }
+
+    fn self_validate(&self) -> Result<(), Box<dyn core::error::Error>> {
+        // The equal ones are spread over the nodes on both sides of a key,
+        // so check the infix order instead of the children.
+        if let Some((k0, k1)) = self.b4.keys().tuple_windows().find(|(k0, k1)| k1 < k0) {
+            return Err(format!("{k0:?} before {k1:?} on infix order").into());
+        }
+
+        // (node, depth)
+        let mut stack = vec![(self.b4.root, 1)];
+        let mut leaf_depth = None;
+
+        unsafe {
+            while let Some((x, depth)) = stack.pop() {
+                if x.is_null() {
+                    continue;
+                }
+
+                let key_num = (*x).keys.len();
+
+                if !(1..=3).contains(&key_num) || (*x).values.len() != key_num {
+                    return Err(format!("{key_num} keys, {} values in a node", (*x).values.len()).into());
+                }
+
+                if (*x).is_leaf() {
+                    if *leaf_depth.get_or_insert(depth) != depth {
+                        return Err(format!("leaves at depth {} and {depth}", leaf_depth.unwrap()).into());
+                    }
+
+                    continue;
+                }
+
+                if (*x).children.len() != key_num + 1 {
+                    return Err(format!("{key_num} keys, {} children in a node", (*x).children.len()).into());
+                }
+
+                for &child in (*x).children.iter() {
+                    if child.is_null() || (*child).paren != x {
+                        return Err("broken paren link".into());
+                    }
+
+                    stack.push((child, depth + 1));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
 
 
 
@@ -646,12 +1064,54 @@ mod tests {
         b4.just_echo_stdout();
     }
 
+    #[test]
+    fn test_b4_fixeddata_case_2() {
+        let mut b4 = B4::<i32, ()>::new();
+
+        for k in [1, 2, 3, 8, 9, 4, 6, 5, 7, 10] {
+            b4.insert(k, ());
+        }
+
+        // the paren of the emptied leaf empties too, it used to leave the leaves at uneven depths
+        assert!(b4.remove(&2).is_some());
+        b4.self_validate().unwrap();
+
+        for k in [1, 3, 8, 9, 4, 6, 5, 7, 10] {
+            assert!(b4.remove(&k).is_some());
+            b4.self_validate().unwrap();
+        }
+    }
+
 
     #[test]
     pub(crate) fn test_b4_randomdata() {
         test_dict!(B4::new());
     }
 
+    #[test]
+    fn test_b4_send_sync() {
+        test_send_sync!(B4::new());
+    }
+
+    #[test]
+    fn test_b4_clone() {
+        test_clone!(B4::new());
+    }
+
+    #[test]
+    fn test_b4_std_traits() {
+        test_std_traits!(B4::new());
+    }
+
+    #[test]
+    fn test_b4_observer() {
+        let counts = test_observer!(B4);
+
+        assert!(counts.split > 0);
+        assert!(counts.merge > 0);
+        assert_eq!(counts.rotate, 0);
+    }
+
     #[test]
     fn test_b4_bulk_load() {
         let mut seq = (10..110).step_by(10).map(|n| (n, ()));
@@ -661,6 +1121,18 @@ mod tests {
         b4.self_validate().unwrap();
         b4.just_echo_stdout();
 
+        let b4 = B4::<i32, ()>::bulk_load(&mut (0..1000).map(|n| (n, ())));
+        b4.self_validate().unwrap();
+    }
+
+    #[test]
+    fn test_b4_remove_range() {
+        test_remove_range!(B4::new());
     }
 
+
+    #[test]
+    fn test_b4_multi() {
+        test_multi_dict!(B4Multi::new());
+    }
 }
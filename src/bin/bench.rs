@@ -0,0 +1,579 @@
+//! Compare the trees on the same workloads, run it with `--release`:
+//!
+//! `cargo run --release --bin bench -- --workloads uniform,zipf --trees avl,rb,b4 --csv`
+//!
+//! The operations are generated up front from the seed, so every tree runs the very same ones,
+//! Treap takes its priorities from the key hash for its shapes not to differ from run to run.
+//! Each of them is timed on its own, the latencies include the `Instant` overhead (some tens of ns).
+
+#![allow(clippy::four_forward_slashes)]
+
+use std::{
+    collections::HashSet,
+    env,
+    hint::black_box,
+    process,
+    time::{Duration, Instant},
+};
+
+use coll_bt::{
+    b3::B3,
+    b4::B4,
+    bst::{aa::AA, avl::AVL, llrb::LLRB, lsg::LSG, rawst::RawST, rb::RB, splay::Splay, treap::Treap},
+    bstar4::BStar4,
+    priority::KeyHash,
+    raw::BT,
+    Dictionary,
+};
+
+
+const USAGE: &str = "\
+usage: bench [options]
+
+    --n <num>             keys loaded before the timed operations (default 100000)
+    --ops <num>           timed operations per workload (default 200000)
+    --seed <num>          (default 0)
+    --workloads <a,b,..>  uniform, zipf, sequential, reverse, scan, churn (default all)
+    --trees <a,b,..>      avl, rb, llrb, aa, treap, splay, lsg, rawst, b3, b4, bstar4 (default all,
+                          rawst and lsg are left out of the sorted workloads unless named)
+    --mix <g,i,r,s>       percentages of gets, inserts, removes and scans, overrides the workload's
+    --theta <num>         skew of zipf in (0, 1) (default 0.99)
+    --scan-len <num>      items of each range scan (default 100)
+    --csv                 print CSV instead of the table
+";
+
+
+////////////////////////////////////////////////////////////////////////////////
+//// Structs
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Get(u64),
+    Insert(u64),
+    Remove(u64),
+    /// from the key on
+    Scan(u64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Dist {
+    Uniform,
+    Zipf,
+    Sequential,
+    Reverse,
+}
+
+/// Percentages of gets, inserts, removes and scans
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Mix {
+    get: u32,
+    insert: u32,
+    remove: u32,
+    scan: u32,
+}
+
+struct Workload {
+    name: &'static str,
+    dist: Dist,
+    mix: Mix,
+    preload: bool,
+}
+
+/// Everything a tree runs for a workload
+struct Plan {
+    preload: Vec<u64>,
+    ops: Vec<Op>,
+    scan_len: usize,
+}
+
+struct Config {
+    n: u64,
+    ops: usize,
+    seed: u64,
+    theta: f64,
+    scan_len: usize,
+    mix: Option<Mix>,
+    workloads: Vec<&'static Workload>,
+    trees: Vec<&'static Tree>,
+    /// trees are named explicitly
+    named: bool,
+    csv: bool,
+}
+
+struct Tree {
+    name: &'static str,
+    run: fn(&Plan) -> (Duration, Vec<u64>),
+    /// it's fit for the sorted keys, RawST degenerates to a list and LSG rebuilds from the root
+    /// on nearly every insert, both go quadratic
+    sorted: bool,
+}
+
+struct Report {
+    workload: &'static str,
+    tree: &'static str,
+    ops: usize,
+    elapsed: Duration,
+    /// nanoseconds of p50, p90, p99, p99.9 and max
+    latency: [u64; 5],
+}
+
+/// SplitMix64, it's all the randomness needed and stays the same across `rand` versions
+struct Rng(u64);
+
+/// Zipfian ranks in `0..n` by Gray et al., "Quickly generating billion-record synthetic databases"
+struct Zipf {
+    n: u64,
+    theta: f64,
+    alpha: f64,
+    zetan: f64,
+    eta: f64,
+}
+
+
+////////////////////////////////////////////////////////////////////////////////
+//// Implement
+
+const fn mix(get: u32, insert: u32, remove: u32, scan: u32) -> Mix {
+    Mix { get, insert, remove, scan }
+}
+
+const WORKLOADS: &[Workload] = &[
+    Workload { name: "uniform", dist: Dist::Uniform, mix: mix(50, 25, 25, 0), preload: true },
+    Workload { name: "zipf", dist: Dist::Zipf, mix: mix(90, 10, 0, 0), preload: true },
+    Workload { name: "sequential", dist: Dist::Sequential, mix: mix(0, 100, 0, 0), preload: false },
+    Workload { name: "reverse", dist: Dist::Reverse, mix: mix(0, 100, 0, 0), preload: false },
+    Workload { name: "scan", dist: Dist::Uniform, mix: mix(0, 10, 0, 90), preload: true },
+    Workload { name: "churn", dist: Dist::Uniform, mix: mix(10, 40, 50, 0), preload: true },
+];
+
+const TREES: &[Tree] = &[
+    Tree { name: "avl", run: run::<AVL<u64, u64>>, sorted: true },
+    Tree { name: "rb", run: run::<RB<u64, u64>>, sorted: true },
+    Tree { name: "llrb", run: run::<LLRB<u64, u64>>, sorted: true },
+    Tree { name: "aa", run: run::<AA<u64, u64>>, sorted: true },
+    Tree { name: "treap", run: run::<Treap<u64, u64, usize, (), KeyHash>>, sorted: true },
+    Tree { name: "splay", run: run::<Splay<u64, u64>>, sorted: true },
+    Tree { name: "lsg", run: run::<LSG<u64, u64>>, sorted: false },
+    Tree { name: "rawst", run: run::<RawST<u64, u64>>, sorted: false },
+    Tree { name: "b3", run: run::<B3<u64, u64>>, sorted: true },
+    Tree { name: "b4", run: run::<B4<u64, u64>>, sorted: true },
+    Tree { name: "bstar4", run: run::<BStar4<u64, u64>>, sorted: true },
+];
+
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        scramble(self.0)
+    }
+
+    /// in [0, 1)
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+}
+
+
+impl Zipf {
+    fn new(n: u64, theta: f64) -> Self {
+        let zeta = |n: u64| (1..=n).map(|i| 1.0 / (i as f64).powf(theta)).sum::<f64>();
+        let zetan = zeta(n);
+
+        Self {
+            n,
+            theta,
+            alpha: 1.0 / (1.0 - theta),
+            zetan,
+            eta: (1.0 - (2.0 / n as f64).powf(1.0 - theta)) / (1.0 - zeta(2) / zetan),
+        }
+    }
+
+    /// rank 0 is the hottest
+    fn sample(&self, rng: &mut Rng) -> u64 {
+        let u = rng.next_f64();
+        let uz = u * self.zetan;
+
+        if uz < 1.0 {
+            return 0;
+        }
+
+        if uz < 1.0 + 0.5f64.powf(self.theta) {
+            return 1;
+        }
+
+        let rank = self.n as f64 * (self.eta * u - self.eta + 1.0).powf(self.alpha);
+
+        (rank as u64).min(self.n - 1)
+    }
+}
+
+
+impl Plan {
+    /// The keys are in `0..2n` apart from the sorted ones, half of them are loaded uniformly,
+    /// removes take an existing key.
+    fn new(workload: &Workload, conf: &Config) -> Self {
+        let mut rng = Rng(conf.seed);
+        let space = conf.n.max(1) * 2;
+        let zipf = (workload.dist == Dist::Zipf).then(|| Zipf::new(space, conf.theta));
+        let mut counter = 0;
+
+        let mut next_key = |rng: &mut Rng| match workload.dist {
+            Dist::Uniform => rng.below(space),
+            // scattered for the hot keys not to be neighbours
+            Dist::Zipf => scramble(zipf.as_ref().unwrap().sample(rng)) % space,
+            Dist::Sequential => {
+                counter += 1;
+                counter
+            }
+            Dist::Reverse => {
+                counter += 1;
+                u64::MAX - counter
+            }
+        };
+
+        let mut live = vec![];
+        let mut present = HashSet::new();
+
+        if workload.preload {
+            while (live.len() as u64) < conf.n {
+                let k = rng.below(space);
+
+                if present.insert(k) {
+                    live.push(k);
+                }
+            }
+        }
+
+        let preload = live.clone();
+        let mix = conf.mix.unwrap_or(workload.mix);
+        let total = (mix.get + mix.insert + mix.remove + mix.scan) as u64;
+        let mut ops = Vec::with_capacity(conf.ops);
+
+        for _ in 0..conf.ops {
+            let mut dice = rng.below(total) as u32;
+
+            if dice < mix.get {
+                ops.push(Op::Get(next_key(&mut rng)));
+                continue;
+            }
+            dice -= mix.get;
+
+            if dice < mix.insert {
+                let k = next_key(&mut rng);
+
+                if present.insert(k) {
+                    live.push(k);
+                }
+
+                ops.push(Op::Insert(k));
+                continue;
+            }
+            dice -= mix.insert;
+
+            if dice < mix.remove {
+                if live.is_empty() {
+                    ops.push(Op::Remove(next_key(&mut rng)));
+                } else {
+                    let k = live.swap_remove(rng.below(live.len() as u64) as usize);
+
+                    present.remove(&k);
+                    ops.push(Op::Remove(k));
+                }
+                continue;
+            }
+
+            ops.push(Op::Scan(next_key(&mut rng)));
+        }
+
+        Self { preload, ops, scan_len: conf.scan_len }
+    }
+}
+
+
+impl Report {
+    fn new(workload: &'static str, tree: &'static str, elapsed: Duration, mut latency: Vec<u64>) -> Self {
+        latency.sort_unstable();
+
+        let at = |q: f64| latency.get(((latency.len().max(1) - 1) as f64 * q) as usize).cloned().unwrap_or(0);
+
+        Self {
+            workload,
+            tree,
+            ops: latency.len(),
+            elapsed,
+            latency: [at(0.5), at(0.9), at(0.99), at(0.999), at(1.0)],
+        }
+    }
+
+    fn ops_per_sec(&self) -> f64 {
+        self.ops as f64 / self.elapsed.as_secs_f64().max(f64::MIN_POSITIVE)
+    }
+}
+
+
+impl Config {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut conf = Config {
+            n: 100_000,
+            ops: 200_000,
+            seed: 0,
+            theta: 0.99,
+            scan_len: 100,
+            mix: None,
+            workloads: WORKLOADS.iter().collect(),
+            trees: TREES.iter().collect(),
+            named: false,
+            csv: false,
+        };
+
+        while let Some(arg) = args.next() {
+            if arg == "--csv" {
+                conf.csv = true;
+                continue;
+            }
+
+            if arg == "--help" || arg == "-h" {
+                return Err(String::new());
+            }
+
+            let val = args.next().ok_or(format!("{arg} needs a value"))?;
+            let num = || val.parse::<u64>().map_err(|_| format!("bad {arg} {val}"));
+
+            match arg.as_str() {
+                "--n" => conf.n = num()?,
+                "--ops" => conf.ops = num()? as usize,
+                "--seed" => conf.seed = num()?,
+                "--scan-len" => conf.scan_len = num()? as usize,
+                "--theta" => {
+                    conf.theta = val.parse().map_err(|_| format!("bad {arg} {val}"))?;
+
+                    if !(conf.theta > 0.0 && conf.theta < 1.0) {
+                        return Err(format!("{arg} should be in (0, 1)"));
+                    }
+                }
+                "--mix" => {
+                    let parts = val
+                        .split(',')
+                        .map(|s| s.parse::<u32>())
+                        .collect::<Result<Vec<_>, _>>()
+                        .map_err(|_| format!("bad {arg} {val}"))?;
+
+                    match parts[..] {
+                        [get, insert, remove, scan] if get + insert + remove + scan > 0 => {
+                            conf.mix = Some(mix(get, insert, remove, scan))
+                        }
+                        _ => return Err(format!("{arg} needs four percentages, not {val}")),
+                    }
+                }
+                "--workloads" => {
+                    conf.workloads = pick(WORKLOADS, &val, |w| w.name)?;
+                }
+                "--trees" => {
+                    conf.trees = pick(TREES, &val, |t| t.name)?;
+                    conf.named = true;
+                }
+                _ => return Err(format!("unknown option {arg}")),
+            }
+        }
+
+        Ok(conf)
+    }
+}
+
+
+////////////////////////////////////////////////////////////////////////////////
+//// Functions
+
+/// The SplitMix64 finalizer
+fn scramble(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+fn pick<T>(all: &'static [T], names: &str, name: impl Fn(&T) -> &str) -> Result<Vec<&'static T>, String> {
+    names
+        .split(',')
+        .map(|s| all.iter().find(|t| name(t) == s).ok_or(format!("unknown {s}")))
+        .collect()
+}
+
+/// Load the keys and time the operations, return the total and the latency of each in ns
+fn run<D>(plan: &Plan) -> (Duration, Vec<u64>)
+where
+    D: Default + Dictionary<u64, u64> + for<'a> BT<'a, u64, u64>,
+{
+    let mut dict = D::default();
+
+    for &k in plan.preload.iter() {
+        dict.insert(k, k);
+    }
+
+    let mut latency = Vec::with_capacity(plan.ops.len());
+    let begin = Instant::now();
+
+    for op in plan.ops.iter() {
+        let start = Instant::now();
+
+        match *op {
+            Op::Get(k) => {
+                black_box(dict.get(&k));
+            }
+            Op::Insert(k) => {
+                black_box(dict.insert(k, k));
+            }
+            Op::Remove(k) => {
+                black_box(dict.remove(&k));
+            }
+            Op::Scan(k) => {
+                black_box(dict.range(k..).take(plan.scan_len).count());
+            }
+        }
+
+        latency.push(start.elapsed().as_nanos() as u64);
+    }
+
+    (begin.elapsed(), latency)
+}
+
+fn print_header(csv: bool) {
+    if csv {
+        println!("workload,tree,ops,secs,ops_per_sec,p50_ns,p90_ns,p99_ns,p999_ns,max_ns");
+    } else {
+        println!(
+            "{:<11}{:<8}{:>10}{:>11}{:>9}{:>9}{:>9}{:>9}{:>11}",
+            "workload", "tree", "ops", "Mops/s", "p50", "p90", "p99", "p99.9", "max(ns)"
+        );
+    }
+}
+
+fn print_report(report: &Report, csv: bool) {
+    let [p50, p90, p99, p999, max] = report.latency;
+
+    if csv {
+        println!(
+            "{},{},{},{:.6},{:.0},{p50},{p90},{p99},{p999},{max}",
+            report.workload,
+            report.tree,
+            report.ops,
+            report.elapsed.as_secs_f64(),
+            report.ops_per_sec()
+        );
+    } else {
+        println!(
+            "{:<11}{:<8}{:>10}{:>11.3}{p50:>9}{p90:>9}{p99:>9}{p999:>9}{max:>11}",
+            report.workload,
+            report.tree,
+            report.ops,
+            report.ops_per_sec() / 1e6
+        );
+    }
+}
+
+
+fn main() {
+    let conf = match Config::parse(env::args().skip(1)) {
+        Ok(conf) => conf,
+        Err(msg) => {
+            if msg.is_empty() {
+                print!("{USAGE}");
+                return;
+            }
+
+            eprintln!("{msg}\n\n{USAGE}");
+            process::exit(2);
+        }
+    };
+
+    print_header(conf.csv);
+
+    for workload in conf.workloads.iter() {
+        let plan = Plan::new(workload, &conf);
+        let sorted = matches!(workload.dist, Dist::Sequential | Dist::Reverse);
+
+        for tree in conf.trees.iter() {
+            if sorted && !tree.sorted && !conf.named {
+                continue;
+            }
+
+            let (elapsed, latency) = (tree.run)(&plan);
+
+            print_report(&Report::new(workload.name, tree.name, elapsed, latency), conf.csv);
+        }
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Config {
+        Config::parse(args.split_whitespace().map(String::from)).unwrap()
+    }
+
+    #[test]
+    fn test_plan() {
+        let conf = parse("--n 500 --ops 2000 --seed 7");
+
+        for workload in WORKLOADS {
+            let plan = Plan::new(workload, &conf);
+
+            assert_eq!(plan.ops, Plan::new(workload, &conf).ops);
+            assert_eq!(plan.ops.len(), 2000);
+            assert_eq!(plan.preload.len(), if workload.preload { 500 } else { 0 });
+
+            // removes hit an existing key while there are some
+            let mut live: HashSet<u64> = plan.preload.iter().cloned().collect();
+
+            for op in plan.ops.iter() {
+                match *op {
+                    Op::Insert(k) => {
+                        live.insert(k);
+                    }
+                    Op::Remove(k) => assert!(live.remove(&k)),
+                    _ => (),
+                }
+            }
+
+            for tree in TREES.iter().filter(|t| t.sorted || workload.preload) {
+                assert_eq!((tree.run)(&plan).1.len(), 2000, "{}", tree.name);
+            }
+        }
+
+        let other = parse("--n 500 --ops 2000 --seed 8");
+        assert_ne!(Plan::new(&WORKLOADS[0], &conf).ops, Plan::new(&WORKLOADS[0], &other).ops);
+    }
+
+    #[test]
+    fn test_zipf() {
+        let zipf = Zipf::new(1000, 0.99);
+        let mut rng = Rng(0);
+        let mut hits = vec![0; 1000];
+
+        for _ in 0..100_000 {
+            hits[zipf.sample(&mut rng) as usize] += 1;
+        }
+
+        assert!(hits[0] > hits[1] && hits[1] > hits[10] && hits[10] > hits[500]);
+        assert!(hits[0] > 100_000 / 20);
+    }
+
+    #[test]
+    fn test_parse() {
+        let conf = parse("--workloads zipf,churn --trees b4 --mix 1,2,3,4 --csv");
+
+        assert_eq!(conf.workloads.len(), 2);
+        assert!(conf.named && conf.csv);
+        assert_eq!(conf.mix, Some(mix(1, 2, 3, 4)));
+
+        assert!(Config::parse(["--mix".to_owned(), "1,2".to_owned()].into_iter()).is_err());
+        assert!(Config::parse(["--trees".to_owned(), "btree".to_owned()].into_iter()).is_err());
+        assert!(Config::parse(["--theta".to_owned(), "1".to_owned()].into_iter()).is_err());
+    }
+}
@@ -17,11 +17,12 @@
 //!
 //! 1. Every node of level greater than one has two children.
 
-use std::{
+use core::{
     cmp::min,
-    fmt::{self, Write},
     ptr::{null, null_mut},
 };
+#[cfg(any(feature = "std", test))]
+use core::fmt::{self, Write};
 
 use either::Either;
 
@@ -30,17 +31,20 @@ use crate::*;
 ////////////////////////////////////////////////////////////////////////////////
 //// Struct
 
-pub struct AA<K, V> {
+pub struct AA<K, V, O = ()> {
     root: *mut AANode<K, V>,
+    /// notified of the structural changes, `()` for none
+    observer: O,
 }
 
 struct AANode<K, V> {
     left: *mut Self,
     right: *mut Self,
     paren: *mut Self,
+    itself: *mut Self,
     level: usize,
-    key: *mut K,
-    value: *mut V,
+    key: K,
+    value: V,
 }
 
 ////////////////////////////////////////////////////////////////////////////////
@@ -56,20 +60,54 @@ fn level<K, V>(node: *mut AANode<K, V>) -> usize {
     }
 }
 
+fn set_level<K: CollKey, V, O: Observer<K>>(node: *mut AANode<K, V>, lv: usize, observer: &mut O) {
+    unsafe {
+        observer.on_level_change(&(*node).key, (*node).level, lv);
+        (*node).level = lv;
+    }
+}
+
+impl<K, V> AANode<K, V> {
+    /// Free the whole subtree, return the number of freed nodes.
+    unsafe fn drop_subtree(x: *mut Self) -> usize {
+        let mut stack = vec![x];
+        let mut cnt = 0;
+
+        while let Some(x) = stack.pop() {
+            if x.is_null() {
+                continue;
+            }
+
+            let node = Box::from_raw(x);
+
+            stack.push(node.left);
+            stack.push(node.right);
+
+            cnt += 1;
+        }
+
+        cnt
+    }
+}
+
 impl<'a, K: CollKey + 'a, V: 'a> AANode<K, V> {
     pub fn new(key: K, value: V) -> *mut Self {
-        Box::into_raw(box Self {
+        let x = Box::into_raw(Box::new(Self {
             left: null_mut(),
             right: null_mut(),
             paren: null_mut(),
+            itself: null_mut(),
             level: 1, // Invariants 1
-            key: Box::into_raw(box key),
-            value: Box::into_raw(box value),
-        })
+            key,
+            value,
+        }));
+        unsafe { (*x).itself = x };
+
+        x
     }
 
     fn leafs(&self) -> Vec<*mut Self> {
-        let mut queue = vecdeq![self as *const Self as *mut Self];
+        let mut queue = vecdeq![self.itself];
         let mut leafs = vec![];
 
         while !queue.is_empty() {
@@ -95,7 +133,7 @@ impl<'a, K: CollKey + 'a, V: 'a> AANode<K, V> {
     }
 
     /// validate red/black
-    pub fn self_validate(&self) -> Result<(), Box<dyn std::error::Error>> {
+    pub fn self_validate(&self) -> Result<(), Box<dyn core::error::Error>> {
         self.basic_self_validate()?;
 
         unsafe {
@@ -135,6 +173,7 @@ impl<'a, K: CollKey + 'a, V: 'a> AANode<K, V> {
         Ok(())
     }
 
+    #[cfg(any(feature = "std", test))]
     pub fn echo_in_mm(&self, cache: &mut String) -> fmt::Result {
         unsafe {
             BSTNode::echo_in_mm(self, cache, |x, cache| {
@@ -145,6 +184,7 @@ impl<'a, K: CollKey + 'a, V: 'a> AANode<K, V> {
         }
     }
 
+    #[cfg(any(feature = "std", test))]
     pub fn echo_stdout(&self) {
         let mut cache = String::new();
 
@@ -152,23 +192,29 @@ impl<'a, K: CollKey + 'a, V: 'a> AANode<K, V> {
 
         println!("{}", cache);
     }
+}
 
-    unsafe fn level_dec(&mut self) {
-        let lv = min(level(self.left), level(self.right)) + 1;
-
-        if lv < self.level {
-            self.level = lv;
+impl<'a, K: CollKey + Clone + 'a, V: Clone + 'a> AANode<K, V> {
+    /// Copy without links
+    fn clone_node(&self) -> *mut Self {
+        let x = Box::into_raw(Box::new(Self {
+            left: null_mut(),
+            right: null_mut(),
+            paren: null_mut(),
+            itself: null_mut(),
+            level: self.level,
+            key: self.key.clone(),
+            value: self.value.clone(),
+        }));
+        unsafe { (*x).itself = x };
 
-            if lv < level(self.right) {
-                (*self.right).level = lv;
-            }
-        }
+        x
     }
 }
 
 impl<'a, K: CollKey + 'a, V: 'a> BTNode<'a, K, V> for AANode<K, V> {
     fn itself(&self) -> *const (dyn BTNode<'a, K, V> + 'a) {
-        self as *const Self
+        self.itself
     }
 
     fn null(&self) -> *const (dyn BTNode<'a, K, V> + 'a) {
@@ -176,7 +222,14 @@ impl<'a, K: CollKey + 'a, V: 'a> BTNode<'a, K, V> for AANode<K, V> {
     }
 
     fn try_as_bst(&self) -> Result<*const (dyn BSTNode<'a, K, V> + 'a), ()> {
-        Ok(self as *const Self)
+        Ok(self.itself)
+    }
+
+    fn downcast(
+        &self,
+        x: *mut (dyn BTNode<'a, K, V> + 'a),
+    ) -> Result<*mut (dyn BSTNode<'a, K, V> + 'a), ()> {
+        Ok(x as *mut Self)
     }
 
     fn order(&self) -> usize {
@@ -203,10 +256,6 @@ impl<'a, K: CollKey + 'a, V: 'a> BTNode<'a, K, V> for AANode<K, V> {
         }
     }
 
-    fn assign_value(&mut self, value: V, _idx: usize) {
-        self.value = Box::into_raw(box value);
-    }
-
     fn assign_paren(&mut self, paren: *mut (dyn BTNode<'a, K, V> + 'a)) {
         self.paren = paren as *mut Self;
     }
@@ -219,31 +268,35 @@ impl<'a, K: CollKey + 'a, V: 'a> BTNode<'a, K, V> for AANode<K, V> {
         self.calc_height()
     }
 
-    fn key_ptr(&self, idx: usize) -> *mut K {
+    fn key(&self, idx: usize) -> Option<&K> {
         if idx == 0 {
-            self.key
+            Some(&self.key)
         } else {
-            null_mut::<K>()
+            None
         }
     }
 
-    fn assign_key_ptr(&mut self, idx: usize, key_ptr: *mut K) {
+    fn key_mut(&mut self, idx: usize) -> Option<&mut K> {
         if idx == 0 {
-            self.key = key_ptr;
+            Some(&mut self.key)
+        } else {
+            None
         }
     }
 
-    fn val_ptr(&self, idx: usize) -> *mut V {
+    fn value(&self, idx: usize) -> Option<&V> {
         if idx == 0 {
-            self.value
+            Some(&self.value)
         } else {
-            null_mut::<V>()
+            None
         }
     }
 
-    fn assign_val_ptr(&mut self, idx: usize, val_ptr: *mut V) {
+    fn value_mut(&mut self, idx: usize) -> Option<&mut V> {
         if idx == 0 {
-            self.value = val_ptr;
+            Some(&mut self.value)
+        } else {
+            None
         }
     }
 }
@@ -252,9 +305,88 @@ impl<'a, K: CollKey + 'a, V: 'a> BSTNode<'a, K, V> for AANode<K, V> {}
 
 impl<'a, K: CollKey + 'a, V: 'a> AA<K, V> {
     pub fn new() -> Self {
-        Self { root: null_mut() }
+        Self::with_observer(())
+    }
+
+    /// Build a perfectly balanced tree from the strictly ascending sequence in O(n),
+    /// Err if it isn't.
+    ///
+    /// It's built as a B3 tree with the least height, 3-node is horizontal right link.
+    #[allow(clippy::result_unit_err, reason = "as `FromSortedIter`")]
+    pub fn from_sorted_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Result<Self, ()> {
+        let nodes: Vec<*mut AANode<K, V>> = collect_sorted(iter)?
+            .into_iter()
+            .map(|(key, value)| AANode::new(key, value))
+            .collect();
+
+        let h = btree_height(nodes.len(), 3);
+
+        Ok(Self { root: unsafe { Self::build(&nodes[..], h) }, observer: () })
+    }
+
+    /// Build B3 subtree of height h (the level of root)
+    unsafe fn build(seq: &[*mut AANode<K, V>], h: u32) -> *mut AANode<K, V> {
+        if seq.is_empty() {
+            return null_mut();
+        }
+
+        if h == 1 {
+            if seq.len() == 2 {
+                (*seq[0]).connect_right(seq[1]);
+            }
+
+            return seq[0];
+        }
+
+        let mut keys = vec![];
+        let mut children = vec![];
+        let mut i = 0;
+
+        for size in btree_partition(seq.len(), h, 3) {
+            if i > 0 {
+                keys.push(seq[i]);
+                i += 1;
+            }
+
+            children.push(Self::build(&seq[i..i + size], h - 1));
+            i += size;
+        }
+
+        for &x in keys.iter() {
+            (*x).level = h as usize;
+        }
+
+        let x = keys[0];
+        (*x).connect_left(children[0]);
+
+        if keys.len() == 1 {
+            (*x).connect_right(children[1]);
+        } else {
+            let y = keys[1];
+
+            (*y).connect_left(children[1]);
+            (*y).connect_right(children[2]);
+            (*x).connect_right(y);
+        }
+
+        x
+    }
+}
+
+impl<'a, K: CollKey + 'a, V: 'a, O: Observer<K>> AA<K, V, O> {
+    pub fn with_observer(observer: O) -> Self {
+        Self { root: null_mut(), observer }
     }
 
+    pub fn observer(&self) -> &O {
+        &self.observer
+    }
+
+    pub fn observer_mut(&mut self) -> &mut O {
+        &mut self.observer
+    }
+
+    #[cfg(any(feature = "std", test))]
     pub fn echo_stdout(&self) {
         if !self.root.is_null() {
             unsafe { (*self.root).echo_stdout() }
@@ -269,9 +401,9 @@ impl<'a, K: CollKey + 'a, V: 'a> AA<K, V> {
     ) -> Result<*mut AANode<K, V>, ()> {
         if t.is_null() {
             t = AANode::new(key, value);
-        } else if key < *(*t).key {
+        } else if key < (*t).key {
             (*t).connect_left(self.insert_at((*t).left, key, value)?)
-        } else if key > *(*t).key {
+        } else if key > (*t).key {
             (*t).connect_right(self.insert_at((*t).right, key, value)?)
         } else {
             return Err(());
@@ -303,7 +435,7 @@ impl<'a, K: CollKey + 'a, V: 'a> AA<K, V> {
         if (*(*(*t).right).right).level == (*t).level {
             let new_t = self.rotate(t, Either::Left(())) as *mut AANode<K, V>;
 
-            (*new_t).level += 1;
+            set_level(new_t, (*new_t).level + 1, &mut self.observer);
 
             return new_t;
         }
@@ -315,7 +447,7 @@ impl<'a, K: CollKey + 'a, V: 'a> AA<K, V> {
         &mut self,
         mut t: *mut AANode<K, V>,
         key: &K,
-        res: &mut Vec<*mut V>,
+        res: &mut Vec<V>,
     ) -> *mut AANode<K, V> {
         if t.is_null() {
             return t;
@@ -327,28 +459,23 @@ impl<'a, K: CollKey + 'a, V: 'a> AA<K, V> {
             (*t).connect_right(self.remove_at((*t).right, key, res));
         } else {
             if (*t).is_leaf() {
-                res.push((*t).value);
-                return (*t).left; // null
+                res.push(Box::from_raw(t).value);
+                return null_mut();
             } else if (*t).left.is_null() {
+                // the key is still the minimum of the right subtree after swap
                 let succ = (*t).successor_bst() as *mut AANode<K, V>;
+                (*t).swap_with(succ);
 
-                (*t).connect_right(self.remove_at((*t).right, (*succ).key_bst(), res));
-
-                res.push((*t).value);
-                (*t).value = (*succ).value;
-                (*t).key = (*succ).key;
+                (*t).connect_right(self.remove_at((*t).right, key, res));
             } else {
                 let prec = (*t).precessor_bst() as *mut AANode<K, V>;
+                (*t).swap_with(prec);
 
-                (*t).connect_left(self.remove_at((*t).left, (*prec).key_bst(), res));
-
-                res.push((*t).value);
-                (*t).value = (*prec).value;
-                (*t).key = (*prec).key;
+                (*t).connect_left(self.remove_at((*t).left, key, res));
             }
         }
 
-        (*t).level_dec();
+        self.level_dec(t);
 
         t = self.skew(t);
         (*t).connect_right(self.skew((*t).right));
@@ -364,9 +491,56 @@ impl<'a, K: CollKey + 'a, V: 'a> AA<K, V> {
 
         t
     }
+
+    unsafe fn level_dec(&mut self, t: *mut AANode<K, V>) {
+        let lv = min(level((*t).left), level((*t).right)) + 1;
+
+        if lv < (*t).level {
+            set_level(t, lv, &mut self.observer);
+
+            if lv < level((*t).right) {
+                set_level((*t).right, lv, &mut self.observer);
+            }
+        }
+    }
+}
+
+impl<'a, K: CollKey + 'a, V: 'a, O: Observer<K> + Default> Default for AA<K, V, O> {
+    fn default() -> Self {
+        Self::with_observer(O::default())
+    }
+}
+
+impl<'a, K: CollKey + 'a, V: 'a> FromSortedIter<K, V> for AA<K, V> {
+    fn from_sorted_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Result<Self, ()> {
+        AA::from_sorted_iter(iter)
+    }
+}
+
+unsafe impl<K: Send, V: Send, O: Send> Send for AA<K, V, O> {}
+unsafe impl<K: Sync, V: Sync, O: Sync> Sync for AA<K, V, O> {}
+
+impl<'a, K: CollKey + Clone + 'a, V: Clone + 'a, O: Observer<K> + Clone> Clone for AA<K, V, O> {
+    fn clone(&self) -> Self {
+        Self {
+            root: unsafe { clone_subtree(self.root, AANode::clone_node) },
+            observer: self.observer.clone(),
+        }
+    }
+}
+
+impl<K, V, O> Drop for AA<K, V, O> {
+    fn drop(&mut self) {
+        unsafe {
+            AANode::drop_subtree(self.root);
+        }
+    }
 }
 
-impl<'a, K: CollKey + 'a, V: 'a> Dictionary<K, V> for AA<K, V> {
+impl_std_traits!(AA<K, V, O>);
+impl_stats!(AA<K, V, StatsObserver>);
+
+impl<'a, K: CollKey + 'a, V: 'a, O: Observer<K>> Dictionary<K, V> for AA<K, V, O> {
     fn insert(&mut self, key: K, value: V) -> bool {
         unsafe {
             if self.root.is_null() {
@@ -392,10 +566,10 @@ impl<'a, K: CollKey + 'a, V: 'a> Dictionary<K, V> for AA<K, V> {
             let mut res = Vec::new();
             let t = self.remove_at(self.root, key, &mut res);
 
-            if let Some(vp) = res.pop() {
+            if let Some(value) = res.pop() {
                 self.root = t;
 
-                Some(*Box::from_raw(vp))
+                Some(value)
             } else {
                 None
             }
@@ -414,7 +588,7 @@ impl<'a, K: CollKey + 'a, V: 'a> Dictionary<K, V> for AA<K, V> {
         self.basic_lookup_mut(income_key)
     }
 
-    fn self_validate(&self) -> Result<(), Box<dyn std::error::Error>> {
+    fn self_validate(&self) -> Result<(), Box<dyn core::error::Error>> {
         self.basic_self_validate()?;
 
         if !self.root.is_null() {
@@ -442,7 +616,7 @@ impl<'a, K: CollKey + 'a, V: 'a> Dictionary<K, V> for AA<K, V> {
     }
 }
 
-impl<'a, K: CollKey + 'a, V: 'a> BT<'a, K, V> for AA<K, V> {
+impl<'a, K: CollKey + 'a, V: 'a, O: Observer<K>> BT<'a, K, V> for AA<K, V, O> {
     fn order(&self) -> usize {
         2
     }
@@ -454,28 +628,71 @@ impl<'a, K: CollKey + 'a, V: 'a> BT<'a, K, V> for AA<K, V> {
     fn assign_root(&mut self, root: *mut (dyn BTNode<'a, K, V> + 'a)) {
         self.root = root as *mut AANode<K, V>;
     }
+
+    fn on_search(&self, visits: usize, comparisons: usize) {
+        self.observer.on_search(visits, comparisons)
+    }
 }
 
-impl<'a, K: CollKey + 'a, V: 'a> BST<'a, K, V> for AA<K, V> {
+impl<'a, K: CollKey + 'a, V: 'a, O: Observer<K>> BST<'a, K, V> for AA<K, V, O> {
     unsafe fn rotate_cleanup(
         &mut self,
         _x: *mut (dyn BSTNode<'a, K, V> + 'a),
         _z: *mut (dyn BSTNode<'a, K, V> + 'a),
     ) {
     }
+
+    unsafe fn on_rotate(
+        &mut self,
+        x: *mut (dyn BSTNode<'a, K, V> + 'a),
+        z: *mut (dyn BSTNode<'a, K, V> + 'a),
+        rotation: Either<(), ()>,
+    ) {
+        self.observer.on_rotate((*x).key_bst(), (*z).key_bst(), rotation);
+    }
+
+    unsafe fn on_double_rotate(
+        &mut self,
+        y: *mut (dyn BSTNode<'a, K, V> + 'a),
+        snd_rotation: Either<(), ()>,
+    ) {
+        self.observer.on_double_rotate((*y).key_bst(), snd_rotation);
+    }
 }
 
 #[cfg(test)]
 mod test {
 
-    use crate::{*, bst::ROTATE_NUM};
+    use crate::*;
     use super::*;
 
     #[test]
     pub(crate) fn test_aa_randomdata() {
         test_dict!(AA::new());
+    }
 
-        println!("AA rotate numer: {}", unsafe { ROTATE_NUM })
+    #[test]
+    fn test_aa_send_sync() {
+        test_send_sync!(AA::new());
+    }
+
+    #[test]
+    fn test_aa_clone() {
+        test_clone!(AA::new());
+    }
+
+    #[test]
+    fn test_aa_std_traits() {
+        test_std_traits!(AA::new());
+    }
+
+    #[test]
+    fn test_aa_observer() {
+        let counts = test_observer!(AA);
+
+        assert!(counts.rotate > 0);
+        assert!(counts.level_change > 0);
+        assert_eq!(counts.recolor, 0);
     }
 
     #[test]
@@ -538,4 +755,10 @@ mod test {
 
         aa.echo_stdout();
     }
+
+
+    #[test]
+    fn test_aa_from_sorted_iter() {
+        test_from_sorted_iter!(AA);
+    }
 }
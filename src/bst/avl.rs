@@ -6,69 +6,159 @@
 //! ref 2: https://en.wikipedia.org/wiki/Binary_search_tree
 //!
 
-use std::{
+use core::{
     cmp::max,
-    fmt::{self, Write},
+    mem::ManuallyDrop,
+    ops::RangeBounds,
     ptr::{null, null_mut},
 };
+#[cfg(any(feature = "std", test))]
+use core::fmt::{self, Write};
 
 use either::Either;
 
-use super::*;
 use crate::*;
 
 ////////////////////////////////////////////////////////////////////////////////
 //// Struct
 ////
 
-pub struct AVL<K, V> {
+pub struct AVL<K, V, O = ()> {
     root: *mut AVLNode<K, V>,
+    /// notified of the structural changes, `()` for none
+    observer: O,
 }
 
 struct AVLNode<K, V> {
     left: *mut Self,
     right: *mut Self,
     paren: *mut Self,
+    itself: *mut Self,
     height: i32, // using C style int, as it's default for Rust
-    key: *mut K,
-    value: *mut V,
+    key: K,
+    value: V,
 }
 
 ////////////////////////////////////////////////////////////////////////////////
 //// Implement
 
+fn height<K, V>(node: *mut AVLNode<K, V>) -> i32 {
+    unsafe {
+        if node.is_null() {
+            -1
+        } else {
+            (*node).height
+        }
+    }
+}
+
+/// The rotation that restores the bf of a node, shared with `PAVL`.
+pub(crate) enum Rebalance {
+    Rotate(Either<(), ()>),
+    DoubleRotate(Either<(), ()>),
+}
+
+/// None if the node is in balance, else rotate to its lower side, a double rotation if
+/// the higher child leans inward.
+///
+/// `child_heights(dir)` gives the (left, right) heights of the children of its `dir` child.
+pub(crate) fn rebalance(
+    left_height: i32,
+    right_height: i32,
+    child_heights: impl FnOnce(Either<(), ()>) -> (i32, i32),
+) -> Option<Rebalance> {
+    if (right_height - left_height).abs() < 2 {
+        return None;
+    }
+
+    let direction = if right_height > left_height {
+        Either::Left(())
+    } else {
+        Either::Right(())
+    };
+
+    let (child_left_height, child_right_height) = child_heights(direction.reverse());
+
+    let (same_direction_height, reverse_direction_height) = if direction.is_left() {
+        (child_right_height, child_left_height)
+    } else {
+        (child_left_height, child_right_height)
+    };
+
+    Some(if same_direction_height >= reverse_direction_height {
+        Rebalance::Rotate(direction)
+    } else {
+        Rebalance::DoubleRotate(direction)
+    })
+}
+
+impl<K, V> AVLNode<K, V> {
+    /// Free the whole subtree, return the number of freed nodes.
+    unsafe fn drop_subtree(x: *mut Self) -> usize {
+        let mut stack = vec![x];
+        let mut cnt = 0;
+
+        while let Some(x) = stack.pop() {
+            if x.is_null() {
+                continue;
+            }
+
+            let node = Box::from_raw(x);
+
+            stack.push(node.left);
+            stack.push(node.right);
+
+            cnt += 1;
+        }
+
+        cnt
+    }
+}
+
 impl<'a, K: CollKey + 'a, V: 'a> AVLNode<K, V> {
     pub fn new(key: K, value: V) -> *mut Self {
-        Box::into_raw(box Self {
+        let x = Box::into_raw(Box::new(Self {
             left: null_mut(),
             right: null_mut(),
             paren: null_mut(),
+            itself: null_mut(),
             height: 0,
-            key: Box::into_raw(box key),
-            value: Box::into_raw(box value),
-        })
+            key,
+            value,
+        }));
+        unsafe { (*x).itself = x };
+
+        x
     }
 
     pub fn into_value(self) -> V {
-        unsafe { *Box::from_raw(self.value) }
+        self.value
     }
 
-    fn bf(&self) -> i32 {
-        self.right_height() - self.left_height()
+    /// Cut x from its parent as an independent tree.
+    unsafe fn detach(x: *mut Self) -> *mut Self {
+        if !x.is_null() {
+            (*x).paren = null_mut();
+        }
+
+        x
     }
 
     fn calc_bf(&self) -> i32 {
         self.calc_right_height() - self.calc_left_height()
     }
 
-    pub fn self_validate(&self) -> Result<(), Box<dyn std::error::Error>> {
+    pub fn self_validate(&self) -> Result<(), Box<dyn core::error::Error>> {
         let bf = self.calc_bf();
 
-        assert!(bf.abs() < 2, "BF: {}", bf);
+        if bf.abs() >= 2 {
+            return Err(format!("BF: {bf}").into());
+        }
 
         Ok(())
     }
 
+    #[cfg(any(feature = "std", test))]
     pub fn echo_in_mm(&self, cache: &mut String) -> fmt::Result {
         unsafe {
             BSTNode::echo_in_mm(self, cache, |x, cache| {
@@ -92,6 +182,7 @@ impl<'a, K: CollKey + 'a, V: 'a> AVLNode<K, V> {
         }
     }
 
+    #[cfg(any(feature = "std", test))]
     pub fn echo_stdout(&self) {
         let mut cache = String::new();
 
@@ -101,44 +192,27 @@ impl<'a, K: CollKey + 'a, V: 'a> AVLNode<K, V> {
     }
 }
 
-impl<'a, K: CollKey + Clone + 'a, V: Clone + 'a> Clone for AVLNode<K, V> {
-    /// Expensive Implements.
-    ///
-    /// **WARNING: The Field `paren` isn't set!, it should be set manually!**
-    fn clone(&self) -> Self {
-        unsafe {
-            let key = Box::into_raw(box (*self.key).clone());
-            let value = Box::into_raw(box (*self.value).clone());
-            let height = self.height;
-
-            // let paren = self.paren;
-            let paren = null_mut();
-            let left = if self.left.is_null() {
-                null_mut()
-            } else {
-                Box::into_raw(box (*self.left).clone())
-            };
-            let right = if self.right.is_null() {
-                null_mut()
-            } else {
-                Box::into_raw(box (*self.right).clone())
-            };
+impl<'a, K: CollKey + Clone + 'a, V: Clone + 'a> AVLNode<K, V> {
+    /// Copy without links
+    fn clone_node(&self) -> *mut Self {
+        let x = Box::into_raw(Box::new(Self {
+            left: null_mut(),
+            right: null_mut(),
+            paren: null_mut(),
+            itself: null_mut(),
+            height: self.height,
+            key: self.key.clone(),
+            value: self.value.clone(),
+        }));
+        unsafe { (*x).itself = x };
 
-            Self {
-                left,
-                right,
-                paren,
-                height,
-                key,
-                value,
-            }
-        }
+        x
     }
 }
 
 impl<'a, K: CollKey + 'a, V: 'a> BTNode<'a, K, V> for AVLNode<K, V> {
     fn itself(&self) -> *const (dyn BTNode<'a, K, V> + 'a) {
-        self as *const Self
+        self.itself
     }
 
     fn null(&self) -> *const (dyn BTNode<'a, K, V> + 'a) {
@@ -146,7 +220,14 @@ impl<'a, K: CollKey + 'a, V: 'a> BTNode<'a, K, V> for AVLNode<K, V> {
     }
 
     fn try_as_bst(&self) -> Result<*const (dyn BSTNode<'a, K, V> + 'a), ()> {
-        Ok(self as *const Self)
+        Ok(self.itself)
+    }
+
+    fn downcast(
+        &self,
+        x: *mut (dyn BTNode<'a, K, V> + 'a),
+    ) -> Result<*mut (dyn BSTNode<'a, K, V> + 'a), ()> {
+        Ok(x as *mut Self)
     }
 
     fn order(&self) -> usize {
@@ -173,10 +254,6 @@ impl<'a, K: CollKey + 'a, V: 'a> BTNode<'a, K, V> for AVLNode<K, V> {
         }
     }
 
-    fn assign_value(&mut self, value: V, _idx: usize) {
-        self.value = Box::into_raw(box value);
-    }
-
     fn assign_paren(&mut self, paren: *mut (dyn BTNode<'a, K, V> + 'a)) {
         self.paren = paren as *mut Self;
     }
@@ -189,31 +266,35 @@ impl<'a, K: CollKey + 'a, V: 'a> BTNode<'a, K, V> for AVLNode<K, V> {
         self.height
     }
 
-    fn key_ptr(&self, idx: usize) -> *mut K {
+    fn key(&self, idx: usize) -> Option<&K> {
         if idx == 0 {
-            self.key
+            Some(&self.key)
         } else {
-            null_mut::<K>()
+            None
         }
     }
 
-    fn assign_key_ptr(&mut self, idx: usize, key_ptr: *mut K) {
+    fn key_mut(&mut self, idx: usize) -> Option<&mut K> {
         if idx == 0 {
-            self.key = key_ptr;
+            Some(&mut self.key)
+        } else {
+            None
         }
     }
 
-    fn val_ptr(&self, idx: usize) -> *mut V {
+    fn value(&self, idx: usize) -> Option<&V> {
         if idx == 0 {
-            self.value
+            Some(&self.value)
         } else {
-            null_mut::<V>()
+            None
         }
     }
 
-    fn assign_val_ptr(&mut self, idx: usize, val_ptr: *mut V) {
+    fn value_mut(&mut self, idx: usize) -> Option<&mut V> {
         if idx == 0 {
-            self.value = val_ptr;
+            Some(&mut self.value)
+        } else {
+            None
         }
     }
 }
@@ -222,16 +303,51 @@ impl<'a, K: CollKey + 'a, V: 'a> BSTNode<'a, K, V> for AVLNode<K, V> {}
 
 impl<'a, K: CollKey + 'a, V: 'a> AVL<K, V> {
     pub fn new() -> Self {
-        #[cfg(test)]
-        {
-            unsafe {
-                ROTATE_NUM = 0;
-            }
+        Self::with_observer(())
+    }
+
+    /// Build a perfectly balanced tree from the strictly ascending sequence in O(n),
+    /// Err if it isn't.
+    #[allow(clippy::result_unit_err, reason = "as `FromSortedIter`")]
+    pub fn from_sorted_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Result<Self, ()> {
+        let nodes: Vec<*mut AVLNode<K, V>> = collect_sorted(iter)?
+            .into_iter()
+            .map(|(key, value)| AVLNode::new(key, value))
+            .collect();
+
+        Ok(Self { root: unsafe { Self::build(&nodes[..]) }, observer: () })
+    }
+}
+
+impl<'a, K: CollKey + 'a, V: 'a, O: Observer<K>> AVL<K, V, O> {
+    pub fn with_observer(observer: O) -> Self {
+        Self { root: null_mut(), observer }
+    }
+
+    pub fn observer(&self) -> &O {
+        &self.observer
+    }
+
+    pub fn observer_mut(&mut self) -> &mut O {
+        &mut self.observer
+    }
+
+    unsafe fn build(seq: &[*mut AVLNode<K, V>]) -> *mut AVLNode<K, V> {
+        if seq.is_empty() {
+            return null_mut();
         }
 
-        Self { root: null_mut() }
+        let mid = seq.len() / 2;
+        let x = seq[mid];
+
+        (*x).connect_left(Self::build(&seq[..mid]));
+        (*x).connect_right(Self::build(&seq[mid + 1..]));
+        (*x).height = 1 + max((*x).left_height(), (*x).right_height());
+
+        x
     }
 
+    #[cfg(any(feature = "std", test))]
     pub fn echo_stdout(&self) {
         if !self.root.is_null() {
             unsafe { (*self.root).echo_stdout() }
@@ -280,64 +396,245 @@ impl<'a, K: CollKey + 'a, V: 'a> AVL<K, V> {
         while !p.is_null() {
             (*p).height = 1 + max((*p).left_height(), (*p).right_height());
 
-            if (*p).bf().abs() > 1 {
-                let x = p;
-
-                let direction = if (*x).right_height() > (*x).left_height() {
-                    Either::Left(())
-                } else {
-                    Either::Right(())
-                };
+            let x = p;
 
-                let same_direction_height =
-                    (*BSTNode::child(&*x, direction.reverse())).child_height(direction.reverse());
+            let rebalance = rebalance((*x).left_height(), (*x).right_height(), |dir| {
+                let child = BSTNode::child(&*x, dir);
 
-                let reverse_direction_height =
-                    (*BSTNode::child(&*x, direction.reverse())).child_height(direction);
+                ((*child).child_height(Either::Left(())), (*child).child_height(Either::Right(())))
+            });
 
-                p = if same_direction_height >= reverse_direction_height {
-                    self.rotate(x, direction)
-                } else {
-                    self.double_rotate(x, direction)
-                } as *mut AVLNode<K, V>;
-            }
+            p = match rebalance {
+                Some(Rebalance::Rotate(direction)) => self.rotate(x, direction) as *mut AVLNode<K, V>,
+                Some(Rebalance::DoubleRotate(direction)) => {
+                    self.double_rotate(x, direction) as *mut AVLNode<K, V>
+                }
+                None => x,
+            };
 
             p = (*p).paren;
         }
     }
-}
 
-impl<'a, K: CollKey + Clone + 'a, V: Clone + 'a> Clone for AVL<K, V> {
-    fn clone(&self) -> Self {
-        if self.root.is_null() {
-            return Self { root: null_mut() };
+    ///
+    /// case-3
+    ///       z
+    ///      / \
+    ///         y
+    ///        / \
+    ///     null  x
+    ///          / \
+    ///
+    /// Unlink z from the tree (z isn't freed)
+    unsafe fn remove_at(&mut self, z: *mut AVLNode<K, V>) {
+        let retracing_entry;
+        if (*z).left().is_null() {
+            retracing_entry = (*z).paren;
+            self.subtree_shift(z, (*z).upcast((*z).right()));
+        } else if (*z).right().is_null() {
+            retracing_entry = (*z).paren;
+            self.subtree_shift(z, (*z).upcast((*z).left()));
+        } else {
+            let y = BSTNode::successor_bst(&*z);
+            retracing_entry = if !core::ptr::addr_eq((*y).paren(), z) {
+                (*y).paren_bst()
+            } else {
+                y
+            } as *mut AVLNode<K, V>;
+
+            if !core::ptr::addr_eq((*y).paren(), z) {
+                self.subtree_shift((*y).as_bt_mut(), (*y).upcast((*y).right()));
+
+                (*y).assign_right((*z).right());
+                (*(*y).right()).assign_paren((*y).as_bt_mut());
+            }
+
+            self.subtree_shift(z, (*y).as_bt_mut());
+            (*y).assign_left((*z).left());
+            (*(*y).left()).assign_paren((*y).as_bt_mut());
         }
+        self.remove_retracing(retracing_entry);
+    }
 
+    /// Remove all entries whose key is in the range, return the number of them.
+    ///
+    /// Two splits and one join, O(log n + k)
+    pub fn remove_range<R: RangeBounds<K>>(&mut self, range: R) -> usize {
         unsafe {
-            let root = Box::into_raw(box (*self.root).clone());
+            let observer = &mut self.observer;
+            let (lf, rest) = Self::split_by(
+                self.root, &|key| key_before_range(key, &range), observer
+            );
+            let (mid, rh) = Self::split_by(
+                rest, &|key| !key_after_range(key, &range), observer
+            );
+
+            let root = Self::join2(lf, rh, observer);
+            self.reset_root(root);
+
+            self.observer.on_tree_split();
+            self.observer.on_tree_split();
+            self.observer.on_tree_join();
+
+            AVLNode::drop_subtree(mid)
+        }
+    }
+
+    /// Split into two by predicate.
+    ///
+    /// The left satisfy `is_lf`, the right don't.
+    ///
+    /// **MUST:** `is_lf` is monotone (true ... true false ... false) on infix order
+    unsafe fn split_by(
+        t: *mut AVLNode<K, V>,
+        is_lf: &dyn Fn(&K) -> bool,
+        observer: &mut O,
+    ) -> (*mut AVLNode<K, V>, *mut AVLNode<K, V>) {
+        if t.is_null() {
+            return (null_mut(), null_mut());
+        }
 
-            (*root).bfs_do(|x| {
-                let x = (*x).try_as_bst_mut().unwrap();
+        let lf = AVLNode::detach((*t).left);
+        let rh = AVLNode::detach((*t).right);
 
-                if !(*x).left().is_null() {
-                    (*(*x).left()).assign_paren((*x).itself_mut());
-                }
+        if is_lf((*t).key_bst()) {
+            let (part_lf, part_rh) = Self::split_by(rh, is_lf, observer);
 
-                if !(*x).right().is_null() {
-                    (*(*x).right()).assign_paren((*x).itself_mut());
-                }
-            });
+            (Self::join(lf, t, part_lf, observer), part_rh)
+        } else {
+            let (part_lf, part_rh) = Self::split_by(lf, is_lf, observer);
+
+            (part_lf, Self::join(part_rh, t, rh, observer))
+        }
+    }
+
+    /// Join two trees with a middle node k.
+    ///
+    /// **MUST:** keys of tl < key of k < keys of tr
+    ///
+    /// O(|h(tl) - h(tr)|)
+    unsafe fn join(
+        tl: *mut AVLNode<K, V>,
+        k: *mut AVLNode<K, V>,
+        tr: *mut AVLNode<K, V>,
+        observer: &mut O,
+    ) -> *mut AVLNode<K, V> {
+        let hl = height(tl);
+        let hr = height(tr);
+
+        (*k).left = null_mut();
+        (*k).right = null_mut();
+        (*k).paren = null_mut();
+
+        // Descend along the spine of the higher tree until the subtree c
+        // with h(c) <= h(lower tree) + 1, replace c with k(c, lower tree).
+        let dir = if hl > hr + 1 {
+            Either::Right(())
+        } else if hr > hl + 1 {
+            Either::Left(())
+        } else {
+            (*k).connect_left(tl);
+            (*k).connect_right(tr);
+            (*k).height = 1 + max(hl, hr);
+
+            return k;
+        };
+
+        let (higher, lower_h) = if dir.is_right() { (tl, hr) } else { (tr, hl) };
+
+        let mut p = null_mut();
+        let mut c = higher;
+
+        while height(c) > lower_h + 1 {
+            p = c;
+            c = BSTNode::child(&*c, dir) as *mut AVLNode<K, V>;
+        }
+
+        if dir.is_right() {
+            (*k).connect_left(c);
+            (*k).connect_right(tr);
+            (*p).connect_right(k);
+        } else {
+            (*k).connect_left(tl);
+            (*k).connect_right(c);
+            (*p).connect_left(k);
+        }
+
+        (*k).height = 1 + max((*k).left_height(), (*k).right_height());
 
-            Self { root }
+        // a view on the nodes, which are owned by the caller
+        let mut avl = ManuallyDrop::new(AVL { root: higher, observer });
+        avl.remove_retracing(p);
+
+        avl.root
+    }
+
+    /// Join two trees.
+    ///
+    /// **MUST:** keys of tl < keys of tr
+    unsafe fn join2(
+        tl: *mut AVLNode<K, V>,
+        tr: *mut AVLNode<K, V>,
+        observer: &mut O,
+    ) -> *mut AVLNode<K, V> {
+        if tl.is_null() {
+            return tr;
+        }
+
+        if tr.is_null() {
+            return tl;
+        }
+
+        let mut avl = ManuallyDrop::new(AVL { root: tr, observer: &mut *observer });
+        let k = (*tr).minimum() as *mut AVLNode<K, V>;
+        avl.remove_at(k);
+
+        let tr = avl.root;
+
+        Self::join(tl, k, tr, observer)
+    }
+}
+
+impl<'a, K: CollKey + 'a, V: 'a, O: Observer<K> + Default> Default for AVL<K, V, O> {
+    fn default() -> Self {
+        Self::with_observer(O::default())
+    }
+}
+
+impl<'a, K: CollKey + 'a, V: 'a> FromSortedIter<K, V> for AVL<K, V> {
+    fn from_sorted_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Result<Self, ()> {
+        AVL::from_sorted_iter(iter)
+    }
+}
+
+unsafe impl<K: Send, V: Send, O: Send> Send for AVL<K, V, O> {}
+unsafe impl<K: Sync, V: Sync, O: Sync> Sync for AVL<K, V, O> {}
+
+impl<'a, K: CollKey + Clone + 'a, V: Clone + 'a, O: Observer<K> + Clone> Clone for AVL<K, V, O> {
+    fn clone(&self) -> Self {
+        Self {
+            root: unsafe { clone_subtree(self.root, AVLNode::clone_node) },
+            observer: self.observer.clone(),
         }
     }
 }
 
-impl<'a, K: CollKey + 'a, V: 'a> Dictionary<K, V> for AVL<K, V> {
+impl<K, V, O> Drop for AVL<K, V, O> {
+    fn drop(&mut self) {
+        unsafe {
+            AVLNode::drop_subtree(self.root);
+        }
+    }
+}
+
+impl_std_traits!(AVL<K, V, O>);
+impl_stats!(AVL<K, V, StatsObserver>);
+
+impl<'a, K: CollKey + 'a, V: 'a, O: Observer<K>> Dictionary<K, V> for AVL<K, V, O> {
     fn insert(&mut self, key: K, value: V) -> bool {
         let new_node = AVLNode::new(key, value);
 
-        if !self.basic_insert(new_node) {
+        if !unsafe { self.basic_insert(new_node) } {
             return false;
         }
 
@@ -360,7 +657,7 @@ impl<'a, K: CollKey + 'a, V: 'a> Dictionary<K, V> for AVL<K, V> {
     ///          / \
     ///
     fn remove(&mut self, key: &K) -> Option<V> {
-        let z = self.search_approximately(&key) as *mut AVLNode<K, V>;
+        let z = self.search_approximately(key) as *mut AVLNode<K, V>;
         if z.is_null() {
             return None;
         }
@@ -370,35 +667,9 @@ impl<'a, K: CollKey + 'a, V: 'a> Dictionary<K, V> for AVL<K, V> {
                 return None;
             }
 
-            let retracing_entry;
-            if (*z).left().is_null() {
-                retracing_entry = (*z).paren;
-                self.subtree_shift(z, (*z).right());
-            } else if (*z).right().is_null() {
-                retracing_entry = (*z).paren;
-                self.subtree_shift(z, (*z).left());
-            } else {
-                let y = BSTNode::successor_bst(&*z);
-                retracing_entry = if (*y).paren() != z {
-                    (*y).paren_bst()
-                } else {
-                    y
-                } as *mut AVLNode<K, V>;
-
-                if (*y).paren() != z {
-                    self.subtree_shift(y, (*y).right());
-
-                    (*y).assign_right((*z).right());
-                    (*(*y).right()).assign_paren(y);
-                }
-
-                self.subtree_shift(z, y);
-                (*y).assign_left((*z).left());
-                (*(*y).left()).assign_paren(y);
-            }
-            self.remove_retracing(retracing_entry);
+            self.remove_at(z);
 
-            let origin_node = Box::from_raw(z as *mut AVLNode<K, V>);
+            let origin_node = Box::from_raw(z);
 
             Some(origin_node.into_value())
         }
@@ -416,7 +687,7 @@ impl<'a, K: CollKey + 'a, V: 'a> Dictionary<K, V> for AVL<K, V> {
         self.basic_lookup_mut(income_key)
     }
 
-    fn self_validate(&self) -> Result<(), Box<dyn std::error::Error>> {
+    fn self_validate(&self) -> Result<(), Box<dyn core::error::Error>> {
         self.basic_self_validate()?;
 
         if !self.root.is_null() {
@@ -427,7 +698,7 @@ impl<'a, K: CollKey + 'a, V: 'a> Dictionary<K, V> for AVL<K, V> {
     }
 }
 
-impl<'a, K: CollKey + 'a, V: 'a> BT<'a, K, V> for AVL<K, V> {
+impl<'a, K: CollKey + 'a, V: 'a, O: Observer<K>> BT<'a, K, V> for AVL<K, V, O> {
     fn order(&self) -> usize {
         2
     }
@@ -439,9 +710,13 @@ impl<'a, K: CollKey + 'a, V: 'a> BT<'a, K, V> for AVL<K, V> {
     fn assign_root(&mut self, root: *mut (dyn BTNode<'a, K, V> + 'a)) {
         self.root = root as *mut AVLNode<K, V>;
     }
+
+    fn on_search(&self, visits: usize, comparisons: usize) {
+        self.observer.on_search(visits, comparisons)
+    }
 }
 
-impl<'a, K: CollKey + 'a, V: 'a> BST<'a, K, V> for AVL<K, V> {
+impl<'a, K: CollKey + 'a, V: 'a, O: Observer<K>> BST<'a, K, V> for AVL<K, V, O> {
     unsafe fn rotate_cleanup(
         &mut self,
         x: *mut (dyn BSTNode<'a, K, V> + 'a),
@@ -450,6 +725,23 @@ impl<'a, K: CollKey + 'a, V: 'a> BST<'a, K, V> for AVL<K, V> {
         (*(x as *mut AVLNode<K, V>)).height = 1 + max((*x).left_height(), (*x).right_height());
         (*(z as *mut AVLNode<K, V>)).height = 1 + max((*z).left_height(), (*z).right_height());
     }
+
+    unsafe fn on_rotate(
+        &mut self,
+        x: *mut (dyn BSTNode<'a, K, V> + 'a),
+        z: *mut (dyn BSTNode<'a, K, V> + 'a),
+        rotation: Either<(), ()>,
+    ) {
+        self.observer.on_rotate((*x).key_bst(), (*z).key_bst(), rotation);
+    }
+
+    unsafe fn on_double_rotate(
+        &mut self,
+        y: *mut (dyn BSTNode<'a, K, V> + 'a),
+        snd_rotation: Either<(), ()>,
+    ) {
+        self.observer.on_double_rotate((*y).key_bst(), snd_rotation);
+    }
 }
 
 #[cfg(test)]
@@ -461,8 +753,29 @@ pub(crate) mod tests {
     #[test]
     pub(crate) fn test_avl_randomdata() {
         test_dict!(AVL::new());
+    }
 
-        println!("rotate numer: {}", unsafe { ROTATE_NUM })
+    #[test]
+    fn test_avl_send_sync() {
+        test_send_sync!(AVL::new());
+    }
+
+    #[test]
+    fn test_avl_clone() {
+        test_clone!(AVL::new());
+    }
+
+    #[test]
+    fn test_avl_std_traits() {
+        test_std_traits!(AVL::new());
+    }
+
+    #[test]
+    fn test_avl_observer() {
+        let counts = test_observer!(AVL);
+
+        assert!(counts.rotate > 0);
+        assert_eq!(counts.recolor + counts.split + counts.rebuild + counts.splay, 0);
     }
 
     #[test]
@@ -587,4 +900,15 @@ pub(crate) mod tests {
 
         // avl.echo_stdout();
     }
+
+    #[test]
+    fn test_avl_remove_range() {
+        test_remove_range!(AVL::new());
+    }
+
+
+    #[test]
+    fn test_avl_from_sorted_iter() {
+        test_from_sorted_iter!(AVL);
+    }
 }
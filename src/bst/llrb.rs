@@ -46,10 +46,11 @@
 //!
 
 
-use std::{
-    fmt::{self, Write},
+use core::{
     ptr::{null, null_mut},
 };
+#[cfg(any(feature = "std", test))]
+use core::fmt::{self, Write};
 
 use either::Either;
 use itertools::Itertools;
@@ -60,18 +61,20 @@ use crate::*;
 ////////////////////////////////////////////////////////////////////////////////
 //// Struct
 
-///
-pub struct LLRB<K, V> {
+pub struct LLRB<K, V, O = ()> {
     root: *mut LLRBNode<K, V>,
+    /// notified of the structural changes, `()` for none
+    observer: O,
 }
 
 struct LLRBNode<K, V> {
     left: *mut Self,
     right: *mut Self,
     paren: *mut Self,
+    itself: *mut Self,
     color: Color,
-    key: *mut K,
-    value: *mut V,
+    key: K,
+    value: V,
 }
 
 #[derive(Copy)]
@@ -112,10 +115,12 @@ fn set_black<K, V>(node: *mut LLRBNode<K, V>) {
     }
 }
 
-fn set_red<K, V>(node: *mut LLRBNode<K, V>) {
+/// Notify the observer if the color changes
+fn paint<K: CollKey, V, O: Observer<K>>(node: *mut LLRBNode<K, V>, color: Color, observer: &mut O) {
     unsafe {
-        if !node.is_null() {
-            (*node).color = Color::RED
+        if !node.is_null() && (*node).color != color {
+            observer.on_recolor(&(*node).key, color == Color::RED);
+            (*node).color = color
         }
     }
 }
@@ -214,7 +219,7 @@ impl<'a, K: CollKey + 'a, V: 'a> PhB4Node<K, V> {
     fn item_iter(&'a self) -> Box<dyn Iterator<Item = *mut LLRBNode<K, V>> + 'a> {
         let mut i = -1i32;
 
-        box std::iter::from_fn(move || -> Option<*mut LLRBNode<K, V>> {
+        Box::new(core::iter::from_fn(move || -> Option<*mut LLRBNode<K, V>> {
             i += 1;
             let item = self.item(i as usize);
 
@@ -223,13 +228,13 @@ impl<'a, K: CollKey + 'a, V: 'a> PhB4Node<K, V> {
             } else {
                 Some(item)
             }
-        })
+        }))
     }
 
     fn children_iter(&'a self) -> Box<dyn Iterator<Item = *mut LLRBNode<K, V>> + 'a> {
         let mut i = -1i32;
 
-        box std::iter::from_fn(move || -> Option<*mut LLRBNode<K, V>> {
+        Box::new(core::iter::from_fn(move || -> Option<*mut LLRBNode<K, V>> {
             i += 1;
             let item = self.child(i as usize);
 
@@ -238,7 +243,7 @@ impl<'a, K: CollKey + 'a, V: 'a> PhB4Node<K, V> {
             } else {
                 Some(item)
             }
-        })
+        }))
     }
 
     /// How many key-value pairs does B-node contains
@@ -268,7 +273,11 @@ impl<'a, K: CollKey + 'a, V: 'a> PhB4Node<K, V> {
         unreachable!()
     }
 
-    unsafe fn pop_item(&mut self, t: &mut LLRB<K, V>, dir: Either<(), ()>) -> *mut LLRBNode<K, V> {
+    unsafe fn pop_item<O: Observer<K>>(
+        &mut self,
+        t: &mut LLRB<K, V, O>,
+        dir: Either<(), ()>,
+    ) -> *mut LLRBNode<K, V> {
         match (self.node_size(), dir) {
             (3, Either::Left(())) => {
                 let child = (*self.centre).left;
@@ -276,7 +285,7 @@ impl<'a, K: CollKey + 'a, V: 'a> PhB4Node<K, V> {
                 (*self.centre).assign_left(null_mut::<LLRBNode<K, V>>());
                 self.centre = t.rotate(self.centre, Either::Left(())) as *mut LLRBNode<K, V>;
 
-                set_black(child);
+                paint(child, Color::BLACK, &mut t.observer);
 
                 child
             }
@@ -284,7 +293,7 @@ impl<'a, K: CollKey + 'a, V: 'a> PhB4Node<K, V> {
                 let child = (*self.centre).right;
 
                 (*self.centre).assign_right(null_mut::<LLRBNode<K, V>>());
-                set_black(child);
+                paint(child, Color::BLACK, &mut t.observer);
 
                 child
             }
@@ -292,19 +301,19 @@ impl<'a, K: CollKey + 'a, V: 'a> PhB4Node<K, V> {
                 let child = (*self.centre).left;
 
                 (*self.centre).assign_left(null_mut::<LLRBNode<K, V>>());
-                set_black(child);
+                paint(child, Color::BLACK, &mut t.observer);
 
                 child
             }
             (2, Either::Right(())) => {
                 // Refactor LLRB
                 t.subtree_shift(self.centre, (*self.centre).left);
-                set_black((*self.centre).left);
+                paint((*self.centre).left, Color::BLACK, &mut t.observer);
 
                 // Update Popped Node
                 let child = self.centre;
                 (*child).left = null_mut();
-                set_black(child);
+                paint(child, Color::BLACK, &mut t.observer);
 
                 // Update B4Node
                 self.centre = (*self.centre).left;
@@ -460,7 +469,7 @@ impl<'a, K: CollKey + 'a, V: 'a> PhB3Node<K, V> {
     fn item_iter(&'a self) -> Box<dyn Iterator<Item = *mut LLRBNode<K, V>> + 'a> {
         let mut i = -1i32;
 
-        box std::iter::from_fn(move || -> Option<*mut LLRBNode<K, V>> {
+        Box::new(core::iter::from_fn(move || -> Option<*mut LLRBNode<K, V>> {
             i += 1;
             let item = self.item(i as usize);
 
@@ -469,13 +478,13 @@ impl<'a, K: CollKey + 'a, V: 'a> PhB3Node<K, V> {
             } else {
                 Some(item)
             }
-        })
+        }))
     }
 
     fn children_iter(&'a self) -> Box<dyn Iterator<Item = *mut LLRBNode<K, V>> + 'a> {
         let mut i = -1i32;
 
-        box std::iter::from_fn(move || -> Option<*mut LLRBNode<K, V>> {
+        Box::new(core::iter::from_fn(move || -> Option<*mut LLRBNode<K, V>> {
             i += 1;
             let item = self.child(i as usize);
 
@@ -484,7 +493,7 @@ impl<'a, K: CollKey + 'a, V: 'a> PhB3Node<K, V> {
             } else {
                 Some(item)
             }
-        })
+        }))
     }
 
     /// How many key-value pairs does B-node contains
@@ -514,7 +523,11 @@ impl<'a, K: CollKey + 'a, V: 'a> PhB3Node<K, V> {
         unreachable!()
     }
 
-    unsafe fn pop_item(&mut self, t: &mut LLRB<K, V>, dir: Either<(), ()>) -> *mut LLRBNode<K, V> {
+    unsafe fn pop_item<O: Observer<K>>(
+        &mut self,
+        t: &mut LLRB<K, V, O>,
+        dir: Either<(), ()>,
+    ) -> *mut LLRBNode<K, V> {
         match (self.node_size(), dir) {
             (2, Either::Left(())) => {
                 let child = (*self.centre).left;
@@ -522,7 +535,7 @@ impl<'a, K: CollKey + 'a, V: 'a> PhB3Node<K, V> {
                 (*self.centre).connect_left((*child).right);
 
                 (*child).right = null_mut();
-                set_black(child);
+                paint(child, Color::BLACK, &mut t.observer);
 
                 child
             }
@@ -536,7 +549,7 @@ impl<'a, K: CollKey + 'a, V: 'a> PhB3Node<K, V> {
                 self.centre = root;
 
                 (*child).left = null_mut();
-                set_black(child);
+                paint(child, Color::BLACK, &mut t.observer);
 
                 child
             }
@@ -584,31 +597,54 @@ impl<'a, K: CollKey + 'a, V: 'a> PhB3Node<K, V> {
     // }
 }
 
+impl<K, V> LLRBNode<K, V> {
+    /// Free the whole subtree, return the number of freed nodes.
+    unsafe fn drop_subtree(x: *mut Self) -> usize {
+        let mut stack = vec![x];
+        let mut cnt = 0;
+
+        while let Some(x) = stack.pop() {
+            if x.is_null() {
+                continue;
+            }
+
+            let node = Box::from_raw(x);
+
+            stack.push(node.left);
+            stack.push(node.right);
+
+            cnt += 1;
+        }
+
+        cnt
+    }
+}
+
 impl<'a, K: CollKey + 'a, V: 'a> LLRBNode<K, V> {
     pub fn new(key: K, value: V) -> *mut Self {
-        Box::into_raw(box Self {
+        let x = Box::into_raw(Box::new(Self {
             left: null_mut(),
             right: null_mut(),
             paren: null_mut(),
+            itself: null_mut(),
             color: Color::RED,
-            key: Box::into_raw(box key),
-            value: Box::into_raw(box value),
-        })
+            key,
+            value,
+        }));
+        unsafe { (*x).itself = x };
+
+        x
     }
 
-    fn leaf(paren: *mut Self) -> *mut Self {
-        Box::into_raw(box Self {
-            left: null_mut(),
-            right: null_mut(),
-            paren,
-            color: Color::BLACK,
-            key: null_mut(),
-            value: null_mut(),
-        })
+    fn node_into_value(node: *mut LLRBNode<K, V>) -> V {
+        unsafe {
+            let origin_node = Box::from_raw(node);
+            origin_node.value
+        }
     }
 
     /// validate red/black
-    pub fn self_validate(&self) -> Result<(), Box<dyn std::error::Error>> {
+    pub fn self_validate(&self) -> Result<(), Box<dyn core::error::Error>> {
         self.basic_self_validate()?;
 
         unsafe {
@@ -675,30 +711,33 @@ impl<'a, K: CollKey + 'a, V: 'a> LLRBNode<K, V> {
         Ok(())
     }
 
-    /// Knuth's leaf, carry no information.
-    pub fn leafs(&self) -> Vec<*mut Self> {
-        let mut queue = vecdeq![self as *const Self as *mut Self];
-        let mut leafs = vec![];
+    /// Black depth of each Knuth's leaf (the nil child),
+    /// that's the black depth of its parent plus the parent itself.
+    pub fn leaf_black_depths(&self) -> Vec<usize> {
+        let mut queue = vecdeq![self.itself];
+        let mut depths = vec![];
 
         while !queue.is_empty() {
             let p = queue.pop_front().unwrap();
 
             unsafe {
+                let leaf_depth = (*p).black_depth() + is_black(p) as usize;
+
                 if (*p).left.is_null() {
-                    leafs.push(LLRBNode::leaf(p));
+                    depths.push(leaf_depth);
                 } else {
                     queue.push_back((*p).left);
                 }
 
                 if (*p).right.is_null() {
-                    leafs.push(LLRBNode::leaf(p));
+                    depths.push(leaf_depth);
                 } else {
                     queue.push_back((*p).right);
                 }
             }
         }
 
-        leafs
+        depths
     }
 
     /// Black nodes number from root to this.
@@ -721,6 +760,7 @@ impl<'a, K: CollKey + 'a, V: 'a> LLRBNode<K, V> {
         }
     }
 
+    #[cfg(any(feature = "std", test))]
     pub fn echo_in_mm(&self, cache: &mut String) -> fmt::Result {
         unsafe {
             BSTNode::echo_in_mm(self, cache, |x, cache| {
@@ -731,6 +771,7 @@ impl<'a, K: CollKey + 'a, V: 'a> LLRBNode<K, V> {
         }
     }
 
+    #[cfg(any(feature = "std", test))]
     pub fn echo_stdout(&self) {
         let mut cache = String::new();
 
@@ -739,28 +780,11 @@ impl<'a, K: CollKey + 'a, V: 'a> LLRBNode<K, V> {
         println!("{}", cache);
     }
 
-    /// Split or Merge of 4-node
-    fn color_flip(&mut self) {
-        self.color = self.color.reverse();
-
-        if !self.left.is_null() {
-            unsafe {
-                (*self.left).color = (*self.left).color.reverse();
-            }
-        }
-
-        if !self.right.is_null() {
-            unsafe {
-                (*self.right).color = (*self.right).color.reverse();
-            }
-        }
-    }
-
     /// WARNING b-node shouldn't be overfilled
     /// b3 or b4 centre
     #[allow(unused)]
     unsafe fn centre(&self) -> *mut Self {
-        let self_ptr = self as *const Self as *mut Self;
+        let self_ptr = self.itself;
 
         if is_red(self.left) {
             self_ptr
@@ -772,9 +796,27 @@ impl<'a, K: CollKey + 'a, V: 'a> LLRBNode<K, V> {
     }
 }
 
+impl<'a, K: CollKey + Clone + 'a, V: Clone + 'a> LLRBNode<K, V> {
+    /// Copy without links
+    fn clone_node(&self) -> *mut Self {
+        let x = Box::into_raw(Box::new(Self {
+            left: null_mut(),
+            right: null_mut(),
+            paren: null_mut(),
+            itself: null_mut(),
+            color: self.color.clone(),
+            key: self.key.clone(),
+            value: self.value.clone(),
+        }));
+        unsafe { (*x).itself = x };
+
+        x
+    }
+}
+
 impl<'a, K: CollKey + 'a, V: 'a> BTNode<'a, K, V> for LLRBNode<K, V> {
     fn itself(&self) -> *const (dyn BTNode<'a, K, V> + 'a) {
-        self as *const Self
+        self.itself
     }
 
     fn null(&self) -> *const (dyn BTNode<'a, K, V> + 'a) {
@@ -782,7 +824,14 @@ impl<'a, K: CollKey + 'a, V: 'a> BTNode<'a, K, V> for LLRBNode<K, V> {
     }
 
     fn try_as_bst(&self) -> Result<*const (dyn BSTNode<'a, K, V> + 'a), ()> {
-        Ok(self as *const Self)
+        Ok(self.itself)
+    }
+
+    fn downcast(
+        &self,
+        x: *mut (dyn BTNode<'a, K, V> + 'a),
+    ) -> Result<*mut (dyn BSTNode<'a, K, V> + 'a), ()> {
+        Ok(x as *mut Self)
     }
 
     fn order(&self) -> usize {
@@ -809,10 +858,6 @@ impl<'a, K: CollKey + 'a, V: 'a> BTNode<'a, K, V> for LLRBNode<K, V> {
         }
     }
 
-    fn assign_value(&mut self, value: V, _idx: usize) {
-        self.value = Box::into_raw(box value);
-    }
-
     fn assign_paren(&mut self, paren: *mut (dyn BTNode<'a, K, V> + 'a)) {
         self.paren = paren as *mut Self;
     }
@@ -825,31 +870,35 @@ impl<'a, K: CollKey + 'a, V: 'a> BTNode<'a, K, V> for LLRBNode<K, V> {
         self.calc_height()
     }
 
-    fn key_ptr(&self, idx: usize) -> *mut K {
+    fn key(&self, idx: usize) -> Option<&K> {
         if idx == 0 {
-            self.key
+            Some(&self.key)
         } else {
-            null_mut::<K>()
+            None
         }
     }
 
-    fn assign_key_ptr(&mut self, idx: usize, key_ptr: *mut K) {
+    fn key_mut(&mut self, idx: usize) -> Option<&mut K> {
         if idx == 0 {
-            self.key = key_ptr;
+            Some(&mut self.key)
+        } else {
+            None
         }
     }
 
-    fn val_ptr(&self, idx: usize) -> *mut V {
+    fn value(&self, idx: usize) -> Option<&V> {
         if idx == 0 {
-            self.value
+            Some(&self.value)
         } else {
-            null_mut::<V>()
+            None
         }
     }
 
-    fn assign_val_ptr(&mut self, idx: usize, val_ptr: *mut V) {
+    fn value_mut(&mut self, idx: usize) -> Option<&mut V> {
         if idx == 0 {
-            self.value = val_ptr;
+            Some(&mut self.value)
+        } else {
+            None
         }
     }
 }
@@ -858,14 +907,94 @@ impl<'a, K: CollKey + 'a, V: 'a> BSTNode<'a, K, V> for LLRBNode<K, V> {}
 
 impl<'a, K: CollKey + 'a, V: 'a> LLRB<K, V> {
     pub fn new() -> Self {
-        #[cfg(test)]
-        {
-            unsafe {
-                super::ROTATE_NUM = 0;
+        Self::with_observer(())
+    }
+
+    /// Build a perfectly balanced tree from the strictly ascending sequence in O(n),
+    /// Err if it isn't.
+    ///
+    /// It's built as a B3 tree with the least height, 3-node is left-leaning red link.
+    #[allow(clippy::result_unit_err, reason = "as `FromSortedIter`")]
+    pub fn from_sorted_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Result<Self, ()> {
+        let nodes: Vec<*mut LLRBNode<K, V>> = collect_sorted(iter)?
+            .into_iter()
+            .map(|(key, value)| LLRBNode::new(key, value))
+            .collect();
+
+        let h = btree_height(nodes.len(), 3);
+
+        Ok(Self { root: unsafe { Self::build(&nodes[..], h) }, observer: () })
+    }
+
+    /// Build B3 subtree of height h (the black height)
+    unsafe fn build(seq: &[*mut LLRBNode<K, V>], h: u32) -> *mut LLRBNode<K, V> {
+        if seq.is_empty() {
+            return null_mut();
+        }
+
+        if h == 1 {
+            let y = seq[seq.len() - 1];
+            set_black(y);
+
+            if seq.len() == 2 {
+                (*y).connect_left(seq[0]);
             }
+
+            return y;
+        }
+
+        let mut keys = vec![];
+        let mut children = vec![];
+        let mut i = 0;
+
+        for size in btree_partition(seq.len(), h, 3) {
+            if i > 0 {
+                keys.push(seq[i]);
+                i += 1;
+            }
+
+            children.push(Self::build(&seq[i..i + size], h - 1));
+            i += size;
+        }
+
+        let y = keys[keys.len() - 1];
+        set_black(y);
+        (*y).connect_right(children[children.len() - 1]);
+
+        if keys.len() == 1 {
+            (*y).connect_left(children[0]);
+        } else {
+            let x = keys[0];
+
+            (*x).connect_left(children[0]);
+            (*x).connect_right(children[1]);
+            (*y).connect_left(x);
         }
 
-        Self { root: null_mut() }
+        y
+    }
+}
+
+impl<'a, K: CollKey + 'a, V: 'a, O: Observer<K>> LLRB<K, V, O> {
+    pub fn with_observer(observer: O) -> Self {
+        Self { root: null_mut(), observer }
+    }
+
+    pub fn observer(&self) -> &O {
+        &self.observer
+    }
+
+    pub fn observer_mut(&mut self) -> &mut O {
+        &mut self.observer
+    }
+
+    /// Split or Merge of 4-node
+    unsafe fn color_flip(&mut self, x: *mut LLRBNode<K, V>) {
+        for y in [x, (*x).left, (*x).right] {
+            if !y.is_null() {
+                paint(y, (*y).color.reverse(), &mut self.observer);
+            }
+        }
     }
 
     #[allow(unused)]
@@ -874,7 +1003,7 @@ impl<'a, K: CollKey + 'a, V: 'a> LLRB<K, V> {
         let x_paren = (*x).paren;
 
         if x_paren.is_null() {
-            set_black(x);
+            paint(x, Color::BLACK, &mut self.observer);
 
             // if !(*x).left.is_null() {
             //     (*(*x).left).try_merge_spec_2_node();
@@ -928,7 +1057,7 @@ impl<'a, K: CollKey + 'a, V: 'a> LLRB<K, V> {
             }
         } else {
             // u_paren is overfilled
-            (*u_paren).color_flip();
+            self.color_flip(u_paren);
 
             // (*u_sibling).try_merge_spec_2_node();
 
@@ -936,8 +1065,8 @@ impl<'a, K: CollKey + 'a, V: 'a> LLRB<K, V> {
         }
     }
 
-    /// B4 Version
-    /// Params: b4centre, removed child index of this centre
+    // B4 Version
+    // Params: b4centre, removed child index of this centre
     // #[allow(unused)]
     // unsafe fn unpromote(
     //     &mut self,
@@ -1194,10 +1323,10 @@ impl<'a, K: CollKey + 'a, V: 'a> LLRB<K, V> {
         match (paren_b3.node_size(), sibl_dir) {
             // 1
             (1, Either::Left(())) => {
-                set_red(sibl);
+                paint(sibl, Color::RED, &mut self.observer);
             }
             (1, Either::Right(())) => {
-                set_red(sibl);
+                paint(sibl, Color::RED, &mut self.observer);
                 self.rotate(paren_b3.centre, Either::Left(()));
             }
 
@@ -1209,17 +1338,17 @@ impl<'a, K: CollKey + 'a, V: 'a> LLRB<K, V> {
                     let paren = paren_b3.centre;
 
                     self.subtree_shift(paren, paren_lf);
-                    set_black(paren_lf);
+                    paint(paren_lf, Color::BLACK, &mut self.observer);
 
                     (*sibl).connect_right(paren);
-                    set_red(paren);
+                    paint(paren, Color::RED, &mut self.observer);
                     (*paren).connect_left(null_mut::<LLRBNode<K, V>>());
                     debug_assert!((*paren).right.is_null());
 
                     self.rotate(sibl, Either::Left(()));
                 } else {
                     // sibl_idx = 0
-                    (*paren_lf).color_flip();
+                    self.color_flip(paren_lf);
                 };
             }
             (2, Either::Right(())) => {
@@ -1227,7 +1356,7 @@ impl<'a, K: CollKey + 'a, V: 'a> LLRB<K, V> {
 
                 let paren_lf = paren_b3.item(0);
 
-                (*paren_lf).color_flip();
+                self.color_flip(paren_lf);
 
                 self.rotate(paren_lf, Either::Left(()));
             }
@@ -1248,8 +1377,8 @@ impl<'a, K: CollKey + 'a, V: 'a> LLRB<K, V> {
         let sibling = (*x).sibling() as *mut LLRBNode<K, V>;
 
         if is_nonnil_black((*sibling).left) && is_nonnil_black((*sibling).right) {
-            set_red((*sibling).left);
-            set_red((*sibling).right);
+            paint((*sibling).left, Color::RED, &mut self.observer);
+            paint((*sibling).right, Color::RED, &mut self.observer);
         }
 
         self.fix_spec_2_node_up(x_paren)
@@ -1267,14 +1396,20 @@ impl<'a, K: CollKey + 'a, V: 'a> LLRB<K, V> {
         }
 
         if is_red((*x).left) && is_red((*x).right) {
-            (*x).color_flip();
+            self.color_flip(x);
         }
 
         x
     }
 
-    unsafe fn remove_min_(&mut self, mut x: *mut LLRBNode<K, V>) -> *mut LLRBNode<K, V> {
+    unsafe fn remove_min_(
+        &mut self,
+        mut x: *mut LLRBNode<K, V>,
+        res: &mut Vec<V>,
+    ) -> *mut LLRBNode<K, V> {
         if (*x).left.is_null() {
+            res.push(LLRBNode::node_into_value(x));
+
             return null_mut();
         }
 
@@ -1282,7 +1417,7 @@ impl<'a, K: CollKey + 'a, V: 'a> LLRB<K, V> {
             x = self.move_red_left(x);
         }
 
-        (*x).connect_left(self.remove_min_((*x).left));
+        (*x).connect_left(self.remove_min_((*x).left, res));
 
         self.fixup(x)
     }
@@ -1296,23 +1431,23 @@ impl<'a, K: CollKey + 'a, V: 'a> LLRB<K, V> {
     ///   (b)
     ///```
     unsafe fn move_red_left(&mut self, mut x: *mut LLRBNode<K, V>) -> *mut LLRBNode<K, V> {
-        (*x).color_flip();
+        self.color_flip(x);
 
         if is_red((*(*x).right).left) {
             x = self.double_rotate(x, Either::Left(())) as *mut LLRBNode<K, V>;
 
-            (*x).color_flip();
+            self.color_flip(x);
         }
 
         x
     }
 
     unsafe fn move_red_right(&mut self, mut x: *mut LLRBNode<K, V>) -> *mut LLRBNode<K, V> {
-        (*x).color_flip();
+        self.color_flip(x);
 
         if is_red((*(*x).left).left) {
             x = self.rotate(x, Either::Right(())) as *mut LLRBNode<K, V>;
-            (*x).color_flip();
+            self.color_flip(x);
         }
 
         x
@@ -1341,7 +1476,7 @@ impl<'a, K: CollKey + 'a, V: 'a> LLRB<K, V> {
             }
 
             if key == (*x).key_bst() && (*x).right.is_null() {
-                res.push(*Box::from_raw((*x).value));
+                res.push(LLRBNode::node_into_value(x));
 
                 return null_mut();
             }
@@ -1354,12 +1489,10 @@ impl<'a, K: CollKey + 'a, V: 'a> LLRB<K, V> {
                 if key == (*x).key_bst() {
                     let nxt = (*x).successor_bst();
 
-                    res.push(*Box::from_raw((*x).value));
+                    // the removed key-value goes to the successor node, then remove it
+                    (*x).swap_with(nxt);
 
-                    (*x).key = (*nxt).key_ptr(0);
-                    (*x).value = (*nxt).val_ptr(0);
-
-                    (*x).connect_right(self.remove_min_((*x).right))
+                    (*x).connect_right(self.remove_min_((*x).right, res))
                 } else {
                     (*x).connect_right(self.remove_((*x).right, key, res))
                 }
@@ -1386,9 +1519,9 @@ impl<'a, K: CollKey + 'a, V: 'a> LLRB<K, V> {
         //     (*x).color_flip();
         // }
 
-        if key == (*(*x).key) {
+        if key == (*x).key {
             return Err(());
-        } else if key < (*(*x).key) {
+        } else if key < (*x).key {
             (*x).connect_left(self.insert_at((*x).left, key, value)?)
         } else {
             (*x).connect_right(self.insert_at((*x).right, key, value)?)
@@ -1406,12 +1539,13 @@ impl<'a, K: CollKey + 'a, V: 'a> LLRB<K, V> {
 
         // split 4-node (protect from possible 5-node) on the way down
         if is_red((*x).left) && is_red((*x).right) {
-            (*x).color_flip();
+            self.color_flip(x);
         }
 
         Ok(x)
     }
 
+    #[cfg(any(feature = "std", test))]
     pub fn echo_stdout(&self) {
         if !self.root.is_null() {
             unsafe { (*self.root).echo_stdout() }
@@ -1421,7 +1555,42 @@ impl<'a, K: CollKey + 'a, V: 'a> LLRB<K, V> {
     }
 }
 
-impl<'a, K: CollKey + 'a, V: 'a> Dictionary<K, V> for LLRB<K, V> {
+impl<'a, K: CollKey + 'a, V: 'a, O: Observer<K> + Default> Default for LLRB<K, V, O> {
+    fn default() -> Self {
+        Self::with_observer(O::default())
+    }
+}
+
+impl<'a, K: CollKey + 'a, V: 'a> FromSortedIter<K, V> for LLRB<K, V> {
+    fn from_sorted_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Result<Self, ()> {
+        LLRB::from_sorted_iter(iter)
+    }
+}
+
+unsafe impl<K: Send, V: Send, O: Send> Send for LLRB<K, V, O> {}
+unsafe impl<K: Sync, V: Sync, O: Sync> Sync for LLRB<K, V, O> {}
+
+impl<'a, K: CollKey + Clone + 'a, V: Clone + 'a, O: Observer<K> + Clone> Clone for LLRB<K, V, O> {
+    fn clone(&self) -> Self {
+        Self {
+            root: unsafe { clone_subtree(self.root, LLRBNode::clone_node) },
+            observer: self.observer.clone(),
+        }
+    }
+}
+
+impl<K, V, O> Drop for LLRB<K, V, O> {
+    fn drop(&mut self) {
+        unsafe {
+            LLRBNode::drop_subtree(self.root);
+        }
+    }
+}
+
+impl_std_traits!(LLRB<K, V, O>);
+impl_stats!(LLRB<K, V, StatsObserver>);
+
+impl<'a, K: CollKey + 'a, V: 'a, O: Observer<K>> Dictionary<K, V> for LLRB<K, V, O> {
     fn insert(&mut self, key: K, value: V) -> bool {
         unsafe {
             let res;
@@ -1433,7 +1602,7 @@ impl<'a, K: CollKey + 'a, V: 'a> Dictionary<K, V> for LLRB<K, V> {
                 res = self.insert_at(self.root, key, value).is_ok();
             }
 
-            set_black(self.root);
+            paint(self.root, Color::BLACK, &mut self.observer);
             res
         }
     }
@@ -1448,7 +1617,7 @@ impl<'a, K: CollKey + 'a, V: 'a> Dictionary<K, V> for LLRB<K, V> {
             let mut res = Vec::new();
             let t = self.remove_(self.root, key, &mut res);
             self.reset_root(t);
-            set_black(self.root);
+            paint(self.root, Color::BLACK, &mut self.observer);
 
             res.pop()
         }
@@ -1634,7 +1803,7 @@ impl<'a, K: CollKey + 'a, V: 'a> Dictionary<K, V> for LLRB<K, V> {
         self.basic_lookup_mut(income_key)
     }
 
-    fn self_validate(&self) -> Result<(), Box<dyn std::error::Error>> {
+    fn self_validate(&self) -> Result<(), Box<dyn core::error::Error>> {
         self.basic_self_validate()?;
 
         assert!(is_black(self.root));
@@ -1644,9 +1813,8 @@ impl<'a, K: CollKey + 'a, V: 'a> Dictionary<K, V> for LLRB<K, V> {
                 (*self.root).self_validate()?;
 
                 let is_black_balance = (*self.root)
-                    .leafs()
+                    .leaf_black_depths()
                     .into_iter()
-                    .map(|leaf| (*leaf).black_depth())
                     .tuple_windows()
                     .all(|(a, b)| a == b);
 
@@ -1655,10 +1823,7 @@ impl<'a, K: CollKey + 'a, V: 'a> Dictionary<K, V> for LLRB<K, V> {
                 // // Relax black balance of restriction a little
                 // // to satisfy the 2-4 tree definition
                 // let black_depths = (*self.root)
-                //     .leafs()
-                //     .into_iter()
-                //     .map(|leaf| (*leaf).black_depth())
-                //     .collect_vec();
+                //     .leaf_black_depths();
 
                 // let max_depth = black_depths.iter().max().unwrap().clone();
                 // let min_depth = black_depths.iter().min().unwrap().clone();
@@ -1673,7 +1838,7 @@ impl<'a, K: CollKey + 'a, V: 'a> Dictionary<K, V> for LLRB<K, V> {
     }
 }
 
-impl<'a, K: CollKey + 'a, V: 'a> BT<'a, K, V> for LLRB<K, V> {
+impl<'a, K: CollKey + 'a, V: 'a, O: Observer<K>> BT<'a, K, V> for LLRB<K, V, O> {
     fn order(&self) -> usize {
         2
     }
@@ -1685,9 +1850,13 @@ impl<'a, K: CollKey + 'a, V: 'a> BT<'a, K, V> for LLRB<K, V> {
     fn assign_root(&mut self, root: *mut (dyn BTNode<'a, K, V> + 'a)) {
         self.root = root as *mut LLRBNode<K, V>;
     }
+
+    fn on_search(&self, visits: usize, comparisons: usize) {
+        self.observer.on_search(visits, comparisons)
+    }
 }
 
-impl<'a, K: CollKey + 'a, V: 'a> BST<'a, K, V> for LLRB<K, V> {
+impl<'a, K: CollKey + 'a, V: 'a, O: Observer<K>> BST<'a, K, V> for LLRB<K, V, O> {
     unsafe fn rotate_cleanup(
         &mut self,
         x: *mut (dyn BSTNode<'a, K, V> + 'a),
@@ -1699,21 +1868,58 @@ impl<'a, K: CollKey + 'a, V: 'a> BST<'a, K, V> for LLRB<K, V> {
         let x_self = x as *mut LLRBNode<K, V>;
         let z_self = z as *mut LLRBNode<K, V>;
 
-        (*z_self).color = (*x_self).color.clone();
-        (*x_self).color = Color::RED;
+        paint(z_self, (*x_self).color.clone(), &mut self.observer);
+        paint(x_self, Color::RED, &mut self.observer);
+    }
+
+    unsafe fn on_rotate(
+        &mut self,
+        x: *mut (dyn BSTNode<'a, K, V> + 'a),
+        z: *mut (dyn BSTNode<'a, K, V> + 'a),
+        rotation: Either<(), ()>,
+    ) {
+        self.observer.on_rotate((*x).key_bst(), (*z).key_bst(), rotation);
+    }
+
+    unsafe fn on_double_rotate(
+        &mut self,
+        y: *mut (dyn BSTNode<'a, K, V> + 'a),
+        snd_rotation: Either<(), ()>,
+    ) {
+        self.observer.on_double_rotate((*y).key_bst(), snd_rotation);
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::bst::ROTATE_NUM;
 
     #[test]
     pub(crate) fn test_llrb_randomdata() {
         test_dict!(LLRB::new());
+    }
+
+    #[test]
+    fn test_llrb_send_sync() {
+        test_send_sync!(LLRB::new());
+    }
+
+    #[test]
+    fn test_llrb_clone() {
+        test_clone!(LLRB::new());
+    }
+
+    #[test]
+    fn test_llrb_std_traits() {
+        test_std_traits!(LLRB::new());
+    }
+
+    #[test]
+    fn test_llrb_observer() {
+        let counts = test_observer!(LLRB);
 
-        println!("LLRB rotate numer: {}", unsafe { ROTATE_NUM })
+        assert!(counts.rotate > 0);
+        assert!(counts.recolor > 0);
     }
 
     #[test]
@@ -1764,4 +1970,10 @@ mod test {
 
         llrb.echo_stdout();
     }
+
+
+    #[test]
+    fn test_llrb_from_sorted_iter() {
+        test_from_sorted_iter!(LLRB);
+    }
 }
@@ -1,31 +1,34 @@
 //! Lazy Scapegoat Tree
 //!
 
-use std::ptr::{null, null_mut};
+use core::ptr::{null, null_mut};
 
 use itertools::Itertools;
 
-use super::*;
 use crate::*;
 
 
 
-pub struct LSG<'a, K: CollKey + 'a, V: 'a> {
-    root: *mut LSGNode<'a, K, V>,
+pub struct LSG<K, V, O = ()> {
+    root: *mut LSGNode<K, V>,
     deleted: usize,
     alpha: f32,
+    /// notified of the structural changes, `()` for none
+    observer: O,
 }
 
-pub struct LSGNode<'a, K: CollKey + 'a, V: 'a> {
+pub struct LSGNode<K, V> {
     paren: *mut Self,
+    itself: *mut Self,
     left: *mut Self,
     right: *mut Self,
 
     size: usize,
     is_deleted: bool,
 
-    key: *mut K,
-    value: *mut V,
+    key: K,
+    /// None if it's deleted
+    value: Option<V>,
 }
 
 
@@ -34,7 +37,7 @@ pub struct LSGNode<'a, K: CollKey + 'a, V: 'a> {
 //// Implement
 
 
-fn size<'a, K: CollKey + 'a, V: 'a> (x: *mut LSGNode<'a, K, V>) -> usize {
+fn size<'a, K: CollKey + 'a, V: 'a> (x: *mut LSGNode<K, V>) -> usize {
     unsafe {
         if x.is_null() {
             return 0;
@@ -45,23 +48,50 @@ fn size<'a, K: CollKey + 'a, V: 'a> (x: *mut LSGNode<'a, K, V>) -> usize {
 }
 
 
-impl<'a, K: CollKey + 'a, V: 'a> LSGNode<'a, K, V> {
+impl<K, V> LSGNode<K, V> {
+    /// Free the whole subtree, return the number of freed nodes.
+    unsafe fn drop_subtree(x: *mut Self) -> usize {
+        let mut stack = vec![x];
+        let mut cnt = 0;
+
+        while let Some(x) = stack.pop() {
+            if x.is_null() {
+                continue;
+            }
+
+            let node = Box::from_raw(x);
+
+            stack.push(node.left);
+            stack.push(node.right);
+
+            cnt += 1;
+        }
+
+        cnt
+    }
+}
+
+impl<'a, K: CollKey + 'a, V: 'a> LSGNode<K, V> {
     pub fn new(key: K, value: V) -> *mut Self {
-        Box::into_raw(box Self {
+        let x = Box::into_raw(Box::new(Self {
             left: null_mut(),
             right: null_mut(),
             paren: null_mut(),
+            itself: null_mut(),
 
             size: 1,
             is_deleted: false,
 
-            key: Box::into_raw(box key),
-            value: Box::into_raw(box value),
-        })
+            key,
+            value: Some(value),
+        }));
+        unsafe { (*x).itself = x };
+
+        x
     }
 
-    pub fn into_value(self) -> V {
-        unsafe { *Box::from_raw(self.value) }
+    pub fn into_value(self) -> Option<V> {
+        self.value
     }
 
     fn update_size(&mut self) {
@@ -96,7 +126,7 @@ impl<'a, K: CollKey + 'a, V: 'a> LSGNode<'a, K, V> {
 
 }
 
-// impl<'a, K: DictKey + 'a, V: 'a> Drop for LSGNode<'a, K, V> {
+// impl<'a, K: DictKey + 'a, V: 'a> Drop for LSGNode<K, V> {
 //     fn drop(&mut self) {
 //         unsafe {
 //             drop(Box::from_raw(self.key));
@@ -113,9 +143,30 @@ impl<'a, K: CollKey + 'a, V: 'a> LSGNode<'a, K, V> {
 // }
 
 
-impl<'a, K: CollKey + 'a, V: 'a> BTNode<'a, K, V> for LSGNode<'a, K, V> {
+impl<'a, K: CollKey + Clone + 'a, V: Clone + 'a> LSGNode<K, V> {
+    /// Copy without links
+    fn clone_node(&self) -> *mut Self {
+        let x = Box::into_raw(Box::new(Self {
+            left: null_mut(),
+            right: null_mut(),
+            paren: null_mut(),
+            itself: null_mut(),
+
+            size: self.size,
+            is_deleted: self.is_deleted,
+
+            key: self.key.clone(),
+            value: self.value.clone(),
+        }));
+        unsafe { (*x).itself = x };
+
+        x
+    }
+}
+
+impl<'a, K: CollKey + 'a, V: 'a> BTNode<'a, K, V> for LSGNode<K, V> {
     fn itself(&self) -> *const (dyn BTNode<'a, K, V> + 'a) {
-        self as *const Self
+        self.itself
     }
 
     fn null(&self) -> *const (dyn BTNode<'a, K, V> + 'a) {
@@ -123,7 +174,14 @@ impl<'a, K: CollKey + 'a, V: 'a> BTNode<'a, K, V> for LSGNode<'a, K, V> {
     }
 
     fn try_as_bst(&self) -> Result<*const (dyn BSTNode<'a, K, V> + 'a), ()> {
-        Ok(self as *const Self)
+        Ok(self.itself)
+    }
+
+    fn downcast(
+        &self,
+        x: *mut (dyn BTNode<'a, K, V> + 'a),
+    ) -> Result<*mut (dyn BSTNode<'a, K, V> + 'a), ()> {
+        Ok(x as *mut Self)
     }
 
     fn order(&self) -> usize {
@@ -154,10 +212,6 @@ impl<'a, K: CollKey + 'a, V: 'a> BTNode<'a, K, V> for LSGNode<'a, K, V> {
         }
     }
 
-    fn assign_value(&mut self, value: V, _idx: usize) {
-        self.value = Box::into_raw(box value);
-    }
-
     fn assign_paren(&mut self, paren: *mut (dyn BTNode<'a, K, V> + 'a)) {
         self.paren = paren as *mut Self;
     }
@@ -168,37 +222,33 @@ impl<'a, K: CollKey + 'a, V: 'a> BTNode<'a, K, V> for LSGNode<'a, K, V> {
 
     fn key(&self, idx: usize) -> Option<&K> {
         if idx == 0 {
-            unsafe { Some(&*self.key) }
+            Some(&self.key)
         } else {
             None
         }
     }
 
-    fn key_ptr(&self, idx: usize) -> *mut K {
+    fn key_mut(&mut self, idx: usize) -> Option<&mut K> {
         if idx == 0 {
-            self.key
+            Some(&mut self.key)
         } else {
-            null_mut::<K>()
-        }
-    }
-
-    fn assign_key_ptr(&mut self, idx: usize, key_ptr: *mut K) {
-        if idx == 0 {
-            self.key = key_ptr;
+            None
         }
     }
 
-    fn val_ptr(&self, idx: usize) -> *mut V {
+    fn value(&self, idx: usize) -> Option<&V> {
         if idx == 0 {
-            self.value
+            self.value.as_ref()
         } else {
-            null_mut::<V>()
+            None
         }
     }
 
-    fn assign_val_ptr(&mut self, idx: usize, val_ptr: *mut V) {
+    fn value_mut(&mut self, idx: usize) -> Option<&mut V> {
         if idx == 0 {
-            self.value = val_ptr;
+            self.value.as_mut()
+        } else {
+            None
         }
     }
 
@@ -207,16 +257,12 @@ impl<'a, K: CollKey + 'a, V: 'a> BTNode<'a, K, V> for LSGNode<'a, K, V> {
     }
 }
 
-impl<'a, K: CollKey + 'a, V: 'a> BSTNode<'a, K, V> for LSGNode<'a, K, V> {}
+impl<'a, K: CollKey + 'a, V: 'a> BSTNode<'a, K, V> for LSGNode<K, V> {}
 
 
-impl<'a, K: CollKey + 'a, V: 'a> LSG<'a, K, V> {
+impl<'a, K: CollKey + 'a, V: 'a> LSG<K, V> {
     pub fn new() -> Self {
-        Self {
-            root: null_mut(),
-            alpha: 0.7,
-            deleted: 0,
-        }
+        Self::with_observer(())
     }
 
     pub fn with_alpha(alpha: f32) -> Self {
@@ -227,45 +273,65 @@ impl<'a, K: CollKey + 'a, V: 'a> LSG<'a, K, V> {
             root: null_mut(),
             alpha,
             deleted: 0,
+            observer: (),
         }
     }
 
-    pub fn size(&self) -> usize {
-        if self.root.is_null() {
-            return 0
-        }
-
-        unsafe { (*self.root).size }
-    }
-
     pub fn bulk_load(iter: &mut dyn Iterator<Item = (K, V)>) -> Self {
         let nodes = iter.map(|(key, val)| LSGNode::new(key, val))
         .collect_vec();
 
         let seq = &nodes[..];
 
-        let mut sg = LSG::new();
+        let mut sg = Self::new();
 
         unsafe {
-            sg.root = LSG::build(seq);
+            sg.root = Self::build(seq);
         }
 
         sg
     }
+}
 
-    unsafe fn build(seq: &[*mut LSGNode<'a, K, V>]) -> *mut LSGNode<'a, K, V> {
+impl<'a, K: CollKey + 'a, V: 'a, O: Observer<K>> LSG<K, V, O> {
+    pub fn with_observer(observer: O) -> Self {
+        Self {
+            root: null_mut(),
+            alpha: 0.7,
+            deleted: 0,
+            observer,
+        }
+    }
+
+    pub fn observer(&self) -> &O {
+        &self.observer
+    }
+
+    pub fn observer_mut(&mut self) -> &mut O {
+        &mut self.observer
+    }
+
+    pub fn size(&self) -> usize {
+        if self.root.is_null() {
+            return 0
+        }
+
+        unsafe { (*self.root).size }
+    }
+
+    unsafe fn build(seq: &[*mut LSGNode<K, V>]) -> *mut LSGNode<K, V> {
         if seq.is_empty() {
             return null_mut();
         }
 
-        LSG::build_(seq, 0, seq.len() - 1)
+        Self::build_(seq, 0, seq.len() - 1)
     }
 
     unsafe fn build_(
-        seq: &[*mut LSGNode<'a, K, V>],
+        seq: &[*mut LSGNode<K, V>],
         low: usize,
         high: usize,
-    ) -> *mut LSGNode<'a, K, V> {
+    ) -> *mut LSGNode<K, V> {
 
         if high < low {
             return null_mut();
@@ -277,14 +343,14 @@ impl<'a, K: CollKey + 'a, V: 'a> LSG<'a, K, V> {
 
         (*x).connect_left(
             if mid == 0 {
-                 null_mut::<LSGNode<'a, K, V>>()
+                 null_mut::<LSGNode<K, V>>()
             } else {
-                LSG::build_(seq, low, mid - 1)
+                Self::build_(seq, low, mid - 1)
             }
         );
 
         (*x).connect_right(
-            LSG::build_(seq, mid + 1, high)
+            Self::build_(seq, mid + 1, high)
         );
 
         (*x).update_size();
@@ -293,32 +359,48 @@ impl<'a, K: CollKey + 'a, V: 'a> LSG<'a, K, V> {
         x
     }
 
-    unsafe fn refact(x: *mut LSGNode<'a, K, V>) -> *mut LSGNode<'a, K, V> {
+    unsafe fn refact(x: *mut LSGNode<K, V>) -> *mut LSGNode<K, V> {
         debug_assert!(!x.is_null());
         // if x.is_null() {
         //     return x;
         // }
 
         let mut nodes = Vec::new();
-        LSG::collect_alive(x, &mut nodes);
+        Self::collect_alive(x, &mut nodes);
 
-        LSG::build(&nodes[..])
+        Self::build(&nodes[..])
     }
 
+    /// Refact and notify the observer
+    unsafe fn rebuild(&mut self, x: *mut LSGNode<K, V>) -> *mut LSGNode<K, V> {
+        let root = Self::refact(x);
+
+        if !root.is_null() {
+            self.observer.on_rebuild((*root).key_bst(), (*root).size);
+        }
 
-    unsafe fn collect_alive(x: *mut LSGNode<'a, K, V>, container: &mut Vec<*mut LSGNode<'a, K, V>>) {
+        root
+    }
+
+
+    unsafe fn collect_alive(x: *mut LSGNode<K, V>, container: &mut Vec<*mut LSGNode<K, V>>) {
         if x.is_null() {
             return;
         }
 
-        LSG::collect_alive((*x).left, container);
+        let right = (*x).right;
+
+        Self::collect_alive((*x).left, container);
         if !(*x).is_deleted {
             container.push(x);
+        } else {
+            // nothing else points to a deleted node after the rebuild
+            drop(Box::from_raw(x));
         }
-        LSG::collect_alive((*x).right, container);
+        Self::collect_alive(right, container);
     }
 
-    unsafe fn find_scapegoat(mut x: *mut LSGNode<'a, K, V>, alpha: f32) -> *mut LSGNode<'a, K, V> {
+    unsafe fn find_scapegoat(mut x: *mut LSGNode<K, V>, alpha: f32) -> *mut LSGNode<K, V> {
 
         while !x.is_null() {
 
@@ -332,20 +414,20 @@ impl<'a, K: CollKey + 'a, V: 'a> LSG<'a, K, V> {
         x
     }
 
-    unsafe fn remove_retracing(&mut self, x: *mut LSGNode<'a, K, V>) {
-        LSG::update_size_to_root(x);
+    unsafe fn remove_retracing(&mut self, x: *mut LSGNode<K, V>) {
+        Self::update_size_to_root(x);
         self.deleted += 1;
 
         if self.deleted >= self.size() {
-            self.reset_root(
-                LSG::refact(self.root)
-            );
+            let root = self.rebuild(self.root);
+            self.reset_root(root);
+            self.deleted = 0;
         }
 
     }
 
 
-    unsafe fn update_size_to_root(mut x: *mut LSGNode<'a, K, V>) {
+    unsafe fn update_size_to_root(mut x: *mut LSGNode<K, V>) {
 
         while !x.is_null() {
             (*x).update_size();
@@ -355,12 +437,12 @@ impl<'a, K: CollKey + 'a, V: 'a> LSG<'a, K, V> {
 
     }
 
-    fn partial_refact(&mut self, sgnode: *mut LSGNode<'a, K, V>) {
+    fn partial_refact(&mut self, sgnode: *mut LSGNode<K, V>) {
         unsafe {
             if (*sgnode).paren.is_null() {
-                self.reset_root(
-                    LSG::refact(sgnode)
-                );
+                let root = self.rebuild(sgnode);
+                self.reset_root(root);
+                self.deleted = 0;
 
             } else {
                 let sgnode_paren = (*sgnode).paren;
@@ -371,7 +453,7 @@ impl<'a, K: CollKey + 'a, V: 'a> LSG<'a, K, V> {
                     (*sgnode_paren).connect_right(sgnode);
                 }
 
-                LSG::update_size_to_root(sgnode_paren);
+                Self::update_size_to_root(sgnode_paren);
             }
 
         }
@@ -380,7 +462,7 @@ impl<'a, K: CollKey + 'a, V: 'a> LSG<'a, K, V> {
 
 }
 
-// impl<'a, K: DictKey + 'a, V: 'a> Drop for LSG<'a, K, V> {
+// impl<'a, K: DictKey + 'a, V: 'a> Drop for LSG<K, V> {
 //     fn drop(&mut self) {
 //         unsafe {
 //             self.reset_root(
@@ -392,19 +474,53 @@ impl<'a, K: CollKey + 'a, V: 'a> LSG<'a, K, V> {
 // }
 
 
-impl<'a, K: CollKey + 'a, V: 'a> Dictionary<K, V> for LSG<'a, K, V> {
+impl<'a, K: CollKey + 'a, V: 'a, O: Observer<K> + Default> Default for LSG<K, V, O> {
+    fn default() -> Self {
+        Self::with_observer(O::default())
+    }
+}
+
+impl<'a, K: CollKey + 'a, V: 'a> FromSortedIter<K, V> for LSG<K, V> {
+    fn from_sorted_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Result<Self, ()> {
+        Ok(LSG::bulk_load(&mut collect_sorted(iter)?.into_iter()))
+    }
+}
+
+unsafe impl<'a, K: CollKey + Send + 'a, V: Send + 'a, O: Send> Send for LSG<K, V, O> {}
+unsafe impl<'a, K: CollKey + Sync + 'a, V: Sync + 'a, O: Sync> Sync for LSG<K, V, O> {}
+
+impl<'a, K: CollKey + Clone + 'a, V: Clone + 'a, O: Observer<K> + Clone> Clone for LSG<K, V, O> {
+    fn clone(&self) -> Self {
+        Self {
+            root: unsafe { clone_subtree(self.root, LSGNode::clone_node) },
+            deleted: self.deleted,
+            alpha: self.alpha,
+            observer: self.observer.clone(),
+        }
+    }
+}
+
+impl<K, V, O> Drop for LSG<K, V, O> {
+    fn drop(&mut self) {
+        unsafe {
+            LSGNode::drop_subtree(self.root);
+        }
+    }
+}
+
+impl_std_traits!(LSG<K, V, O>);
+impl_stats!(LSG<K, V, StatsObserver>);
+
+impl<'a, K: CollKey + 'a, V: 'a, O: Observer<K>> Dictionary<K, V> for LSG<K, V, O> {
     fn insert(&mut self, key: K, value: V) -> bool {
         unsafe {
-            let approxi_node =
-                (*self.search_approximately(&key))
-                .try_as_bst_mut().unwrap() as *mut LSGNode<'a, K, V>;
+            let approxi_node = self.search_approximately(&key) as *mut LSGNode<K, V>;
 
 
             let entry;
             if !approxi_node.is_null() && (*approxi_node).key_bst() == &key {
                 if (*approxi_node).is_deleted {
-                    let vp = Box::into_raw(box value);
-                    (*approxi_node).value = vp;
+                    (*approxi_node).value = Some(value);
                     (*approxi_node).is_deleted = false;
 
                     entry = approxi_node;
@@ -428,8 +544,8 @@ impl<'a, K: CollKey + 'a, V: 'a> Dictionary<K, V> for LSG<'a, K, V> {
                 entry = new_node;
             }
 
-            LSG::update_size_to_root(entry);
-            let sgnode = LSG::find_scapegoat(entry, self.alpha);
+            Self::update_size_to_root(entry);
+            let sgnode = Self::find_scapegoat(entry, self.alpha);
 
             if !sgnode.is_null() {
                 self.partial_refact(sgnode);
@@ -448,9 +564,7 @@ impl<'a, K: CollKey + 'a, V: 'a> Dictionary<K, V> for LSG<'a, K, V> {
 
     fn remove(&mut self, key: &K) -> Option<V> {
         unsafe {
-            let approxi_node =
-            (*self.search_approximately(&key))
-            .try_as_bst_mut().unwrap() as *mut LSGNode<'a, K, V>;
+            let approxi_node = self.search_approximately(key) as *mut LSGNode<K, V>;
 
             if approxi_node.is_null() {
                 return None;
@@ -458,12 +572,11 @@ impl<'a, K: CollKey + 'a, V: 'a> Dictionary<K, V> for LSG<'a, K, V> {
 
             if (*approxi_node).key_bst() == key && !(*approxi_node).is_deleted {
                 (*approxi_node).is_deleted = true;
+                // the retracing may free the node
+                let value = (*approxi_node).value.take();
                 self.remove_retracing(approxi_node);
 
-                let value = (*approxi_node).value;
-                (*approxi_node).value = null_mut();
-
-                Some(*Box::from_raw(value))
+                value
 
             } else {
                 None
@@ -475,20 +588,23 @@ impl<'a, K: CollKey + 'a, V: 'a> Dictionary<K, V> for LSG<'a, K, V> {
     }
 
     fn modify(&mut self, key: &K, value: V) -> bool {
-
-
-        self.basic_modify(key, value)
+        if let Some(here) = self.get_mut(key) {
+            *here = value;
+            true
+        } else {
+            false
+        }
     }
 
     fn get(&self, key: &K) -> Option<&V> {
         unsafe {
-            let res = self.search_approximately(key) as *mut LSGNode<'a, K, V>;
+            let res = self.search_approximately(key) as *mut LSGNode<K, V>;
 
             if res.is_null() || (*res).key_bst() != key || (*res).is_deleted {
                 None
             } else {
 
-                Some(&*(*res).value)
+                (*res).value.as_ref()
             }
         }
 
@@ -496,26 +612,26 @@ impl<'a, K: CollKey + 'a, V: 'a> Dictionary<K, V> for LSG<'a, K, V> {
 
     fn get_mut(&mut self, key: &K) -> Option<&mut V> {
         unsafe {
-            let res = self.search_approximately(key) as *mut LSGNode<'a, K, V>;
+            let res = self.search_approximately(key) as *mut LSGNode<K, V>;
 
             if res.is_null() || (*res).key_bst() != key || (*res).is_deleted {
                 None
             } else {
 
-                Some(&mut *(*res).value)
+                (*res).value.as_mut()
             }
         }
 
     }
 
-    fn self_validate(&self) -> Result<(), Box<dyn std::error::Error>> {
+    fn self_validate(&self) -> Result<(), Box<dyn core::error::Error>> {
         self.basic_self_validate()
     }
 }
 
 
 
-impl<'a, K: CollKey + 'a, V: 'a> BT<'a, K, V> for LSG<'a, K, V> {
+impl<'a, K: CollKey + 'a, V: 'a, O: Observer<K>> BT<'a, K, V> for LSG<K, V, O> {
     fn order(&self) -> usize {
         2
     }
@@ -525,18 +641,39 @@ impl<'a, K: CollKey + 'a, V: 'a> BT<'a, K, V> for LSG<'a, K, V> {
     }
 
     fn assign_root(&mut self, root: *mut (dyn BTNode<'a, K, V> + 'a)) {
-        self.root = root as *mut LSGNode<'a, K, V>;
+        self.root = root as *mut LSGNode<K, V>;
+    }
+
+    fn on_search(&self, visits: usize, comparisons: usize) {
+        self.observer.on_search(visits, comparisons)
     }
 }
 
 
-impl<'a, K: CollKey + 'a, V: 'a> BST<'a, K, V> for LSG<'a, K, V> {
+impl<'a, K: CollKey + 'a, V: 'a, O: Observer<K>> BST<'a, K, V> for LSG<K, V, O> {
     unsafe fn rotate_cleanup(
         &mut self,
         _x: *mut (dyn BSTNode<'a, K, V> + 'a),
         _z: *mut (dyn BSTNode<'a, K, V> + 'a),
     ) {
     }
+
+    unsafe fn on_rotate(
+        &mut self,
+        x: *mut (dyn BSTNode<'a, K, V> + 'a),
+        z: *mut (dyn BSTNode<'a, K, V> + 'a),
+        rotation: Either<(), ()>,
+    ) {
+        self.observer.on_rotate((*x).key_bst(), (*z).key_bst(), rotation);
+    }
+
+    unsafe fn on_double_rotate(
+        &mut self,
+        y: *mut (dyn BSTNode<'a, K, V> + 'a),
+        snd_rotation: Either<(), ()>,
+    ) {
+        self.observer.on_double_rotate((*y).key_bst(), snd_rotation);
+    }
 }
 
 
@@ -553,6 +690,29 @@ pub(crate) mod tests {
         test_dict!(LSG::new());
     }
 
+    #[test]
+    fn test_lsg_send_sync() {
+        test_send_sync!(LSG::new());
+    }
+
+    #[test]
+    fn test_lsg_clone() {
+        test_clone!(LSG::new());
+    }
+
+    #[test]
+    fn test_lsg_std_traits() {
+        test_std_traits!(LSG::new());
+    }
+
+    #[test]
+    fn test_lsg_observer() {
+        let counts = test_observer!(LSG);
+
+        assert!(counts.rebuild > 0);
+        assert_eq!(counts.rotate, 0);
+    }
+
 
     #[test]
     fn test_lsg_fixeddata_case_1() {
@@ -0,0 +1,396 @@
+//! Persistent AVL, updates copy the search path and share the rest with the old version,
+//! so every version stays alive and readable, and clone is O(1).
+//!
+//! The balance is the same as `AVL` (bf in [-1, 1]), it shares the rotation choice of `AVL`.
+//!
+//! ref: https://en.wikipedia.org/wiki/Persistent_data_structure#Trees
+//!
+
+use alloc::sync::Arc;
+use core::{
+    cmp::{max, Ordering::*},
+    iter::from_fn,
+};
+
+use either::Either;
+
+use super::avl::{rebalance, Rebalance};
+use crate::*;
+
+////////////////////////////////////////////////////////////////////////////////
+//// Struct
+////
+
+/// Cheap to clone, `insert`/`remove` derive a new version, leaving `self` as it is.
+pub struct PAVL<K, V> {
+    root: Link<K, V>,
+    len: usize,
+}
+
+struct PAVLNode<K, V> {
+    left: Link<K, V>,
+    right: Link<K, V>,
+    height: i32,
+    key: K,
+    value: V,
+}
+
+type Link<K, V> = Option<Arc<PAVLNode<K, V>>>;
+
+////////////////////////////////////////////////////////////////////////////////
+//// Implement
+
+fn height<K, V>(x: &Link<K, V>) -> i32 {
+    x.as_ref().map_or(-1, |x| x.height)
+}
+
+impl<'a, K: CollKey + Clone + 'a, V: Clone + 'a> PAVLNode<K, V> {
+    fn new(left: Link<K, V>, key: K, value: V, right: Link<K, V>) -> Link<K, V> {
+        let height = 1 + max(height(&left), height(&right));
+
+        Some(Arc::new(Self { left, right, height, key, value }))
+    }
+
+    /// Copy of the parts, the subtrees are shared
+    fn split(&self) -> (Link<K, V>, K, V, Link<K, V>) {
+        (self.left.clone(), self.key.clone(), self.value.clone(), self.right.clone())
+    }
+
+    /// ```ignore
+    ///     x              z
+    ///   /   \          /   \
+    ///  a     z   =>   x     c
+    ///       / \      / \
+    ///      b   c    a   b
+    /// ```
+    fn rotate_left(left: Link<K, V>, key: K, value: V, right: Link<K, V>) -> Link<K, V> {
+        let (b, z_key, z_value, c) = right.unwrap().split();
+
+        PAVLNode::new(PAVLNode::new(left, key, value, b), z_key, z_value, c)
+    }
+
+    fn rotate_right(left: Link<K, V>, key: K, value: V, right: Link<K, V>) -> Link<K, V> {
+        let (a, y_key, y_value, b) = left.unwrap().split();
+
+        PAVLNode::new(a, y_key, y_value, PAVLNode::new(b, key, value, right))
+    }
+
+    /// Build the node and restore its bf, the subtrees differ in height by at most 2.
+    fn balance(left: Link<K, V>, key: K, value: V, right: Link<K, V>) -> Link<K, V> {
+        let rebalance = rebalance(height(&left), height(&right), |dir| {
+            let child = if dir.is_left() { &left } else { &right }.as_ref().unwrap();
+
+            (height(&child.left), height(&child.right))
+        });
+
+        match rebalance {
+            Some(Rebalance::Rotate(Either::Left(()))) => PAVLNode::rotate_left(left, key, value, right),
+            Some(Rebalance::Rotate(Either::Right(()))) => PAVLNode::rotate_right(left, key, value, right),
+            Some(Rebalance::DoubleRotate(Either::Left(()))) => {
+                let (b, z_key, z_value, c) = right.unwrap().split();
+                let right = PAVLNode::rotate_right(b, z_key, z_value, c);
+
+                PAVLNode::rotate_left(left, key, value, right)
+            }
+            Some(Rebalance::DoubleRotate(Either::Right(()))) => {
+                let (a, y_key, y_value, b) = left.unwrap().split();
+                let left = PAVLNode::rotate_left(a, y_key, y_value, b);
+
+                PAVLNode::rotate_right(left, key, value, right)
+            }
+            None => PAVLNode::new(left, key, value, right),
+        }
+    }
+
+    /// Return the new subtree and whether the key is new
+    fn insert(x: &Link<K, V>, key: K, value: V) -> (Link<K, V>, bool) {
+        let x = match x {
+            Some(x) => x,
+            None => return (PAVLNode::new(None, key, value, None), true),
+        };
+
+        match key.cmp(&x.key) {
+            Less => {
+                let (left, is_new) = PAVLNode::insert(&x.left, key, value);
+                let x_key = x.key.clone();
+                let x_value = x.value.clone();
+
+                (PAVLNode::balance(left, x_key, x_value, x.right.clone()), is_new)
+            }
+            Greater => {
+                let (right, is_new) = PAVLNode::insert(&x.right, key, value);
+                let x_key = x.key.clone();
+                let x_value = x.value.clone();
+
+                (PAVLNode::balance(x.left.clone(), x_key, x_value, right), is_new)
+            }
+            Equal => {
+                let node = PAVLNode {
+                    left: x.left.clone(),
+                    right: x.right.clone(),
+                    height: x.height,
+                    key,
+                    value,
+                };
+
+                (Some(Arc::new(node)), false)
+            }
+        }
+    }
+
+    /// None if the key doesn't exist
+    fn remove(x: &Link<K, V>, key: &K) -> Option<Link<K, V>> {
+        let x = x.as_ref()?;
+
+        match key.cmp(&x.key) {
+            Less => {
+                let left = PAVLNode::remove(&x.left, key)?;
+                let (_, x_key, x_value, right) = x.split();
+
+                Some(PAVLNode::balance(left, x_key, x_value, right))
+            }
+            Greater => {
+                let right = PAVLNode::remove(&x.right, key)?;
+                let (left, x_key, x_value, _) = x.split();
+
+                Some(PAVLNode::balance(left, x_key, x_value, right))
+            }
+            Equal => {
+                if x.left.is_none() {
+                    return Some(x.right.clone());
+                }
+                if x.right.is_none() {
+                    return Some(x.left.clone());
+                }
+
+                let (right, succ_key, succ_value) = PAVLNode::remove_min(x.right.as_ref().unwrap());
+
+                Some(PAVLNode::balance(x.left.clone(), succ_key, succ_value, right))
+            }
+        }
+    }
+
+    /// Return the rest subtree and the minimum item
+    fn remove_min(x: &Arc<Self>) -> (Link<K, V>, K, V) {
+        match &x.left {
+            None => (x.right.clone(), x.key.clone(), x.value.clone()),
+            Some(left) => {
+                let (left, min_key, min_value) = PAVLNode::remove_min(left);
+                let (_, x_key, x_value, right) = x.split();
+
+                (PAVLNode::balance(left, x_key, x_value, right), min_key, min_value)
+            }
+        }
+    }
+
+    /// Return the height of subtree
+    fn self_validate(&self) -> Result<i32, Box<dyn core::error::Error>> {
+        let mut left_height = -1;
+        let mut right_height = -1;
+
+        if let Some(left) = &self.left {
+            if left.key >= self.key {
+                return Err(format!("left key {:?} >= key {:?}", left.key, self.key).into());
+            }
+            left_height = left.self_validate()?;
+        }
+        if let Some(right) = &self.right {
+            if right.key <= self.key {
+                return Err(format!("right key {:?} <= key {:?}", right.key, self.key).into());
+            }
+            right_height = right.self_validate()?;
+        }
+
+        let bf = right_height - left_height;
+
+        if bf.abs() >= 2 {
+            return Err(format!("BF: {bf} at {:?}", self.key).into());
+        }
+        if self.height != 1 + max(left_height, right_height) {
+            return Err(format!("height {} at {:?}", self.height, self.key).into());
+        }
+
+        Ok(self.height)
+    }
+}
+
+
+impl<'a, K: CollKey + Clone + 'a, V: Clone + 'a> PAVL<K, V> {
+    pub fn new() -> Self {
+        Self { root: None, len: 0 }
+    }
+
+    /// A new version with the pair, the value is replaced if the key exists
+    pub fn insert(&self, key: K, value: V) -> Self {
+        let (root, is_new) = PAVLNode::insert(&self.root, key, value);
+
+        Self { root, len: self.len + is_new as usize }
+    }
+
+    /// A new version without the key, sharing the whole tree if it doesn't exist
+    pub fn remove(&self, key: &K) -> Self {
+        match PAVLNode::remove(&self.root, key) {
+            Some(root) => Self { root, len: self.len - 1 },
+            None => self.clone(),
+        }
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        let mut x = &self.root;
+
+        while let Some(node) = x {
+            x = match key.cmp(&node.key) {
+                Less => &node.left,
+                Greater => &node.right,
+                Equal => return Some(&node.value),
+            }
+        }
+
+        None
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// In key order
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        let mut stack = vec![];
+        let mut x = self.root.as_deref();
+
+        from_fn(move || {
+            while let Some(node) = x {
+                stack.push(node);
+                x = node.left.as_deref();
+            }
+
+            let node = stack.pop()?;
+            x = node.right.as_deref();
+
+            Some((&node.key, &node.value))
+        })
+    }
+
+    pub fn height(&self) -> i32 {
+        height(&self.root)
+    }
+
+    /// Both versions share the same root (no copy since then)
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        match (&self.root, &other.root) {
+            (Some(x), Some(y)) => Arc::ptr_eq(x, y),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+
+    pub fn self_validate(&self) -> Result<(), Box<dyn core::error::Error>> {
+        if let Some(root) = &self.root {
+            root.self_validate()?;
+        }
+
+        let count = self.iter().count();
+
+        if count != self.len {
+            return Err(format!("len {} but {count} items", self.len).into());
+        }
+
+        Ok(())
+    }
+}
+
+
+impl<K, V> Coll for PAVL<K, V> {
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+impl<'a, K: CollKey + Clone + 'a, V: Clone + 'a> Default for PAVL<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// O(1), the versions share all nodes
+impl<K, V> Clone for PAVL<K, V> {
+    fn clone(&self) -> Self {
+        Self { root: self.root.clone(), len: self.len }
+    }
+}
+
+impl<'a, K: CollKey + Clone + 'a, V: core::fmt::Debug + Clone + 'a> core::fmt::Debug for PAVL<K, V> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<'a, K: CollKey + Clone + 'a, V: PartialEq + Clone + 'a> PartialEq for PAVL<K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl<'a, K: CollKey + Clone + 'a, V: Eq + Clone + 'a> Eq for PAVL<K, V> {}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pavl_randomdata() {
+        test_persistent!(PAVL::new());
+    }
+
+    #[test]
+    fn test_pavl_fixeddata_case_0() {
+        let v0 = PAVL::<i32, i32>::new();
+        let v1 = (0..10).fold(v0.clone(), |x, i| x.insert(i, i));
+        let v2 = v1.remove(&3).insert(4, 40);
+
+        v1.self_validate().unwrap();
+        v2.self_validate().unwrap();
+
+        assert!(v0.is_empty());
+        assert_eq!(v1.len(), 10);
+        assert_eq!(v2.len(), 9);
+        assert_eq!(v1.get(&3), Some(&3));
+        assert_eq!(v1.get(&4), Some(&4));
+        assert_eq!(v2.get(&3), None);
+        assert_eq!(v2.get(&4), Some(&40));
+
+        // missing key shares the whole tree
+        assert!(v2.remove(&100).ptr_eq(&v2));
+    }
+
+    /// Nodes that only this version holds, the shared subtrees aren't visited
+    fn fresh_nodes<K, V>(x: &Link<K, V>) -> usize {
+        match x {
+            Some(node) if Arc::strong_count(node) == 1 => {
+                1 + fresh_nodes(&node.left) + fresh_nodes(&node.right)
+            }
+            _ => 0,
+        }
+    }
+
+    #[test]
+    fn test_pavl_path_copying() {
+        let n = crate::test_size(10000usize, 500);
+        let v1 = (0..n).fold(PAVL::new(), |x, i| x.insert(i * 2, i));
+        let log_n = (usize::BITS - n.leading_zeros()) as usize;
+
+        for k in [0, n / 3, n, n * 2 - 2, n * 2 + 1] {
+            let v2 = v1.insert(k, 0);
+            assert!(fresh_nodes(&v2.root) <= 4 * log_n);
+
+            let v2 = v1.remove(&k);
+            assert!(fresh_nodes(&v2.root) <= 4 * log_n);
+        }
+
+        // untouched by the derived versions
+        v1.self_validate().unwrap();
+        assert!(v1.iter().map(|(k, v)| (*k, *v)).eq((0..n).map(|i| (i * 2, i))));
+    }
+}
@@ -0,0 +1,551 @@
+//! Persistent Red-Black Tree, updates copy the search path and share the rest with the old version,
+//! so every version stays alive and readable, and clone is O(1).
+//!
+//! Insert is Okasaki's and remove is Kahrs', both rebalance by the red-red cases of `RB`.
+//!
+//! ref 1: Chris Okasaki, Red-Black Trees in a Functional Setting (1999)
+//!
+//! ref 2: Stefan Kahrs, Red-black trees with types (2001)
+//!
+
+use alloc::sync::Arc;
+use core::{
+    cmp::Ordering::*,
+    iter::from_fn,
+};
+
+use either::Either;
+use itertools::Itertools;
+
+use super::rb::{red_red_fix, Color, RedRedFix};
+use crate::*;
+
+////////////////////////////////////////////////////////////////////////////////
+//// Struct
+////
+
+/// Cheap to clone, `insert`/`remove` derive a new version, leaving `self` as it is.
+pub struct PRB<K, V> {
+    root: Link<K, V>,
+    len: usize,
+}
+
+struct PRBNode<K, V> {
+    left: Link<K, V>,
+    right: Link<K, V>,
+    color: Color,
+    key: K,
+    value: V,
+}
+
+type Link<K, V> = Option<Arc<PRBNode<K, V>>>;
+
+////////////////////////////////////////////////////////////////////////////////
+//// Implement
+
+fn is_red<K, V>(x: &Link<K, V>) -> bool {
+    x.as_ref().is_some_and(|x| x.color == Color::RED)
+}
+
+/// Nil isn't included
+fn is_black_node<K, V>(x: &Link<K, V>) -> bool {
+    x.as_ref().is_some_and(|x| x.color == Color::BLACK)
+}
+
+impl<'a, K: CollKey + Clone + 'a, V: Clone + 'a> PRBNode<K, V> {
+    fn new(color: Color, left: Link<K, V>, key: K, value: V, right: Link<K, V>) -> Link<K, V> {
+        Some(Arc::new(Self { left, right, color, key, value }))
+    }
+
+    /// Copy of the parts, the subtrees are shared
+    fn split(x: &Link<K, V>) -> (Link<K, V>, K, V, Link<K, V>) {
+        let x = x.as_ref().unwrap();
+
+        (x.left.clone(), x.key.clone(), x.value.clone(), x.right.clone())
+    }
+
+    fn child(x: &Link<K, V>, dir: Either<(), ()>) -> &Link<K, V> {
+        let x = x.as_ref().unwrap();
+
+        if dir.is_left() {
+            &x.left
+        } else {
+            &x.right
+        }
+    }
+
+    fn recolor(x: Link<K, V>, color: Color) -> Link<K, V> {
+        match x {
+            Some(node) if node.color != color => {
+                let (left, key, value, right) = PRBNode::split(&Some(node));
+                PRBNode::new(color, left, key, value, right)
+            }
+            _ => x,
+        }
+    }
+
+    /// Black to red, the black height of x decreases by one.
+    fn sub1(x: Link<K, V>) -> Link<K, V> {
+        debug_assert!(is_black_node(&x));
+
+        PRBNode::recolor(x, Color::RED)
+    }
+
+    /// Build a black node (or a red one with two black children),
+    /// fixing a red-red violation on one side by the same cases as `RB`.
+    fn balance(left: Link<K, V>, key: K, value: V, right: Link<K, V>) -> Link<K, V> {
+        use Color::*;
+
+        let side = |dir: Either<(), ()>| if dir.is_left() { &left } else { &right };
+
+        let red_red = [Either::Left(()), Either::Right(())]
+            .into_iter()
+            .cartesian_product([Either::Left(()), Either::Right(())])
+            .find(|&(pdir, x_dir)| {
+                let p = side(pdir);
+
+                is_red(p) && is_red(PRBNode::child(p, x_dir))
+            });
+
+        let Some((pdir, x_dir)) = red_red else {
+            return PRBNode::new(BLACK, left, key, value, right);
+        };
+
+        match red_red_fix(pdir, x_dir, is_red(side(pdir.reverse()))) {
+            RedRedFix::Recolor => PRBNode::new(
+                RED,
+                PRBNode::recolor(left, BLACK),
+                key,
+                value,
+                PRBNode::recolor(right, BLACK),
+            ),
+            RedRedFix::Rotate(Either::Right(())) => {
+                let (a, y_key, y_value, c) = PRBNode::split(&left);
+                let (a, x_key, x_value, b) = PRBNode::split(&a);
+
+                PRBNode::new(
+                    RED,
+                    PRBNode::new(BLACK, a, x_key, x_value, b),
+                    y_key,
+                    y_value,
+                    PRBNode::new(BLACK, c, key, value, right),
+                )
+            }
+            RedRedFix::DoubleRotate(Either::Right(())) => {
+                let (a, y_key, y_value, c) = PRBNode::split(&left);
+                let (b, z_key, z_value, c) = PRBNode::split(&c);
+
+                PRBNode::new(
+                    RED,
+                    PRBNode::new(BLACK, a, y_key, y_value, b),
+                    z_key,
+                    z_value,
+                    PRBNode::new(BLACK, c, key, value, right),
+                )
+            }
+            RedRedFix::DoubleRotate(Either::Left(())) => {
+                let (b, z_key, z_value, d) = PRBNode::split(&right);
+                let (b, y_key, y_value, c) = PRBNode::split(&b);
+
+                PRBNode::new(
+                    RED,
+                    PRBNode::new(BLACK, left, key, value, b),
+                    y_key,
+                    y_value,
+                    PRBNode::new(BLACK, c, z_key, z_value, d),
+                )
+            }
+            RedRedFix::Rotate(Either::Left(())) => {
+                let (b, z_key, z_value, d) = PRBNode::split(&right);
+                let (c, w_key, w_value, d) = PRBNode::split(&d);
+
+                PRBNode::new(
+                    RED,
+                    PRBNode::new(BLACK, left, key, value, b),
+                    z_key,
+                    z_value,
+                    PRBNode::new(BLACK, c, w_key, w_value, d),
+                )
+            }
+        }
+    }
+
+    /// The left side is one black shorter.
+    fn balance_left(left: Link<K, V>, key: K, value: V, right: Link<K, V>) -> Link<K, V> {
+        use Color::*;
+
+        if is_red(&left) {
+            PRBNode::new(RED, PRBNode::recolor(left, BLACK), key, value, right)
+        } else if is_black_node(&right) {
+            PRBNode::balance(left, key, value, PRBNode::recolor(right, RED))
+        } else {
+            let (b, z_key, z_value, c) = PRBNode::split(&right);
+            debug_assert!(is_black_node(&b));
+            let (a, y_key, y_value, b) = PRBNode::split(&b);
+
+            PRBNode::new(
+                RED,
+                PRBNode::new(BLACK, left, key, value, a),
+                y_key,
+                y_value,
+                PRBNode::balance(b, z_key, z_value, PRBNode::sub1(c)),
+            )
+        }
+    }
+
+    /// The right side is one black shorter.
+    fn balance_right(left: Link<K, V>, key: K, value: V, right: Link<K, V>) -> Link<K, V> {
+        use Color::*;
+
+        if is_red(&right) {
+            PRBNode::new(RED, left, key, value, PRBNode::recolor(right, BLACK))
+        } else if is_black_node(&left) {
+            PRBNode::balance(PRBNode::recolor(left, RED), key, value, right)
+        } else {
+            let (a, x_key, x_value, b) = PRBNode::split(&left);
+            debug_assert!(is_black_node(&b));
+            let (b, y_key, y_value, c) = PRBNode::split(&b);
+
+            PRBNode::new(
+                RED,
+                PRBNode::balance(PRBNode::sub1(a), x_key, x_value, b),
+                y_key,
+                y_value,
+                PRBNode::new(BLACK, c, key, value, right),
+            )
+        }
+    }
+
+    /// Return the new subtree (maybe red-red at top) and whether the key is new
+    fn insert(x: &Link<K, V>, key: K, value: V) -> (Link<K, V>, bool) {
+        let x = match x {
+            Some(x) => x,
+            None => return (PRBNode::new(Color::RED, None, key, value, None), true),
+        };
+
+        match key.cmp(&x.key) {
+            Less => {
+                let (left, is_new) = PRBNode::insert(&x.left, key, value);
+                let (x_key, x_value) = (x.key.clone(), x.value.clone());
+
+                let x = if x.color == Color::BLACK {
+                    PRBNode::balance(left, x_key, x_value, x.right.clone())
+                } else {
+                    PRBNode::new(Color::RED, left, x_key, x_value, x.right.clone())
+                };
+
+                (x, is_new)
+            }
+            Greater => {
+                let (right, is_new) = PRBNode::insert(&x.right, key, value);
+                let (x_key, x_value) = (x.key.clone(), x.value.clone());
+
+                let x = if x.color == Color::BLACK {
+                    PRBNode::balance(x.left.clone(), x_key, x_value, right)
+                } else {
+                    PRBNode::new(Color::RED, x.left.clone(), x_key, x_value, right)
+                };
+
+                (x, is_new)
+            }
+            Equal => {
+                let x = PRBNode::new(x.color.clone(), x.left.clone(), key, value, x.right.clone());
+
+                (x, false)
+            }
+        }
+    }
+
+    /// The key must exist, the result of a black x is one black shorter.
+    fn remove(x: &Link<K, V>, key: &K) -> Link<K, V> {
+        let (left, x_key, x_value, right) = PRBNode::split(x);
+
+        match key.cmp(&x_key) {
+            Less => {
+                if is_black_node(&left) {
+                    PRBNode::balance_left(PRBNode::remove(&left, key), x_key, x_value, right)
+                } else {
+                    PRBNode::new(Color::RED, PRBNode::remove(&left, key), x_key, x_value, right)
+                }
+            }
+            Greater => {
+                if is_black_node(&right) {
+                    PRBNode::balance_right(left, x_key, x_value, PRBNode::remove(&right, key))
+                } else {
+                    PRBNode::new(Color::RED, left, x_key, x_value, PRBNode::remove(&right, key))
+                }
+            }
+            Equal => PRBNode::append(&left, &right),
+        }
+    }
+
+    /// Join two subtrees of the same black height, all keys of x are less than y's.
+    fn append(x: &Link<K, V>, y: &Link<K, V>) -> Link<K, V> {
+        use Color::*;
+
+        if x.is_none() {
+            return y.clone();
+        }
+        if y.is_none() {
+            return x.clone();
+        }
+
+        let (a, x_key, x_value, b) = PRBNode::split(x);
+        let (c, y_key, y_value, d) = PRBNode::split(y);
+
+        match (is_red(x), is_red(y)) {
+            (true, true) => {
+                let bc = PRBNode::append(&b, &c);
+
+                if is_red(&bc) {
+                    let (b, z_key, z_value, c) = PRBNode::split(&bc);
+
+                    PRBNode::new(
+                        RED,
+                        PRBNode::new(RED, a, x_key, x_value, b),
+                        z_key,
+                        z_value,
+                        PRBNode::new(RED, c, y_key, y_value, d),
+                    )
+                } else {
+                    PRBNode::new(RED, a, x_key, x_value, PRBNode::new(RED, bc, y_key, y_value, d))
+                }
+            }
+            (false, false) => {
+                let bc = PRBNode::append(&b, &c);
+
+                if is_red(&bc) {
+                    let (b, z_key, z_value, c) = PRBNode::split(&bc);
+
+                    PRBNode::new(
+                        RED,
+                        PRBNode::new(BLACK, a, x_key, x_value, b),
+                        z_key,
+                        z_value,
+                        PRBNode::new(BLACK, c, y_key, y_value, d),
+                    )
+                } else {
+                    PRBNode::balance_left(a, x_key, x_value, PRBNode::new(BLACK, bc, y_key, y_value, d))
+                }
+            }
+            (false, true) => PRBNode::new(RED, PRBNode::append(x, &c), y_key, y_value, d),
+            (true, false) => PRBNode::new(RED, a, x_key, x_value, PRBNode::append(&b, y)),
+        }
+    }
+
+    /// Return the black height of subtree
+    fn self_validate(&self) -> Result<usize, Box<dyn core::error::Error>> {
+        let mut left_black_height = 0;
+        let mut right_black_height = 0;
+
+        if self.color == Color::RED && (is_red(&self.left) || is_red(&self.right)) {
+            return Err(format!("red-red at {:?}", self.key).into());
+        }
+
+        if let Some(left) = &self.left {
+            if left.key >= self.key {
+                return Err(format!("left key {:?} >= key {:?}", left.key, self.key).into());
+            }
+            left_black_height = left.self_validate()?;
+        }
+        if let Some(right) = &self.right {
+            if right.key <= self.key {
+                return Err(format!("right key {:?} <= key {:?}", right.key, self.key).into());
+            }
+            right_black_height = right.self_validate()?;
+        }
+
+        if left_black_height != right_black_height {
+            return Err(format!(
+                "black heights {left_black_height} != {right_black_height} at {:?}",
+                self.key
+            )
+            .into());
+        }
+
+        Ok(left_black_height + (self.color == Color::BLACK) as usize)
+    }
+}
+
+
+impl<'a, K: CollKey + Clone + 'a, V: Clone + 'a> PRB<K, V> {
+    pub fn new() -> Self {
+        Self { root: None, len: 0 }
+    }
+
+    /// A new version with the pair, the value is replaced if the key exists
+    pub fn insert(&self, key: K, value: V) -> Self {
+        let (root, is_new) = PRBNode::insert(&self.root, key, value);
+
+        Self { root: PRBNode::recolor(root, Color::BLACK), len: self.len + is_new as usize }
+    }
+
+    /// A new version without the key, sharing the whole tree if it doesn't exist
+    pub fn remove(&self, key: &K) -> Self {
+        if !self.contains_key(key) {
+            return self.clone();
+        }
+
+        let root = PRBNode::remove(&self.root, key);
+
+        Self { root: PRBNode::recolor(root, Color::BLACK), len: self.len - 1 }
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        let mut x = &self.root;
+
+        while let Some(node) = x {
+            x = match key.cmp(&node.key) {
+                Less => &node.left,
+                Greater => &node.right,
+                Equal => return Some(&node.value),
+            }
+        }
+
+        None
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// In key order
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        let mut stack = vec![];
+        let mut x = self.root.as_deref();
+
+        from_fn(move || {
+            while let Some(node) = x {
+                stack.push(node);
+                x = node.left.as_deref();
+            }
+
+            let node = stack.pop()?;
+            x = node.right.as_deref();
+
+            Some((&node.key, &node.value))
+        })
+    }
+
+    /// Both versions share the same root (no copy since then)
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        match (&self.root, &other.root) {
+            (Some(x), Some(y)) => Arc::ptr_eq(x, y),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+
+    pub fn self_validate(&self) -> Result<(), Box<dyn core::error::Error>> {
+        if let Some(root) = &self.root {
+            if root.color != Color::BLACK {
+                return Err("red root".into());
+            }
+            root.self_validate()?;
+        }
+
+        let count = self.iter().count();
+
+        if count != self.len {
+            return Err(format!("len {} but {count} items", self.len).into());
+        }
+
+        Ok(())
+    }
+}
+
+
+impl<K, V> Coll for PRB<K, V> {
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+impl<'a, K: CollKey + Clone + 'a, V: Clone + 'a> Default for PRB<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// O(1), the versions share all nodes
+impl<K, V> Clone for PRB<K, V> {
+    fn clone(&self) -> Self {
+        Self { root: self.root.clone(), len: self.len }
+    }
+}
+
+impl<'a, K: CollKey + Clone + 'a, V: core::fmt::Debug + Clone + 'a> core::fmt::Debug for PRB<K, V> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<'a, K: CollKey + Clone + 'a, V: PartialEq + Clone + 'a> PartialEq for PRB<K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl<'a, K: CollKey + Clone + 'a, V: Eq + Clone + 'a> Eq for PRB<K, V> {}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prb_randomdata() {
+        test_persistent!(PRB::new());
+    }
+
+    #[test]
+    fn test_prb_fixeddata_case_0() {
+        let v0 = PRB::<i32, i32>::new();
+        let v1 = (0..10).fold(v0.clone(), |x, i| x.insert(i, i));
+        let v2 = v1.remove(&3).insert(4, 40);
+
+        v1.self_validate().unwrap();
+        v2.self_validate().unwrap();
+
+        assert!(v0.is_empty());
+        assert_eq!(v1.len(), 10);
+        assert_eq!(v2.len(), 9);
+        assert_eq!(v1.get(&3), Some(&3));
+        assert_eq!(v1.get(&4), Some(&4));
+        assert_eq!(v2.get(&3), None);
+        assert_eq!(v2.get(&4), Some(&40));
+
+        // missing key shares the whole tree
+        assert!(v2.remove(&100).ptr_eq(&v2));
+    }
+
+    /// Nodes that only this version holds, the shared subtrees aren't visited
+    fn fresh_nodes<K, V>(x: &Link<K, V>) -> usize {
+        match x {
+            Some(node) if Arc::strong_count(node) == 1 => {
+                1 + fresh_nodes(&node.left) + fresh_nodes(&node.right)
+            }
+            _ => 0,
+        }
+    }
+
+    #[test]
+    fn test_prb_path_copying() {
+        let n = crate::test_size(10000usize, 500);
+        let v1 = (0..n).fold(PRB::new(), |x, i| x.insert(i * 2, i));
+        let log_n = (usize::BITS - n.leading_zeros()) as usize;
+
+        for k in [0, n / 3, n, n * 2 - 2, n * 2 + 1] {
+            let v2 = v1.insert(k, 0);
+            assert!(fresh_nodes(&v2.root) <= 4 * log_n);
+
+            let v2 = v1.remove(&k);
+            assert!(fresh_nodes(&v2.root) <= 4 * log_n);
+        }
+
+        // untouched by the derived versions
+        v1.self_validate().unwrap();
+        assert!(v1.iter().map(|(k, v)| (*k, *v)).eq((0..n).map(|i| (i * 2, i))));
+    }
+}
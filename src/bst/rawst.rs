@@ -1,22 +1,24 @@
 //! Unbalanced Search Tree
 //!
 
-use std::ptr::{null, null_mut};
+use core::ptr::{null, null_mut};
 
-use super::*;
 use crate::*;
 
 
-pub struct RawST<K, V> {
+pub struct RawST<K, V, O = ()> {
     root: *mut RawSTNode<K, V>,
+    /// notified of the structural changes, `()` for none
+    observer: O,
 }
 
 pub struct RawSTNode<K, V> {
     paren: *mut Self,
+    itself: *mut Self,
     left: *mut Self,
     right: *mut Self,
-    key: *mut K,
-    value: *mut V,
+    key: K,
+    value: V,
 }
 
 
@@ -24,26 +26,70 @@ pub struct RawSTNode<K, V> {
 ////////////////////////////////////////////////////////////////////////////////
 //// Implement
 
+impl<K, V> RawSTNode<K, V> {
+    /// Free the whole subtree, return the number of freed nodes.
+    unsafe fn drop_subtree(x: *mut Self) -> usize {
+        let mut stack = vec![x];
+        let mut cnt = 0;
+
+        while let Some(x) = stack.pop() {
+            if x.is_null() {
+                continue;
+            }
+
+            let node = Box::from_raw(x);
+
+            stack.push(node.left);
+            stack.push(node.right);
+
+            cnt += 1;
+        }
+
+        cnt
+    }
+}
+
 impl<'a, K: CollKey + 'a, V: 'a> RawSTNode<K, V> {
     pub fn new(key: K, value: V) -> *mut Self {
-        Box::into_raw(box Self {
+        let x = Box::into_raw(Box::new(Self {
             left: null_mut(),
             right: null_mut(),
             paren: null_mut(),
-            key: Box::into_raw(box key),
-            value: Box::into_raw(box value),
-        })
+            itself: null_mut(),
+            key,
+            value,
+        }));
+        unsafe { (*x).itself = x };
+
+        x
     }
 
     pub fn into_value(self) -> V {
-        unsafe { *Box::from_raw(self.value) }
+        self.value
     }
 }
 
 
+impl<'a, K: CollKey + Clone + 'a, V: Clone + 'a> RawSTNode<K, V> {
+    /// Copy without links
+    fn clone_node(&self) -> *mut Self {
+        let x = Box::into_raw(Box::new(Self {
+            paren: null_mut(),
+            itself: null_mut(),
+            left: null_mut(),
+            right: null_mut(),
+            key: self.key.clone(),
+            value: self.value.clone(),
+        }));
+        unsafe { (*x).itself = x };
+
+        x
+    }
+}
+
 impl<'a, K: CollKey + 'a, V: 'a> BTNode<'a, K, V> for RawSTNode<K, V> {
     fn itself(&self) -> *const (dyn BTNode<'a, K, V> + 'a) {
-        self as *const Self
+        self.itself
     }
 
     fn null(&self) -> *const (dyn BTNode<'a, K, V> + 'a) {
@@ -51,7 +97,14 @@ impl<'a, K: CollKey + 'a, V: 'a> BTNode<'a, K, V> for RawSTNode<K, V> {
     }
 
     fn try_as_bst(&self) -> Result<*const (dyn BSTNode<'a, K, V> + 'a), ()> {
-        Ok(self as *const Self)
+        Ok(self.itself)
+    }
+
+    fn downcast(
+        &self,
+        x: *mut (dyn BTNode<'a, K, V> + 'a),
+    ) -> Result<*mut (dyn BSTNode<'a, K, V> + 'a), ()> {
+        Ok(x as *mut Self)
     }
 
     fn order(&self) -> usize {
@@ -82,10 +135,6 @@ impl<'a, K: CollKey + 'a, V: 'a> BTNode<'a, K, V> for RawSTNode<K, V> {
         }
     }
 
-    fn assign_value(&mut self, value: V, _idx: usize) {
-        self.value = Box::into_raw(box value);
-    }
-
     fn assign_paren(&mut self, paren: *mut (dyn BTNode<'a, K, V> + 'a)) {
         self.paren = paren as *mut Self;
     }
@@ -96,37 +145,33 @@ impl<'a, K: CollKey + 'a, V: 'a> BTNode<'a, K, V> for RawSTNode<K, V> {
 
     fn key(&self, idx: usize) -> Option<&K> {
         if idx == 0 {
-            unsafe { Some(&*self.key) }
+            Some(&self.key)
         } else {
             None
         }
     }
 
-    fn key_ptr(&self, idx: usize) -> *mut K {
+    fn key_mut(&mut self, idx: usize) -> Option<&mut K> {
         if idx == 0 {
-            self.key
+            Some(&mut self.key)
         } else {
-            null_mut::<K>()
-        }
-    }
-
-    fn assign_key_ptr(&mut self, idx: usize, key_ptr: *mut K) {
-        if idx == 0 {
-            self.key = key_ptr;
+            None
         }
     }
 
-    fn val_ptr(&self, idx: usize) -> *mut V {
+    fn value(&self, idx: usize) -> Option<&V> {
         if idx == 0 {
-            self.value
+            Some(&self.value)
         } else {
-            null_mut::<V>()
+            None
         }
     }
 
-    fn assign_val_ptr(&mut self, idx: usize, val_ptr: *mut V) {
+    fn value_mut(&mut self, idx: usize) -> Option<&mut V> {
         if idx == 0 {
-            self.value = val_ptr;
+            Some(&mut self.value)
+        } else {
+            None
         }
     }
 
@@ -140,25 +185,96 @@ impl<'a, K: CollKey + 'a, V: 'a> BSTNode<'a, K, V> for RawSTNode<K, V> {}
 
 impl<'a, K: CollKey + 'a, V: 'a> RawST<K, V> {
     pub fn new() -> Self {
-        Self { root: null_mut() }
+        Self::with_observer(())
+    }
+
+    /// Build a perfectly balanced tree from the strictly ascending sequence in O(n),
+    /// Err if it isn't.
+    #[allow(clippy::result_unit_err, reason = "as `FromSortedIter`")]
+    pub fn from_sorted_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Result<Self, ()> {
+        let nodes: Vec<*mut RawSTNode<K, V>> = collect_sorted(iter)?
+            .into_iter()
+            .map(|(key, value)| RawSTNode::new(key, value))
+            .collect();
+
+        Ok(Self { root: unsafe { Self::build(&nodes[..]) }, observer: () })
+    }
+
+    unsafe fn build(seq: &[*mut RawSTNode<K, V>]) -> *mut RawSTNode<K, V> {
+        if seq.is_empty() {
+            return null_mut();
+        }
+
+        let mid = seq.len() / 2;
+        let x = seq[mid];
+
+        (*x).connect_left(Self::build(&seq[..mid]));
+        (*x).connect_right(Self::build(&seq[mid + 1..]));
+
+        x
     }
 }
 
-impl<'a, K: CollKey + 'a, V: 'a> Dictionary<K, V> for RawST<K, V> {
+impl<'a, K: CollKey + 'a, V: 'a, O: Observer<K>> RawST<K, V, O> {
+    pub fn with_observer(observer: O) -> Self {
+        Self { root: null_mut(), observer }
+    }
+
+    pub fn observer(&self) -> &O {
+        &self.observer
+    }
+
+    pub fn observer_mut(&mut self) -> &mut O {
+        &mut self.observer
+    }
+}
+
+impl<'a, K: CollKey + 'a, V: 'a, O: Observer<K> + Default> Default for RawST<K, V, O> {
+    fn default() -> Self {
+        Self::with_observer(O::default())
+    }
+}
+
+impl<'a, K: CollKey + 'a, V: 'a> FromSortedIter<K, V> for RawST<K, V> {
+    fn from_sorted_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Result<Self, ()> {
+        RawST::from_sorted_iter(iter)
+    }
+}
+
+unsafe impl<K: Send, V: Send, O: Send> Send for RawST<K, V, O> {}
+unsafe impl<K: Sync, V: Sync, O: Sync> Sync for RawST<K, V, O> {}
+
+impl<'a, K: CollKey + Clone + 'a, V: Clone + 'a, O: Observer<K> + Clone> Clone for RawST<K, V, O> {
+    fn clone(&self) -> Self {
+        Self {
+            root: unsafe { clone_subtree(self.root, RawSTNode::clone_node) },
+            observer: self.observer.clone(),
+        }
+    }
+}
+
+impl<K, V, O> Drop for RawST<K, V, O> {
+    fn drop(&mut self) {
+        unsafe {
+            RawSTNode::drop_subtree(self.root);
+        }
+    }
+}
+
+impl_std_traits!(RawST<K, V, O>);
+impl_stats!(RawST<K, V, StatsObserver>);
+
+impl<'a, K: CollKey + 'a, V: 'a, O: Observer<K>> Dictionary<K, V> for RawST<K, V, O> {
     fn insert(&mut self, key: K, value: V) -> bool {
         let new_node = RawSTNode::new(key, value);
 
-        self.basic_insert(new_node)
+        unsafe { self.basic_insert(new_node) }
     }
 
     fn remove(&mut self, key: &K) -> Option<V> {
-        if let Some(node) = self.basic_remove(key) {
-            Some(unsafe {
-                Box::from_raw(node as *mut RawSTNode<K, V>).into_value()
-            })
-        } else {
-            None
-        }
+        self.basic_remove(key).map(|node| unsafe {
+            Box::from_raw(node as *mut RawSTNode<K, V>).into_value()
+        })
     }
 
     fn modify(&mut self, key: &K, value: V) -> bool {
@@ -173,14 +289,14 @@ impl<'a, K: CollKey + 'a, V: 'a> Dictionary<K, V> for RawST<K, V> {
         self.basic_lookup_mut(key)
     }
 
-    fn self_validate(&self) -> Result<(), Box<dyn std::error::Error>> {
+    fn self_validate(&self) -> Result<(), Box<dyn core::error::Error>> {
         self.basic_self_validate()
     }
 }
 
 
 
-impl<'a, K: CollKey + 'a, V: 'a> BT<'a, K, V> for RawST<K, V> {
+impl<'a, K: CollKey + 'a, V: 'a, O: Observer<K>> BT<'a, K, V> for RawST<K, V, O> {
     fn order(&self) -> usize {
         2
     }
@@ -192,15 +308,36 @@ impl<'a, K: CollKey + 'a, V: 'a> BT<'a, K, V> for RawST<K, V> {
     fn assign_root(&mut self, root: *mut (dyn BTNode<'a, K, V> + 'a)) {
         self.root = root as *mut RawSTNode<K, V>;
     }
+
+    fn on_search(&self, visits: usize, comparisons: usize) {
+        self.observer.on_search(visits, comparisons)
+    }
 }
 
 
-impl<'a, K: CollKey + 'a, V: 'a> BST<'a, K, V> for RawST<K, V> {
+impl<'a, K: CollKey + 'a, V: 'a, O: Observer<K>> BST<'a, K, V> for RawST<K, V, O> {
     unsafe fn rotate_cleanup(
         &mut self,
         _x: *mut (dyn BSTNode<'a, K, V> + 'a),
         _z: *mut (dyn BSTNode<'a, K, V> + 'a),
     ) {}
+
+    unsafe fn on_rotate(
+        &mut self,
+        x: *mut (dyn BSTNode<'a, K, V> + 'a),
+        z: *mut (dyn BSTNode<'a, K, V> + 'a),
+        rotation: Either<(), ()>,
+    ) {
+        self.observer.on_rotate((*x).key_bst(), (*z).key_bst(), rotation);
+    }
+
+    unsafe fn on_double_rotate(
+        &mut self,
+        y: *mut (dyn BSTNode<'a, K, V> + 'a),
+        snd_rotation: Either<(), ()>,
+    ) {
+        self.observer.on_double_rotate((*y).key_bst(), snd_rotation);
+    }
 }
 
 
@@ -217,4 +354,24 @@ pub(crate) mod tests {
         test_dict!(RawST::new());
     }
 
+    #[test]
+    fn test_rawst_send_sync() {
+        test_send_sync!(RawST::new());
+    }
+
+    #[test]
+    fn test_rawst_clone() {
+        test_clone!(RawST::new());
+    }
+
+    #[test]
+    fn test_rawst_std_traits() {
+        test_std_traits!(RawST::new());
+    }
+
+
+    #[test]
+    fn test_rawst_from_sorted_iter() {
+        test_from_sorted_iter!(RawST);
+    }
 }
@@ -3,36 +3,53 @@
 //! Red is central item of 2-4 tree
 //!
 
-use std::{
-    fmt::{self, Write},
+use core::{
+    mem::ManuallyDrop,
+    ops::RangeBounds,
     ptr::{null, null_mut},
 };
+#[cfg(any(feature = "std", test))]
+use core::fmt::{self, Write};
 
 use either::Either;
 use itertools::Itertools;
 
-use super::*;
 use crate::*;
 
 ////////////////////////////////////////////////////////////////////////////////
 //// Struct
 ////
 
-pub struct RB<K, V> {
+pub struct RB<K, V, O = ()> {
     root: *mut RBNode<K, V>,
+    /// notified of the structural changes, `()` for none
+    observer: O,
+}
+
+/// RB allows duplicate keys.
+///
+/// The new node is inserted as the successor of the equal ones,
+/// so they keep the insertion order on infix order.
+///
+/// The rotations keep the infix order but may take an equal one into the left subtree,
+/// so the invariant is the infix order (left <= node <= right), not "equal keys go right".
+pub struct RBMulti<K, V> {
+    rb: RB<K, V>,
 }
 
 struct RBNode<K, V> {
     left: *mut Self,
     right: *mut Self,
     paren: *mut Self,
+    itself: *mut Self,
     color: Color,
-    key: *mut K,
-    value: *mut V,
+    key: K,
+    value: V,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 #[repr(u8)]
+#[allow(clippy::upper_case_acronyms)]
 pub(crate) enum Color {
     RED,
     BLACK,
@@ -66,48 +83,117 @@ fn set_black<K, V>(node: *mut RBNode<K, V>) {
     }
 }
 
-fn set_red<K, V>(node: *mut RBNode<K, V>) {
+/// The fix-up of a red node x under a red paren p under a black grand paren, shared with `PRB`.
+pub(crate) enum RedRedFix {
+    /// The uncle is red too, paint p and the uncle black and the grand paren red
+    Recolor,
+    /// Rotate the grand paren, x is an outer grandchild
+    Rotate(Either<(), ()>),
+    /// Rotate p and then the grand paren, x is an inner grandchild
+    DoubleRotate(Either<(), ()>),
+}
+
+/// `pdir` is the side of p, `x_dir` the side of x under p.
+pub(crate) fn red_red_fix(pdir: Either<(), ()>, x_dir: Either<(), ()>, uncle_is_red: bool) -> RedRedFix {
+    if uncle_is_red {
+        RedRedFix::Recolor
+    } else if pdir == x_dir {
+        RedRedFix::Rotate(pdir.reverse())
+    } else {
+        RedRedFix::DoubleRotate(pdir.reverse())
+    }
+}
+
+/// Notify the observer if the color changes
+fn paint<K: CollKey, V, O: Observer<K>>(node: *mut RBNode<K, V>, color: Color, observer: &mut O) {
     unsafe {
-        if !node.is_null() {
-            (*node).color = Color::RED
+        if !node.is_null() && (*node).color != color {
+            observer.on_recolor(&(*node).key, color == Color::RED);
+            (*node).color = color
         }
     }
 }
 
+/// Black nodes number from this (inclusive) to the leaf.
+fn black_height<K, V>(mut node: *mut RBNode<K, V>) -> usize {
+    let mut acc = 0;
+
+    unsafe {
+        while !node.is_null() {
+            if is_black(node) {
+                acc += 1;
+            }
+
+            node = (*node).left;
+        }
+    }
+
+    acc
+}
+
+impl<K, V> RBNode<K, V> {
+    /// Free the whole subtree, return the number of freed nodes.
+    unsafe fn drop_subtree(x: *mut Self) -> usize {
+        let mut stack = vec![x];
+        let mut cnt = 0;
+
+        while let Some(x) = stack.pop() {
+            if x.is_null() {
+                continue;
+            }
+
+            let node = Box::from_raw(x);
+
+            stack.push(node.left);
+            stack.push(node.right);
+
+            cnt += 1;
+        }
+
+        cnt
+    }
+}
+
 impl<'a, K: CollKey + 'a, V: 'a> RBNode<K, V> {
     pub fn new(key: K, value: V) -> *mut Self {
-        Box::into_raw(box Self {
+        let x = Box::into_raw(Box::new(Self {
             left: null_mut(),
             right: null_mut(),
             paren: null_mut(),
+            itself: null_mut(),
             color: Color::RED,
-            key: Box::into_raw(box key),
-            value: Box::into_raw(box value),
-        })
-    }
+            key,
+            value,
+        }));
+        unsafe { (*x).itself = x };
 
-    fn leaf(paren: *mut Self) -> *mut Self {
-        Box::into_raw(box Self {
-            left: null_mut(),
-            right: null_mut(),
-            paren,
-            color: Color::BLACK,
-            key: null_mut(),
-            value: null_mut(),
-        })
+        x
     }
 
     fn node_into_value(node: *mut RBNode<K, V>) -> V {
         unsafe {
             let origin_node = Box::from_raw(node);
-            *Box::from_raw(origin_node.value)
+            origin_node.value
+        }
+    }
+
+    /// Cut x from its parent as an independent tree.
+    unsafe fn detach(x: *mut Self) -> *mut Self {
+        if !x.is_null() {
+            (*x).paren = null_mut();
         }
+
+        x
     }
 
     /// validate red/black
-    pub fn self_validate(&self) -> Result<(), Box<dyn std::error::Error>> {
+    pub fn self_validate(&self) -> Result<(), Box<dyn core::error::Error>> {
         self.basic_self_validate()?;
+        self.color_self_validate()
+    }
 
+    /// validate red/black without key order
+    fn color_self_validate(&self) -> Result<(), Box<dyn core::error::Error>> {
         unsafe {
             // Single Red Color Rule
             if self.color == Color::RED {
@@ -116,8 +202,9 @@ impl<'a, K: CollKey + 'a, V: 'a> RBNode<K, V> {
                 //     assert!(self.color.is_black());
                 // }
 
-                assert!(is_black(self.left));
-                assert!(is_black(self.right));
+                if !is_black(self.left) || !is_black(self.right) {
+                    return Err(format!("red {:?} has a red child", self.key_bst()).into());
+                }
             }
 
             // All descendant leaf's black depth
@@ -125,41 +212,52 @@ impl<'a, K: CollKey + 'a, V: 'a> RBNode<K, V> {
 
             // Validate recursively
             if !self.left.is_null() {
-                (*self.left).self_validate()?;
+                (*self.left).color_self_validate()?;
             }
 
             if !self.right.is_null() {
-                (*self.right).self_validate()?;
+                (*self.right).color_self_validate()?;
             }
         }
 
         Ok(())
     }
 
-    /// Knuth's leaf, carry no information.
-    pub fn leafs(&self) -> Vec<*mut Self> {
-        let mut queue = vecdeq![self as *const Self as *mut Self];
-        let mut leafs = vec![];
+    /// All leafs have the same black depth
+    fn is_black_balance(&self) -> bool {
+        self.leaf_black_depths()
+            .into_iter()
+            .tuple_windows()
+            .all(|(a, b)| a == b)
+    }
+
+    /// Black depth of each Knuth's leaf (the nil child),
+    /// that's the black depth of its parent plus the parent itself.
+    pub fn leaf_black_depths(&self) -> Vec<usize> {
+        let mut queue = vecdeq![self.itself];
+        let mut depths = vec![];
 
         while !queue.is_empty() {
             let p = queue.pop_front().unwrap();
 
             unsafe {
+                let leaf_depth = (*p).black_depth() + is_black(p) as usize;
+
                 if (*p).left.is_null() {
-                    leafs.push(RBNode::leaf(p));
+                    depths.push(leaf_depth);
                 } else {
                     queue.push_back((*p).left);
                 }
 
                 if (*p).right.is_null() {
-                    leafs.push(RBNode::leaf(p));
+                    depths.push(leaf_depth);
                 } else {
                     queue.push_back((*p).right);
                 }
             }
         }
 
-        leafs
+        depths
     }
 
     /// Black nodes number from root to this.
@@ -182,6 +280,7 @@ impl<'a, K: CollKey + 'a, V: 'a> RBNode<K, V> {
         }
     }
 
+    #[cfg(any(feature = "std", test))]
     pub fn echo_in_mm(&self, cache: &mut String) -> fmt::Result {
         unsafe {
             BSTNode::echo_in_mm(self, cache, |x, cache| {
@@ -192,6 +291,7 @@ impl<'a, K: CollKey + 'a, V: 'a> RBNode<K, V> {
         }
     }
 
+    #[cfg(any(feature = "std", test))]
     pub fn echo_stdout(&self) {
         let mut cache = String::new();
 
@@ -201,9 +301,27 @@ impl<'a, K: CollKey + 'a, V: 'a> RBNode<K, V> {
     }
 }
 
+impl<'a, K: CollKey + Clone + 'a, V: Clone + 'a> RBNode<K, V> {
+    /// Copy without links
+    fn clone_node(&self) -> *mut Self {
+        let x = Box::into_raw(Box::new(Self {
+            left: null_mut(),
+            right: null_mut(),
+            paren: null_mut(),
+            itself: null_mut(),
+            color: self.color.clone(),
+            key: self.key.clone(),
+            value: self.value.clone(),
+        }));
+        unsafe { (*x).itself = x };
+
+        x
+    }
+}
+
 impl<'a, K: CollKey + 'a, V: 'a> BTNode<'a, K, V> for RBNode<K, V> {
     fn itself(&self) -> *const (dyn BTNode<'a, K, V> + 'a) {
-        self as *const Self
+        self.itself
     }
 
     fn null(&self) -> *const (dyn BTNode<'a, K, V> + 'a) {
@@ -211,7 +329,14 @@ impl<'a, K: CollKey + 'a, V: 'a> BTNode<'a, K, V> for RBNode<K, V> {
     }
 
     fn try_as_bst(&self) -> Result<*const (dyn BSTNode<'a, K, V> + 'a), ()> {
-        Ok(self as *const Self)
+        Ok(self.itself)
+    }
+
+    fn downcast(
+        &self,
+        x: *mut (dyn BTNode<'a, K, V> + 'a),
+    ) -> Result<*mut (dyn BSTNode<'a, K, V> + 'a), ()> {
+        Ok(x as *mut Self)
     }
 
     fn order(&self) -> usize {
@@ -238,10 +363,6 @@ impl<'a, K: CollKey + 'a, V: 'a> BTNode<'a, K, V> for RBNode<K, V> {
         }
     }
 
-    fn assign_value(&mut self, value: V, _idx: usize) {
-        self.value = Box::into_raw(box value);
-    }
-
     fn assign_paren(&mut self, paren: *mut (dyn BTNode<'a, K, V> + 'a)) {
         self.paren = paren as *mut Self;
     }
@@ -254,31 +375,35 @@ impl<'a, K: CollKey + 'a, V: 'a> BTNode<'a, K, V> for RBNode<K, V> {
         self.calc_height()
     }
 
-    fn key_ptr(&self, idx: usize) -> *mut K {
+    fn key(&self, idx: usize) -> Option<&K> {
         if idx == 0 {
-            self.key
+            Some(&self.key)
         } else {
-            null_mut::<K>()
+            None
         }
     }
 
-    fn assign_key_ptr(&mut self, idx: usize, key_ptr: *mut K) {
+    fn key_mut(&mut self, idx: usize) -> Option<&mut K> {
         if idx == 0 {
-            self.key = key_ptr;
+            Some(&mut self.key)
+        } else {
+            None
         }
     }
 
-    fn val_ptr(&self, idx: usize) -> *mut V {
+    fn value(&self, idx: usize) -> Option<&V> {
         if idx == 0 {
-            self.value
+            Some(&self.value)
         } else {
-            null_mut::<V>()
+            None
         }
     }
 
-    fn assign_val_ptr(&mut self, idx: usize, val_ptr: *mut V) {
+    fn value_mut(&mut self, idx: usize) -> Option<&mut V> {
         if idx == 0 {
-            self.value = val_ptr;
+            Some(&mut self.value)
+        } else {
+            None
         }
     }
 }
@@ -287,14 +412,60 @@ impl<'a, K: CollKey + 'a, V: 'a> BSTNode<'a, K, V> for RBNode<K, V> {}
 
 impl<'a, K: CollKey + 'a, V: 'a> RB<K, V> {
     pub fn new() -> Self {
-        #[cfg(test)]
-        {
-            unsafe {
-                ROTATE_NUM = 0;
-            }
+        Self::with_observer(())
+    }
+
+    /// Build a perfectly balanced tree from the strictly ascending sequence in O(n),
+    /// Err if it isn't.
+    ///
+    /// All nodes are black except those of the lowest level if it's incomplete.
+    #[allow(clippy::result_unit_err, reason = "as `FromSortedIter`")]
+    pub fn from_sorted_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Result<Self, ()> {
+        let nodes: Vec<*mut RBNode<K, V>> = collect_sorted(iter)?
+            .into_iter()
+            .map(|(key, value)| RBNode::new(key, value))
+            .collect();
+
+        // levels above it are complete
+        let red_depth = (nodes.len() + 1).ilog2() as usize;
+
+        Ok(Self { root: unsafe { Self::build(&nodes[..], 0, red_depth) }, observer: () })
+    }
+
+    unsafe fn build(
+        seq: &[*mut RBNode<K, V>],
+        depth: usize,
+        red_depth: usize,
+    ) -> *mut RBNode<K, V> {
+        if seq.is_empty() {
+            return null_mut();
         }
 
-        Self { root: null_mut() }
+        let mid = seq.len() / 2;
+        let x = seq[mid];
+
+        (*x).connect_left(Self::build(&seq[..mid], depth + 1, red_depth));
+        (*x).connect_right(Self::build(&seq[mid + 1..], depth + 1, red_depth));
+
+        if depth < red_depth {
+            set_black(x);
+        }
+
+        x
+    }
+}
+
+impl<'a, K: CollKey + 'a, V: 'a, O: Observer<K>> RB<K, V, O> {
+    pub fn with_observer(observer: O) -> Self {
+        Self { root: null_mut(), observer }
+    }
+
+    pub fn observer(&self) -> &O {
+        &self.observer
+    }
+
+    pub fn observer_mut(&mut self) -> &mut O {
+        &mut self.observer
     }
 
     // ref: https://www.geeksforgeeks.org/red-black-tree-set-3-delete-2/?ref=lbp
@@ -322,7 +493,7 @@ impl<'a, K: CollKey + 'a, V: 'a> RB<K, V> {
                 if is_black(u) {
                     self.remove_retracing_black_non_root_leaf(u);
                 } else {
-                    set_red((*u).sibling() as *mut RBNode<K, V>);
+                    paint((*u).sibling() as *mut RBNode<K, V>, Color::RED, &mut self.observer);
                 }
 
                 self.subtree_shift(u, v);
@@ -337,7 +508,7 @@ impl<'a, K: CollKey + 'a, V: 'a> RB<K, V> {
         self.subtree_shift(u, v);
 
         if is_red(u) || is_red(v) {
-            set_black(v);
+            paint(v, Color::BLACK, &mut self.observer);
 
             return u;
         }
@@ -383,8 +554,8 @@ impl<'a, K: CollKey + 'a, V: 'a> RB<K, V> {
         if is_red(s) {
             // indicates that p c d are black
             self.rotate(p, dir);
-            (*p).color = Color::RED;
-            (*s).color = Color::BLACK;
+            paint(p, Color::RED, &mut self.observer);
+            paint(s, Color::BLACK, &mut self.observer);
 
             return self.remove_retracing_black_non_root_leaf(n);
         }
@@ -392,30 +563,30 @@ impl<'a, K: CollKey + 'a, V: 'a> RB<K, V> {
         /* s is black */
 
         if is_black(c) && is_black(d) {
-            (*s).color = Color::RED;
+            paint(s, Color::RED, &mut self.observer);
 
             if is_black(p) {
                 self.remove_retracing_black_non_root_leaf(p);
             } else {
-                set_black(p);
+                paint(p, Color::BLACK, &mut self.observer);
             }
         } else if is_red(c) {
             self.double_rotate(p, dir);
-            (*c).color = (*p).color.clone();
-            (*p).color = Color::BLACK;
+            paint(c, (*p).color.clone(), &mut self.observer);
+            paint(p, Color::BLACK, &mut self.observer);
         } else {
             // d is red
             self.rotate(p, dir);
-            (*s).color = (*p).color.clone();
-            (*d).color = Color::BLACK;
-            (*p).color = Color::BLACK;
+            paint(s, (*p).color.clone(), &mut self.observer);
+            paint(d, Color::BLACK, &mut self.observer);
+            paint(p, Color::BLACK, &mut self.observer);
         }
     }
 
     unsafe fn insert_retracing(&mut self, x: *mut RBNode<K, V>) {
         let p = (*x).paren;
         if p.is_null() {
-            set_black(x);
+            paint(x, Color::BLACK, &mut self.observer);
             return;
         }
 
@@ -426,71 +597,235 @@ impl<'a, K: CollKey + 'a, V: 'a> RB<K, V> {
         // p is red
         let g = (*p).paren; // grand paren
         if g.is_null() {
-            set_black(p);
+            paint(p, Color::BLACK, &mut self.observer);
             return;
         }
 
         let u = (*p).sibling() as *mut RBNode<K, V>; // uncle
+        let pdir = (*p).dir();
+        let x_dir = (*x).dir();
+
+        let (new_root, rotation) = match red_red_fix(pdir, x_dir, is_red(u)) {
+            RedRedFix::Recolor => {
+                // g should be black
+                // Repaint
+                paint(p, Color::BLACK, &mut self.observer);
+                paint(u, Color::BLACK, &mut self.observer);
+                paint(g, Color::RED, &mut self.observer);
+
+                return self.insert_retracing(g);
+            }
+            RedRedFix::Rotate(rotation) => (self.rotate(g, rotation), rotation),
+            RedRedFix::DoubleRotate(rotation) => (self.double_rotate(g, rotation), rotation),
+        };
 
-        if is_red(u) {
-            // g should be black
-            // Repaint
-            set_black(p);
-            set_black(u);
-            set_red(g);
+        // g is moved down to the side of the rotation
+        let the_other_child = (*new_root).child_bst(rotation);
 
-            self.insert_retracing(g)
+        let new_root_self = new_root as *mut RBNode<K, V>;
+        let the_other_child_self = the_other_child as *mut RBNode<K, V>;
+
+        paint(new_root_self, Color::BLACK, &mut self.observer);
+        paint(the_other_child_self, Color::RED, &mut self.observer);
+    }
+
+    #[cfg(any(feature = "std", test))]
+    pub fn echo_stdout(&self) {
+        if !self.root.is_null() {
+            unsafe { (*self.root).echo_stdout() }
+        }
+    }
+
+    /// Remove all entries whose key is in the range, return the number of them.
+    ///
+    /// Two splits and one join, O(log n + k)
+    pub fn remove_range<R: RangeBounds<K>>(&mut self, range: R) -> usize {
+        unsafe {
+            let observer = &mut self.observer;
+            let (lf, rest) = Self::split_by(
+                self.root, &|key| key_before_range(key, &range), observer
+            );
+            let (mid, rh) = Self::split_by(
+                rest, &|key| !key_after_range(key, &range), observer
+            );
+
+            self.root = Self::join2(lf, rh, observer);
+
+            self.observer.on_tree_split();
+            self.observer.on_tree_split();
+            self.observer.on_tree_join();
+
+            RBNode::drop_subtree(mid)
+        }
+    }
+
+    /// Split into two by predicate.
+    ///
+    /// The left satisfy `is_lf`, the right don't.
+    ///
+    /// **MUST:** `is_lf` is monotone (true ... true false ... false) on infix order
+    unsafe fn split_by(
+        t: *mut RBNode<K, V>,
+        is_lf: &dyn Fn(&K) -> bool,
+        observer: &mut O,
+    ) -> (*mut RBNode<K, V>, *mut RBNode<K, V>) {
+        if t.is_null() {
+            return (null_mut(), null_mut());
+        }
+
+        let lf = RBNode::detach((*t).left);
+        let rh = RBNode::detach((*t).right);
+
+        if is_lf((*t).key_bst()) {
+            let (part_lf, part_rh) = Self::split_by(rh, is_lf, observer);
+
+            (Self::join(lf, t, part_lf, observer), part_rh)
         } else {
-            // uncle is black
-            let pdir = (*p).dir();
-            let x_dir = (*x).dir();
+            let (part_lf, part_rh) = Self::split_by(lf, is_lf, observer);
 
-            let new_root;
-            let the_other_child;
+            (part_lf, Self::join(part_rh, t, rh, observer))
+        }
+    }
 
-            match (pdir, x_dir) {
-                (Either::Left(_), Either::Left(_)) => {
-                    new_root = self.rotate(g, Either::Right(()));
-                }
-                (Either::Left(_), Either::Right(_)) => {
-                    new_root = self.double_rotate(g, Either::Right(()));
-                }
-                (Either::Right(_), Either::Left(_)) => {
-                    new_root = self.double_rotate(g, Either::Left(()));
-                }
-                (Either::Right(_), Either::Right(_)) => {
-                    new_root = self.rotate(g, Either::Left(()));
-                }
+    /// Join two trees with a middle node k, the result root is black.
+    ///
+    /// **MUST:** keys of tl < key of k < keys of tr
+    ///
+    /// O(|bh(tl) - bh(tr)|)
+    unsafe fn join(
+        tl: *mut RBNode<K, V>,
+        k: *mut RBNode<K, V>,
+        tr: *mut RBNode<K, V>,
+        observer: &mut O,
+    ) -> *mut RBNode<K, V> {
+        // Red root can always be painted black as an independent tree.
+        paint(tl, Color::BLACK, observer);
+        paint(tr, Color::BLACK, observer);
+
+        let bhl = black_height(tl);
+        let bhr = black_height(tr);
+
+        (*k).left = null_mut();
+        (*k).right = null_mut();
+        (*k).paren = null_mut();
+
+        // Descend along the spine of the higher tree until the black node c
+        // with bh(c) == bh(lower tree), replace c with red k(c, lower tree).
+        let dir = if bhl > bhr {
+            Either::Right(())
+        } else if bhr > bhl {
+            Either::Left(())
+        } else {
+            (*k).connect_left(tl);
+            (*k).connect_right(tr);
+            (*k).color = Color::BLACK;
+
+            return k;
+        };
+
+        let (higher, mut bh, lower_bh) = if dir.is_right() {
+            (tl, bhl, bhr)
+        } else {
+            (tr, bhr, bhl)
+        };
+
+        let mut p = null_mut();
+        let mut c = higher;
+
+        while !(is_black(c) && bh == lower_bh) {
+            if is_black(c) {
+                bh -= 1;
             }
 
-            let the_other_dir = if pdir == x_dir {
-                x_dir.reverse()
-            } else {
-                x_dir
-            };
+            p = c;
+            c = BSTNode::child(&*c, dir) as *mut RBNode<K, V>;
+        }
+
+        (*k).color = Color::RED;
+
+        if dir.is_right() {
+            (*k).connect_left(c);
+            (*k).connect_right(tr);
+            (*p).connect_right(k);
+        } else {
+            (*k).connect_left(tl);
+            (*k).connect_right(c);
+            (*p).connect_left(k);
+        }
+
+        // a view on the nodes, which are owned by the caller
+        let mut rb = ManuallyDrop::new(RB { root: higher, observer });
+        rb.insert_retracing(k);
+        paint(rb.root, Color::BLACK, &mut rb.observer);
 
-            the_other_child = (*new_root).child_bst(the_other_dir);
+        rb.root
+    }
 
-            let new_root_self = new_root as *mut RBNode<K, V>;
-            let the_other_child_self = the_other_child as *mut RBNode<K, V>;
+    /// Join two trees.
+    ///
+    /// **MUST:** keys of tl < keys of tr
+    unsafe fn join2(
+        tl: *mut RBNode<K, V>,
+        tr: *mut RBNode<K, V>,
+        observer: &mut O,
+    ) -> *mut RBNode<K, V> {
+        if tl.is_null() {
+            return tr;
+        }
 
-            set_black(new_root_self);
-            set_red(the_other_child_self);
+        if tr.is_null() {
+            return tl;
         }
+
+        let mut rb = ManuallyDrop::new(RB { root: tr, observer: &mut *observer });
+        // the removed node carries the key-value of the minimum
+        let k = rb.remove_retracing((*tr).minimum() as *mut RBNode<K, V>);
+        let tr = rb.root;
+
+        Self::join(tl, k, tr, observer)
     }
+}
 
-    pub fn echo_stdout(&self) {
-        if !self.root.is_null() {
-            unsafe { (*self.root).echo_stdout() }
+impl<'a, K: CollKey + 'a, V: 'a, O: Observer<K> + Default> Default for RB<K, V, O> {
+    fn default() -> Self {
+        Self::with_observer(O::default())
+    }
+}
+
+impl<'a, K: CollKey + 'a, V: 'a> FromSortedIter<K, V> for RB<K, V> {
+    fn from_sorted_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Result<Self, ()> {
+        RB::from_sorted_iter(iter)
+    }
+}
+
+unsafe impl<K: Send, V: Send, O: Send> Send for RB<K, V, O> {}
+unsafe impl<K: Sync, V: Sync, O: Sync> Sync for RB<K, V, O> {}
+
+impl<'a, K: CollKey + Clone + 'a, V: Clone + 'a, O: Observer<K> + Clone> Clone for RB<K, V, O> {
+    fn clone(&self) -> Self {
+        Self {
+            root: unsafe { clone_subtree(self.root, RBNode::clone_node) },
+            observer: self.observer.clone(),
         }
     }
 }
 
-impl<'a, K: CollKey + 'a, V: 'a> Dictionary<K, V> for RB<K, V> {
+impl<K, V, O> Drop for RB<K, V, O> {
+    fn drop(&mut self) {
+        unsafe {
+            RBNode::drop_subtree(self.root);
+        }
+    }
+}
+
+impl_std_traits!(RB<K, V, O>);
+impl_stats!(RB<K, V, StatsObserver>);
+
+impl<'a, K: CollKey + 'a, V: 'a, O: Observer<K>> Dictionary<K, V> for RB<K, V, O> {
     fn insert(&mut self, key: K, value: V) -> bool {
         let new_node = RBNode::new(key, value);
 
-        if !self.basic_insert(new_node) {
+        if !unsafe { self.basic_insert(new_node) } {
             return false;
         }
 
@@ -503,12 +838,14 @@ impl<'a, K: CollKey + 'a, V: 'a> Dictionary<K, V> for RB<K, V> {
 
     fn remove(&mut self, key: &K) -> Option<V> {
         unsafe {
-            let approxi_node = (*self.search_approximately(&key)).try_as_bst_mut().unwrap();
+            let approxi_node = self.search_approximately(key);
 
             if approxi_node.is_null() {
                 return None;
             }
 
+            let approxi_node = (*approxi_node).itself_bst_mut();
+
             if BSTNode::key_bst(&*approxi_node) != key {
                 return None;
             }
@@ -531,21 +868,14 @@ impl<'a, K: CollKey + 'a, V: 'a> Dictionary<K, V> for RB<K, V> {
         self.basic_lookup_mut(income_key)
     }
 
-    fn self_validate(&self) -> Result<(), Box<dyn std::error::Error>> {
+    fn self_validate(&self) -> Result<(), Box<dyn core::error::Error>> {
         self.basic_self_validate()?;
 
         if !self.root.is_null() {
             unsafe {
                 (*self.root).self_validate()?;
 
-                let is_black_balance = (*self.root)
-                    .leafs()
-                    .into_iter()
-                    .map(|leaf| (*leaf).black_depth())
-                    .tuple_windows()
-                    .all(|(a, b)| a == b);
-
-                assert!(is_black_balance)
+                assert!((*self.root).is_black_balance())
             }
         }
 
@@ -553,7 +883,7 @@ impl<'a, K: CollKey + 'a, V: 'a> Dictionary<K, V> for RB<K, V> {
     }
 }
 
-impl<'a, K: CollKey + 'a, V: 'a> BT<'a, K, V> for RB<K, V> {
+impl<'a, K: CollKey + 'a, V: 'a, O: Observer<K>> BT<'a, K, V> for RB<K, V, O> {
     fn order(&self) -> usize {
         2
     }
@@ -565,17 +895,169 @@ impl<'a, K: CollKey + 'a, V: 'a> BT<'a, K, V> for RB<K, V> {
     fn assign_root(&mut self, root: *mut (dyn BTNode<'a, K, V> + 'a)) {
         self.root = root as *mut RBNode<K, V>;
     }
+
+    fn on_search(&self, visits: usize, comparisons: usize) {
+        self.observer.on_search(visits, comparisons)
+    }
 }
 
-impl<'a, K: CollKey + 'a, V: 'a> BST<'a, K, V> for RB<K, V> {
+impl<'a, K: CollKey + 'a, V: 'a, O: Observer<K>> BST<'a, K, V> for RB<K, V, O> {
     unsafe fn rotate_cleanup(
         &mut self,
         _x: *mut (dyn BSTNode<'a, K, V> + 'a),
         _z: *mut (dyn BSTNode<'a, K, V> + 'a),
     ) {
     }
+
+    unsafe fn on_rotate(
+        &mut self,
+        x: *mut (dyn BSTNode<'a, K, V> + 'a),
+        z: *mut (dyn BSTNode<'a, K, V> + 'a),
+        rotation: Either<(), ()>,
+    ) {
+        self.observer.on_rotate((*x).key_bst(), (*z).key_bst(), rotation);
+    }
+
+    unsafe fn on_double_rotate(
+        &mut self,
+        y: *mut (dyn BSTNode<'a, K, V> + 'a),
+        snd_rotation: Either<(), ()>,
+    ) {
+        self.observer.on_double_rotate((*y).key_bst(), snd_rotation);
+    }
+}
+
+
+impl<'a, K: CollKey + 'a, V: 'a> RBMulti<K, V> {
+    pub fn new() -> Self {
+        Self { rb: RB::new() }
+    }
+
+    /// The first one on infix order whose key is equal to the key
+    unsafe fn search_first(&self, key: &K) -> *mut RBNode<K, V> {
+        let mut res = null_mut();
+        let mut x = self.rb.root;
+
+        while !x.is_null() {
+            if (*x).key_bst() < key {
+                x = (*x).right;
+            } else {
+                res = x;
+                x = (*x).left;
+            }
+        }
+
+        if !res.is_null() && (*res).key_bst() == key {
+            res
+        } else {
+            null_mut()
+        }
+    }
 }
 
+impl<'a, K: CollKey + 'a, V: 'a> Default for RBMulti<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, K: CollKey + 'a, V: 'a> MultiDictionary<K, V> for RBMulti<K, V> {
+    fn insert(&mut self, key: K, value: V) {
+        let new_node = RBNode::new(key, value);
+
+        unsafe {
+            let key = (*new_node).key_bst();
+            let mut y = null_mut::<RBNode<K, V>>();
+            let mut x = self.rb.root;
+
+            // equal key goes right
+            while !x.is_null() {
+                y = x;
+
+                if key < (*x).key_bst() {
+                    x = (*x).left;
+                } else {
+                    x = (*x).right;
+                }
+            }
+
+            if y.is_null() {
+                self.rb.root = new_node;
+            } else if key < (*y).key_bst() {
+                (*y).connect_left(new_node);
+            } else {
+                (*y).connect_right(new_node);
+            }
+
+            self.rb.insert_retracing(new_node);
+        }
+    }
+
+    fn remove_one(&mut self, key: &K) -> Option<V> {
+        unsafe {
+            let x = self.search_first(key);
+
+            if x.is_null() {
+                return None;
+            }
+
+            let removed_node = self.rb.remove_retracing(x);
+
+            Some(RBNode::node_into_value(removed_node))
+        }
+    }
+
+    fn get_all<'b>(&'b self, key: &K) -> Box<dyn Iterator<Item = &'b V> + 'b> {
+        unsafe {
+            let first = self.search_first(key);
+
+            if first.is_null() {
+                return Box::new(core::iter::empty());
+            }
+
+            let key = (*first).key_bst();
+
+            let iter = core::iter::successors(Some(first), move |&x| {
+                let y = (*x).successor_bst() as *mut RBNode<K, V>;
+
+                if !y.is_null() && (*y).key_bst() == key {
+                    Some(y)
+                } else {
+                    None
+                }
+            })
+            .map(|x| &(*x).value);
+
+            Box::new(iter)
+        }
+    }
+
+    fn self_validate(&self) -> Result<(), Box<dyn core::error::Error>> {
+        if self.rb.root.is_null() {
+            return Ok(());
+        }
+
+        unsafe {
+            // Rotation moves the equal ones into left subtree as well,
+            // so check the infix order instead of the children.
+            let keys = self.rb.nodes_iter().map(|x| (*x).key_bst());
+
+            if let Some((k0, k1)) = keys.tuple_windows().find(|(k0, k1)| k1 < k0) {
+                return Err(format!("{k0:?} before {k1:?} on infix order").into());
+            }
+
+            (*self.rb.root).color_self_validate()?;
+
+            if !(*self.rb.root).is_black_balance() {
+                return Err("leaves at different black depths".into());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+
 #[cfg(test)]
 mod test {
 
@@ -585,8 +1067,29 @@ mod test {
     #[test]
     pub(crate) fn test_rb_randomdata() {
         test_dict!(RB::new());
+    }
 
-        println!("rotate numer: {}", unsafe { ROTATE_NUM })
+    #[test]
+    fn test_rb_send_sync() {
+        test_send_sync!(RB::new());
+    }
+
+    #[test]
+    fn test_rb_clone() {
+        test_clone!(RB::new());
+    }
+
+    #[test]
+    fn test_rb_std_traits() {
+        test_std_traits!(RB::new());
+    }
+
+    #[test]
+    fn test_rb_observer() {
+        let counts = test_observer!(RB);
+
+        assert!(counts.rotate > 0);
+        assert!(counts.recolor > 0);
     }
 
     #[test]
@@ -706,4 +1209,45 @@ mod test {
 
         rb.echo_stdout();
     }
+
+    #[test]
+    fn test_rb_remove_range() {
+        test_remove_range!(RB::new());
+    }
+
+
+    #[test]
+    fn test_rb_from_sorted_iter() {
+        test_from_sorted_iter!(RB);
+    }
+
+
+    #[test]
+    fn test_rb_multi() {
+        test_multi_dict!(RBMulti::new());
+    }
+
+    #[test]
+    fn test_rb_multi_equal_left() {
+        let mut dict = RBMulti::new();
+
+        for i in 0..3 {
+            dict.insert(1, i);
+        }
+
+        // the third one rotates the first one into the left subtree of the second one
+        unsafe {
+            assert_eq!((*dict.rb.root).value, 1);
+            assert_eq!((*(*dict.rb.root).left).key, 1);
+        }
+
+        dict.self_validate().unwrap();
+        assert!(dict.get_all(&1).eq([0, 1, 2].iter()));
+
+        unsafe {
+            (*(*dict.rb.root).left).key = 2;
+        }
+
+        assert!(dict.self_validate().is_err());
+    }
 }
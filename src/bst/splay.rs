@@ -2,22 +2,31 @@
 //! Normal BST except splayed recent accessed node.
 //!
 
-use std::ptr::{null, null_mut};
+use core::{
+    cell::{Cell, UnsafeCell},
+    mem::ManuallyDrop,
+    ops::RangeBounds,
+    ptr::{null, null_mut},
+};
 
-use super::*;
 use crate::*;
 
 
-pub struct Splay<K, V> {
-    root: *mut SplayNode<K, V>,
+pub struct Splay<K, V, O = ()> {
+    /// In a `Cell` for `get` splays through `&self`
+    root: Cell<*mut SplayNode<K, V>>,
+    /// notified of the structural changes, `()` for none,
+    /// and in an `UnsafeCell` for the same reason
+    observer: UnsafeCell<O>,
 }
 
 pub struct SplayNode<K, V> {
     paren: *mut Self,
+    itself: *mut Self,
     left: *mut Self,
     right: *mut Self,
-    key: *mut K,
-    value: *mut V,
+    key: K,
+    value: V,
 }
 
 
@@ -25,26 +34,71 @@ pub struct SplayNode<K, V> {
 ////////////////////////////////////////////////////////////////////////////////
 //// Implement
 
+impl<K, V> SplayNode<K, V> {
+    /// Free the whole subtree, return the number of freed nodes.
+    unsafe fn drop_subtree(x: *mut Self) -> usize {
+        let mut stack = vec![x];
+        let mut cnt = 0;
+
+        while let Some(x) = stack.pop() {
+            if x.is_null() {
+                continue;
+            }
+
+            let node = Box::from_raw(x);
+
+            stack.push(node.left);
+            stack.push(node.right);
+
+            cnt += 1;
+        }
+
+        cnt
+    }
+}
+
 impl<'a, K: CollKey + 'a, V: 'a> SplayNode<K, V> {
     pub fn new(key: K, value: V) -> *mut Self {
-        Box::into_raw(box Self {
+        let x = Box::into_raw(Box::new(Self {
             left: null_mut(),
             right: null_mut(),
             paren: null_mut(),
-            key: Box::into_raw(box key),
-            value: Box::into_raw(box value),
-        })
+            itself: null_mut(),
+            key,
+            value,
+        }));
+        unsafe { (*x).itself = x };
+
+        x
     }
 
     pub fn into_value(self) -> V {
-        unsafe { *Box::from_raw(self.value) }
+        self.value
     }
+
 }
 
 
+impl<'a, K: CollKey + Clone + 'a, V: Clone + 'a> SplayNode<K, V> {
+    /// Copy without links
+    fn clone_node(&self) -> *mut Self {
+        let x = Box::into_raw(Box::new(Self {
+            paren: null_mut(),
+            itself: null_mut(),
+            left: null_mut(),
+            right: null_mut(),
+            key: self.key.clone(),
+            value: self.value.clone(),
+        }));
+        unsafe { (*x).itself = x };
+
+        x
+    }
+}
+
 impl<'a, K: CollKey + 'a, V: 'a> BTNode<'a, K, V> for SplayNode<K, V> {
     fn itself(&self) -> *const (dyn BTNode<'a, K, V> + 'a) {
-        self as *const Self
+        self.itself
     }
 
     fn null(&self) -> *const (dyn BTNode<'a, K, V> + 'a) {
@@ -52,7 +106,14 @@ impl<'a, K: CollKey + 'a, V: 'a> BTNode<'a, K, V> for SplayNode<K, V> {
     }
 
     fn try_as_bst(&self) -> Result<*const (dyn BSTNode<'a, K, V> + 'a), ()> {
-        Ok(self as *const Self)
+        Ok(self.itself)
+    }
+
+    fn downcast(
+        &self,
+        x: *mut (dyn BTNode<'a, K, V> + 'a),
+    ) -> Result<*mut (dyn BSTNode<'a, K, V> + 'a), ()> {
+        Ok(x as *mut Self)
     }
 
     fn order(&self) -> usize {
@@ -83,10 +144,6 @@ impl<'a, K: CollKey + 'a, V: 'a> BTNode<'a, K, V> for SplayNode<K, V> {
         }
     }
 
-    fn assign_value(&mut self, value: V, _idx: usize) {
-        self.value = Box::into_raw(box value);
-    }
-
     fn assign_paren(&mut self, paren: *mut (dyn BTNode<'a, K, V> + 'a)) {
         self.paren = paren as *mut Self;
     }
@@ -97,37 +154,33 @@ impl<'a, K: CollKey + 'a, V: 'a> BTNode<'a, K, V> for SplayNode<K, V> {
 
     fn key(&self, idx: usize) -> Option<&K> {
         if idx == 0 {
-            unsafe { Some(&*self.key) }
+            Some(&self.key)
         } else {
             None
         }
     }
 
-    fn key_ptr(&self, idx: usize) -> *mut K {
+    fn key_mut(&mut self, idx: usize) -> Option<&mut K> {
         if idx == 0 {
-            self.key
+            Some(&mut self.key)
         } else {
-            null_mut::<K>()
-        }
-    }
-
-    fn assign_key_ptr(&mut self, idx: usize, key_ptr: *mut K) {
-        if idx == 0 {
-            self.key = key_ptr;
+            None
         }
     }
 
-    fn val_ptr(&self, idx: usize) -> *mut V {
+    fn value(&self, idx: usize) -> Option<&V> {
         if idx == 0 {
-            self.value
+            Some(&self.value)
         } else {
-            null_mut::<V>()
+            None
         }
     }
 
-    fn assign_val_ptr(&mut self, idx: usize, val_ptr: *mut V) {
+    fn value_mut(&mut self, idx: usize) -> Option<&mut V> {
         if idx == 0 {
-            self.value = val_ptr;
+            Some(&mut self.value)
+        } else {
+            None
         }
     }
 
@@ -141,37 +194,227 @@ impl<'a, K: CollKey + 'a, V: 'a> BSTNode<'a, K, V> for SplayNode<K, V> {}
 
 impl<'a, K: CollKey + 'a, V: 'a> Splay<K, V> {
     pub fn new() -> Self {
-        Self { root: null_mut() }
+        Self::with_observer(())
+    }
+
+    /// Build a perfectly balanced tree from the strictly ascending sequence in O(n),
+    /// Err if it isn't.
+    #[allow(clippy::result_unit_err, reason = "as `FromSortedIter`")]
+    pub fn from_sorted_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Result<Self, ()> {
+        let nodes: Vec<*mut SplayNode<K, V>> = collect_sorted(iter)?
+            .into_iter()
+            .map(|(key, value)| SplayNode::new(key, value))
+            .collect();
+
+        Ok(Self {
+            root: Cell::new(unsafe { Self::build(&nodes[..]) }),
+            observer: UnsafeCell::new(()),
+        })
+    }
+
+    unsafe fn build(seq: &[*mut SplayNode<K, V>]) -> *mut SplayNode<K, V> {
+        if seq.is_empty() {
+            return null_mut();
+        }
+
+        let mid = seq.len() / 2;
+        let x = seq[mid];
+
+        (*x).connect_left(Self::build(&seq[..mid]));
+        (*x).connect_right(Self::build(&seq[mid + 1..]));
+
+        x
+    }
+}
+
+impl<'a, K: CollKey + 'a, V: 'a, O: Observer<K>> Splay<K, V, O> {
+    pub fn with_observer(observer: O) -> Self {
+        Self { root: Cell::new(null_mut()), observer: UnsafeCell::new(observer) }
+    }
+
+    /// No `&self` version, `get` notifies it through `&self`
+    pub fn observer_mut(&mut self) -> &mut O {
+        self.observer.get_mut()
     }
 
     /// Rotate to root
     unsafe fn splay(&mut self, mut x: *mut SplayNode<K, V>) {
+        let mut depth = 0;
+
         while !(*x).paren.is_null() {
             let x_dir = (*x).dir();
 
             x = self.rotate((*x).paren, x_dir.reverse()) as *mut SplayNode<K, V>;
+            depth += 1;
         }
 
+        self.observer.get_mut().on_splay((*x).key_bst(), depth);
     }
 
+    /// Remove all entries whose key is in the range, return the number of them.
+    ///
+    /// Splay the last node before the range to the root, and then splay the first node
+    /// after the range to the root of right subtree, the range is exactly the left subtree of the latter.
+    ///
+    /// O(log n + k) amortized
+    pub fn remove_range<R: RangeBounds<K>>(&mut self, range: R) -> usize {
+        unsafe {
+            let lf = self.search_last(|key| key_before_range(key, &range));
+
+            let rest = if lf.is_null() {
+                self.root.replace(null_mut())
+            } else {
+                self.splay(lf);
+
+                let rest = (*lf).right;
+                (*lf).right = null_mut();
+
+                rest
+            };
+
+            if rest.is_null() {
+                return 0;
+            }
+
+            (*rest).paren = null_mut();
+
+            let mut rh_tree = ManuallyDrop::new(Splay {
+                root: Cell::new(rest),
+                observer: UnsafeCell::new(self.observer.get_mut()),
+            });
+            let rh = rh_tree.search_first(|key| key_after_range(key, &range));
+
+            let mid = if rh.is_null() {
+                rh_tree.root.replace(null_mut())
+            } else {
+                rh_tree.splay(rh);
+
+                let mid = (*rh).left;
+                (*rh).left = null_mut();
+
+                mid
+            };
+
+            // Join, all keys of rh_tree > lf (the maximum of the left)
+            if lf.is_null() {
+                self.root.set(rh_tree.root.get());
+            } else {
+                (*lf).connect_right(rh_tree.root.get());
+            }
+
+            SplayNode::drop_subtree(mid)
+        }
+    }
+
+    /// The last node satisfy `pred`,
+    ///
+    /// **MUST:** `pred` is monotone (true ... true false ... false) on infix order
+    unsafe fn search_last(&self, pred: impl Fn(&K) -> bool) -> *mut SplayNode<K, V> {
+        let mut res = null_mut();
+        let mut x = self.root.get();
+
+        while !x.is_null() {
+            if pred((*x).key_bst()) {
+                res = x;
+                x = (*x).right;
+            } else {
+                x = (*x).left;
+            }
+        }
+
+        res
+    }
+
+    /// The first node satisfy `pred`,
+    ///
+    /// **MUST:** `pred` is monotone (false ... false true ... true) on infix order
+    unsafe fn search_first(&self, pred: impl Fn(&K) -> bool) -> *mut SplayNode<K, V> {
+        let mut res = null_mut();
+        let mut x = self.root.get();
+
+        while !x.is_null() {
+            if pred((*x).key_bst()) {
+                res = x;
+                x = (*x).left;
+            } else {
+                x = (*x).right;
+            }
+        }
+
+        res
+    }
+
+}
+
+impl<'a, K: CollKey + 'a, V: 'a, O: Observer<K> + Default> Default for Splay<K, V, O> {
+    fn default() -> Self {
+        Self::with_observer(O::default())
+    }
+}
+
+impl<'a, K: CollKey + 'a, V: 'a> FromSortedIter<K, V> for Splay<K, V> {
+    fn from_sorted_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Result<Self, ()> {
+        Splay::from_sorted_iter(iter)
+    }
+}
+
+/// Splay isn't `Sync`, `get` splays the tree through `&self`,
+/// so concurrent readers would restructure it at the same time.
+unsafe impl<K: Send, V: Send, O: Send> Send for Splay<K, V, O> {}
+
+impl<'a, K: CollKey + Clone + 'a, V: Clone + 'a, O: Observer<K> + Clone> Clone for Splay<K, V, O> {
+    fn clone(&self) -> Self {
+        unsafe {
+            Self {
+                root: Cell::new(clone_subtree(self.root.get(), SplayNode::clone_node)),
+                observer: UnsafeCell::new((*self.observer.get()).clone()),
+            }
+        }
+    }
+}
+
+impl<K, V, O> Drop for Splay<K, V, O> {
+    fn drop(&mut self) {
+        unsafe {
+            SplayNode::drop_subtree(self.root.get());
+        }
+    }
 }
 
-impl<'a, K: CollKey + 'a, V: 'a> Dictionary<K, V> for Splay<K, V> {
+impl_std_traits!(Splay<K, V, O>);
+
+// not `impl_stats`, there is no `observer(&self)` to read it
+impl<'a, K: CollKey + 'a, V: 'a> Splay<K, V, StatsObserver> {
+    /// Count the operations, see `Stats`
+    pub fn with_stats() -> Self {
+        Self::with_observer(StatsObserver::default())
+    }
+
+    pub fn stats(&self) -> Stats {
+        unsafe { (*self.observer.get()).stats() }
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.observer_mut().reset()
+    }
+}
+
+impl<'a, K: CollKey + 'a, V: 'a, O: Observer<K>> Dictionary<K, V> for Splay<K, V, O> {
     fn insert(&mut self, key: K, value: V) -> bool {
         let new_node = SplayNode::new(key, value);
 
         unsafe {
             let key = BSTNode::key_bst(&*new_node);
-            let approxi_node =
-                (*self.search_approximately(&key)).try_as_bst_mut().unwrap();
+            let approxi_node = (*new_node).downcast(self.search_approximately(key)).unwrap();
 
             if !approxi_node.is_null() && BSTNode::key_bst(&*approxi_node) == key {
+                drop(Box::from_raw(new_node));
                 return false;
             }
 
             // duplcate code for there is no guanrantee on Clone
             if approxi_node.is_null() {
-                (*new_node).assign_paren(approxi_node);
+                (*new_node).assign_paren((*new_node).null_mut());
 
                 self.assign_root(new_node)
             } else if key < BSTNode::key_bst(&*approxi_node) {
@@ -188,9 +431,7 @@ impl<'a, K: CollKey + 'a, V: 'a> Dictionary<K, V> for Splay<K, V> {
 
     fn remove(&mut self, key: &K) -> Option<V> {
         unsafe {
-            let approxi_node =
-                (*self.search_approximately(&key)).try_as_bst_mut().unwrap()
-                as *mut SplayNode<K, V>;
+            let approxi_node = self.search_approximately(key) as *mut SplayNode<K, V>;
 
             if approxi_node.is_null() {
                 return None;
@@ -203,21 +444,21 @@ impl<'a, K: CollKey + 'a, V: 'a> Dictionary<K, V> for Splay<K, V> {
             self.splay(approxi_node);
 
             if (*approxi_node).left().is_null() {
-                self.subtree_shift(approxi_node, (*approxi_node).right())
+                self.subtree_shift(approxi_node, (*approxi_node).upcast((*approxi_node).right()))
             } else if (*approxi_node).right().is_null() {
-                self.subtree_shift(approxi_node, (*approxi_node).left())
+                self.subtree_shift(approxi_node, (*approxi_node).upcast((*approxi_node).left()))
             } else {
                 let y = BSTNode::successor_bst(&*approxi_node);
                 // y should be leaf.
 
-                if (*y).paren_bst() != approxi_node {
-                    self.subtree_shift(y, (*y).right());
+                if !core::ptr::addr_eq((*y).paren_bst(), approxi_node) {
+                    self.subtree_shift((*y).as_bt_mut(), (*y).upcast((*y).right()));
                     (*y).assign_right((*approxi_node).right());
-                    (*(*y).right()).assign_paren(y);
+                    (*(*y).right()).assign_paren((*y).as_bt_mut());
                 }
-                self.subtree_shift(approxi_node, y);
+                self.subtree_shift(approxi_node, (*y).as_bt_mut());
                 (*y).assign_left((*approxi_node).left());
-                (*(*y).left()).assign_paren(y);
+                (*(*y).left()).assign_paren((*y).as_bt_mut());
             }
 
             Some(Box::from_raw(approxi_node).into_value())
@@ -225,19 +466,11 @@ impl<'a, K: CollKey + 'a, V: 'a> Dictionary<K, V> for Splay<K, V> {
     }
 
     fn modify(&mut self, key: &K, value: V) -> bool {
-        unsafe {
-            let app_node
-            = (*self.search_approximately(key))
-            .try_as_bst_mut().unwrap() as *mut SplayNode<K, V>;
-
-            if app_node.is_null() {
-                false
-            } else {
-                (*app_node).assign_value(value, 0);
-                self.splay(app_node);
-
-                true
-            }
+        if let Some(here) = self.get_mut(key) {
+            *here = value;
+            true
+        } else {
+            false
         }
     }
 
@@ -248,10 +481,15 @@ impl<'a, K: CollKey + 'a, V: 'a> Dictionary<K, V> for Splay<K, V> {
             if res.is_null() || (*res).key_bst() != key {
                 None
             } else {
-                let self_mut = &mut *(self as *const Splay<K, V> as *mut Splay<K, V>);
-
-                self_mut.splay(res);
-                Some(&*(*res).value)
+                // Splay on an alias sharing the nodes, then take its new root back
+                let mut alias = ManuallyDrop::new(Splay {
+                    root: Cell::new(self.root.get()),
+                    observer: UnsafeCell::new(&mut *self.observer.get()),
+                });
+
+                alias.splay(res);
+                self.root.set(alias.root.get());
+                Some(&(*res).value)
             }
         }
     }
@@ -264,39 +502,60 @@ impl<'a, K: CollKey + 'a, V: 'a> Dictionary<K, V> for Splay<K, V> {
                 None
             } else {
                 self.splay(res);
-                Some(&mut *(*res).value)
+                Some(&mut (*res).value)
             }
         }
     }
 
-    fn self_validate(&self) -> Result<(), Box<dyn std::error::Error>> {
+    fn self_validate(&self) -> Result<(), Box<dyn core::error::Error>> {
         self.basic_self_validate()
     }
 }
 
 
 
-impl<'a, K: CollKey + 'a, V: 'a> BT<'a, K, V> for Splay<K, V> {
+impl<'a, K: CollKey + 'a, V: 'a, O: Observer<K>> BT<'a, K, V> for Splay<K, V, O> {
     fn order(&self) -> usize {
         2
     }
 
     fn root(&self) -> *mut (dyn BTNode<'a, K, V> + 'a) {
-        self.root
+        self.root.get()
     }
 
     fn assign_root(&mut self, root: *mut (dyn BTNode<'a, K, V> + 'a)) {
-        self.root = root as *mut SplayNode<K, V>;
+        self.root.set(root as *mut SplayNode<K, V>);
+    }
+
+    fn on_search(&self, visits: usize, comparisons: usize) {
+        unsafe { (*self.observer.get()).on_search(visits, comparisons) }
     }
 }
 
 
-impl<'a, K: CollKey + 'a, V: 'a> BST<'a, K, V> for Splay<K, V> {
+impl<'a, K: CollKey + 'a, V: 'a, O: Observer<K>> BST<'a, K, V> for Splay<K, V, O> {
     unsafe fn rotate_cleanup(
         &mut self,
         _x: *mut (dyn BSTNode<'a, K, V> + 'a),
         _z: *mut (dyn BSTNode<'a, K, V> + 'a),
     ) {}
+
+    unsafe fn on_rotate(
+        &mut self,
+        x: *mut (dyn BSTNode<'a, K, V> + 'a),
+        z: *mut (dyn BSTNode<'a, K, V> + 'a),
+        rotation: Either<(), ()>,
+    ) {
+        self.observer.get_mut().on_rotate((*x).key_bst(), (*z).key_bst(), rotation);
+    }
+
+    unsafe fn on_double_rotate(
+        &mut self,
+        y: *mut (dyn BSTNode<'a, K, V> + 'a),
+        snd_rotation: Either<(), ()>,
+    ) {
+        self.observer.get_mut().on_double_rotate((*y).key_bst(), snd_rotation);
+    }
 }
 
 
@@ -313,6 +572,29 @@ pub(crate) mod tests {
         test_dict!(Splay::new());
     }
 
+    #[test]
+    fn test_splay_send_sync() {
+        test_send_sync!(Splay::new(), Mutex);
+    }
+
+    #[test]
+    fn test_splay_clone() {
+        test_clone!(Splay::new());
+    }
+
+    #[test]
+    fn test_splay_std_traits() {
+        test_std_traits!(Splay::new());
+    }
+
+    #[test]
+    fn test_splay_observer() {
+        let counts = test_observer!(Splay);
+
+        assert!(counts.splay > 0);
+        assert_eq!(counts.rotate, counts.splay_depth);
+    }
+
 
     #[test]
     fn test_splay_fixeddata_case_1() {
@@ -329,4 +611,48 @@ pub(crate) mod tests {
         BST::just_echo_stdout(&splay);
     }
 
+    #[test]
+    fn test_splay_fixeddata_case_2() {
+        let mut splay = Splay::<i32, i32>::new();
+
+        for k in [10, 20, 30] {
+            splay.insert(k, k);
+        }
+
+        // modify on a missing key used to overwrite the node splayed to instead
+        assert!(!splay.modify(&25, 0));
+        assert!(splay.remove(&20).is_some());
+        assert!(!splay.modify(&20, 0));
+
+        assert_eq!(splay.get(&10), Some(&10));
+        assert_eq!(splay.get(&30), Some(&30));
+        assert!(splay.get(&20).is_none() && splay.get(&25).is_none());
+
+        splay.self_validate().unwrap();
+    }
+
+    #[test]
+    fn test_splay_index_in_iter() {
+        let splay = Splay::from_sorted_iter((0..30).map(|k| (k, k))).unwrap();
+        let mut keys = vec![];
+
+        // index doesn't splay under the iterator
+        for (k, _) in splay.iter() {
+            assert_eq!(splay[&(29 - k)], 29 - k);
+            keys.push(*k);
+        }
+
+        assert!(keys.into_iter().eq(0..30));
+    }
+
+    #[test]
+    fn test_splay_remove_range() {
+        test_remove_range!(Splay::new());
+    }
+
+
+    #[test]
+    fn test_splay_from_sorted_iter() {
+        test_from_sorted_iter!(Splay);
+    }
 }
@@ -3,53 +3,85 @@
 //! https://en.wikipedia.org/wiki/Treap
 //!
 
-use std::{
-    fmt::{self, Write},
+use core::{
+    hash::Hash,
+    ops::RangeBounds,
     ptr::{null, null_mut},
 };
+#[cfg(any(feature = "std", test))]
+use core::fmt::{self, Write};
 
-use rand::random;
-
-use super::*;
 use crate::*;
 
 ////////////////////////////////////////////////////////////////////////////////
 //// Struct
 
-pub struct Treap<K, V, W = usize> {
+pub struct Treap<K, V, W = usize, O = (), P = GlobalRandom> {
     root: *mut TreapNode<K, V, W>,
+    /// notified of the structural changes, `()` for none
+    observer: O,
+    /// priorities of the keys `insert`ed
+    priority: P,
 }
 
 struct TreapNode<K, V, W = usize> {
     left: *mut Self,
     right: *mut Self,
     paren: *mut Self,
+    itself: *mut Self,
     weight: W,
-    key: *mut K,
-    value: *mut V,
+    key: K,
+    value: V,
 }
 
 ////////////////////////////////////////////////////////////////////////////////
 //// Implement
 
+impl<K, V, W> TreapNode<K, V, W> {
+    /// Free the whole subtree, return the number of freed nodes.
+    unsafe fn drop_subtree(x: *mut Self) -> usize {
+        let mut stack = vec![x];
+        let mut cnt = 0;
+
+        while let Some(x) = stack.pop() {
+            if x.is_null() {
+                continue;
+            }
+
+            let node = Box::from_raw(x);
+
+            stack.push(node.left);
+            stack.push(node.right);
+
+            cnt += 1;
+        }
+
+        cnt
+    }
+}
+
 impl<'a, K: CollKey + 'a, V: 'a, W: CollKey> TreapNode<K, V, W> {
     pub fn new(key: K, value: V, weight: W) -> *mut Self {
-        Box::into_raw(box Self {
+        let x = Box::into_raw(Box::new(Self {
             left: null_mut(),
             right: null_mut(),
             paren: null_mut(),
+            itself: null_mut(),
             weight,
-            key: Box::into_raw(box key),
-            value: Box::into_raw(box value),
-        })
+            key,
+            value,
+        }));
+        unsafe { (*x).itself = x };
+
+        x
     }
 
     fn into_value(self) -> V {
-        unsafe { *Box::from_raw(self.value) }
+        self.value
     }
 
     /// validate red/black
-    pub fn self_validate(&self) -> Result<(), Box<dyn std::error::Error>> {
+    pub fn self_validate(&self) -> Result<(), Box<dyn core::error::Error>> {
         self.basic_self_validate()?;
 
         unsafe {
@@ -79,6 +111,7 @@ impl<'a, K: CollKey + 'a, V: 'a, W: CollKey> TreapNode<K, V, W> {
         Ok(())
     }
 
+    #[cfg(any(feature = "std", test))]
     pub fn echo_in_mm(&self, cache: &mut String) -> fmt::Result {
         unsafe {
             BSTNode::echo_in_mm(self, cache, |x, cache| {
@@ -89,6 +122,7 @@ impl<'a, K: CollKey + 'a, V: 'a, W: CollKey> TreapNode<K, V, W> {
         }
     }
 
+    #[cfg(any(feature = "std", test))]
     pub fn echo_stdout(&self) {
         let mut cache = String::new();
 
@@ -98,9 +132,27 @@ impl<'a, K: CollKey + 'a, V: 'a, W: CollKey> TreapNode<K, V, W> {
     }
 }
 
+impl<'a, K: CollKey + Clone + 'a, V: Clone + 'a, W: Clone + 'a> TreapNode<K, V, W> {
+    /// Copy without links
+    fn clone_node(&self) -> *mut Self {
+        let x = Box::into_raw(Box::new(Self {
+            left: null_mut(),
+            right: null_mut(),
+            paren: null_mut(),
+            itself: null_mut(),
+            weight: self.weight.clone(),
+            key: self.key.clone(),
+            value: self.value.clone(),
+        }));
+        unsafe { (*x).itself = x };
+
+        x
+    }
+}
+
 impl<'a, K: CollKey + 'a, V: 'a, W: 'a> BTNode<'a, K, V> for TreapNode<K, V, W> {
     fn itself(&self) -> *const (dyn BTNode<'a, K, V> + 'a) {
-        self as *const Self
+        self.itself
     }
 
     fn null(&self) -> *const (dyn BTNode<'a, K, V> + 'a) {
@@ -108,7 +160,14 @@ impl<'a, K: CollKey + 'a, V: 'a, W: 'a> BTNode<'a, K, V> for TreapNode<K, V, W>
     }
 
     fn try_as_bst(&self) -> Result<*const (dyn BSTNode<'a, K, V> + 'a), ()> {
-        Ok(self as *const Self)
+        Ok(self.itself)
+    }
+
+    fn downcast(
+        &self,
+        x: *mut (dyn BTNode<'a, K, V> + 'a),
+    ) -> Result<*mut (dyn BSTNode<'a, K, V> + 'a), ()> {
+        Ok(x as *mut Self)
     }
 
     fn order(&self) -> usize {
@@ -135,10 +194,6 @@ impl<'a, K: CollKey + 'a, V: 'a, W: 'a> BTNode<'a, K, V> for TreapNode<K, V, W>
         }
     }
 
-    fn assign_value(&mut self, value: V, _idx: usize) {
-        self.value = Box::into_raw(box value);
-    }
-
     fn assign_paren(&mut self, paren: *mut (dyn BTNode<'a, K, V> + 'a)) {
         self.paren = paren as *mut Self;
     }
@@ -151,31 +206,35 @@ impl<'a, K: CollKey + 'a, V: 'a, W: 'a> BTNode<'a, K, V> for TreapNode<K, V, W>
         self.calc_height()
     }
 
-    fn key_ptr(&self, idx: usize) -> *mut K {
+    fn key(&self, idx: usize) -> Option<&K> {
         if idx == 0 {
-            self.key
+            Some(&self.key)
         } else {
-            null_mut::<K>()
+            None
         }
     }
 
-    fn assign_key_ptr(&mut self, idx: usize, key_ptr: *mut K) {
+    fn key_mut(&mut self, idx: usize) -> Option<&mut K> {
         if idx == 0 {
-            self.key = key_ptr;
+            Some(&mut self.key)
+        } else {
+            None
         }
     }
 
-    fn val_ptr(&self, idx: usize) -> *mut V {
+    fn value(&self, idx: usize) -> Option<&V> {
         if idx == 0 {
-            self.value
+            Some(&self.value)
         } else {
-            null_mut::<V>()
+            None
         }
     }
 
-    fn assign_val_ptr(&mut self, idx: usize, val_ptr: *mut V) {
+    fn value_mut(&mut self, idx: usize) -> Option<&mut V> {
         if idx == 0 {
-            self.value = val_ptr;
+            Some(&mut self.value)
+        } else {
+            None
         }
     }
 }
@@ -184,15 +243,11 @@ impl<'a, K: CollKey + 'a, V: 'a, W: 'a> BSTNode<'a, K, V> for TreapNode<K, V, W>
 
 impl<'a, K: CollKey + 'a, V: 'a, W: CollKey> Treap<K, V, W> {
     pub fn new() -> Self {
-        Self { root: null_mut() }
-    }
-
-    pub fn is_empty(&self) -> bool {
-        self.root.is_null()
+        Self::with_observer(())
     }
 
     pub fn bulk_load(seq: &mut dyn Iterator<Item = (K, V, W)>) -> Self {
-        let mut treap = Treap::new();
+        let mut treap = Self::new();
         // let mut rhlink = Vec::new();
 
         if let Some((key, value, weight)) = seq.next() {
@@ -229,13 +284,83 @@ impl<'a, K: CollKey + 'a, V: 'a, W: CollKey> Treap<K, V, W> {
 
         treap
     }
+}
 
+#[cfg(any(feature = "std", test))]
+impl<'a, K: CollKey + 'a, V: 'a, R: rand::RngCore> Treap<K, V, usize, (), RngPriority<R>> {
+    /// Priorities drawn from `rng`, a seeded one makes the shapes reproducible
+    pub fn with_rng(rng: R) -> Self {
+        Self::with_parts((), RngPriority(rng))
+    }
+}
+
+impl<'a, K: CollKey + Hash + 'a, V: 'a> Treap<K, V, usize, (), KeyHash> {
+    /// Priorities from the hash of the key, no matter the insertion order
+    pub fn with_key_hash() -> Self {
+        Self::with_parts((), KeyHash)
+    }
+}
+
+impl<'a, K: CollKey + 'a, V: 'a, W: CollKey, O: Observer<K>, P> Treap<K, V, W, O, P> {
+    pub fn with_observer(observer: O) -> Self
+    where
+        P: Default,
+    {
+        Self::with_parts(observer, P::default())
+    }
+
+    pub fn with_parts(observer: O, priority: P) -> Self {
+        Self { root: null_mut(), observer, priority }
+    }
+
+    pub fn observer(&self) -> &O {
+        &self.observer
+    }
+
+    pub fn observer_mut(&mut self) -> &mut O {
+        &mut self.observer
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_null()
+    }
+
+    /// Insert with a priority of one's own instead, the higher the closer to the root.
+    ///
+    /// Return false if the key is already present.
+    pub fn insert_with_priority(&mut self, key: K, value: V, priority: W) -> bool {
+        self.insert_(key, value, priority)
+    }
+
+    #[cfg(any(feature = "std", test))]
     pub fn echo_stdout(&self) {
         if !self.root.is_null() {
             unsafe { (*self.root).echo_stdout() }
         }
     }
 
+    /// Remove all entries whose key is in the range, return the number of them.
+    ///
+    /// Two splits and one join, O(log n + k)
+    pub fn remove_range<R: RangeBounds<K>>(&mut self, range: R) -> usize {
+        unsafe {
+            let (lf, rest) = Self::split_by(
+                self.root, &|key| key_before_range(key, &range)
+            );
+            let (mid, rh) = Self::split_by(
+                rest, &|key| !key_after_range(key, &range)
+            );
+
+            self.reset_root(Self::join(lf, rh));
+
+            self.observer.on_tree_split();
+            self.observer.on_tree_split();
+            self.observer.on_tree_join();
+
+            TreapNode::drop_subtree(mid)
+        }
+    }
+
     fn reset_root(&mut self, root: *mut TreapNode<K, V, W>) {
         self.root = root;
 
@@ -253,15 +378,19 @@ impl<'a, K: CollKey + 'a, V: 'a, W: CollKey> Treap<K, V, W> {
         }
 
         unsafe {
-            if !Treap::find(self.root, &key).is_null() {
+            if !Self::find(self.root, &key).is_null() {
                 return false;
             }
 
-            let (mut lf, rh) = Treap::split(self.root, &key);
+            let (mut lf, rh) = Self::split(self.root, &key);
+
+            lf = Self::join(lf, TreapNode::new(key, value, weight));
 
-            lf = Treap::join(lf, TreapNode::new(key, value, weight));
+            self.reset_root(Self::join(lf, rh));
 
-            self.reset_root(Treap::join(lf, rh));
+            self.observer.on_tree_split();
+            self.observer.on_tree_join();
+            self.observer.on_tree_join();
         }
 
         true
@@ -273,26 +402,22 @@ impl<'a, K: CollKey + 'a, V: 'a, W: CollKey> Treap<K, V, W> {
         }
 
         unsafe {
-            let x = Treap::find(self.root, key);
+            let x = Self::find(self.root, key);
 
             if x.is_null() {
                 return None;
             }
 
-            let pred = (*x).precessor_bst() as *mut TreapNode<K, V, W>;
+            // split by the key itself instead of the precessor's,
+            // which borrows from a node under restructure.
+            let (lf, rest) = Self::split_by(self.root, &|k| k < key);
+            let (_, rh) = Self::split(rest, key);
 
-            if pred.is_null() {
-                // key is the minimum
-                let (_, rh) = Treap::split(self.root, key);
-                self.reset_root(rh);
-            } else {
-                let pred_key = (*pred).key_bst();
+            self.reset_root(Self::join(lf, rh));
 
-                let (pred_lf, pred_rh) = Treap::split(self.root, pred_key);
-                let (_, rh) = Treap::split(pred_rh, key);
-
-                self.reset_root(Treap::join(pred_lf, rh));
-            }
+            self.observer.on_tree_split();
+            self.observer.on_tree_split();
+            self.observer.on_tree_join();
 
             Some(Box::from_raw(x))
         }
@@ -308,9 +433,9 @@ impl<'a, K: CollKey + 'a, V: 'a, W: CollKey> Treap<K, V, W> {
         }
 
         if key < (*x).key_bst() {
-            Treap::find((*x).left, key)
+            Self::find((*x).left, key)
         } else {
-            Treap::find((*x).right, key)
+            Self::find((*x).right, key)
         }
     }
 
@@ -329,18 +454,45 @@ impl<'a, K: CollKey + 'a, V: 'a, W: CollKey> Treap<K, V, W> {
         }
 
         if key < (*t).key_bst() {
-            let (lf_treap, part_rh_treap) = Treap::split((*t).left, key);
+            let (lf_treap, part_rh_treap) = Self::split((*t).left, key);
             (*t).connect_left(part_rh_treap);
 
             (lf_treap, t)
         } else {
-            let (part_lf_treap, rh_treap) = Treap::split((*t).right, key);
+            let (part_lf_treap, rh_treap) = Self::split((*t).right, key);
             (*t).connect_right(part_lf_treap);
 
             (t, rh_treap)
         }
     }
 
+    /// Split into two by predicate.
+    ///
+    /// The left satisfy `is_lf`, the right don't.
+    ///
+    /// **MUST:** `is_lf` is monotone (true ... true false ... false) on infix order
+    ///
+    unsafe fn split_by(
+        t: *mut TreapNode<K, V, W>,
+        is_lf: &dyn Fn(&K) -> bool,
+    ) -> (*mut TreapNode<K, V, W>, *mut TreapNode<K, V, W>) {
+        if t.is_null() {
+            return (null_mut(), null_mut());
+        }
+
+        if is_lf((*t).key_bst()) {
+            let (part_lf_treap, rh_treap) = Self::split_by((*t).right, is_lf);
+            (*t).connect_right(part_lf_treap);
+
+            (t, rh_treap)
+        } else {
+            let (lf_treap, part_rh_treap) = Self::split_by((*t).left, is_lf);
+            (*t).connect_left(part_rh_treap);
+
+            (lf_treap, t)
+        }
+    }
+
     /// Join:
     ///
     /// merge left and right tree based on weight.
@@ -360,18 +512,18 @@ impl<'a, K: CollKey + 'a, V: 'a, W: CollKey> Treap<K, V, W> {
         }
 
         if (*u).weight > (*v).weight {
-            (*u).connect_right(Treap::join((*u).right, v));
+            (*u).connect_right(Self::join((*u).right, v));
 
             u
         } else {
-            (*v).connect_left(Treap::join(u, (*v).left));
+            (*v).connect_left(Self::join(u, (*v).left));
 
             v
         }
     }
 }
 
-impl<'a, K: CollKey + 'a, V: 'a> BT<'a, K, V> for Treap<K, V> {
+impl<'a, K: CollKey + 'a, V: 'a, O: Observer<K>, P: Priority<K>> BT<'a, K, V> for Treap<K, V, usize, O, P> {
     fn order(&self) -> usize {
         2
     }
@@ -383,20 +535,75 @@ impl<'a, K: CollKey + 'a, V: 'a> BT<'a, K, V> for Treap<K, V> {
     fn assign_root(&mut self, root: *mut (dyn BTNode<'a, K, V> + 'a)) {
         self.root = root as *mut TreapNode<K, V>;
     }
+
+    fn on_search(&self, visits: usize, comparisons: usize) {
+        self.observer.on_search(visits, comparisons)
+    }
 }
 
-impl<'a, K: CollKey + 'a, V: 'a> BST<'a, K, V> for Treap<K, V> {
+impl<'a, K: CollKey + 'a, V: 'a, O: Observer<K>, P: Priority<K>> BST<'a, K, V> for Treap<K, V, usize, O, P> {
     unsafe fn rotate_cleanup(
         &mut self,
         _x: *mut (dyn BSTNode<'a, K, V> + 'a),
         _z: *mut (dyn BSTNode<'a, K, V> + 'a),
     ) {
     }
+
+    unsafe fn on_rotate(
+        &mut self,
+        x: *mut (dyn BSTNode<'a, K, V> + 'a),
+        z: *mut (dyn BSTNode<'a, K, V> + 'a),
+        rotation: Either<(), ()>,
+    ) {
+        self.observer.on_rotate((*x).key_bst(), (*z).key_bst(), rotation);
+    }
+
+    unsafe fn on_double_rotate(
+        &mut self,
+        y: *mut (dyn BSTNode<'a, K, V> + 'a),
+        snd_rotation: Either<(), ()>,
+    ) {
+        self.observer.on_double_rotate((*y).key_bst(), snd_rotation);
+    }
+}
+
+impl<'a, K: CollKey + 'a, V: 'a, W: CollKey, O: Observer<K> + Default, P: Default> Default for Treap<K, V, W, O, P> {
+    fn default() -> Self {
+        Self::with_parts(O::default(), P::default())
+    }
+}
+
+unsafe impl<K: Send, V: Send, W: Send, O: Send, P: Send> Send for Treap<K, V, W, O, P> {}
+unsafe impl<K: Sync, V: Sync, W: Sync, O: Sync, P: Sync> Sync for Treap<K, V, W, O, P> {}
+
+impl<'a, K: CollKey + Clone + 'a, V: Clone + 'a, W: Clone + 'a, O: Observer<K> + Clone, P: Clone> Clone
+    for Treap<K, V, W, O, P>
+{
+    fn clone(&self) -> Self {
+        Self {
+            root: unsafe { clone_subtree(self.root, TreapNode::clone_node) },
+            observer: self.observer.clone(),
+            priority: self.priority.clone(),
+        }
+    }
 }
 
-impl<'a, K: CollKey + 'a, V: 'a> Dictionary<K, V> for Treap<K, V> {
+impl<K, V, W, O, P> Drop for Treap<K, V, W, O, P> {
+    fn drop(&mut self) {
+        unsafe {
+            TreapNode::drop_subtree(self.root);
+        }
+    }
+}
+
+impl_std_traits!(Treap<K, V, usize, O, P>, P: Priority<K>);
+impl_stats!(Treap<K, V, usize, StatsObserver>);
+
+impl<'a, K: CollKey + 'a, V: 'a, O: Observer<K>, P: Priority<K>> Dictionary<K, V> for Treap<K, V, usize, O, P> {
     fn insert(&mut self, key: K, value: V) -> bool {
-        self.insert_(key, value, random::<usize>())
+        let priority = self.priority.priority(&key);
+
+        self.insert_(key, value, priority)
     }
 
     fn remove(&mut self, key: &K) -> Option<V> {
@@ -415,7 +622,7 @@ impl<'a, K: CollKey + 'a, V: 'a> Dictionary<K, V> for Treap<K, V> {
         self.basic_lookup_mut(income_key)
     }
 
-    fn self_validate(&self) -> Result<(), Box<dyn std::error::Error>> {
+    fn self_validate(&self) -> Result<(), Box<dyn core::error::Error>> {
         self.basic_self_validate()?;
 
         if !self.root.is_null() {
@@ -448,10 +655,13 @@ impl<K: CollKey, W: CollKey> Heap<K, W> for Treap<K, (), W> {
             return None;
         }
 
+        // the root has the top weight, remove it without borrowing its key
         unsafe {
-            let key = &*(*self.root).key;
+            let root = self.root;
+
+            self.reset_root(Self::join((*root).left, (*root).right));
 
-            self.remove_(key).map(|node| node.weight)
+            Some(Box::from_raw(root).weight)
         }
     }
 
@@ -470,6 +680,79 @@ mod test {
         test_dict!(Treap::new());
     }
 
+    #[test]
+    fn test_treap_randomdata_seeded() {
+        use rand::{rngs::StdRng, SeedableRng};
+
+        test_dict!(Treap::with_rng(StdRng::seed_from_u64(7)));
+        test_dict!(Treap::with_key_hash());
+    }
+
+    #[test]
+    fn test_treap_priority() {
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        use crate::raw::same_shape;
+
+        let mut rng = StdRng::seed_from_u64(42);
+        let keys: Vec<u64> = (0..crate::test_size(1000, 50))
+            .map(|_| rng.gen_range(0, 10000))
+            .collect();
+
+        // the same seed, the same shape
+        let mut x = Treap::with_rng(StdRng::seed_from_u64(7));
+        let mut y = Treap::with_rng(StdRng::seed_from_u64(7));
+
+        for &k in keys.iter() {
+            x.insert(k, k);
+            y.insert(k, k);
+        }
+
+        x.self_validate().unwrap();
+        assert!(unsafe { same_shape(x.root(), y.root()) });
+
+        // the same keys, the same shape in any order
+        let mut x = Treap::with_key_hash();
+        let mut y = Treap::with_key_hash();
+
+        for &k in keys.iter() {
+            x.insert(k, k);
+        }
+
+        for &k in keys.iter().rev() {
+            y.insert(k, k);
+        }
+
+        x.self_validate().unwrap();
+        assert!(unsafe { same_shape(x.root(), y.root()) });
+
+        // the highest on the top
+        let mut treap = Treap::new();
+
+        for (i, k) in [5, 3, 8, 1].into_iter().enumerate() {
+            assert!(treap.insert_with_priority(k, (), i));
+        }
+
+        assert!(!treap.insert_with_priority(5, (), 100));
+        assert_eq!(unsafe { (*treap.root).key }, 1);
+        treap.self_validate().unwrap();
+    }
+
+    #[test]
+    fn test_treap_send_sync() {
+        test_send_sync!(Treap::new());
+    }
+
+    #[test]
+    fn test_treap_clone() {
+        test_clone!(Treap::new());
+    }
+
+    #[test]
+    fn test_treap_std_traits() {
+        test_std_traits!(Treap::new());
+    }
+
     #[test]
     fn test_treap_fixeddata_case_0() {
         let mut treap = Treap::<i32, ()>::new();
@@ -570,9 +853,14 @@ mod test {
 
     #[test]
     fn test_treap_bulk_load() {
-        let mut seq = (0..1000).map(|i| (i, (), random()));
+        let mut seq = (0..1000).map(|i| (i, (), rand::random()));
 
         let treap = Treap::bulk_load(&mut seq);
         treap.self_validate().unwrap();
     }
+
+    #[test]
+    fn test_treap_remove_range() {
+        test_remove_range!(Treap::new());
+    }
 }
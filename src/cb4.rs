@@ -0,0 +1,683 @@
+//! Concurrent 2-3-4 tree, readers and writers go down with latch crabbing on per-node latches.
+//!
+//! The splits (B4 `promote`) and the borrows/merges (B4 `unpromote`) are done proactively on the way down,
+//! so a writer never comes back up and releases the parent as soon as the child is safe:
+//! a child to insert into isn't full and a child to remove from has more than one key.
+//! Only the root split and the root merge hold the root pointer.
+//!
+//! ref: Bayer and Schkolnick, Concurrency of Operations on B-Trees (1977)
+
+use core::{
+    cell::UnsafeCell,
+    mem::replace,
+    ptr::null_mut,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::{b4::B4Arrays, *};
+
+
+////////////////////////////////////////////////////////////////////////////////
+//// Structs
+
+/// 2-3-4 Tree shared by threads, all operations take `&self`.
+pub struct CB4<K, V> {
+    /// guards the root pointer
+    latch: Latch,
+    root: UnsafeCell<*mut CB4Node<K, V>>,
+    len: AtomicUsize,
+}
+
+/// Accessed only under its latch and field by field,
+/// never as a whole `&mut`, as the others may be spinning on the latch inside.
+struct CB4Node<K, V> {
+    latch: Latch,
+    /// three items at most, the slot to spare is of `B4Arrays`
+    keys: Array<K, 4>,
+    values: Array<V, 4>,
+    children: Array<*mut Self, 5>,
+}
+
+/// What to remove from a subtree
+enum Target<'k, K> {
+    Key(&'k K),
+    Min,
+    Max,
+}
+
+
+////////////////////////////////////////////////////////////////////////////////
+//// Implement
+
+impl<K, V> B4Arrays<K, V> for CB4Node<K, V> {
+    fn new_empty() -> *mut Self {
+        Box::into_raw(Box::new(Self {
+            latch: Latch::new(),
+            keys: Array::new(),
+            values: Array::new(),
+            children: Array::new(),
+        }))
+    }
+
+    unsafe fn keys<'n>(x: *mut Self) -> &'n mut Array<K, 4> {
+        &mut (*x).keys
+    }
+
+    unsafe fn values<'n>(x: *mut Self) -> &'n mut Array<V, 4> {
+        &mut (*x).values
+    }
+
+    unsafe fn children<'n>(x: *mut Self) -> &'n mut Array<*mut Self, 5> {
+        &mut (*x).children
+    }
+
+    unsafe fn link(_x: *mut Self, _child: *mut Self) {}
+}
+
+
+impl<K, V> CB4Node<K, V> {
+    /// Free the whole subtree, return the number of freed nodes.
+    unsafe fn drop_subtree(x: *mut Self) -> usize {
+        let mut stack = vec![x];
+        let mut cnt = 0;
+
+        while let Some(x) = stack.pop() {
+            if x.is_null() {
+                continue;
+            }
+
+            let node = Box::from_raw(x);
+
+            stack.extend(node.children.iter().cloned());
+
+            cnt += 1;
+        }
+
+        cnt
+    }
+
+    unsafe fn is_leaf(x: *mut Self) -> bool {
+        (*x).children.is_empty()
+    }
+
+    unsafe fn is_full(x: *mut Self) -> bool {
+        (*x).keys.len() == 3
+    }
+
+    /// Put the item at idx, return the old one
+    unsafe fn replace_item(x: *mut Self, idx: usize, key: K, value: V) -> (K, V) {
+        let keys = &mut (*x).keys;
+        let values = &mut (*x).values;
+
+        (replace(&mut keys[idx], key), replace(&mut values[idx], value))
+    }
+
+    /// Split the full child at idx, its middle item goes up to x.
+    ///
+    /// Both are write latched, the new right sibling isn't.
+    unsafe fn split_child(x: *mut Self, idx: usize) {
+        let y = (&(*x).children)[idx];
+        // the children after the items go with them, so the middle one is the first of z
+        let z = CB4Node::split_off(y, 1);
+
+        (*x).keys.insert(idx, (*z).keys.pop_front().unwrap());
+        (*x).values.insert(idx, (*z).values.pop_front().unwrap());
+        (*x).children.insert(idx + 1, z);
+    }
+
+    /// Make the child at idx have more than one key, by borrowing from or merging with a sibling,
+    /// return the child to go on with.
+    ///
+    /// x and the child are write latched, so is the returned one.
+    /// A merged away sibling is freed, no one else could reach it as x is latched.
+    unsafe fn fill_child(x: *mut Self, idx: usize) -> *mut Self {
+        let c = (&(*x).children)[idx];
+
+        if (*c).keys.len() > 1 {
+            return c;
+        }
+
+        if idx > 0 {
+            let sibling = (&(*x).children)[idx - 1];
+            (*sibling).latch.write();
+
+            if (*sibling).keys.len() > 1 {
+                CB4Node::borrow_left(x, idx);
+                (*sibling).latch.write_unlock();
+
+                return c;
+            }
+
+            // the last child
+            if idx + 1 == (*x).children.len() {
+                CB4Node::merge_children(x, idx - 1);
+
+                return sibling;
+            }
+
+            (*sibling).latch.write_unlock();
+        }
+
+        let sibling = (&(*x).children)[idx + 1];
+        (*sibling).latch.write();
+
+        if (*sibling).keys.len() > 1 {
+            CB4Node::borrow_right(x, idx);
+            (*sibling).latch.write_unlock();
+        } else {
+            CB4Node::merge_children(x, idx);
+        }
+
+        c
+    }
+}
+
+
+impl<'a, K: CollKey + 'a, V: 'a> CB4Node<K, V> {
+    /// Ok if the target is the item at idx of x, or else Err with the child to go
+    unsafe fn locate(x: *mut Self, target: &Target<K>) -> Result<usize, usize> {
+        match target {
+            Target::Key(key) => (*x).keys.binary_search(key),
+            Target::Min if CB4Node::is_leaf(x) => Ok(0),
+            Target::Min => Err(0),
+            Target::Max if CB4Node::is_leaf(x) => Ok((*x).keys.len() - 1),
+            Target::Max => Err((*x).children.len() - 1),
+        }
+    }
+
+    /// Return the depth of the leaves and the number of items
+    unsafe fn self_validate(x: *mut Self, lower: Option<&K>, upper: Option<&K>) -> (usize, usize) {
+        let keys = &(*x).keys;
+
+        assert!((*x).latch.is_free());
+        assert!(!keys.is_empty());
+        assert_eq!(keys.len(), (*x).values.len());
+        assert!(keys.windows(2).all(|w| w[0] < w[1]));
+        assert!(lower.is_none_or(|lower| lower < &keys[0]));
+        assert!(upper.is_none_or(|upper| &keys[keys.len() - 1] < upper));
+
+        if CB4Node::is_leaf(x) {
+            return (0, keys.len());
+        }
+
+        assert_eq!((*x).children.len(), keys.len() + 1);
+
+        let mut depth = None;
+        let mut cnt = keys.len();
+
+        for (i, &child) in (*x).children.iter().enumerate() {
+            let lower = if i == 0 { lower } else { Some(&keys[i - 1]) };
+            let upper = if i == keys.len() { upper } else { Some(&keys[i]) };
+
+            let (child_depth, child_cnt) = CB4Node::self_validate(child, lower, upper);
+
+            assert_eq!(*depth.get_or_insert(child_depth), child_depth, "leaves at uneven depths");
+            cnt += child_cnt;
+        }
+
+        (depth.unwrap() + 1, cnt)
+    }
+}
+
+
+impl<'a, K: CollKey + 'a, V: 'a> CB4<K, V> {
+    pub fn new() -> Self {
+        Self {
+            latch: Latch::new(),
+            root: UnsafeCell::new(null_mut()),
+            len: AtomicUsize::new(0),
+        }
+    }
+
+    /// Return false if the key exists (the value isn't replaced)
+    pub fn insert(&self, key: K, value: V) -> bool {
+        unsafe {
+            self.latch.write();
+
+            let root = *self.root.get();
+
+            if root.is_null() {
+                let x = CB4Node::new_empty();
+                (*x).keys.push_back(key);
+                (*x).values.push_back(value);
+
+                *self.root.get() = x;
+                self.latch.write_unlock();
+                self.len.fetch_add(1, Ordering::Relaxed);
+
+                return true;
+            }
+
+            (*root).latch.write();
+
+            let mut x = root;
+
+            if CB4Node::is_full(root) {
+                // the only split that holds the root pointer
+                x = CB4Node::new_empty();
+                (*x).latch.write();
+                (*x).children.push_back(root);
+
+                CB4Node::split_child(x, 0);
+                (*root).latch.write_unlock();
+
+                *self.root.get() = x;
+            }
+
+            self.latch.write_unlock();
+
+            // x is write latched and not full
+            loop {
+                let idx = match (*x).keys.binary_search(&key) {
+                    Ok(_) => {
+                        (*x).latch.write_unlock();
+                        return false;
+                    }
+                    Err(idx) => idx,
+                };
+
+                if CB4Node::is_leaf(x) {
+                    (*x).keys.insert(idx, key);
+                    (*x).values.insert(idx, value);
+                    (*x).latch.write_unlock();
+                    self.len.fetch_add(1, Ordering::Relaxed);
+
+                    return true;
+                }
+
+                let mut c = (&(*x).children)[idx];
+                (*c).latch.write();
+
+                if CB4Node::is_full(c) {
+                    CB4Node::split_child(x, idx);
+
+                    if key == (&(*x).keys)[idx] {
+                        (*c).latch.write_unlock();
+                        (*x).latch.write_unlock();
+
+                        return false;
+                    }
+
+                    if key > (&(*x).keys)[idx] {
+                        let sibling = (&(*x).children)[idx + 1];
+                        (*sibling).latch.write();
+                        (*c).latch.write_unlock();
+
+                        c = sibling;
+                    }
+                }
+
+                (*x).latch.write_unlock();
+                x = c;
+            }
+        }
+    }
+
+    pub fn remove(&self, key: &K) -> Option<V> {
+        unsafe {
+            self.latch.write();
+
+            let root = *self.root.get();
+
+            if root.is_null() {
+                self.latch.write_unlock();
+                return None;
+            }
+
+            (*root).latch.write();
+
+            let (_, value) = self.remove_(root, Target::Key(key), true)?;
+            self.len.fetch_sub(1, Ordering::Relaxed);
+
+            Some(value)
+        }
+    }
+
+    /// x is write latched and has more than one key unless it's the root,
+    /// the root pointer is write latched as well on `at_root`.
+    ///
+    /// Everything is unlatched on return.
+    unsafe fn remove_(
+        &self,
+        mut x: *mut CB4Node<K, V>,
+        target: Target<K>,
+        mut at_root: bool,
+    ) -> Option<(K, V)> {
+        loop {
+            let c = match CB4Node::locate(x, &target) {
+                Ok(idx) if CB4Node::is_leaf(x) => {
+                    let item = ((*x).keys.remove(idx).unwrap(), (*x).values.remove(idx).unwrap());
+
+                    if at_root && (*x).keys.is_empty() {
+                        *self.root.get() = null_mut();
+                        drop(Box::from_raw(x));
+                    } else {
+                        (*x).latch.write_unlock();
+                    }
+
+                    if at_root {
+                        self.latch.write_unlock();
+                    }
+
+                    return Some(item);
+                }
+                Ok(idx) => {
+                    let y = (&(*x).children)[idx];
+                    (*y).latch.write();
+
+                    let mut sibling_target = None;
+
+                    if (*y).keys.len() > 1 {
+                        sibling_target = Some((y, Target::Max));
+                    } else {
+                        let z = (&(*x).children)[idx + 1];
+                        (*z).latch.write();
+
+                        if (*z).keys.len() > 1 {
+                            (*y).latch.write_unlock();
+                            sibling_target = Some((z, Target::Min));
+                        }
+                    }
+
+                    // replace it with the precessor or the successor
+                    if let Some((sibling, sibling_target)) = sibling_target {
+                        let (key, value) = self.remove_(sibling, sibling_target, false).unwrap();
+
+                        let item = CB4Node::replace_item(x, idx, key, value);
+
+                        (*x).latch.write_unlock();
+
+                        if at_root {
+                            self.latch.write_unlock();
+                        }
+
+                        return Some(item);
+                    }
+
+                    // or else merge it down, freeing the right one (x is latched)
+                    CB4Node::merge_children(x, idx);
+
+                    y
+                }
+                Err(_) if CB4Node::is_leaf(x) => {
+                    (*x).latch.write_unlock();
+
+                    if at_root {
+                        self.latch.write_unlock();
+                    }
+
+                    return None;
+                }
+                Err(idx) => {
+                    let c = (&(*x).children)[idx];
+                    (*c).latch.write();
+
+                    CB4Node::fill_child(x, idx)
+                }
+            };
+
+            if at_root && (*x).keys.is_empty() {
+                // the only merge that holds the root pointer
+                *self.root.get() = c;
+                drop(Box::from_raw(x));
+            } else {
+                (*x).latch.write_unlock();
+
+                if at_root {
+                    self.latch.write_unlock();
+                    at_root = false;
+                }
+            }
+
+            x = c;
+        }
+    }
+
+    pub fn get(&self, key: &K) -> Option<V>
+    where
+        V: Clone,
+    {
+        unsafe {
+            self.latch.read();
+
+            let mut x = *self.root.get();
+
+            if x.is_null() {
+                self.latch.read_unlock();
+                return None;
+            }
+
+            (*x).latch.read();
+            self.latch.read_unlock();
+
+            loop {
+                let res = match (*x).keys.binary_search(key) {
+                    Ok(idx) => Some((&(*x).values)[idx].clone()),
+                    Err(_) if CB4Node::is_leaf(x) => None,
+                    Err(idx) => {
+                        let c = (&(*x).children)[idx];
+                        (*c).latch.read();
+                        (*x).latch.read_unlock();
+
+                        x = c;
+                        continue;
+                    }
+                };
+
+                (*x).latch.read_unlock();
+
+                return res;
+            }
+        }
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        unsafe {
+            self.latch.read();
+
+            let mut x = *self.root.get();
+
+            if x.is_null() {
+                self.latch.read_unlock();
+                return false;
+            }
+
+            (*x).latch.read();
+            self.latch.read_unlock();
+
+            loop {
+                let res = match (*x).keys.binary_search(key) {
+                    Ok(_) => true,
+                    Err(_) if CB4Node::is_leaf(x) => false,
+                    Err(idx) => {
+                        let c = (&(*x).children)[idx];
+                        (*c).latch.read();
+                        (*x).latch.read_unlock();
+
+                        x = c;
+                        continue;
+                    }
+                };
+
+                (*x).latch.read_unlock();
+
+                return res;
+            }
+        }
+    }
+
+    /// Exclusive, as a tree under change can't be checked as a whole.
+    pub fn self_validate(&mut self) -> Result<(), Box<dyn core::error::Error>> {
+        let root = *self.root.get_mut();
+
+        assert!(self.latch.is_free());
+
+        let cnt = if root.is_null() {
+            0
+        } else {
+            unsafe { CB4Node::self_validate(root, None, None).1 }
+        };
+
+        assert_eq!(cnt, *self.len.get_mut());
+
+        Ok(())
+    }
+}
+
+
+impl<K, V> Coll for CB4<K, V> {
+    fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+}
+
+impl<'a, K: CollKey + 'a, V: 'a> Default for CB4<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl<K: Send, V: Send> Send for CB4<K, V> {}
+unsafe impl<K: Send + Sync, V: Send + Sync> Sync for CB4<K, V> {}
+
+impl<K, V> Drop for CB4<K, V> {
+    fn drop(&mut self) {
+        unsafe {
+            CB4Node::drop_subtree(*self.root.get_mut());
+        }
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, thread};
+
+    use super::*;
+
+    #[test]
+    fn test_cb4_randomdata() {
+        let mut dict = CB4::new();
+        let mut ref_map = BTreeMap::new();
+
+        for i in 0..crate::test_size(20000u64, 600) {
+            let k = rand::random::<u64>() % 500;
+
+            if rand::random::<bool>() {
+                let is_new = !ref_map.contains_key(&k);
+
+                if is_new {
+                    ref_map.insert(k, i);
+                }
+
+                assert_eq!(dict.insert(k, i), is_new);
+            } else {
+                assert_eq!(dict.remove(&k), ref_map.remove(&k));
+            }
+
+            if i % 100 == 0 {
+                dict.self_validate().unwrap();
+            }
+        }
+
+        dict.self_validate().unwrap();
+
+        for k in 0..500 {
+            assert_eq!(dict.get(&k), ref_map.get(&k).cloned());
+        }
+    }
+
+    /// Each thread owns the keys of its residue, readers go along
+    #[test]
+    fn test_cb4_stress_disjoint() {
+        let threads = crate::test_size(8u64, 3);
+        let n = crate::test_size(4000u64, 40);
+        let mut dict = CB4::new();
+
+        thread::scope(|s| {
+            for t in 0..threads {
+                let dict = &dict;
+
+                s.spawn(move || {
+                    let keys = (0..n).map(|i| i * threads + t);
+
+                    for k in keys.clone() {
+                        assert!(dict.insert(k, k + 1));
+                        assert_eq!(dict.get(&k), Some(k + 1));
+                    }
+
+                    for k in keys.clone().filter(|k| k % 3 == 0) {
+                        assert_eq!(dict.remove(&k), Some(k + 1));
+                        assert!(!dict.contains_key(&k));
+                    }
+
+                    for k in keys {
+                        assert_eq!(dict.get(&k), (k % 3 != 0).then_some(k + 1));
+                    }
+                });
+
+                s.spawn(move || {
+                    for _ in 0..n {
+                        let k = rand::random::<u64>() % (n * threads);
+
+                        if let Some(v) = dict.get(&k) {
+                            assert_eq!(v, k + 1);
+                        }
+                    }
+                });
+            }
+        });
+
+        dict.self_validate().unwrap();
+
+        let total = n * threads;
+
+        assert_eq!(dict.len() as u64, (0..total).filter(|k| k % 3 != 0).count() as u64);
+
+        for k in 0..total {
+            assert_eq!(dict.get(&k), (k % 3 != 0).then_some(k + 1));
+        }
+    }
+
+    /// All threads fight over a few keys, splits and merges keep going at the root
+    #[test]
+    fn test_cb4_stress_contended() {
+        let threads = crate::test_size(8, 3);
+        let n = crate::test_size(10000, 100);
+        let range = 64u64;
+        let mut dict = CB4::new();
+
+        thread::scope(|s| {
+            for _ in 0..threads {
+                let dict = &dict;
+
+                s.spawn(move || {
+                    for _ in 0..n {
+                        let k = rand::random::<u64>() % range;
+
+                        match rand::random::<u8>() % 3 {
+                            0 => {
+                                dict.insert(k, k);
+                            }
+                            1 => {
+                                if let Some(v) = dict.remove(&k) {
+                                    assert_eq!(v, k);
+                                }
+                            }
+                            _ => {
+                                if let Some(v) = dict.get(&k) {
+                                    assert_eq!(v, k);
+                                }
+                            }
+                        }
+                    }
+                });
+            }
+        });
+
+        dict.self_validate().unwrap();
+
+        assert_eq!(dict.len(), (0..range).filter(|k| dict.contains_key(k)).count());
+    }
+}
@@ -0,0 +1,256 @@
+//! Differential tests, random interleaved ops on each tree and on `BTreeMap` side by side,
+//! comparing the answers, the iteration order and `self_validate` after every op.
+//!
+//! A failing sequence is shrunk to a minimal one and printed with its seed,
+//! `COLL_BT_SEED=<seed> cargo test differential` runs just that seed again.
+
+use core::mem::ManuallyDrop;
+use std::{
+    collections::BTreeMap,
+    env,
+    panic::{self, AssertUnwindSafe},
+};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    b3::B3,
+    b4::B4,
+    bst::{aa::AA, avl::AVL, llrb::LLRB, lsg::LSG, rawst::RawST, rb::RB, splay::Splay, treap},
+    bstar4::BStar4,
+    *,
+};
+
+
+/// Priorities from the key hash, so that a seed (and each shrink run) builds the same shapes
+type Treap<K, V> = treap::Treap<K, V, usize, (), KeyHash>;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Insert(u64, u64),
+    Remove(u64),
+    Modify(u64, u64),
+    Get(u64),
+    /// add the value through the reference
+    GetMut(u64, u64),
+    /// `lo..hi`
+    Range(u64, u64),
+}
+
+impl Op {
+    /// The same op with a smaller key or value
+    fn simpler(self) -> Vec<Op> {
+        let keys = |k: u64| [0, k / 2, k.saturating_sub(1)].into_iter().filter(move |&x| x < k);
+
+        match self {
+            Op::Insert(k, v) => keys(k).map(|k| Op::Insert(k, v)).chain((v > 0).then_some(Op::Insert(k, 0))).collect(),
+            Op::Remove(k) => keys(k).map(Op::Remove).collect(),
+            Op::Modify(k, v) => keys(k).map(|k| Op::Modify(k, v)).chain((v > 0).then_some(Op::Modify(k, 0))).collect(),
+            Op::Get(k) => keys(k).map(Op::Get).collect(),
+            Op::GetMut(k, v) => keys(k).map(|k| Op::GetMut(k, v)).collect(),
+            Op::Range(lo, hi) => keys(lo).map(|lo| Op::Range(lo, hi)).chain(keys(hi).map(|hi| Op::Range(lo, hi))).collect(),
+        }
+    }
+}
+
+
+fn gen_ops(seed: u64, len: usize) -> Vec<Op> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    // a small key space for hits, a large one for shapes
+    let space = [8, 64, 1024][rng.gen_range(0, 3)];
+
+    (0..len)
+        .map(|_| {
+            let k = rng.gen_range(0, space);
+            let v = rng.gen_range(0, 1000);
+
+            match rng.gen_range(0, 10) {
+                0..=3 => Op::Insert(k, v),
+                4 | 5 => Op::Remove(k),
+                6 => Op::Modify(k, v),
+                7 => Op::Get(k),
+                8 => Op::GetMut(k, v),
+                _ => Op::Range(k, k + rng.gen_range(0, space / 2)),
+            }
+        })
+        .collect()
+}
+
+fn step<D>(dict: &mut D, model: &mut BTreeMap<u64, u64>, op: Op) -> Result<(), String>
+where
+    D: Dictionary<u64, u64> + for<'a> BT<'a, u64, u64>,
+{
+    macro_rules! expect {
+        ($found:expr, $expected:expr) => {{
+            let (found, expected) = ($found, $expected);
+
+            if found != expected {
+                return Err(format!("{op:?} found {found:?}, expected {expected:?}"));
+            }
+        }};
+    }
+
+    match op {
+        Op::Insert(k, v) => {
+            let expected = !model.contains_key(&k);
+
+            if expected {
+                model.insert(k, v);
+            }
+
+            expect!(dict.insert(k, v), expected)
+        }
+        Op::Remove(k) => expect!(dict.remove(&k), model.remove(&k)),
+        Op::Modify(k, v) => expect!(dict.modify(&k, v), model.get_mut(&k).map(|x| *x = v).is_some()),
+        Op::Get(k) => expect!(dict.get(&k), model.get(&k)),
+        Op::GetMut(k, v) => {
+            let add = |x: &mut u64| {
+                *x += v;
+                *x
+            };
+
+            expect!(dict.get_mut(&k).map(add), model.get_mut(&k).map(add))
+        }
+        Op::Range(lo, hi) => expect!(
+            dict.range(lo..hi).collect::<Vec<_>>(),
+            model.range(lo..hi).collect::<Vec<_>>()
+        ),
+    }
+
+    if let Err(err) = dict.self_validate() {
+        return Err(format!("{op:?} left it invalid: {err}"));
+    }
+
+    expect!(dict.iter().collect::<Vec<_>>(), model.iter().collect::<Vec<_>>());
+
+    Ok(())
+}
+
+/// The index of the first op going wrong and why
+fn check<D>(ops: &[Op]) -> Result<(), (usize, String)>
+where
+    D: Default + Dictionary<u64, u64> + for<'a> BT<'a, u64, u64>,
+{
+    // a broken tree may not survive the drop
+    let mut dict = ManuallyDrop::new(D::default());
+    let mut model = BTreeMap::new();
+
+    for (i, op) in ops.iter().enumerate() {
+        match panic::catch_unwind(AssertUnwindSafe(|| step(&mut *dict, &mut model, *op))) {
+            Ok(Ok(())) => (),
+            Ok(Err(msg)) => return Err((i, msg)),
+            Err(_) => return Err((i, format!("{op:?} panicked"))),
+        }
+    }
+
+    unsafe { ManuallyDrop::drop(&mut dict) };
+
+    Ok(())
+}
+
+/// Drop chunks of ops and simplify the rest while it still fails
+fn shrink(mut ops: Vec<Op>, fails: impl Fn(&[Op]) -> bool) -> Vec<Op> {
+    loop {
+        let before = ops.clone();
+        let mut chunk = ops.len() / 2;
+
+        while chunk > 0 {
+            let mut i = 0;
+
+            while i < ops.len() {
+                let mut cand = ops.clone();
+                cand.drain(i..(i + chunk).min(ops.len()));
+
+                if fails(&cand) {
+                    ops = cand;
+                } else {
+                    i += chunk;
+                }
+            }
+
+            chunk /= 2;
+        }
+
+        for i in 0..ops.len() {
+            for op in ops[i].simpler() {
+                let mut cand = ops.clone();
+                cand[i] = op;
+
+                if fails(&cand) {
+                    ops = cand;
+                    break;
+                }
+            }
+        }
+
+        if ops == before {
+            return ops;
+        }
+    }
+}
+
+fn differential<D>(name: &str)
+where
+    D: Default + Dictionary<u64, u64> + for<'a> BT<'a, u64, u64>,
+{
+    let len = crate::test_size(300, 40);
+
+    let seeds: Vec<u64> = match env::var("COLL_BT_SEED") {
+        Ok(seed) => vec![seed.parse().expect("COLL_BT_SEED")],
+        Err(_) => {
+            let base = rand::random::<u64>();
+            (0..crate::test_size(40, 2)).map(|i| base.wrapping_add(i)).collect()
+        }
+    };
+
+    for seed in seeds {
+        let ops = gen_ops(seed, len);
+
+        if let Err((i, _)) = check::<D>(&ops) {
+            let ops = shrink(ops[..=i].to_vec(), |ops| check::<D>(ops).is_err());
+            let (_, msg) = check::<D>(&ops).unwrap_err();
+
+            panic!("{name} fails with COLL_BT_SEED={seed}: {msg}\nminimal ops: {ops:?}");
+        }
+    }
+}
+
+macro_rules! differential {
+    ($($ty:ident),*) => {
+        $(
+            differential::<$ty<u64, u64>>(stringify!($ty));
+        )*
+    };
+}
+
+
+#[test]
+fn test_differential_bst() {
+    differential!(AVL, RB, LLRB, AA, Treap, Splay, LSG, RawST);
+}
+
+#[test]
+fn test_differential_btree() {
+    differential!(B3, B4, BStar4);
+}
+
+#[test]
+fn test_differential_shrink() {
+    // fails once 3 is removed and something above 4 inserted after it
+    let fails = |ops: &[Op]| {
+        ops.iter()
+            .skip_while(|op| !matches!(op, Op::Remove(3)))
+            .any(|op| matches!(op, Op::Insert(k, _) if *k > 4))
+    };
+
+    let mut ops = gen_ops(1, 200);
+    ops.insert(50, Op::Remove(3));
+    ops.push(Op::Insert(900, 7));
+
+    assert!(fails(&ops));
+    assert_eq!(shrink(ops, fails), [Op::Remove(3), Op::Insert(5, 0)]);
+
+    assert_eq!(gen_ops(7, 100), gen_ops(7, 100));
+    assert!(check::<AVL<u64, u64>>(&gen_ops(7, 100)).is_ok());
+}
@@ -0,0 +1,154 @@
+//! Epoch-based reclamation, just enough for one writer at a time retiring what readers may still see.
+//!
+//! A reader pins its slot with the global epoch before loading a shared pointer, and unpins after.
+//! The global epoch moves on only if every pinned slot has seen the current one,
+//! so a pointer unlinked at epoch e isn't reachable by anyone once the global epoch is e + 2.
+//!
+//! ref: Keir Fraser, Practical lock-freedom (2004), section 5.2.3
+
+use core::{
+    ptr::null_mut,
+    sync::atomic::{fence, AtomicBool, AtomicPtr, AtomicUsize, Ordering},
+};
+
+use crate::*;
+
+
+////////////////////////////////////////////////////////////////////////////////
+//// Structs
+
+pub(crate) struct Collector {
+    epoch: AtomicUsize,
+    /// never shrinks until the collector drops, a released slot is reused
+    slots: AtomicPtr<Slot>,
+}
+
+struct Slot {
+    /// 0 if unpinned, or else epoch << 1 | 1
+    state: AtomicUsize,
+    in_use: AtomicBool,
+    next: *mut Slot,
+}
+
+/// Registered slot of a reader
+pub(crate) struct Handle<'c> {
+    slot: &'c Slot,
+}
+
+
+////////////////////////////////////////////////////////////////////////////////
+//// Implement
+
+impl Collector {
+    pub(crate) fn new() -> Self {
+        Self {
+            epoch: AtomicUsize::new(0),
+            slots: AtomicPtr::new(null_mut()),
+        }
+    }
+
+    pub(crate) fn epoch(&self) -> usize {
+        self.epoch.load(Ordering::SeqCst)
+    }
+
+    /// Reuse a released slot or push a new one (lock-free).
+    pub(crate) fn register(&self) -> Handle<'_> {
+        let mut x = self.slots.load(Ordering::Acquire);
+
+        while !x.is_null() {
+            let slot = unsafe { &*x };
+
+            if !slot.in_use.load(Ordering::Relaxed)
+                && slot
+                    .in_use
+                    .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+            {
+                return Handle { slot };
+            }
+
+            x = slot.next;
+        }
+
+        let x = Box::into_raw(Box::new(Slot {
+            state: AtomicUsize::new(0),
+            in_use: AtomicBool::new(true),
+            next: null_mut(),
+        }));
+
+        let mut head = self.slots.load(Ordering::Relaxed);
+
+        loop {
+            unsafe { (*x).next = head };
+
+            match self
+                .slots
+                .compare_exchange_weak(head, x, Ordering::Release, Ordering::Relaxed)
+            {
+                Ok(_) => return Handle { slot: unsafe { &*x } },
+                Err(cur) => head = cur,
+            }
+        }
+    }
+
+    /// Advance the global epoch if all pinned slots are at it, return the global epoch.
+    ///
+    /// Garbage retired at epoch e can be freed if it returns e + 2 or more.
+    pub(crate) fn try_advance(&self) -> usize {
+        let epoch = self.epoch();
+        let mut x = self.slots.load(Ordering::Acquire);
+
+        while !x.is_null() {
+            let slot = unsafe { &*x };
+            let state = slot.state.load(Ordering::SeqCst);
+
+            if state & 1 == 1 && state >> 1 != epoch {
+                return epoch;
+            }
+
+            x = slot.next;
+        }
+
+        // the only advancer is the writer, no race here
+        self.epoch.store(epoch + 1, Ordering::SeqCst);
+
+        epoch + 1
+    }
+}
+
+impl Drop for Collector {
+    fn drop(&mut self) {
+        let mut x = *self.slots.get_mut();
+
+        while !x.is_null() {
+            let slot = unsafe { Box::from_raw(x) };
+            x = slot.next;
+        }
+    }
+}
+
+
+// `next` is set before the slot is published and never after
+unsafe impl Sync for Slot {}
+
+
+impl Handle<'_> {
+    /// Wait-free, the pointers loaded after it stay valid until `unpin`.
+    pub(crate) fn pin(&self, collector: &Collector) {
+        let epoch = collector.epoch();
+
+        self.slot.state.store(epoch << 1 | 1, Ordering::SeqCst);
+        fence(Ordering::SeqCst);
+    }
+
+    pub(crate) fn unpin(&self) {
+        self.slot.state.store(0, Ordering::Release);
+    }
+}
+
+impl Drop for Handle<'_> {
+    fn drop(&mut self) {
+        self.slot.state.store(0, Ordering::Release);
+        self.slot.in_use.store(false, Ordering::Release);
+    }
+}
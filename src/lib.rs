@@ -1,35 +1,77 @@
-#![feature(trait_alias)]
-#![feature(box_syntax)]
-#![feature(macro_metavar_expr)]
-#![feature(is_sorted)]
-#![feature(int_roundings)]
-#![feature(trait_upcasting)]
-
-
 //! B-Tree alias as M-ary Tree,
 //! Bayer and McCreight never explained what, if anything, the B stands for: Boeing, balanced, broad, bushy, and Bayer have been suggested.
 //! McCreight has said that "the more you think about what the B in B-trees means, the better you understand B-trees.
-/// According to Knuth's definition, a B-tree of order m is a tree which satisfies the following properties:
-/// 1. Every node has at most m children.
-/// 1. Every non-leaf node (except root) has at least ⌈m/2⌉ child nodes.
-/// 1. The root has at least two children if it is not a leaf node.
-/// 1. A non-leaf node with k children contains k − 1 keys.
-/// 1. All leaves appear in the same level and carry no information.
-/// Here, It's not restrict B-Tree as it save key-value in internal node instead of leaf(nil),
-/// we do it just for comparison convenience with other BT impl with Dictionary Trait
-
-
-use std::{fmt::Debug, fmt::Write, collections::{VecDeque, BinaryHeap}};
-
-use self::bst::{BSTNode, BST};
+//! According to Knuth's definition, a B-tree of order m is a tree which satisfies the following properties:
+//! 1. Every node has at most m children.
+//! 1. Every non-leaf node (except root) has at least ⌈m/2⌉ child nodes.
+//! 1. The root has at least two children if it is not a leaf node.
+//! 1. A non-leaf node with k children contains k − 1 keys.
+//! 1. All leaves appear in the same level and carry no information.
+//!
+//! Here, It's not restrict B-Tree as it save key-value in internal node instead of leaf(nil),
+//! we do it just for comparison convenience with other BT impl with Dictionary Trait
+//!
+//! The crate is `no_std` and only needs `alloc`, the default `std` feature adds the stdout echo helpers
+//! and `rand` for the default Treap priorities (a global xorshift stands in without it),
+//! see `priority` for the reproducible ones.
+//!
+//! The test suite also runs under Miri (`cargo +nightly miri test`) with smaller sizes,
+//! nodes keep the pointer they were allocated with for that, see `BTNode::itself`.
+//!
+//! The `testing` feature publishes the checks the trees are tested with, for implementations
+//! of `Dictionary`, `Heap` and `BT` elsewhere.
+//!
+//! `cargo run --release --bin bench -- --help` compares the trees on the same workloads.
+
+// Two layout lints only, the rest are allowed item by item where needed.
+#![allow(clippy::extra_unused_lifetimes, reason = "the `impl<'a, K: CollKey + 'a, V: 'a>` header is kept uniform")]
+#![allow(clippy::four_forward_slashes, reason = "`////` marks the sections of a file")]
+#![no_std]
+
+extern crate alloc;
+#[cfg(any(feature = "std", test))]
+extern crate std;
+
+
+use alloc::collections::BinaryHeap;
+use core::fmt::Debug;
+
+use either::Either;
 
 pub mod bst;
 pub mod b3;
 pub mod b4;
 pub mod bstar4;
+pub mod cb4;
+pub mod snapshot;
+pub mod sharded;
+pub mod transaction;
+pub mod stats;
+pub mod priority;
+pub mod trace;
+pub mod map;
+pub mod set;
+pub mod raw;
 mod aux;
+mod epoch;
+#[cfg(test)]
+mod differential;
+#[cfg(any(feature = "testing", test))]
+pub mod testing;
 
 pub(crate) use aux::*;
+pub(crate) use alloc::{
+    boxed::Box,
+    format,
+    string::String,
+    vec,
+    vec::Vec,
+};
+#[cfg(any(feature = "std", test))]
+pub(crate) use std::println;
+pub(crate) use raw::{clone_subtree, impl_std_traits, BSTNode, BTItem, BTNode, BST, BT};
+pub(crate) use stats::{impl_stats, Stats, StatsObserver};
+pub(crate) use priority::*;
 
 ////////////////////////////////////////////////////////////////////////////////
 //// Common Trait
@@ -58,1278 +100,695 @@ pub trait Dictionary<K: CollKey, V> {
     fn get_mut(&mut self, key: &K) -> Option<&mut V>;
 
     // check if dict's structure looks like it's expected.
-    fn self_validate(&self) -> Result<(), Box<dyn std::error::Error>>;
-}
+    fn self_validate(&self) -> Result<(), Box<dyn core::error::Error>>;
 
-pub trait CollKey = Ord + Debug;
+    /// Run the updates of `f` all or nothing, they're rolled back if it returns Err or panics.
+    fn transaction<T, E, F>(&mut self, f: F) -> Result<T, E>
+    where
+        Self: Sized,
+        K: Clone,
+        V: Clone,
+        F: FnOnce(&mut transaction::Transaction<'_, K, V, Self>) -> Result<T, E>,
+    {
+        let mut txn = transaction::Transaction::new(self);
+        let res = f(&mut txn);
 
+        if res.is_ok() {
+            txn.commit();
+        }
 
-pub trait Coll {
-    fn len(&self) -> usize;
-
-    fn is_empty(&self) -> bool {
-        self.len() == 0
+        res
     }
 }
 
 
-pub trait Heap<K: CollKey, T: CollKey>: Coll {
-    // max for max-heap and min for min-heap respectly.
-    fn top(&self) -> Option<&T>;
+/// Dictionary allows duplicate keys,
+/// values of the same key keep the insertion order.
+pub trait MultiDictionary<K: CollKey, V> {
+    /// always succeed
+    fn insert(&mut self, key: K, value: V);
 
-    fn pop(&mut self) -> Option<T>;
-
-    fn push(&mut self, key: K, val: T);
-}
+    /// remove the earliest inserted one
+    fn remove_one(&mut self, key: &K) -> Option<V>;
 
+    /// in insertion order
+    fn remove_all(&mut self, key: &K) -> Vec<V> {
+        let mut res = vec![];
 
-/// B-Tree (not restrictly, storing info in the internal node)
-pub trait BT<'a, K: CollKey + 'a, V: 'a>: Dictionary<K, V> {
-    fn order(&self) -> usize;  // >= 2
-    fn root(&self) -> *mut (dyn BTNode<'a, K, V> + 'a);
-    fn assign_root(&mut self, root: *mut (dyn BTNode<'a, K, V> + 'a));
-    fn reset_root(&mut self, root: *mut (dyn BTNode<'a, K, V> + 'a)) {
-        unsafe {
-            if !root.is_null() {
-                (*root).assign_paren((*root).null_mut());
-            }
+        while let Some(value) = self.remove_one(key) {
+            res.push(value);
         }
 
-        self.assign_root(root);
-    }
-
-    /// alias as transplant
-    fn subtree_shift(
-        &mut self,
-        u: *mut (dyn BTNode<'a, K, V> + 'a),
-        v: *mut (dyn BTNode<'a, K, V> + 'a),
-    ) {
-        unsafe {
-            let u_paren = (*u).paren();
-
-            if u_paren.is_null() {
-                self.assign_root(v);
-            } else {
-                let u_idx = (*u_paren).index_of_child(u);
-                (*u_paren).assign_child(v, u_idx);
-            }
-
-            if !v.is_null() {
-                (*v).assign_paren(u_paren)
-            }
-        }
+        res
     }
 
-    // ////////////////////////////////////////////////////////////////////////////
-    // //// Introspection
-    // fn try_as_bst(&self) -> Result<*const (dyn BST<'a, K, V> + 'a), ()>;
-    // fn try_as_bst_mut(&self) -> Result<*mut (dyn BST<'a, K, V> + 'a), ()> {
-    //     if let Ok(p) = self.try_as_bst() {
-    //         Ok(p as *mut (dyn BST<'a, K, V> + 'a))
-    //     } else {
-    //         Err(())
-    //     }
-    // }
-
-    fn root_bst(&self) -> *mut (dyn BSTNode<'a, K, V> + 'a) {
-        unsafe { (*self.root()).try_as_bst_mut().unwrap() }
-    }
+    /// in insertion order
+    fn get_all<'b>(&'b self, key: &K) -> Box<dyn Iterator<Item = &'b V> + 'b>;
 
-    fn minimum(&self) -> *mut (dyn BTNode<'a, K, V> + 'a) {
-        unsafe{
-            if self.root().is_null() {
-                self.root()
-            } else {
-                (*self.root()).minimum()
-            }
-        }
+    fn count(&self, key: &K) -> usize {
+        self.get_all(key).count()
     }
 
+    /// The keys are in order on infix order, an equal one may be on either side of a key.
+    fn self_validate(&self) -> Result<(), Box<dyn core::error::Error>>;
+}
 
-    fn maximum(&self) -> *mut (dyn BTNode<'a, K, V> + 'a) {
-        unsafe {
-            if self.root().is_null() {
-                self.root()
-            } else {
-                (*self.root()).maximum()
-            }
-        }
-    }
+/// Build a tree from the strictly ascending pairs in O(n) (about), Err if they aren't.
+pub trait FromSortedIter<K, V>: Sized {
+    #[allow(clippy::result_unit_err, reason = "a pair out of order is the only way to fail")]
+    fn from_sorted_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Result<Self, ()>;
+}
 
+/// Structural changes of a tree, for visualization, debugging and metrics,
+/// all are no-op by default.
+///
+/// A tree calls its observer in place (`with_observer`), and `()` observes nothing,
+/// so it's compiled away if unused.
+pub trait Observer<K> {
+    /// `x` went down under `z` by a rotation, to the left if `dir` is Left
+    fn on_rotate(&mut self, _x: &K, _z: &K, _dir: Either<(), ()>) {}
 
-    fn search_approximately(
-        &self,
-        income_key: &K,
-    ) -> *mut (dyn BTNode<'a, K, V> + 'a) {
-        if !self.root().is_null() {
-            unsafe { (*self.root()).search_approximately(income_key) }
-        } else {
-            self.root()
-        }
-    }
+    /// `y` came up two levels by a double rotation, `dir` is the second one,
+    /// after the two `on_rotate` of it
+    fn on_double_rotate(&mut self, _y: &K, _dir: Either<(), ()>) {}
 
-    /// BFS Echo
-    fn echo_in_mm(
-        &self,
-        cache: &mut String,
-        action: fn(
-            *mut (dyn BTNode<'a, K, V> + 'a),
-            &mut String,
-        ) -> std::fmt::Result,
-    ) -> std::fmt::Result {
-        if self.root().is_null() {
-            writeln!(cache, "ROOT: null")
-        } else {
-            unsafe {
-                writeln!(cache, "ROOT: {:?}", (*self.root()).format_keys())?;
-
-                (*self.root()).echo_in_mm(cache, action)
-            }
-        }
-    }
+    /// A search went down `visits` nodes with `comparisons` key comparisons,
+    /// through `&self` as `get` searches too
+    fn on_search(&self, _visits: usize, _comparisons: usize) {}
 
-    // fn bfs_do(
-    //     &self,
-    //     action: fn(
-    //         *mut (dyn BSTNode<'a, K, V> + 'a),
-    //     )
-    // ) {
-    //     if !self.root().is_null() {
-    //         unsafe{ (*self.root_bst()).bfs_do(action) }
-    //     }
-
-    // }
-
-    fn just_echo_stdout(&self) {
-        if !self.root().is_null() {
-            unsafe { (*self.root()).just_echo_stdout() }
-        } else {
-            println!("EMPTY.")
-        }
-    }
+    /// A B-Tree node is split and `key` goes up into the paren
+    fn on_split(&mut self, _key: &K) {}
 
-    fn calc_height(&self) -> i32 {
-        if self.root().is_null() {
-            return -1;
-        }
+    /// Two B-Tree nodes are merged with `key` from the paren between them
+    fn on_merge(&mut self, _key: &K) {}
 
-        unsafe { (*self.root()).calc_height() }
-    }
+    /// A B-Tree node without key borrows `key` from the paren, which takes one from a sibling
+    fn on_borrow(&mut self, _key: &K) {}
 
-    fn height(&self) -> i32 {
-        if self.root().is_null() {
-            return -1;
-        }
+    /// The whole tree is split into two (by key or by a predicate)
+    fn on_tree_split(&mut self) {}
 
-        unsafe { (*self.root()).height() }
-    }
+    /// Two trees are joined into one
+    fn on_tree_join(&mut self) {}
 
-    fn total(&self) -> usize {
-        if self.root().is_null() {
-            0
-        } else {
-            unsafe { (*self.root()).total() }
-        }
-    }
+    /// The node turns red or black
+    fn on_recolor(&mut self, _key: &K, _red: bool) {}
 
-    fn basic_lookup(
-        &self,
-        income_key: &K,
-    ) -> Option<&V> {
-        let res = self.search_approximately(income_key);
+    /// The AA level of the node changes
+    fn on_level_change(&mut self, _key: &K, _from: usize, _to: usize) {}
 
-        if res.is_null() {
-            None
-        } else {
-            unsafe {
-                // println!("{:?}", (*res).format_keys());
+    /// LSG rebuilds a subtree of `size` nodes, `key` is its new root
+    fn on_rebuild(&mut self, _key: &K, _size: usize) {}
 
-                if let Some(idx) = (*res).find_pos_of_key(income_key) {
-                    Some(&*(*res).val_ptr(idx))
-                } else {
-                    None
-                }
-            }
-        }
-    }
-
-    fn basic_lookup_mut(
-        &mut self,
-        income_key: &K,
-    ) -> Option<&mut V> {
-        let res = self.search_approximately(income_key);
+    /// Splay brings `key` up to the root from `depth`
+    fn on_splay(&mut self, _key: &K, _depth: usize) {}
+}
 
-        if res.is_null() {
-            None
-        } else {
-            unsafe {
-                // println!("{:?}", (*res).format_keys());
+impl<K> Observer<K> for () {}
 
-                if let Some(idx) = (*res).find_pos_of_key(income_key) {
-                    Some(&mut *(*res).val_ptr(idx))
-                } else {
-                    None
-                }
-            }
-        }
+impl<K, T: Observer<K> + ?Sized> Observer<K> for &mut T {
+    fn on_rotate(&mut self, x: &K, z: &K, dir: Either<(), ()>) {
+        (**self).on_rotate(x, z, dir)
     }
 
-    fn basic_modify(&mut self, key: &K, value: V) -> bool {
-        unsafe {
-            let app_node
-            = (*self.search_approximately(key)).try_as_bst_mut().unwrap();
-
-            if app_node.is_null() {
-                false
-            } else if let Some(idx) = (*app_node).find_pos_of_key(key) {
-                (*app_node).assign_value(value, idx);
-                true
-            } else {
-                false
-            }
-        }
+    fn on_double_rotate(&mut self, y: &K, dir: Either<(), ()>) {
+        (**self).on_double_rotate(y, dir)
     }
 
-    fn bfs_do(
-        &self,
-        action: fn(
-            *mut (dyn BTNode<'a, K, V> + 'a),
-        )
-    ) {
-        if !self.root().is_null() {
-            unsafe{ (*self.root()).bfs_do(action) }
-        }
-
+    fn on_search(&self, visits: usize, comparisons: usize) {
+        (**self).on_search(visits, comparisons)
     }
 
-    fn basic_self_validate(&self) -> Result<(), Box<dyn std::error::Error>> {
-        if self.root().is_null() {
-            Ok(())
-        } else {
-            unsafe {
-                (*self.root()).basic_self_validate()
-            }
-        }
+    fn on_split(&mut self, key: &K) {
+        (**self).on_split(key)
     }
 
-}
-
-
-/// B-Tree Node
-pub trait BTNode<'a, K: CollKey + 'a, V: 'a> {
-
-    ////////////////////////////////////////////////////////////////////////////
-    //// Introspection
-
-    fn itself(&self) -> *const (dyn BTNode<'a, K, V> + 'a);
-    fn itself_mut(&self) -> *mut (dyn BTNode<'a, K, V> + 'a) {
-        self.itself() as *mut (dyn BTNode<'a, K, V> + 'a)
-    }
-    fn null(&self) -> *const (dyn BTNode<'a, K, V> + 'a);
-    fn null_mut(&self) -> *mut (dyn BTNode<'a, K, V> + 'a) {
-        self.null() as *mut (dyn BTNode<'a, K, V> + 'a)
+    fn on_merge(&mut self, key: &K) {
+        (**self).on_merge(key)
     }
 
-
-    fn try_as_bst(&self) -> Result<*const (dyn BSTNode<'a, K, V> + 'a), ()>;
-    fn try_as_bst_mut(&self) -> Result<*mut (dyn BSTNode<'a, K, V> + 'a), ()> {
-        if let Ok(p) = self.try_as_bst() {
-            Ok(p as *mut (dyn BSTNode<'a, K, V> + 'a))
-        } else {
-            Err(())
-        }
-    }
-    fn itself_bst(&self) -> *const (dyn BSTNode<'a, K, V> + 'a) {
-        self.try_as_bst().unwrap()
+    fn on_borrow(&mut self, key: &K) {
+        (**self).on_borrow(key)
     }
-    fn itself_bst_mut(&self) -> *mut (dyn BSTNode<'a, K, V> + 'a) {
-        self.try_as_bst_mut().unwrap()
-    }
-
-    fn order(&self) -> usize;  // >= 2
 
-    /// 0 <= idx <= order, child(order) is temporary case.
-    fn child(&self, idx: usize) -> *mut (dyn BTNode<'a, K, V> + 'a);
-    fn child_first(&self) -> *mut (dyn BTNode<'a, K, V> + 'a) {
-        self.child(0)
-    }
-    fn child_last(&self) -> *mut (dyn BTNode<'a, K, V> + 'a) {
-        self.child(self.order() - 1)
+    fn on_tree_split(&mut self) {
+        (**self).on_tree_split()
     }
 
-    /// 0 <= idx <= order, child(order) is temporary case.
-    fn assign_child(&mut self, child: *mut (dyn BTNode<'a, K, V> + 'a), idx: usize);
-    fn assign_value(&mut self, value: V, idx: usize);
-    fn assign_paren(&mut self, paren: *mut (dyn BTNode<'a, K, V> + 'a));
-
-    fn paren(&self) -> *mut (dyn BTNode<'a, K, V> + 'a);
-    fn paren_bst(&self) -> *mut (dyn BSTNode<'a, K, V> + 'a) {
-        unsafe { (*self.paren()).try_as_bst_mut().unwrap() }
+    fn on_tree_join(&mut self) {
+        (**self).on_tree_join()
     }
 
-    fn key(&self, idx: usize) -> Option<&K> {
-        if !self.key_ptr(idx).is_null() {
-            Some(unsafe{ &*self.key_ptr(idx) })
-        } else {
-            None
-        }
+    fn on_recolor(&mut self, key: &K, red: bool) {
+        (**self).on_recolor(key, red)
     }
 
-    fn key_mut(&mut self, idx: usize) -> Option<&mut K> {
-        if !self.key_ptr(idx).is_null() {
-            Some(unsafe{ &mut *self.key_ptr(idx) })
-        } else {
-            None
-        }
-    }
-    fn key_ptr(&self, idx: usize) -> *mut K;
-    fn assign_key_ptr(&mut self, idx: usize, key_ptr: *mut K);
-
-    fn value(&self, idx: usize) -> Option<&V> {
-        if !self.val_ptr(idx).is_null() {
-            Some(unsafe{ &*self.val_ptr(idx) })
-        } else {
-            None
-        }
+    fn on_level_change(&mut self, key: &K, from: usize, to: usize) {
+        (**self).on_level_change(key, from, to)
     }
 
-    fn value_mut(&mut self, idx: usize) -> Option<&mut V> {
-        if !self.val_ptr(idx).is_null() {
-            Some(unsafe{ &mut *self.val_ptr(idx) })
-        } else {
-            None
-        }
+    fn on_rebuild(&mut self, key: &K, size: usize) {
+        (**self).on_rebuild(key, size)
     }
-    fn val_ptr(&self, idx: usize) -> *mut V;
-    fn assign_val_ptr(&mut self, idx: usize, val_ptr: *mut V);
 
-    fn connect_child(&mut self, child: *mut (dyn BTNode<'a, K, V> + 'a), idx: usize) {
-        if !child.is_null() {
-            unsafe{ (*child).assign_paren(self.itself_mut()) };
-        }
-
-        self.assign_child(child, idx);
+    fn on_splay(&mut self, key: &K, depth: usize) {
+        (**self).on_splay(key, depth)
     }
+}
 
-    // fn right_sibling(&self) -> *mut (dyn BTNode<'a, K, V> + 'a) {
-    //     let paren = (*self).paren();
-
-    //     if paren.is_null() {
-    //         return paren;
-    //     }
-
-    //     unsafe {
-    //         let idx = (*paren).index_of_child(self.itself_mut());
-
-    //         (*paren).child(idx + 1)
-    //     }
-
-    // }
-
-
-    // fn left_sibling(&self) -> *mut (dyn BTNode<'a, K, V> + 'a) {
-    //     let paren = (*self).paren();
-
-    //     if paren.is_null() {
-    //         return paren;
-    //     }
-
-    //     unsafe {
-    //         let idx = (*paren).index_of_child(self.itself_mut());
-
-    //         if idx > 0 {
-    //             (*paren).child(idx - 1)
-    //         } else {
-    //             self.null_mut()
-    //         }
-    //     }
-
-    // }
+pub trait CollKey: Ord + Debug {}
 
+impl<T: Ord + Debug> CollKey for T {}
 
-    fn index_of_child(&self, child: *mut (dyn BTNode<'a, K, V> + 'a)) -> usize {
-        for i in 0..self.order() {
-            // as *const () just to ignore the vtable variant from the fat pointer
-            if self.child(i) as *const () == child as *const () {
-                return i;
-            }
 
-        }
+pub trait Coll {
+    fn len(&self) -> usize;
 
-        unreachable!()
+    fn is_empty(&self) -> bool {
+        self.len() == 0
     }
+}
 
-    /// key must in it!!
-    fn index_of_key(&self, key: &K) -> usize {
-        for i in 0..self.order() {
-            if self.key(i).unwrap() == key {
-                return i;
-            }
 
-        }
-
-        unreachable!()
-    }
+pub trait Heap<K: CollKey, T: CollKey>: Coll {
+    // max for max-heap and min for min-heap respectly.
+    fn top(&self) -> Option<&T>;
 
-    fn find_pos_of_key(&self, key: &K) -> Option<usize> {
-        for i in 0..self.order() {
-            if let Some(here_key) = self.key(i) {
-                if here_key == key {
-                    return Some(i);
-                }
-            }
-        }
+    fn pop(&mut self) -> Option<T>;
 
-        None
-    }
+    fn push(&mut self, key: K, val: T);
+}
 
-    /// If this node contains key (exclude the subtree)
-    #[inline]
-    fn node_contains(&self, key: &K) -> bool {
-        for i in 0..self.order() {
-            let key_opt = self.key(i);
-            if key_opt.is_some() && key_opt.unwrap() == key {
-                return true;
-            }
-        }
 
-        false
-    }
+////////////////////////////////////////////////////////////////////////////////
+//// Unify Test
 
-    fn node_last_key(&self) -> &K {
-        self.key(self.key_num() - 1).unwrap()
+/// Shrink the test size under Miri, which runs orders of magnitude slower
+#[cfg(test)]
+pub(crate) fn test_size<T>(native: T, miri: T) -> T {
+    if cfg!(miri) {
+        miri
+    } else {
+        native
     }
+}
 
-    fn node_first_key(&self) -> &K {
-        self.key(0).unwrap()
+/// A random seed and the sizes above for the `testing` checks
+#[cfg(test)]
+pub(crate) fn test_config() -> testing::Config {
+    testing::Config {
+        rounds: test_size(20, 2),
+        size: test_size(1000, 80),
+        seed: rand::random(),
+        ..testing::Config::default()
     }
+}
 
-    /// How many key-values does this node contains?
-    fn node_size(&self) -> usize {
-        self.key_num()
-    }
+#[cfg(test)]
+macro_rules! test_dict {
+    ($dict: expr) => {
+        $crate::testing::check_dictionary(|| $dict, $crate::testing::clustered_u64(), &$crate::test_config())
+    };
+}
 
-    fn key_num(&self) -> usize {
-        for i in 0..self.order() {
-            if self.key(i).is_none() {
-                return i;  // i must be greater than one in this case.
-            }
-        }
+#[cfg(test)]
+pub(crate) use test_dict;
 
-        self.order()
-    }
 
-    fn key_iter(&'a self) -> Box<dyn Iterator<Item=&K> + 'a> {
-        let mut i = -1i32;
+/// Test remove_range with all kinds of bounds
+#[cfg(test)]
+macro_rules! test_remove_range {
+    ($dict: expr) => {
+        use std::collections::BTreeMap;
+        use rand::{prelude::SliceRandom, rngs::StdRng, Rng, SeedableRng};
 
-        box std::iter::from_fn(move || -> Option<&K> {
-            i += 1;
-            self.key(i as usize)
-        })
-    }
+        let batch_num = $crate::test_size(1000u64, 100);
 
-    fn val_num(&self) -> usize {
-        for i in 0..self.order() {
-            if self.value(i).is_none() {
-                return i;  // i must be greater than one in this case.
-            }
-        }
+        for _ in 0..$crate::test_size(20, 1) {
+            let seed = rand::random::<u64>();
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut dict = $dict;
+            let mut refmap = BTreeMap::new();
 
-        self.order()
-    }
+            let mut keys = (0..batch_num).collect::<Vec<u64>>();
+            keys.shuffle(&mut rng);
 
-    fn children_num(&self) -> usize {
-        for i in 0..self.order() + 1 {
-            if self.child(i).is_null() {
-                return i;  // i must be greater than one in this case.
+            for k in keys {
+                dict.insert(k, k + 1000);
+                refmap.insert(k, k + 1000);
             }
-        }
 
-        self.order()
-    }
+            for i in 0..12 {
+                let a = rng.gen_range(0, batch_num);
+                let b = a + rng.gen_range(0, batch_num / 10);
 
-    fn node_is_overfilled(&self) -> bool {
-        self.node_size() >= self.order()
-    }
+                let (removed, expect): (usize, Vec<u64>) = match i % 6 {
+                    0 => (dict.remove_range(a..b), refmap.range(a..b).map(|(k, _)| *k).collect()),
+                    1 => (dict.remove_range(a..=b), refmap.range(a..=b).map(|(k, _)| *k).collect()),
+                    2 => (dict.remove_range(..a / 4), refmap.range(..a / 4).map(|(k, _)| *k).collect()),
+                    3 => (dict.remove_range(b + batch_num / 2..), refmap.range(b + batch_num / 2..).map(|(k, _)| *k).collect()),
+                    4 => (dict.remove_range(b..a), vec![]),
+                    _ => (dict.remove_range(a..a + 1), refmap.range(a..a + 1).map(|(k, _)| *k).collect()),
+                };
 
-    fn node_is_fullfilled(&self) -> bool {
-        self.node_size() >= self.order() - 1
-    }
-
-    fn height(&self) -> i32;
-
-    #[inline]
-    fn calc_height(&self) -> i32 {
-        (0..self.order())
-        .into_iter()
-        .map(|i| {
-            if self.child(i).is_null() {
-                -1
-            } else {
-                unsafe { (*self.child(i)).calc_height() }
-            }
-        }).max().unwrap() + 1
+                assert_eq!(removed, expect.len(), "[dict remove_range] removed number, seed {seed}");
 
-    }
-
-
-    fn total(&self) -> usize {
-        let mut total = 1;
+                for k in expect.iter() {
+                    refmap.remove(k);
+                    assert_eq!(dict.get(k), None, "[dict remove_range] Assure get None, seed {seed}");
+                }
 
-        for i in 0..self.order() {
-            let child = self.child(i);
+                dict.self_validate().unwrap_or_else(|err| panic!("{err}, seed {seed}"));
 
-            if !child.is_null() {
-                unsafe{ total += (*child).total() + 1; }
+                for (k, v) in refmap.iter() {
+                    assert_eq!(dict.get(k), Some(v), "[dict remove_range] Assure get Some, seed {seed}");
+                }
             }
-        }
-
-        total
-    }
-
-
-    fn minimum(&self) -> *mut (dyn BTNode<'a, K, V> + 'a) {
-        let mut x = self.itself_mut();
 
-        while unsafe { !(*x).child_first().is_null() } {
-            unsafe { x = (*x).child_first() }
+            let removed = dict.remove_range(..);
+            assert_eq!(removed, refmap.len());
+            dict.self_validate().unwrap();
         }
+    };
+}
 
-        x
-    }
-
-
-    fn maximum(&self) -> *mut (dyn BTNode<'a, K, V> + 'a) {
-        let mut x = self.itself_mut();
-
-        while unsafe { !(*x).child_last().is_null() } {
-            unsafe { x = (*x).child_last() }
-        }
+#[cfg(test)]
+pub(crate) use test_remove_range;
 
-        x
-    }
 
-    fn is_leaf(&self) -> bool {
-        for i in 0..self.order() {
-            if !self.child(i).is_null() {
-                return false;
-            }
-        }
-
-        true
-    }
+/// Test from_sorted_iter, the tree should keep valid on the following inserts
+#[cfg(test)]
+macro_rules! test_from_sorted_iter {
+    ($ty:ident) => {
+        use rand::{prelude::SliceRandom, thread_rng};
 
-    /// successor of item whose key is key.
-    fn successor(&self, key: &K) -> BTItem<'a, K, V> {
-        let k_idx = self.index_of_key(key);
+        let sizes = $crate::test_size(vec![1000, 4321], vec![100]);
 
-        unsafe {
-            if self.is_leaf() {
-                if self.key(k_idx + 1).is_none() {  // Goto parent
-                    let mut x = self.itself_mut();
-                    let mut y = (*x).paren();
+        for n in (0..$crate::test_size(100, 20)).chain(sizes) {
+            let mut dict = $ty::from_sorted_iter((0..n).map(|k: u64| (k * 2, k))).unwrap();
 
-                    while !y.is_null() {
-                        let idx = (*y).index_of_child(x);
+            dict.self_validate().unwrap();
 
-                        if (*y).key(idx).is_some() {
-                            return BTItem::new(y, idx);
-                        }
+            for k in 0..n {
+                assert_eq!(dict.get(&(k * 2)), Some(&k), "[dict from_sorted_iter] get");
+            }
 
-                        x = y;
-                        y = (*x).paren();
-                    }
+            let mut keys = (0..n).collect::<Vec<u64>>();
+            keys.shuffle(&mut thread_rng());
 
-                    BTItem::new(y, 0)
+            for k in keys {
+                assert!(dict.insert(k * 2 + 1, k));
 
-                } else {
-                    BTItem::new(self.itself_mut(), k_idx + 1)
+                if n < 100 {
+                    dict.self_validate().unwrap();
                 }
-
-            } else {
-
-                BTItem::new((*self.child(k_idx + 1)).minimum(), 0)
             }
-        }
-    }
-
-
-    /// precessor of item whose key is key.
-    fn precessor(&self, key: &K) -> BTItem<'a, K, V> {
-        let k_idx = self.index_of_key(key);
-
-        unsafe {
-            if self.is_leaf() {
-                if k_idx == 0 {  // Goto parent
-                    let mut x = self.itself_mut();
-                    let mut y = (*x).paren();
-
-                    while !y.is_null() {
-                        let idx = (*y).index_of_child(x);
-
-                        if idx > 0 {
-                            return BTItem::new(y, idx - 1);
-                        }
 
-                        x = y;
-                        y = (*x).paren();
-                    }
+            dict.self_validate().unwrap();
+        }
 
-                    BTItem::new(y, 0)
+        assert!($ty::from_sorted_iter([(1, ()), (3, ()), (2, ())]).is_err());
+        assert!($ty::from_sorted_iter([(1, ()), (1, ())]).is_err());
+    };
+}
 
-                } else {
-                    BTItem::new(self.itself_mut(), k_idx - 1)
-                }
+#[cfg(test)]
+pub(crate) use test_from_sorted_iter;
 
-            } else {
-                let pre_ptr = (*self.child(k_idx)).maximum();
 
-                BTItem::new(pre_ptr, (*pre_ptr).node_size() - 1)
-            }
-        }
-    }
+/// Test multi dict with lots of duplicate keys
+#[cfg(test)]
+macro_rules! test_multi_dict {
+    ($dict: expr) => {
+        use std::collections::{BTreeMap, VecDeque};
 
+        let mut dict = $dict;
+        let mut refmap = BTreeMap::<u64, VecDeque<u64>>::new();
 
-    #[inline]
-    fn search_approximately(
-        &self,
-        income_key: &K,
-    ) -> *mut (dyn BTNode<'a, K, V> + 'a) {
-        let mut y = self.null_mut();
-        let mut x = self.itself_mut();
+        for i in 0..$crate::test_size(10000u64, 600) {
+            let k = rand::random::<u64>() % 100;
 
-        unsafe {
-            while !x.is_null() {
-                y = x;
+            match rand::random::<u64>() % 10 {
+                0 => {
+                    let expect = refmap.remove(&k).map(Vec::from).unwrap_or_default();
 
-                if (*x).node_contains(income_key) || (*x).is_leaf() {
-                    break;
+                    assert_eq!(dict.remove_all(&k), expect, "[multi dict] remove_all");
                 }
+                1..=3 => {
+                    let expect = refmap.get_mut(&k).and_then(|values| values.pop_front());
 
-                let mut i = 0;
-                let mut encountered = false;
-                loop {
-                    if let Some(key) = (*x).key(i) {
-                        if income_key < key {
-                            x = (*x).child(i);
-                            encountered = true;
-
-                            break;
-                        }
-                    } else {
-                        break;
+                    if refmap.get(&k).map_or(false, |values| values.is_empty()) {
+                        refmap.remove(&k);
                     }
 
-                    i += 1;
+                    assert_eq!(dict.remove_one(&k), expect, "[multi dict] remove_one");
                 }
-
-                if !encountered {
-                    x = (*x).child(i);
+                _ => {
+                    dict.insert(k, i);
+                    refmap.entry(k).or_default().push_back(i);
                 }
             }
-        }
 
-        y
-    }
-
-    fn swap_to_leaf(&mut self, idx: usize) -> BTItem<'a, K, V> {
-        let mut item_x = BTItem::new(self.itself_mut(), idx);
-
-        while let Ok(item_nxt) = item_x.swap_with_successor_until_leaf() {
-            item_x = item_nxt;
+            if i % 100 == 0 {
+                dict.self_validate().unwrap();
+            }
         }
 
-        // unsafe {
-        //     if (*item_x.node).is_leaf() {
-        //         return item_x;
-        //     }
-        // }
+        dict.self_validate().unwrap();
 
-        // while let Ok(item_nxt) = item_x.swap_with_precessor_until_leaf() {
-        //     item_x = item_nxt;
-        // }
+        for k in 0..100 {
+            let expect = refmap.get(&k).map(|values| values.iter().collect()).unwrap_or(vec![]);
 
-        item_x
-    }
+            assert_eq!(dict.get_all(&k).collect::<Vec<_>>(), expect, "[multi dict] get_all");
+            assert_eq!(dict.count(&k), expect.len(), "[multi dict] count");
+        }
+    };
+}
 
-    // fn swap_to_valid(&mut self, idx: usize) -> BTItem<'a, K, V> {
-    //     let mut item_x = BTItem::new(self.itself_mut(), idx);
+#[cfg(test)]
+pub(crate) use test_multi_dict;
 
-    //     while let Ok(item_nxt) = item_x.swap_with_successor_until_valid() {
-    //         item_x = item_nxt;
-    //     }
 
-    //     item_x
-    // }
+/// Fill the dict in a worker thread, move it back,
+/// then share it with reader and writer threads behind the lock.
+#[cfg(test)]
+macro_rules! test_send_sync {
+    ($dict: expr) => {
+        test_send_sync!(@ $dict, RwLock, read, write)
+    };
+    ($dict: expr, Mutex) => {
+        test_send_sync!(@ $dict, Mutex, lock, lock)
+    };
+    (@ $dict: expr, $lock: ident, $read: ident, $write: ident) => {
+        use std::{sync::{Arc, $lock}, thread};
 
-    fn just_echo_stdout(&self) {
-        let mut cache = String::new();
+        let n = $crate::test_size(1000u64, 40);
+        let mut dict = $dict;
 
-        self.echo_in_mm(&mut cache, |_, _| Ok(())).unwrap();
+        dict = thread::spawn(move || {
+            for i in 0..n {
+                dict.insert(i, i);
+            }
 
-        println!("{}", cache);
-    }
+            dict
+        })
+        .join()
+        .unwrap();
 
-    fn format_keys(&self) -> String {
-        let mut keys_s = vec![];
+        dict.self_validate().unwrap();
 
-        for i in 0..self.order() {
-            let key_s = if let Some(key) = self.key(i) {
-               format!("{:?}", key)
-            } else {
-                break;
-            };
+        let dict = Arc::new($lock::new(dict));
 
-            keys_s.push(key_s)
-        }
+        let handles = (0..4u64)
+            .map(|t| {
+                let dict = dict.clone();
 
-        format!("({})", keys_s.join(", "))
-    }
+                thread::spawn(move || {
+                    for i in (t..n).step_by(4) {
+                        assert_eq!(dict.$read().unwrap().get(&i), Some(&i));
 
-    /// BFS Echo
-    fn echo_in_mm(
-        &self,
-        cache: &mut String,
-        action: fn(
-            *mut (dyn BTNode<'a, K, V> + 'a),
-            &mut String,
-        ) -> std::fmt::Result,
-    ) -> std::fmt::Result {
-        unsafe {
-            writeln!(cache, "Entry: {}", self.format_keys())?;
-
-            let mut this_level_queue: VecDeque<
-                *mut (dyn BTNode<'a, K, V> + 'a),
-            > = VecDeque::new();
-
-            this_level_queue
-                .push_back(self.itself_mut());
-            let mut level = 0;
-
-            while !this_level_queue.is_empty() {
-                writeln!(cache)?;
-                writeln!(
-                    cache,
-                    "############ Level: {} #############",
-                    level
-                )?;
-                writeln!(cache)?;
-
-                let mut nxt_level_queue: VecDeque<
-                    *mut (dyn BTNode<'a, K, V> + 'a),
-                > = VecDeque::new();
-
-                while !this_level_queue.is_empty() {
-                    let x = this_level_queue.pop_front().unwrap();
-
-
-                    action(x, cache)?;
-
-                    writeln!(cache, "{}", (*x).format_keys() )?;
-                    for i in 0..self.order() {
-                        let child = (*x).child(i);
-
-                        if !child.is_null() {
-                            writeln!(
-                                cache,
-                                "{} -({})-> {}",
-                                "  |",
-                                i,
-                                (*child).format_keys(),
-                            )?;
-
-                            nxt_level_queue.push_back(child)
-                        } else {
-                            writeln!(cache, "{} -({})-> null", "  |", i)?;
+                        // remove the odd ones
+                        if i % 2 == 1 {
+                            assert_eq!(dict.$write().unwrap().remove(&i), Some(i));
                         }
                     }
+                })
+            })
+            .collect::<Vec<_>>();
 
-                    writeln!(cache)?;
-                }
-
-                this_level_queue = nxt_level_queue;
-                level += 1;
-            }
-
-            writeln!(cache, "{}", "------------- end --------------")?;
-            writeln!(cache)?;
+        for handle in handles {
+            handle.join().unwrap();
         }
 
+        let dict = dict.$read().unwrap();
 
-        Ok(())
-    }
-
-    fn bfs_do(
-        &self,
-        action: fn(
-            *mut (dyn BTNode<'a, K, V> + 'a),
-        )
-    ) {
-        let mut queue= VecDeque::new();
-
-        queue.push_back(self.itself_mut());
-        while !queue.is_empty() {
-            let x = queue.pop_front().unwrap();
+        dict.self_validate().unwrap();
 
-            action(x);
-
-            unsafe {
-                for i in 0..self.order() {
-                    let child = BTNode::child (&*x, i);
-
-                    if !child.is_null() {
-                        queue.push_back(child);
-                    } else {
-                        break;
-                    }
-                }
-            }
+        for i in 0..n {
+            let expect = if i % 2 == 0 { Some(&i) } else { None };
 
+            assert_eq!(dict.get(&i), expect);
         }
-    }
-
-    /// 1. N(keys) = N(vals)
-    /// 1. Keep infix-order
-    /// 1. non-leaf node (except root) has at least ⌈m/2⌉ child nodes.
-    /// 1. A non-leaf node with k children contains k − 1 keys (m >= 3).
-    /// 1. The root has at least two children if it is not a leaf node.
-    ///
-    fn basic_self_validate(&'a self) -> Result<(), Box<dyn std::error::Error>> {
-        if self.order() == 2 {
-            unsafe {
-                if !self.child(0).is_null() {
-                    assert!((*self.child(0)).key(0).unwrap() < self.key(0).unwrap());
-
-                    (*self.child(0)).basic_self_validate()?;
-                }
-
-                if !self.child(1).is_null() {
-                    assert!((*self.child(1)).key(0).unwrap() > self.key(0).unwrap());
-
-                    (*self.child(1)).basic_self_validate()?;
-                }
-            }
-
-            return Ok(());
-        }
-
+    };
+}
 
-        let key_num = self.key_num();
-        assert_eq!(key_num, self.val_num());
+#[cfg(test)]
+pub(crate) use test_send_sync;
 
-        if self.is_leaf() {
-            assert!(self.key_iter().is_sorted())
-        } else {
-            let children_num = self.children_num();
 
-            assert_eq!(key_num + 1, children_num);
+/// The clone keeps the exact shape and doesn't share anything with the origin.
+#[cfg(test)]
+macro_rules! test_clone {
+    ($dict: expr) => {
+        let mut dict = $dict;
 
-            if self.paren().is_null() {
-                assert!(children_num >= 2);
-            } else {
-                assert!(children_num >= self.order().div_ceil(2));
-            }
+        assert!(dict.clone().root().is_null());
 
-            for i in 0..self.key_num() {
-                unsafe {
-                    let cur_key = self.key(i).unwrap();
-                    let lf_child = self.child(i);
-                    let rh_child = self.child(i + 1);
+        let mut keys = vec![];
 
-                    if !lf_child.is_null() {
-                        assert!((*lf_child).node_last_key() < cur_key);
-                    }
+        for _ in 0..$crate::test_size(2000, 100) {
+            let k = rand::random::<u64>() % 3000;
 
-                    if !rh_child.is_null() {
-                        assert!((*rh_child).node_first_key() > cur_key);
-                    }
-                }
-            }
-
-            for i in 0..self.order() {
-                if !self.child(i).is_null() {
-                    unsafe { (*self.child(i)).basic_self_validate()?; }
-                }
+            if dict.insert(k, k) {
+                keys.push(k);
             }
         }
 
-
-        Ok(())
-    }
-
-}
-
-
-#[derive(Clone)]
-pub struct BTItem<'a, K, V> {
-    node: *mut (dyn BTNode<'a, K, V> + 'a),
-    idx: usize
-}
-
-impl<'a, K: CollKey, V> BTItem<'a, K, V> {
-    pub fn new(node: *mut (dyn BTNode<'a, K, V> + 'a), idx: usize) -> Self {
-        Self {
-            node,
-            idx,
-        }
-    }
-
-    pub fn is_valid(&self) -> bool {
-        !self.node.is_null()
-    }
-
-    pub fn key(&self) -> *mut K {
-        unsafe {
-            (*self.node).key_ptr(self.idx)
+        for k in keys.drain(..keys.len() / 3) {
+            assert_eq!(dict.remove(&k), Some(k));
         }
-    }
 
-    pub fn assign_key(&mut self, key: *mut K) {
-        unsafe {
-            (*self.node).assign_key_ptr(self.idx, key)
-        }
-    }
+        let copy = dict.clone();
 
-    pub fn assign_val(&mut self, val: *mut V) {
-        unsafe {
-            (*self.node).assign_val_ptr(self.idx, val)
-        }
-    }
+        copy.self_validate().unwrap();
+        assert!(unsafe { crate::raw::same_shape(dict.root(), copy.root()) });
 
-    pub fn val(&self) -> *mut V {
-        unsafe {
-            (*self.node).val_ptr(self.idx)
+        for k in keys.iter() {
+            dict.remove(k);
         }
-    }
 
-    pub fn successor(&self) -> Self {
-        unsafe {
-            (*self.node).successor(&*self.key())
-        }
-    }
+        copy.self_validate().unwrap();
 
-    pub fn precessor(&self) -> Self {
-        unsafe {
-            (*self.node).precessor(&*self.key())
+        for k in keys.iter() {
+            assert_eq!(copy.get(k), Some(k));
         }
-    }
+    };
+}
 
-    pub fn swap(x: &mut Self, y: &mut Self) {
-        let tmp_key = y.key();
-        let tmp_val = y.val();
+#[cfg(test)]
+pub(crate) use test_clone;
 
-        y.assign_key(x.key());
-        y.assign_val(x.val());
 
-        x.assign_key(tmp_key);
-        x.assign_val(tmp_val);
-    }
+/// Test the std traits on the content, against an RB with the same pairs and BTreeMap
+#[cfg(test)]
+macro_rules! test_std_traits {
+    ($dict: expr) => {
+        use std::{
+            collections::{hash_map::DefaultHasher, BTreeMap},
+            hash::{Hash, Hasher},
+        };
 
-    pub fn swap_with_successor_until_leaf(&mut self) -> Result<Self, ()> {
-        unsafe {
-            if (*self.node).is_leaf() {
-                return Err(())
-            }
+        let hash = |x: &dyn Fn(&mut DefaultHasher)| {
+            let mut state = DefaultHasher::new();
+            x(&mut state);
+            state.finish()
+        };
 
-            let mut nxt_item = self.successor();
+        let mut dict = $dict;
+        let mut ref_map = BTreeMap::new();
 
-            if nxt_item.is_valid() {
-                BTItem::swap(self, &mut nxt_item);
+        assert_eq!(format!("{:?}", dict), "{}");
 
-                Ok(nxt_item)
-            } else {
-                Err(())
-            }
+        for _ in 0..$crate::test_size(1000, 60) {
+            let k = rand::random::<u64>() % 2000;
+            let v = rand::random::<u64>();
 
+            dict.extend([(k, v)]);
+            ref_map.insert(k, v);
         }
-    }
 
-    /// END COND: is_leaf || key < successor's key
-    pub fn swap_with_successor_until_valid(&mut self) -> Result<Self, ()> {
-        unsafe {
-            if (*self.node).is_leaf() {
-                return Err(())
-            }
-
-            let mut nxt_item = self.successor();
+        let other: crate::bst::rb::RB<u64, u64> = ref_map.clone().into_iter().collect();
 
-            if nxt_item.is_valid() && self.key() > nxt_item.key() {
-                BTItem::swap(self, &mut nxt_item);
-
-                Ok(nxt_item)
-            } else {
-                Err(())
-            }
+        assert_eq!(dict, other);
+        assert_eq!(format!("{:?}", dict), format!("{:?}", ref_map));
+        assert_eq!(hash(&|s| dict.hash(s)), hash(&|s| other.hash(s)));
+        assert_eq!(dict.partial_cmp(&other), Some(core::cmp::Ordering::Equal));
 
+        for (k, v) in ref_map.iter() {
+            assert_eq!(dict[k], *v);
         }
-    }
 
-    pub fn swap_with_precessor_until_leaf(&mut self) -> Result<Self, ()> {
-        unsafe {
-            if (*self.node).is_leaf() {
-                return Err(())
-            }
+        let mut other = other;
+        other.insert(3000, 0);
 
-            let mut nxt_item = self.precessor();
+        assert_ne!(dict, other);
+        assert!(dict < other);
+        assert_ne!(hash(&|s| dict.hash(s)), hash(&|s| other.hash(s)));
 
-            if nxt_item.is_valid() {
-                BTItem::swap(self, &mut nxt_item);
+        let (k, v) = ref_map.iter().next().map(|(k, v)| (*k, *v)).unwrap();
 
-                Ok(nxt_item)
-            } else {
-                Err(())
-            }
-
-        }
-    }
+        dict.extend([(k, v.wrapping_add(1))]);
+        dict.self_validate().unwrap();
 
+        assert_eq!(dict[&k], v.wrapping_add(1));
+        assert!(dict > other);
+    };
 }
 
-
-
-////////////////////////////////////////////////////////////////////////////////
-//// Unify Test
-
-#[cfg(test)]
-macro_rules! gen_data {
-    ($get_one: ident, $group: expr, $num: expr) => {{
-        let group = $group;
-        let num = $num;
-
-        let mut keys = std::collections::HashSet::new();
-        let mut elems = vec![];
-
-        for _ in 0..num {
-            let mut k = $get_one();
-            let mut j = 0;
-
-            while j < group {
-                k = k.wrapping_add(1);
-                if keys.contains(&k) {
-                    continue;
-                }
-
-                keys.insert(k);
-                elems.push((k, k.wrapping_add(1000)));
-
-                j += 1;
-            }
-        }
-
-        elems
-    }};
-}
 #[cfg(test)]
-pub(crate) use gen_data;
+pub(crate) use test_std_traits;
 
 
+/// Test a persistent map against BTreeMap, the kept old versions must stay the same
 #[cfg(test)]
-macro_rules! test_dict {
+macro_rules! test_persistent {
     ($dict: expr) => {
-        let get_one = || rand::random::<u64>();
+        use std::collections::BTreeMap;
 
-        for _ in 0..20 {
+        for _ in 0..$crate::test_size(10, 1) {
             let mut dict = $dict;
-            let mut elems = $crate::gen_data!(get_one, 10, 100);
+            let mut ref_map = BTreeMap::new();
+            let mut versions = vec![];
 
-            /* Verify Create */
+            for i in 0..$crate::test_size(2000u64, 200) {
+                let k = rand::random::<u64>() % 1000;
 
-            for (i, (k, v)) in elems.iter().cloned().enumerate() {
-                assert!(
-                    dict.insert(k, v),
-                    "[dict insert] insert res invalid"
-                );
-                assert_eq!(
-                    dict.get(&k), Some(&v),
-                     "[dict insert] insert but query failed"
-                );
+                if rand::random::<bool>() {
+                    dict = dict.insert(k, i);
+                    ref_map.insert(k, i);
+                } else {
+                    dict = dict.remove(&k);
+                    ref_map.remove(&k);
+                }
 
-                if i % 20 == 0 {
+                if i % $crate::test_size(100, 20) == 0 {
                     dict.self_validate().unwrap();
+                    versions.push((dict.clone(), ref_map.clone()));
                 }
-                // println!("{i}. insert: ");
             }
 
-            dict.self_validate().unwrap();
-
-            /* Verify Update */
-
-            for (i, (k, v)) in elems.clone().into_iter().enumerate() {
-                assert_eq!(dict.get(&k), Some(&v));
-
-                let newv = k + 500;
+            for (dict, ref_map) in versions {
+                dict.self_validate().unwrap();
 
-                assert!(dict.modify(&k, newv));
-
-                elems[i] = (k, newv);
-
-                assert_eq!(dict.get(&k), Some(&newv));
-            }
-
-            /* Verify Remove */
-
-            use rand::{prelude::SliceRandom, thread_rng};
-
-            elems.shuffle(&mut thread_rng());
-
-            for (i, (k, v)) in elems.into_iter().enumerate() {
-                assert_eq!(
-                    dict.get(&k),
-                    Some(&v),
-                    "[dict remove] Assure get Some"
-                );
-                assert_eq!(
-                    dict.remove(&k),
-                    Some(v),
-                    "[dict remove] Assert remove failed"
-                );
-                assert_eq!(
-                    dict.get(&k),
-                    None,
-                    "[dict remove] Assure get None"
-                );
-
-                // println!("[dict remove]: {}", i);
-
-                // sample to save time
-                if i % 10 == 0 {
-                    dict.self_validate().unwrap();
-                }
+                assert_eq!(dict.len(), ref_map.len());
+                assert!(dict.iter().map(|(k, v)| (*k, *v)).eq(ref_map.into_iter()));
             }
         }
     };
 }
-
 #[cfg(test)]
-pub(crate) use test_dict;
+pub(crate) use test_persistent;
 
 
-/// Test heap push/pop
+/// Counts the events, for `test_observer`
 #[cfg(test)]
-macro_rules! test_heap {
-    ($heap:expr, $endian:ident) => {
-        test_heap!($heap, $endian, push:push, pop:pop);
-    };
-    ($heap:expr, $endian:ident, push:$push:ident, pop:$pop:ident) => {
-        use $crate::gen_unique;
-        let get_one = || rand::random::<u64>();
-
-        let non_dec = $crate::heap_endian_no_dec!($endian);
-
-        for _ in 0..20 {
-            /* Basic Test */
-
-            let mut heap = $heap;
-            let mut unique = gen_unique();
-
-            let batch_num = 1000;
-
-            for _ in 0..batch_num {
-                let e = get_one();
-                heap.push(unique(), e);
-            }
-
-            let mut res = vec![];
-
-            for _ in 0..batch_num {
-                res.push(heap.pop().unwrap());
-            }
-
-            if !non_dec {
-                res.reverse();
-            }
-
-            assert!(res.is_sorted());
-
-            /* Accompany Test */
-
-            // In-In Out-Out, generate in/out sequence
-            let mut seq = vec![];
-            let mut rems = 0;
+#[derive(Default, Clone, Debug)]
+pub(crate) struct CountObserver {
+    pub(crate) rotate: usize,
+    pub(crate) split: usize,
+    pub(crate) merge: usize,
+    pub(crate) recolor: usize,
+    pub(crate) level_change: usize,
+    pub(crate) rebuild: usize,
+    pub(crate) splay: usize,
+    /// sum of the splay depths, one rotation each
+    pub(crate) splay_depth: usize,
+}
 
-            // pad 25% of batch
-            for _ in 0..batch_num / 4 {
-                seq.push(true); // push
-                rems += 1;
-            }
+#[cfg(test)]
+impl<K: CollKey> Observer<K> for CountObserver {
+    fn on_rotate(&mut self, x: &K, z: &K, _dir: Either<(), ()>) {
+        assert_ne!(x, z);
+        self.rotate += 1;
+    }
 
-            for _ in 0..(3 * batch_num) / 4 {
-                if random::<usize>() % 2 == 0 {
-                    seq.push(true);
-                    rems += 1;
-                } else {
-                    seq.push(false);
-                    rems -= 1;
-                }
+    fn on_split(&mut self, _key: &K) {
+        self.split += 1;
+    }
 
-                if rems == 0 {
-                    break;
-                }
-            }
+    fn on_merge(&mut self, _key: &K) {
+        self.merge += 1;
+    }
 
-            let mut refheap = $crate::union_heap!($endian);
-            let mut testheap = $heap;
-            let mut unique = gen_unique();
+    fn on_recolor(&mut self, _key: &K, _red: bool) {
+        self.recolor += 1;
+    }
 
-            for flag in seq {
-                if flag {
-                    let e = get_one();
-                    let i = unique();
+    fn on_level_change(&mut self, _key: &K, from: usize, to: usize) {
+        assert_ne!(from, to);
+        self.level_change += 1;
+    }
 
-                    refheap.push(i, e.clone());
-                    testheap.push(i, e);
-                } else {
-                    let target = refheap.pop();
-                    assert_eq!(testheap.pop(), target);
-                }
-            }
-        }
+    fn on_rebuild(&mut self, _key: &K, size: usize) {
+        assert!(size > 0);
+        self.rebuild += 1;
+    }
 
+    fn on_splay(&mut self, _key: &K, depth: usize) {
+        self.splay += 1;
+        self.splay_depth += depth;
     }
 }
 
+/// Ascending inserts and every other removed, return the counts
 #[cfg(test)]
-pub(crate) use test_heap;
+macro_rules! test_observer {
+    ($ty: ident) => {{
+        let mut dict = $ty::with_observer($crate::CountObserver::default());
+        let n = $crate::test_size(1000, 60);
 
+        for k in 0..n {
+            assert!(dict.insert(k, k));
+        }
 
-#[cfg(test)]
-macro_rules! union_heap {
-    (MAX) => {
-        {
-            $crate::MaxDictHeap::new()
+        dict.self_validate().unwrap();
+
+        for k in (0..n).step_by(2) {
+            assert_eq!(dict.remove(&k), Some(k));
         }
-    };
-    (MIN) => {
-        {
-            $crate::MinDictHeap::new()
+
+        for k in 0..n {
+            assert_eq!(dict.get(&k), (k % 2 == 1).then_some(&k));
         }
-    }
-}
 
+        dict.self_validate().unwrap();
+
+        dict.observer_mut().clone()
+    }};
+}
 #[cfg(test)]
-pub(crate) use union_heap;
+pub(crate) use test_observer;
 
 
+/// Test heap push/pop
 #[cfg(test)]
-macro_rules! heap_endian_no_dec {
-    (MAX) => {
-        false
+macro_rules! test_heap {
+    ($heap:expr, MAX) => {
+        test_heap!($heap, $crate::testing::Endian::Max)
     };
-    (MIN) => {
-        true
+    ($heap:expr, MIN) => {
+        test_heap!($heap, $crate::testing::Endian::Min)
+    };
+    ($heap:expr, $endian:expr) => {
+        $crate::testing::check_heap(
+            || $heap,
+            $endian,
+            $crate::testing::uniform::<u64>,
+            &$crate::testing::Config { size: $crate::test_size(1000, 60), ..$crate::test_config() },
+        )
     };
 }
 
 #[cfg(test)]
-pub(crate) use heap_endian_no_dec;
+pub(crate) use test_heap;
 
 
-pub struct MinHeap<T>(BinaryHeap<std::cmp::Reverse<T>>);
+pub struct MinHeap<T>(BinaryHeap<core::cmp::Reverse<T>>);
 
 
 /// Fake Min Dict Heap
 pub struct MinDictHeap<T> {
-    inner: BinaryHeap<std::cmp::Reverse<T>>,
+    inner: BinaryHeap<core::cmp::Reverse<T>>,
 }
 
 
@@ -1345,7 +804,7 @@ impl<T: Ord> MinHeap<T> {
     }
 
     pub fn push(&mut self, v: T) {
-        self.0.push(std::cmp::Reverse(v));
+        self.0.push(core::cmp::Reverse(v));
     }
 
     pub fn pop(&mut self) -> Option<T> {
@@ -1353,6 +812,12 @@ impl<T: Ord> MinHeap<T> {
     }
 }
 
+impl<T: Ord> Default for MinHeap<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 
 impl<T: Ord> MinDictHeap<T> {
     pub fn new() -> Self {
@@ -1362,7 +827,7 @@ impl<T: Ord> MinDictHeap<T> {
     }
 
     pub fn push<I>(&mut self, _i: I, v: T) {
-        self.inner.push(std::cmp::Reverse(v));
+        self.inner.push(core::cmp::Reverse(v));
     }
 
     pub fn pop(&mut self) -> Option<T> {
@@ -1370,6 +835,12 @@ impl<T: Ord> MinDictHeap<T> {
     }
 }
 
+impl<T: Ord> Default for MinDictHeap<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 
 impl<T: Ord> MaxDictHeap<T> {
     pub fn new() -> Self {
@@ -1386,3 +857,9 @@ impl<T: Ord> MaxDictHeap<T> {
         self.inner.pop()
     }
 }
+
+impl<T: Ord> Default for MaxDictHeap<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,233 @@
+//! Map on any Dictionary, the safe interface for applications,
+//! no raw node pointer comes out of it.
+
+use core::{marker::PhantomData, mem, ops::RangeBounds};
+
+use crate::{
+    b3::B3,
+    b4::B4,
+    bst::{aa::AA, avl::AVL, llrb::LLRB, lsg::LSG, rawst::RawST, rb::RB, splay::Splay, treap::Treap},
+    bstar4::BStar4,
+    *,
+};
+
+
+////////////////////////////////////////////////////////////////////////////////
+//// Structs
+
+pub struct Map<K, V, D> {
+    dict: D,
+    len: usize,
+    _marker: PhantomData<(K, V)>,
+}
+
+pub type AVLMap<K, V> = Map<K, V, AVL<K, V>>;
+pub type RBMap<K, V> = Map<K, V, RB<K, V>>;
+pub type LLRBMap<K, V> = Map<K, V, LLRB<K, V>>;
+pub type AAMap<K, V> = Map<K, V, AA<K, V>>;
+pub type TreapMap<K, V> = Map<K, V, Treap<K, V>>;
+pub type SplayMap<K, V> = Map<K, V, Splay<K, V>>;
+pub type LSGMap<K, V> = Map<K, V, LSG<K, V>>;
+pub type RawSTMap<K, V> = Map<K, V, RawST<K, V>>;
+pub type B3Map<K, V> = Map<K, V, B3<K, V>>;
+pub type B4Map<K, V> = Map<K, V, B4<K, V>>;
+pub type BStar4Map<K, V> = Map<K, V, BStar4<K, V>>;
+
+
+
+////////////////////////////////////////////////////////////////////////////////
+//// Implement
+
+impl<'a, K: CollKey + 'a, V: 'a, D: Dictionary<K, V> + BT<'a, K, V>> Map<K, V, D> {
+    pub fn new() -> Self
+    where
+        D: Default,
+    {
+        Self {
+            dict: D::default(),
+            len: 0,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Return the old value if it's already present.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        if let Some(here) = self.dict.get_mut(&key) {
+            return Some(mem::replace(here, value));
+        }
+
+        self.dict.insert(key, value);
+        self.len += 1;
+
+        None
+    }
+
+    /// A plain search, it never restructures the tree (Splay `get` splays),
+    /// as the iterators borrowed from `&self` may be in the middle of it.
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.dict.basic_lookup(key)
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.dict.get_mut(key)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let res = self.dict.remove(key);
+
+        if res.is_some() {
+            self.len -= 1;
+        }
+
+        res
+    }
+
+    /// In ascending order of keys
+    pub fn iter(&'a self) -> impl Iterator<Item = (&'a K, &'a V)> + 'a {
+        self.dict.iter()
+    }
+
+    /// In ascending order of keys
+    pub fn range<R: RangeBounds<K> + 'a>(&'a self, range: R) -> impl Iterator<Item = (&'a K, &'a V)> + 'a {
+        self.dict.range(range)
+    }
+
+    pub fn keys(&'a self) -> impl Iterator<Item = &'a K> + 'a {
+        self.dict.keys()
+    }
+
+    pub fn values(&'a self) -> impl Iterator<Item = &'a V> + 'a {
+        self.dict.iter().map(|(_, value)| value)
+    }
+
+    pub fn self_validate(&self) -> Result<(), Box<dyn core::error::Error>> {
+        self.dict.self_validate()
+    }
+}
+
+
+impl<K, V, D> Coll for Map<K, V, D> {
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+
+impl<'a, K: CollKey + 'a, V: 'a, D: Dictionary<K, V> + BT<'a, K, V> + Default> Default
+    for Map<K, V, D>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+
+    macro_rules! test_map {
+        ($ty:ident) => {
+            for _ in 0..crate::test_size(20, 1) {
+                let mut map = $ty::new();
+                let mut ref_map = BTreeMap::new();
+
+                for _ in 0..300 {
+                    let k = rand::random::<u32>() % 200;
+                    let v = rand::random::<u32>();
+
+                    assert_eq!(map.insert(k, v), ref_map.insert(k, v));
+                }
+
+                for _ in 0..100 {
+                    let k = rand::random::<u32>() % 200;
+
+                    assert_eq!(map.remove(&k), ref_map.remove(&k));
+                    assert_eq!(map.contains_key(&k), ref_map.contains_key(&k));
+                }
+
+                for _ in 0..100 {
+                    let k = rand::random::<u32>() % 200;
+
+                    if let Some(v) = map.get_mut(&k) {
+                        *v += 1;
+                    }
+
+                    if let Some(v) = ref_map.get_mut(&k) {
+                        *v += 1;
+                    }
+
+                    assert_eq!(map.get(&k), ref_map.get(&k));
+                }
+
+                map.self_validate().unwrap();
+                assert_eq!(map.len(), ref_map.len());
+                assert!(map.iter().eq(ref_map.iter()));
+                assert!(map.keys().eq(ref_map.keys()));
+                assert!(map.values().eq(ref_map.values()));
+
+                for _ in 0..20 {
+                    let lo = rand::random::<u32>() % 220;
+                    let hi = lo + rand::random::<u32>() % 50;
+
+                    assert!(map.range(lo..hi).eq(ref_map.range(lo..hi)));
+                    assert!(map.range(..=lo).eq(ref_map.range(..=lo)));
+                    assert!(map.range(hi..).eq(ref_map.range(hi..)));
+                }
+            }
+        };
+    }
+
+
+    #[test]
+    fn test_map_bst() {
+        test_map!(AVLMap);
+        test_map!(RBMap);
+        test_map!(LLRBMap);
+        test_map!(AAMap);
+        test_map!(TreapMap);
+        test_map!(SplayMap);
+        test_map!(LSGMap);
+        test_map!(RawSTMap);
+    }
+
+    #[test]
+    fn test_map_get_in_iter() {
+        let mut map = SplayMap::new();
+
+        for k in 0..30 {
+            map.insert(k, k);
+        }
+
+        // a splay on the way would take the iterator back over the keys
+        let iters: [(Box<dyn Iterator<Item = _>>, _); 2] =
+            [(Box::new(map.iter()), 0..30), (Box::new(map.range(10..20)), 10..20)];
+
+        for (iter, expect) in iters {
+            let mut keys = vec![];
+
+            for (k, _) in iter {
+                assert_eq!(map.get(&(29 - k)), Some(&(29 - k)));
+                assert!(map.contains_key(&(k / 2)));
+                keys.push(*k);
+            }
+
+            assert!(keys.into_iter().eq(expect));
+        }
+    }
+
+    #[test]
+    fn test_map_btree() {
+        test_map!(B3Map);
+        test_map!(B4Map);
+        test_map!(BStar4Map);
+    }
+}
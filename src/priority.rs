@@ -0,0 +1,80 @@
+//! Where a randomized tree takes the priority of a new key from, it's a type parameter
+//! of the tree (Treap's `P`) defaulted to `GlobalRandom`, so a shape can be made reproducible:
+//! `Treap::with_rng(StdRng::seed_from_u64(7))` or `Treap::with_key_hash()`.
+//!
+//! A randomized structure added later takes a `P: Priority<K>` the same way.
+
+use core::hash::{Hash, Hasher};
+
+use crate::*;
+
+
+////////////////////////////////////////////////////////////////////////////////
+//// Structs
+
+/// `rand::random` (a global xorshift without `std`), the shapes differ from run to run
+#[derive(Default, Debug, Clone, Copy)]
+pub struct GlobalRandom;
+
+/// Draw from the rng
+#[cfg(any(feature = "std", test))]
+#[derive(Default, Debug, Clone)]
+pub struct RngPriority<R>(pub R);
+
+/// A hash of the key, the same keys get the same shape whatever the order they come in
+#[derive(Default, Debug, Clone, Copy)]
+pub struct KeyHash;
+
+/// FNV-1a with a SplitMix64 finish, fixed unlike the std `RandomState`
+struct FixedHasher(u64);
+
+
+////////////////////////////////////////////////////////////////////////////////
+//// Traits
+
+pub trait Priority<K> {
+    fn priority(&mut self, key: &K) -> usize;
+}
+
+
+////////////////////////////////////////////////////////////////////////////////
+//// Implement
+
+impl<K> Priority<K> for GlobalRandom {
+    fn priority(&mut self, _key: &K) -> usize {
+        random_usize()
+    }
+}
+
+#[cfg(any(feature = "std", test))]
+impl<K, R: rand::RngCore> Priority<K> for RngPriority<R> {
+    fn priority(&mut self, _key: &K) -> usize {
+        self.0.next_u64() as usize
+    }
+}
+
+impl<K: Hash> Priority<K> for KeyHash {
+    fn priority(&mut self, key: &K) -> usize {
+        let mut hasher = FixedHasher(0xcbf2_9ce4_8422_2325);
+
+        key.hash(&mut hasher);
+
+        hasher.finish() as usize
+    }
+}
+
+impl Hasher for FixedHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 = (self.0 ^ b as u64).wrapping_mul(0x100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        let mut x = self.0;
+
+        x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        x ^ (x >> 31)
+    }
+}
@@ -0,0 +1,651 @@
+//! Binary search tree specialization of the pointer-level traits
+
+use alloc::collections::VecDeque;
+use core::{
+    
+    fmt::{self, Write},
+};
+
+use either::Either;
+
+use crate::*;
+
+
+/// LF(key) < MID(key) < RH(key)
+pub trait BST<'a, K: CollKey + 'a, V: 'a>: BT<'a, K, V> {
+    /// The new node is freed if its key is already there.
+    ///
+    /// # Safety
+    ///
+    /// `new_node` is a node of this tree's kind, detached and allocated by `Box`.
+    unsafe fn basic_insert(
+        &mut self,
+        new_node: *mut (dyn BSTNode<'a, K, V> + 'a),
+    ) -> bool {
+        let key = BSTNode::key_bst(&*new_node);
+        let approxi_node = (*new_node).downcast(self.search_approximately(key)).unwrap();
+
+        if !approxi_node.is_null() && BSTNode::key_bst(&*approxi_node) == key {
+            drop(Box::from_raw(new_node));
+            return false;
+        }
+
+        // duplcate code for there is no guanrantee on Clone
+        if approxi_node.is_null() {
+            (*new_node).assign_paren((*new_node).null_mut());
+
+            self.assign_root((*new_node).as_bt_mut())
+        } else if key < BSTNode::key_bst(&*approxi_node) {
+            (*approxi_node).connect_left(new_node)
+        } else {
+            (*approxi_node).connect_right(new_node)
+        }
+
+        true
+    }
+
+    fn basic_remove(
+        &mut self,
+        key: &K,
+    ) -> Option<*mut (dyn BSTNode<'a, K, V> + 'a)> {
+        unsafe {
+            let approxi_node = self.search_approximately(key);
+
+            if approxi_node.is_null() {
+                return None;
+            }
+
+            let approxi_node = (*approxi_node).itself_bst_mut();
+
+            if BSTNode::key_bst(&*approxi_node) != key {
+                return None;
+            }
+
+            if (*approxi_node).left().is_null() {
+                self.subtree_shift((*approxi_node).as_bt_mut(), (*approxi_node).upcast((*approxi_node).right()))
+            } else if (*approxi_node).right().is_null() {
+                self.subtree_shift((*approxi_node).as_bt_mut(), (*approxi_node).upcast((*approxi_node).left()))
+            } else {
+                let y = BSTNode::successor_bst(&*approxi_node);
+                // y should be leaf.
+
+                if !core::ptr::addr_eq((*y).paren_bst(), approxi_node) {
+                    self.subtree_shift((*y).as_bt_mut(), (*y).upcast((*y).right()));
+                    (*y).assign_right((*approxi_node).right());
+                    (*(*y).right()).assign_paren((*y).as_bt_mut());
+                }
+                self.subtree_shift((*approxi_node).as_bt_mut(), (*y).as_bt_mut());
+                (*y).assign_left((*approxi_node).left());
+                (*(*y).left()).assign_paren((*y).as_bt_mut());
+            }
+
+            Some(approxi_node)
+        }
+    }
+
+    /// Fix the tree-specific info of `x` and `z` after rotation.
+    ///
+    /// # Safety
+    ///
+    /// `x` is just rotated down under `z`, both of this tree.
+    unsafe fn rotate_cleanup(
+        &mut self,
+        x: *mut (dyn BSTNode<'a, K, V> + 'a),
+        z: *mut (dyn BSTNode<'a, K, V> + 'a),
+    );
+
+    /// Hook after each rotation for the observer of the tree, see `Observer::on_rotate`.
+    ///
+    /// # Safety
+    ///
+    /// The same as `rotate_cleanup`.
+    unsafe fn on_rotate(
+        &mut self,
+        _x: *mut (dyn BSTNode<'a, K, V> + 'a),
+        _z: *mut (dyn BSTNode<'a, K, V> + 'a),
+        _rotation: Either<(), ()>,
+    ) {
+    }
+
+    /// Hook after each double rotation, see `Observer::on_double_rotate`.
+    ///
+    /// # Safety
+    ///
+    /// `x` is the new root of the subtree.
+    unsafe fn on_double_rotate(
+        &mut self,
+        _x: *mut (dyn BSTNode<'a, K, V> + 'a),
+        _snd_rotation: Either<(), ()>,
+    ) {
+    }
+
+    /// Simple Rotation
+    /// ```ignore
+    ///             rotate left
+    ///    x        =========>          z
+    ///  /  \                          / \
+    /// t1   z                        x   t4
+    /// |   / \                      / \   |
+    ///   t23 t4                    t1 t23 |
+    ///     |  |                     |   |
+    ///        |
+    /// ```
+    ///
+    /// # Safety
+    ///
+    /// `x` is a node of this tree with a child on the side it rotates from.
+    unsafe fn rotate(
+        &mut self,
+        x: *mut (dyn BSTNode<'a, K, V> + 'a),
+        rotation: Either<(), ()>, // rotate to left = from right rotation
+    ) -> *mut (dyn BSTNode<'a, K, V> + 'a) {
+        let z = if rotation.is_left() {
+            (*x).right()
+        } else {
+            (*x).left()
+        };
+
+        let t23 = if rotation.is_left() {
+            (*z).left()
+        } else {
+            (*z).right()
+        };
+
+        if !t23.is_null() {
+            (*t23).assign_paren((*x).as_bt_mut());
+        }
+
+        if rotation.is_left() {
+            (*x).assign_right(t23);
+            (*z).assign_left(x);
+        } else {
+            (*x).assign_left(t23);
+            (*z).assign_right(x);
+        }
+
+        self.subtree_shift((*x).as_bt_mut(), (*z).as_bt_mut());
+        (*x).assign_paren((*z).as_bt_mut());
+
+        self.rotate_cleanup(x, z);
+        self.on_rotate(x, z, rotation);
+
+        z
+    }
+
+
+    /// Double Rotation
+    /// ```ignore
+    ///             rotate [right]-left         rotate right-[left]
+    ///    x        =========>         x        =========>       y
+    ///  /   \                        /  \                      / \
+    /// t1    z                      t1   y                    x   z
+    /// |   /  \                     |   / \                  / \ / \
+    ///    y   t4                      t2   z                t1 t2t3t4
+    ///   / \   |                       |  / \                |  | | |
+    ///  t2 t3                            t3 t4
+    ///   |  |                            |   |
+    /// ```
+    ///
+    /// # Safety
+    ///
+    /// `x` is a node of this tree with the grandchild `y` as drawn.
+    unsafe fn double_rotate(
+        &mut self,
+        x: *mut (dyn BSTNode<'a, K, V> + 'a),
+        snd_rotation: Either<(), ()>,
+    ) -> *mut (dyn BSTNode<'a, K, V> + 'a) {
+        let z = if snd_rotation.is_left() {
+            (*x).right()
+        } else {
+            (*x).left()
+        };
+
+        self.rotate(z, snd_rotation.reverse());
+        let y = self.rotate(x, snd_rotation);
+
+        self.on_double_rotate(y, snd_rotation);
+
+        y
+
+        // // Manualy Implements
+        // /* FIRST ROTATION */
+        // // z is by 2 higher than its sibing(t1)
+        // // y is by 1 higher than its sibling(t4) (thereis shouldn't be empty)
+        // let z = if snd_rotation.is_left() {
+        //     (*x).right
+        // } else {
+        //     (*x).left
+        // };
+
+        // let y = if snd_rotation.is_left() {
+        //     (*z).left
+        // } else {
+        //     (*z).right
+        // };
+
+        // let (t2, t3) = if snd_rotation.is_left() {
+        //     ((*y).left, (*y).right)
+        // } else {
+        //     ((*y).right, (*y).left)
+        // };
+
+        // if !t3.is_null() {
+        //     (*t3).assign_paren(z);
+        // }
+        // (*z).assign_paren(y);
+
+        // if snd_rotation.is_left() {
+        //     (*z).assign_left(t3);
+        //     (*y).assign_right(z);
+        // } else {
+        //     (*z).assign_right(t3);
+        //     (*y).assign_left(z);
+        // }
+
+        // // skip x-R->z => x-R->y for it would be overrided by second rotation
+
+        // /* SECOND ROTATION */
+        // if snd_rotation.is_left() {
+        //     (*x).assign_right(t2);
+        //     (*y).assign_left(x);
+        // } else {
+        //     (*x).assign_left(t2);
+        //     (*y).assign_right(x);
+        // }
+        // if !t2.is_null() {
+        //     (*t2).assign_paren(x);
+        // }
+
+        // self.subtree_shift(x, y);
+        // (*x).assign_paren(y);
+
+        // y
+    }
+
+    /// BFS Echo
+    fn echo_in_mm(
+        &self,
+        cache: &mut String,
+        action: fn(
+            *mut (dyn BSTNode<'a, K, V> + 'a),
+            &mut String,
+        ) -> fmt::Result,
+    ) -> fmt::Result {
+        if self.root().is_null() {
+            writeln!(cache, "ROOT: null")
+        } else {
+            unsafe {
+                writeln!(
+                    cache,
+                    "ROOT: {:?}",
+                    BSTNode::key_bst(&*self.root_bst())
+                )?;
+
+                BSTNode::echo_in_mm(&*self.root_bst(), cache, action)
+            }
+        }
+    }
+
+    #[cfg(any(feature = "std", test))]
+    fn just_echo_stdout(&self) {
+        if !self.root().is_null() {
+            unsafe { BSTNode::just_echo_stdout(&*self.root_bst()) }
+        }
+    }
+
+
+    fn nodes_iter(&'a self) -> Box<dyn Iterator<Item = *mut (dyn BSTNode<'a, K, V> + 'a)> + 'a> {
+        if self.root().is_null() {
+            return Box::new(core::iter::from_fn(|| None));
+        }
+
+        unsafe {
+            (*self.root_bst()).nodes_iter()
+        }
+
+    }
+}
+
+
+pub trait BSTNode<'a, K: CollKey + 'a, V: 'a>: BTNode<'a, K, V> {
+    fn left(&self) -> *mut (dyn BSTNode<'a, K, V> + 'a) {
+        self.downcast(BTNode::child(self, 0)).unwrap()
+    }
+    fn right(&self) -> *mut (dyn BSTNode<'a, K, V> + 'a) {
+        self.downcast(BTNode::child(self, 1)).unwrap()
+    }
+    fn sibling(&self) -> *mut (dyn BSTNode<'a, K, V> + 'a) {
+        unsafe {
+            let paren = self.paren_bst();
+            debug_assert!(!paren.is_null());
+
+            BSTNode::child(&*paren, (*self).dir().reverse())
+        }
+    }
+
+    fn uncle(&self) -> *mut (dyn BSTNode<'a, K, V> + 'a) {
+        unsafe {
+            let paren = self.paren_bst();
+            debug_assert!(!paren.is_null());
+
+            (*paren).sibling()
+
+        }
+    }
+
+    /// Explicit upcast to `dyn BTNode`, the counterpart of `try_as_bst_mut`
+    fn as_bt_mut(&self) -> *mut (dyn BTNode<'a, K, V> + 'a) {
+        self.itself_mut()
+    }
+
+    /// Upcast a node of the same tree, null into the null of this node type.
+    /// # Safety
+    ///
+    /// `x` is null or a live node.
+    unsafe fn upcast(
+        &self,
+        x: *mut (dyn BSTNode<'a, K, V> + 'a),
+    ) -> *mut (dyn BTNode<'a, K, V> + 'a) {
+        if x.is_null() {
+            self.null_mut()
+        } else {
+            (*x).as_bt_mut()
+        }
+    }
+
+    fn key_bst(&self) -> &K {
+        BTNode::key(self, 0).unwrap()
+    }
+    fn value_bst(&self) -> &V {
+        BTNode::value(self, 0).unwrap()
+    }
+    fn value_bst_mut(&mut self) -> &mut V {
+        BTNode::value_mut(self, 0).unwrap()
+    }
+
+    ////////////////////////////////////////////////////////////////////////////
+    //// Introspection
+
+    fn dir(&self) -> Either<(), ()> {
+        unsafe {
+            debug_assert!(!self.paren().is_null());
+
+            if (*self.paren()).index_of_child(self.itself_mut()) == 0 {
+                Either::Left(())
+            } else {
+                Either::Right(())
+            }
+        }
+    }
+
+    fn child(
+        &self,
+        direction: Either<(), ()>,
+    ) -> *mut (dyn BSTNode<'a, K, V> + 'a) {
+        if direction.is_left() {
+            self.left()
+        } else {
+            self.right()
+        }
+    }
+
+    fn child_bst(
+        &self,
+        direction: Either<(), ()>,
+    ) -> *mut (dyn BSTNode<'a, K, V> + 'a) {
+        if direction.is_left() {
+            self.left()
+        } else {
+            self.right()
+        }
+    }
+
+    fn child_height(&self, direction: Either<(), ()>) -> i32 {
+        if BSTNode::child(self, direction).is_null() {
+            -1
+        } else {
+            unsafe { (*BSTNode::child(self, direction)).height() }
+        }
+    }
+
+    /// # Safety
+    ///
+    /// `left` is null or a live node of the same tree, so are the other child arguments below.
+    unsafe fn assign_left(&mut self, left: *mut (dyn BSTNode<'a, K, V> + 'a)) {
+        self.assign_child(self.upcast(left), 0)
+    }
+
+    /// # Safety
+    ///
+    /// See `assign_left`.
+    unsafe fn assign_right(&mut self, right: *mut (dyn BSTNode<'a, K, V> + 'a)) {
+        self.assign_child(self.upcast(right), 1)
+    }
+
+    /// # Safety
+    ///
+    /// See `assign_left`.
+    unsafe fn connect_left(&mut self, child: *mut (dyn BSTNode<'a, K, V> + 'a)) {
+        self.connect_child(self.upcast(child), 0)
+    }
+
+    /// # Safety
+    ///
+    /// See `assign_left`.
+    unsafe fn connect_right(&mut self, child: *mut (dyn BSTNode<'a, K, V> + 'a)) {
+        self.connect_child(self.upcast(child), 1)
+    }
+
+    fn calc_left_height(&self) -> i32 {
+        if !self.left().is_null() {
+            unsafe { (*self.left()).calc_height() }
+        } else {
+            -1
+        }
+    }
+
+    fn calc_right_height(&self) -> i32 {
+        if !self.right().is_null() {
+            unsafe { (*self.right()).calc_height() }
+        } else {
+            -1
+        }
+    }
+
+    fn left_height(&self) -> i32 {
+        if !self.left().is_null() {
+            unsafe { (*self.left()).height() }
+        } else {
+            -1
+        }
+    }
+
+    fn right_height(&self) -> i32 {
+        if !self.right().is_null() {
+            unsafe { (*self.right()).height() }
+        } else {
+            -1
+        }
+    }
+
+    fn precessor_bst(&self) -> *mut (dyn BSTNode<'a, K, V> + 'a) {
+        let mut x = self.itself_bst_mut();
+
+        unsafe {
+            if !(*x).left().is_null() {
+                return (*(*(*x).left()).maximum()).try_as_bst_mut().unwrap();
+            }
+
+            let mut y = (*x).paren_bst();
+
+            while !y.is_null() && core::ptr::addr_eq(x, (*y).left()) {
+                x = y;
+                y = (*y).paren_bst();
+            }
+
+            y
+        }
+    }
+
+    fn successor_bst(&self) -> *mut (dyn BSTNode<'a, K, V> + 'a) {
+        let mut x = self.itself_bst_mut();
+
+        unsafe {
+            if !(*x).right().is_null() {
+                return (*(*(*x).right()).minimum()).try_as_bst_mut().unwrap();
+            }
+
+            let mut y = (*x).paren_bst();
+
+            while !y.is_null() && core::ptr::addr_eq(x, (*y).right()) {
+                x = y;
+                y = (*y).paren_bst();
+            }
+
+            y
+        }
+    }
+
+    // fn swap_with_successor_until_null(
+    //     &mut self,
+    // ) -> *mut (dyn BSTNode<'a, K, V> + 'a) {
+    //     unsafe {
+    //         let mut x = self.itself_bst_mut();
+    //         let mut p;
+
+    //         loop {
+    //             p = x;
+    //             x = BSTNode::successor(&*x);
+
+    //             if x.is_null() {
+    //                 break;
+    //             }
+
+    //             (*p).swap_with(x);
+    //         }
+
+    //         p
+    //     }
+    // }
+
+
+    /// Just swap key and value
+    ///
+    /// # Safety
+    ///
+    /// `other` is a valid node which isn't `self`.
+    unsafe fn swap_with(&mut self, other: *mut (dyn BSTNode<'a, K, V> + 'a)) {
+        debug_assert!(!other.is_null());
+
+        core::mem::swap(self.key_mut(0).unwrap(), (*other).key_mut(0).unwrap());
+        core::mem::swap(self.value_mut(0).unwrap(), (*other).value_mut(0).unwrap());
+    }
+
+    #[cfg(any(feature = "std", test))]
+    fn just_echo_stdout(&self) {
+        let mut cache = String::new();
+
+        BSTNode::echo_in_mm(self, &mut cache, |_, _| Ok(())).unwrap();
+
+        println!("{}", cache);
+    }
+
+    /// BFS Echo
+    fn echo_in_mm(
+        &self,
+        cache: &mut String,
+        action: fn(
+            *mut (dyn BSTNode<'a, K, V> + 'a),
+            &mut String,
+        ) -> fmt::Result,
+    ) -> fmt::Result {
+        unsafe {
+            writeln!(cache, "Entry: {:?}", BSTNode::key_bst(self))?;
+
+            let mut this_level_queue: VecDeque<
+                *mut (dyn BSTNode<'a, K, V> + 'a),
+            > = VecDeque::new();
+            this_level_queue.push_back(self.itself_bst_mut());
+            let mut level = 0;
+
+            while !this_level_queue.is_empty() {
+                writeln!(cache)?;
+                writeln!(
+                    cache,
+                    "############ Level: {} #############",
+                    level
+                )?;
+                writeln!(cache)?;
+
+                let mut nxt_level_queue: VecDeque<
+                    *mut (dyn BSTNode<'a, K, V> + 'a),
+                > = VecDeque::new();
+
+                while !this_level_queue.is_empty() {
+                    let x = this_level_queue.pop_front().unwrap();
+
+                    // writeln!(cache, "{:?}", (*x).key() )?;
+
+                    action(x, cache)?;
+
+                    if !(*x).left().is_null() {
+                        writeln!(
+                            cache,
+                            "{:?} -L-> {:?}",
+                            BSTNode::key_bst(&*x),
+                            BSTNode::key_bst(&*(*x).left())
+                        )?;
+
+                        nxt_level_queue.push_back((*x).left())
+                    } else {
+                        writeln!(cache, "{:?} -L-> null", BSTNode::key_bst(&*x))?;
+                    }
+
+                    if !(*x).right().is_null() {
+                        writeln!(
+                            cache,
+                            "{:?} -R-> {:?}",
+                            BSTNode::key_bst(&*x),
+                            BSTNode::key_bst(&*(*x).right())
+                        )?;
+
+                        nxt_level_queue.push_back((*x).right())
+                    } else {
+                        writeln!(cache, "{:?} -R-> null", BSTNode::key_bst(&*x))?;
+                    }
+
+                    writeln!(cache)?;
+                }
+
+                this_level_queue = nxt_level_queue;
+                level += 1;
+            }
+
+            writeln!(cache, "------------- end --------------")?;
+            writeln!(cache)?;
+        }
+
+
+        Ok(())
+    }
+
+
+    // Infix order (DFS)
+    fn nodes_iter(&'a self) -> Box<dyn Iterator<Item = *mut (dyn BSTNode<'a, K, V> + 'a)> + 'a> {
+        unsafe {
+            let mut x = (*self.minimum()).try_as_bst_mut().unwrap();
+
+            Box::new(core::iter::from_fn(move || {
+                if !x.is_null() {
+                    let prev = x;
+                    x = (*x).successor_bst();
+                    Some(prev)
+                } else {
+                    None
+                }
+
+            }))
+        }
+    }
+
+}
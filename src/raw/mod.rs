@@ -0,0 +1,1296 @@
+//! Pointer-level traits which the trees are built on,
+//! nodes are passed around as raw pointers,
+//! and the methods dereferencing one given by the caller are `unsafe fn`.
+//!
+//! It's for implementing the trees, use the safe [`Map`](crate::map::Map) and [`Set`](crate::set::Set) instead.
+//!
+//! Send and Sync: a tree owns its nodes exclusively, the nodes are never shared across trees,
+//! and they are only restructured through `&mut self` except `Splay::get`.
+//! So a tree is `Send`/`Sync` as long as its keys and values are, but `Splay` is only `Send`.
+
+pub mod bst;
+
+use alloc::collections::VecDeque;
+use core::{
+    cmp::Ordering,
+    fmt::{self, Debug, Write},
+    hash::{Hash, Hasher},
+    ops::RangeBounds,
+    ptr,
+};
+
+use itertools::Itertools;
+
+use crate::*;
+
+pub use self::bst::{BSTNode, BST};
+
+
+/// B-Tree (not restrictly, storing info in the internal node)
+pub trait BT<'a, K: CollKey + 'a, V: 'a>: Dictionary<K, V> {
+    fn order(&self) -> usize;  // >= 2
+    fn root(&self) -> *mut (dyn BTNode<'a, K, V> + 'a);
+    fn assign_root(&mut self, root: *mut (dyn BTNode<'a, K, V> + 'a));
+    /// # Safety
+    ///
+    /// `root` is null or a live node of this tree.
+    unsafe fn reset_root(&mut self, root: *mut (dyn BTNode<'a, K, V> + 'a)) {
+        if !root.is_null() {
+            (*root).assign_paren((*root).null_mut());
+        }
+
+        self.assign_root(root);
+    }
+
+    /// alias as transplant
+    ///
+    /// # Safety
+    ///
+    /// `u` is a live node of this tree, `v` is null or a live node.
+    unsafe fn subtree_shift(
+        &mut self,
+        u: *mut (dyn BTNode<'a, K, V> + 'a),
+        v: *mut (dyn BTNode<'a, K, V> + 'a),
+    ) {
+        let u_paren = (*u).paren();
+
+        if u_paren.is_null() {
+            self.assign_root(v);
+        } else {
+            let u_idx = (*u_paren).index_of_child(u);
+            (*u_paren).assign_child(v, u_idx);
+        }
+
+        if !v.is_null() {
+            (*v).assign_paren(u_paren)
+        }
+    }
+
+    // ////////////////////////////////////////////////////////////////////////////
+    // //// Introspection
+    // fn try_as_bst(&self) -> Result<*const (dyn BST<'a, K, V> + 'a), ()>;
+    // fn try_as_bst_mut(&self) -> Result<*mut (dyn BST<'a, K, V> + 'a), ()> {
+    //     if let Ok(p) = self.try_as_bst() {
+    //         Ok(p as *mut (dyn BST<'a, K, V> + 'a))
+    //     } else {
+    //         Err(())
+    //     }
+    // }
+
+    /// The tree must not be empty
+    fn root_bst(&self) -> *mut (dyn BSTNode<'a, K, V> + 'a) {
+        debug_assert!(!self.root().is_null());
+
+        unsafe { (*self.root()).itself_bst_mut() }
+    }
+
+    fn minimum(&self) -> *mut (dyn BTNode<'a, K, V> + 'a) {
+        unsafe{
+            if self.root().is_null() {
+                self.root()
+            } else {
+                (*self.root()).minimum()
+            }
+        }
+    }
+
+
+    fn maximum(&self) -> *mut (dyn BTNode<'a, K, V> + 'a) {
+        unsafe {
+            if self.root().is_null() {
+                self.root()
+            } else {
+                (*self.root()).maximum()
+            }
+        }
+    }
+
+
+    fn search_approximately(
+        &self,
+        income_key: &K,
+    ) -> *mut (dyn BTNode<'a, K, V> + 'a) {
+        if !self.root().is_null() {
+            let (mut visits, mut comparisons) = (0, 0);
+            let x = unsafe {
+                (*self.root()).search_counting(income_key, &mut visits, &mut comparisons)
+            };
+
+            self.on_search(visits, comparisons);
+
+            x
+        } else {
+            self.root()
+        }
+    }
+
+    /// Hook after each search for the observer of the tree, see `Observer::on_search`.
+    fn on_search(&self, _visits: usize, _comparisons: usize) {}
+
+    /// Items in infix order, skip the ones without value (e.g. lazy deleted)
+    fn iter(&'a self) -> Box<dyn Iterator<Item = (&'a K, &'a V)> + 'a> {
+        unsafe { infix_iter(self.root()) }
+    }
+
+    /// Items in the range in infix order, O(h) to the first one
+    fn range<R: RangeBounds<K> + 'a>(&'a self, range: R) -> Box<dyn Iterator<Item = (&'a K, &'a V)> + 'a>
+    where
+        Self: Sized,
+    {
+        unsafe { infix_range_iter(self.root(), range) }
+    }
+
+    /// Keys in infix order
+    fn keys(&'a self) -> Box<dyn Iterator<Item = &'a K> + 'a> {
+        let iter = self.iter().map(|(key, _)| key);
+
+        Box::new(iter)
+    }
+
+    /// BFS Echo
+    fn echo_in_mm(
+        &self,
+        cache: &mut String,
+        action: fn(
+            *mut (dyn BTNode<'a, K, V> + 'a),
+            &mut String,
+        ) -> core::fmt::Result,
+    ) -> core::fmt::Result {
+        if self.root().is_null() {
+            writeln!(cache, "ROOT: null")
+        } else {
+            unsafe {
+                writeln!(cache, "ROOT: {:?}", (*self.root()).format_keys())?;
+
+                (*self.root()).echo_in_mm(cache, action)
+            }
+        }
+    }
+
+    // fn bfs_do(
+    //     &self,
+    //     action: fn(
+    //         *mut (dyn BSTNode<'a, K, V> + 'a),
+    //     )
+    // ) {
+    //     if !self.root().is_null() {
+    //         unsafe{ (*self.root_bst()).bfs_do(action) }
+    //     }
+
+    // }
+
+    #[cfg(any(feature = "std", test))]
+    fn just_echo_stdout(&self) {
+        if !self.root().is_null() {
+            unsafe { (*self.root()).just_echo_stdout() }
+        } else {
+            println!("EMPTY.")
+        }
+    }
+
+    fn calc_height(&self) -> i32 {
+        if self.root().is_null() {
+            return -1;
+        }
+
+        unsafe { (*self.root()).calc_height() }
+    }
+
+    fn height(&self) -> i32 {
+        if self.root().is_null() {
+            return -1;
+        }
+
+        unsafe { (*self.root()).height() }
+    }
+
+    fn total(&self) -> usize {
+        if self.root().is_null() {
+            0
+        } else {
+            unsafe { (*self.root()).total() }
+        }
+    }
+
+    fn basic_lookup(
+        &self,
+        income_key: &K,
+    ) -> Option<&V> {
+        let res = self.search_approximately(income_key);
+
+        if res.is_null() {
+            None
+        } else {
+            unsafe {
+                // println!("{:?}", (*res).format_keys());
+
+                if let Some(idx) = (*res).find_pos_of_key(income_key) {
+                    (*res).value(idx).map(|value| &*(value as *const V))
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    fn basic_lookup_mut(
+        &mut self,
+        income_key: &K,
+    ) -> Option<&mut V> {
+        let res = self.search_approximately(income_key);
+
+        if res.is_null() {
+            None
+        } else {
+            unsafe {
+                // println!("{:?}", (*res).format_keys());
+
+                if let Some(idx) = (*res).find_pos_of_key(income_key) {
+                    (*res).value_mut(idx).map(|value| &mut *(value as *mut V))
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    fn basic_modify(&mut self, key: &K, value: V) -> bool {
+        unsafe {
+            let app_node = self.search_approximately(key);
+
+            if app_node.is_null() {
+                false
+            } else if let Some(idx) = (*app_node).find_pos_of_key(key) {
+                (*app_node).assign_value(value, idx);
+                true
+            } else {
+                false
+            }
+        }
+    }
+
+    fn bfs_do(
+        &self,
+        action: fn(
+            *mut (dyn BTNode<'a, K, V> + 'a),
+        )
+    ) {
+        if !self.root().is_null() {
+            unsafe{ (*self.root()).bfs_do(action) }
+        }
+
+    }
+
+    fn basic_self_validate(&self) -> Result<(), Box<dyn core::error::Error>> {
+        if self.root().is_null() {
+            return Ok(());
+        }
+
+        unsafe {
+            (*self.root()).basic_self_validate()?;
+
+            // all leaves at the same depth (B-Tree)
+            if self.order() > 2 {
+                let mut leaf_depths = vec![];
+                let mut stack = vec![(self.root(), 0)];
+
+                while let Some((x, depth)) = stack.pop() {
+                    if (*x).is_leaf() {
+                        leaf_depths.push(depth);
+                    }
+
+                    for i in 0..(*x).children_num() {
+                        stack.push(((*x).child(i), depth + 1));
+                    }
+                }
+
+                if !leaf_depths.iter().all_equal() {
+                    return Err(format!("leaf depths: {leaf_depths:?}").into());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+}
+
+
+/// B-Tree Node
+pub trait BTNode<'a, K: CollKey + 'a, V: 'a> {
+
+    ////////////////////////////////////////////////////////////////////////////
+    //// Introspection
+
+    /// The pointer the node was allocated with (kept in the node),
+    /// never derived from `&self`, so it stays writable for the aliasing model.
+    fn itself(&self) -> *const (dyn BTNode<'a, K, V> + 'a);
+    fn itself_mut(&self) -> *mut (dyn BTNode<'a, K, V> + 'a) {
+        self.itself() as *mut (dyn BTNode<'a, K, V> + 'a)
+    }
+    fn null(&self) -> *const (dyn BTNode<'a, K, V> + 'a);
+    fn null_mut(&self) -> *mut (dyn BTNode<'a, K, V> + 'a) {
+        self.null() as *mut (dyn BTNode<'a, K, V> + 'a)
+    }
+
+
+    #[allow(clippy::result_unit_err, reason = "Err is just \"not a BST node\"")]
+    fn try_as_bst(&self) -> Result<*const (dyn BSTNode<'a, K, V> + 'a), ()>;
+    #[allow(clippy::result_unit_err, reason = "as `try_as_bst`")]
+    fn try_as_bst_mut(&self) -> Result<*mut (dyn BSTNode<'a, K, V> + 'a), ()> {
+        if let Ok(p) = self.try_as_bst() {
+            Ok(p as *mut (dyn BSTNode<'a, K, V> + 'a))
+        } else {
+            Err(())
+        }
+    }
+    /// Downcast a node of the same tree as this one, null included.
+    #[allow(clippy::result_unit_err, reason = "Err is just \"not a BST node\"")]
+    fn downcast(
+        &self,
+        x: *mut (dyn BTNode<'a, K, V> + 'a),
+    ) -> Result<*mut (dyn BSTNode<'a, K, V> + 'a), ()>;
+    fn itself_bst(&self) -> *const (dyn BSTNode<'a, K, V> + 'a) {
+        self.try_as_bst().unwrap()
+    }
+    fn itself_bst_mut(&self) -> *mut (dyn BSTNode<'a, K, V> + 'a) {
+        self.try_as_bst_mut().unwrap()
+    }
+
+    fn order(&self) -> usize;  // >= 2
+
+    /// 0 <= idx <= order, child(order) is temporary case.
+    fn child(&self, idx: usize) -> *mut (dyn BTNode<'a, K, V> + 'a);
+    fn child_first(&self) -> *mut (dyn BTNode<'a, K, V> + 'a) {
+        self.child(0)
+    }
+    fn child_last(&self) -> *mut (dyn BTNode<'a, K, V> + 'a) {
+        self.child(self.order() - 1)
+    }
+
+    /// 0 <= idx <= order, child(order) is temporary case.
+    fn assign_child(&mut self, child: *mut (dyn BTNode<'a, K, V> + 'a), idx: usize);
+    fn assign_value(&mut self, value: V, idx: usize) {
+        *self.value_mut(idx).unwrap() = value;
+    }
+    fn assign_paren(&mut self, paren: *mut (dyn BTNode<'a, K, V> + 'a));
+
+    fn paren(&self) -> *mut (dyn BTNode<'a, K, V> + 'a);
+    fn paren_bst(&self) -> *mut (dyn BSTNode<'a, K, V> + 'a) {
+        self.downcast(self.paren()).unwrap()
+    }
+
+    /// Keys and values are stored inline in node.
+    fn key(&self, idx: usize) -> Option<&K>;
+    fn key_mut(&mut self, idx: usize) -> Option<&mut K>;
+
+    fn value(&self, idx: usize) -> Option<&V>;
+    fn value_mut(&mut self, idx: usize) -> Option<&mut V>;
+
+    /// # Safety
+    ///
+    /// `child` is null or a live node of the same tree.
+    unsafe fn connect_child(&mut self, child: *mut (dyn BTNode<'a, K, V> + 'a), idx: usize) {
+        if !child.is_null() {
+            (*child).assign_paren(self.itself_mut());
+        }
+
+        self.assign_child(child, idx);
+    }
+
+    // fn right_sibling(&self) -> *mut (dyn BTNode<'a, K, V> + 'a) {
+    //     let paren = (*self).paren();
+
+    //     if paren.is_null() {
+    //         return paren;
+    //     }
+
+    //     unsafe {
+    //         let idx = (*paren).index_of_child(self.itself_mut());
+
+    //         (*paren).child(idx + 1)
+    //     }
+
+    // }
+
+
+    // fn left_sibling(&self) -> *mut (dyn BTNode<'a, K, V> + 'a) {
+    //     let paren = (*self).paren();
+
+    //     if paren.is_null() {
+    //         return paren;
+    //     }
+
+    //     unsafe {
+    //         let idx = (*paren).index_of_child(self.itself_mut());
+
+    //         if idx > 0 {
+    //             (*paren).child(idx - 1)
+    //         } else {
+    //             self.null_mut()
+    //         }
+    //     }
+
+    // }
+
+
+    fn index_of_child(&self, child: *mut (dyn BTNode<'a, K, V> + 'a)) -> usize {
+        for i in 0..self.order() {
+            // as *const () just to ignore the vtable variant from the fat pointer
+            if self.child(i) as *const () == child as *const () {
+                return i;
+            }
+
+        }
+
+        unreachable!()
+    }
+
+    /// key must in it!!
+    fn index_of_key(&self, key: &K) -> usize {
+        for i in 0..self.order() {
+            if self.key(i).unwrap() == key {
+                return i;
+            }
+
+        }
+
+        unreachable!()
+    }
+
+    fn find_pos_of_key(&self, key: &K) -> Option<usize> {
+        for i in 0..self.order() {
+            if let Some(here_key) = self.key(i) {
+                if here_key == key {
+                    return Some(i);
+                }
+            }
+        }
+
+        None
+    }
+
+    /// If this node contains key (exclude the subtree)
+    #[inline]
+    fn node_contains(&self, key: &K) -> bool {
+        for i in 0..self.order() {
+            let key_opt = self.key(i);
+            if key_opt.is_some() && key_opt.unwrap() == key {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    fn node_last_key(&self) -> &K {
+        self.key(self.key_num() - 1).unwrap()
+    }
+
+    fn node_first_key(&self) -> &K {
+        self.key(0).unwrap()
+    }
+
+    /// How many key-values does this node contains?
+    fn node_size(&self) -> usize {
+        self.key_num()
+    }
+
+    fn key_num(&self) -> usize {
+        for i in 0..self.order() {
+            if self.key(i).is_none() {
+                return i;  // i must be greater than one in this case.
+            }
+        }
+
+        self.order()
+    }
+
+    fn key_iter(&'a self) -> Box<dyn Iterator<Item=&'a K> + 'a> {
+        let mut i = -1i32;
+
+        Box::new(core::iter::from_fn(move || -> Option<&K> {
+            i += 1;
+            self.key(i as usize)
+        }))
+    }
+
+    fn val_num(&self) -> usize {
+        for i in 0..self.order() {
+            if self.value(i).is_none() {
+                return i;  // i must be greater than one in this case.
+            }
+        }
+
+        self.order()
+    }
+
+    fn children_num(&self) -> usize {
+        for i in 0..self.order() + 1 {
+            if self.child(i).is_null() {
+                return i;  // i must be greater than one in this case.
+            }
+        }
+
+        self.order()
+    }
+
+    fn node_is_overfilled(&self) -> bool {
+        self.node_size() >= self.order()
+    }
+
+    fn node_is_fullfilled(&self) -> bool {
+        self.node_size() >= self.order() - 1
+    }
+
+    fn height(&self) -> i32;
+
+    #[inline]
+    fn calc_height(&self) -> i32 {
+        (0..self.order())
+        .map(|i| {
+            if self.child(i).is_null() {
+                -1
+            } else {
+                unsafe { (*self.child(i)).calc_height() }
+            }
+        }).max().unwrap() + 1
+
+    }
+
+
+    fn total(&self) -> usize {
+        let mut total = 1;
+
+        for i in 0..self.order() {
+            let child = self.child(i);
+
+            if !child.is_null() {
+                unsafe{ total += (*child).total() + 1; }
+            }
+        }
+
+        total
+    }
+
+
+    fn minimum(&self) -> *mut (dyn BTNode<'a, K, V> + 'a) {
+        let mut x = self.itself_mut();
+
+        while unsafe { !(*x).child_first().is_null() } {
+            unsafe { x = (*x).child_first() }
+        }
+
+        x
+    }
+
+
+    fn maximum(&self) -> *mut (dyn BTNode<'a, K, V> + 'a) {
+        let mut x = self.itself_mut();
+
+        // child_last is null for B-tree node which is not full
+        while unsafe { !(*x).child((*x).node_size()).is_null() } {
+            unsafe { x = (*x).child((*x).node_size()) }
+        }
+
+        x
+    }
+
+    fn is_leaf(&self) -> bool {
+        for i in 0..self.order() {
+            if !self.child(i).is_null() {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// successor of item whose key is key.
+    fn successor(&self, key: &K) -> BTItem<'a, K, V> {
+        let k_idx = self.index_of_key(key);
+
+        unsafe {
+            if self.is_leaf() {
+                if self.key(k_idx + 1).is_none() {  // Goto parent
+                    let mut x = self.itself_mut();
+                    let mut y = (*x).paren();
+
+                    while !y.is_null() {
+                        let idx = (*y).index_of_child(x);
+
+                        if (*y).key(idx).is_some() {
+                            return BTItem::new(y, idx);
+                        }
+
+                        x = y;
+                        y = (*x).paren();
+                    }
+
+                    BTItem::new(y, 0)
+
+                } else {
+                    BTItem::new(self.itself_mut(), k_idx + 1)
+                }
+
+            } else {
+
+                BTItem::new((*self.child(k_idx + 1)).minimum(), 0)
+            }
+        }
+    }
+
+
+    /// precessor of item whose key is key.
+    fn precessor(&self, key: &K) -> BTItem<'a, K, V> {
+        let k_idx = self.index_of_key(key);
+
+        unsafe {
+            if self.is_leaf() {
+                if k_idx == 0 {  // Goto parent
+                    let mut x = self.itself_mut();
+                    let mut y = (*x).paren();
+
+                    while !y.is_null() {
+                        let idx = (*y).index_of_child(x);
+
+                        if idx > 0 {
+                            return BTItem::new(y, idx - 1);
+                        }
+
+                        x = y;
+                        y = (*x).paren();
+                    }
+
+                    BTItem::new(y, 0)
+
+                } else {
+                    BTItem::new(self.itself_mut(), k_idx - 1)
+                }
+
+            } else {
+                let pre_ptr = (*self.child(k_idx)).maximum();
+
+                BTItem::new(pre_ptr, (*pre_ptr).node_size() - 1)
+            }
+        }
+    }
+
+
+    #[inline]
+    fn search_approximately(
+        &self,
+        income_key: &K,
+    ) -> *mut (dyn BTNode<'a, K, V> + 'a) {
+        self.search_counting(income_key, &mut 0, &mut 0)
+    }
+
+    /// `search_approximately` adding up the nodes visited and the key comparisons
+    #[inline]
+    fn search_counting(
+        &self,
+        income_key: &K,
+        visits: &mut usize,
+        comparisons: &mut usize,
+    ) -> *mut (dyn BTNode<'a, K, V> + 'a) {
+        let mut y = self.null_mut();
+        let mut x = self.itself_mut();
+
+        unsafe {
+            'descend: while !x.is_null() {
+                y = x;
+                *visits += 1;
+
+                // the first key not less than it
+                let mut i = 0;
+                while let Some(key) = (*x).key(i) {
+                    *comparisons += 1;
+
+                    match income_key.cmp(key) {
+                        Ordering::Less => break,
+                        Ordering::Equal => break 'descend,
+                        Ordering::Greater => i += 1,
+                    }
+                }
+
+                if (*x).is_leaf() {
+                    break;
+                }
+
+                x = (*x).child(i);
+            }
+        }
+
+        y
+    }
+
+    // fn swap_to_valid(&mut self, idx: usize) -> BTItem<'a, K, V> {
+    //     let mut item_x = BTItem::new(self.itself_mut(), idx);
+
+    //     while let Ok(item_nxt) = item_x.swap_with_successor_until_valid() {
+    //         item_x = item_nxt;
+    //     }
+
+    //     item_x
+    // }
+
+    #[cfg(any(feature = "std", test))]
+    fn just_echo_stdout(&self) {
+        let mut cache = String::new();
+
+        self.echo_in_mm(&mut cache, |_, _| Ok(())).unwrap();
+
+        println!("{}", cache);
+    }
+
+    fn format_keys(&self) -> String {
+        let mut keys_s = vec![];
+
+        for i in 0..self.order() {
+            let key_s = if let Some(key) = self.key(i) {
+               format!("{:?}", key)
+            } else {
+                break;
+            };
+
+            keys_s.push(key_s)
+        }
+
+        format!("({})", keys_s.join(", "))
+    }
+
+    /// BFS Echo
+    fn echo_in_mm(
+        &self,
+        cache: &mut String,
+        action: fn(
+            *mut (dyn BTNode<'a, K, V> + 'a),
+            &mut String,
+        ) -> core::fmt::Result,
+    ) -> core::fmt::Result {
+        unsafe {
+            writeln!(cache, "Entry: {}", self.format_keys())?;
+
+            let mut this_level_queue: VecDeque<
+                *mut (dyn BTNode<'a, K, V> + 'a),
+            > = VecDeque::new();
+
+            this_level_queue
+                .push_back(self.itself_mut());
+            let mut level = 0;
+
+            while !this_level_queue.is_empty() {
+                writeln!(cache)?;
+                writeln!(
+                    cache,
+                    "############ Level: {} #############",
+                    level
+                )?;
+                writeln!(cache)?;
+
+                let mut nxt_level_queue: VecDeque<
+                    *mut (dyn BTNode<'a, K, V> + 'a),
+                > = VecDeque::new();
+
+                while !this_level_queue.is_empty() {
+                    let x = this_level_queue.pop_front().unwrap();
+
+
+                    action(x, cache)?;
+
+                    writeln!(cache, "{}", (*x).format_keys() )?;
+                    for i in 0..self.order() {
+                        let child = (*x).child(i);
+
+                        if !child.is_null() {
+                            writeln!(
+                                cache,
+                                "  | -({})-> {}",
+                                i,
+                                (*child).format_keys(),
+                            )?;
+
+                            nxt_level_queue.push_back(child)
+                        } else {
+                            writeln!(cache, "  | -({})-> null", i)?;
+                        }
+                    }
+
+                    writeln!(cache)?;
+                }
+
+                this_level_queue = nxt_level_queue;
+                level += 1;
+            }
+
+            writeln!(cache, "------------- end --------------")?;
+            writeln!(cache)?;
+        }
+
+
+        Ok(())
+    }
+
+    fn bfs_do(
+        &self,
+        action: fn(
+            *mut (dyn BTNode<'a, K, V> + 'a),
+        )
+    ) {
+        let mut queue= VecDeque::new();
+
+        queue.push_back(self.itself_mut());
+        while !queue.is_empty() {
+            let x = queue.pop_front().unwrap();
+
+            action(x);
+
+            unsafe {
+                for i in 0..self.order() {
+                    let child = BTNode::child (&*x, i);
+
+                    if !child.is_null() {
+                        queue.push_back(child);
+                    } else {
+                        break;
+                    }
+                }
+            }
+
+        }
+    }
+
+    /// 1. N(keys) = N(vals)
+    /// 1. Keep infix-order
+    /// 1. non-leaf node (except root) has at least ⌈m/2⌉ child nodes.
+    /// 1. A non-leaf node with k children contains k − 1 keys (m >= 3).
+    /// 1. The root has at least two children if it is not a leaf node.
+    ///
+    fn basic_self_validate(&'a self) -> Result<(), Box<dyn core::error::Error>> {
+        if self.order() == 2 {
+            unsafe {
+                if !self.child(0).is_null() {
+                    assert!((*self.child(0)).key(0).unwrap() < self.key(0).unwrap());
+
+                    (*self.child(0)).basic_self_validate()?;
+                }
+
+                if !self.child(1).is_null() {
+                    assert!((*self.child(1)).key(0).unwrap() > self.key(0).unwrap());
+
+                    (*self.child(1)).basic_self_validate()?;
+                }
+            }
+
+            return Ok(());
+        }
+
+
+        let key_num = self.key_num();
+        assert_eq!(key_num, self.val_num());
+
+        if self.is_leaf() {
+            assert!(self.key_iter().is_sorted())
+        } else {
+            let children_num = self.children_num();
+
+            assert_eq!(key_num + 1, children_num);
+
+            if self.paren().is_null() {
+                assert!(children_num >= 2);
+            } else {
+                assert!(children_num >= self.order().div_ceil(2));
+            }
+
+            for i in 0..self.key_num() {
+                unsafe {
+                    let cur_key = self.key(i).unwrap();
+                    let lf_child = self.child(i);
+                    let rh_child = self.child(i + 1);
+
+                    if !lf_child.is_null() {
+                        assert!((*lf_child).node_last_key() < cur_key);
+                    }
+
+                    if !rh_child.is_null() {
+                        assert!((*rh_child).node_first_key() > cur_key);
+                    }
+                }
+            }
+
+            for i in 0..self.order() {
+                if !self.child(i).is_null() {
+                    unsafe { (*self.child(i)).basic_self_validate()?; }
+                }
+            }
+        }
+
+
+        Ok(())
+    }
+
+}
+
+
+/// Items of the subtree in infix order, skip the ones without value (e.g. lazy deleted).
+///
+/// The borrow is up to the caller, as it's on raw pointer.
+pub(crate) unsafe fn infix_iter<'a: 'b, 'b, K: CollKey + 'a, V: 'a>(
+    root: *mut (dyn BTNode<'a, K, V> + 'a),
+) -> Box<dyn Iterator<Item = (&'b K, &'b V)> + 'b> {
+    infix_range_iter(root, ..)
+}
+
+/// `infix_iter` on the range, it seeks the first item down a single path.
+pub(crate) unsafe fn infix_range_iter<'a: 'b, 'b, K: CollKey + 'a, V: 'a, R: RangeBounds<K> + 'b>(
+    root: *mut (dyn BTNode<'a, K, V> + 'a),
+    range: R,
+) -> Box<dyn Iterator<Item = (&'b K, &'b V)> + 'b> {
+    // (node, index of the next key)
+    let mut stack: Vec<(*mut (dyn BTNode<'a, K, V> + 'a), usize)> = vec![];
+
+    let push_left_spine = |stack: &mut Vec<_>, mut x: *mut (dyn BTNode<'a, K, V> + 'a)| {
+        while !x.is_null() {
+            stack.push((x, 0));
+            x = unsafe { (*x).child(0) };
+        }
+    };
+
+    // the keys before the idx and their left children are all before the range
+    let mut x = root;
+
+    while !x.is_null() {
+        let mut idx = 0;
+
+        while (*x).key(idx).is_some_and(|key| key_before_range(key, &range)) {
+            idx += 1;
+        }
+
+        stack.push((x, idx));
+        x = (*x).child(idx);
+    }
+
+    let iter = core::iter::from_fn(move || unsafe {
+        while let Some((x, idx)) = stack.pop() {
+            if let Some(key) = (*x).key(idx) {
+                stack.push((x, idx + 1));
+                push_left_spine(&mut stack, (*x).child(idx + 1));
+
+                if let Some(value) = (*x).value(idx) {
+                    return Some((key, value));
+                }
+            }
+        }
+
+        None
+    });
+
+    Box::new(iter.map_while(move |(key, value)| (!key_after_range(key, &range)).then_some((key, value))))
+}
+
+/// Content-based comparison and hashing, regardless of the shape and the kind of tree,
+/// so that an `RB` and an `AVL` holding the same pairs are equal.
+pub(crate) unsafe fn content_eq<'a, K: CollKey + 'a, V: PartialEq + 'a>(
+    x: *mut (dyn BTNode<'a, K, V> + 'a),
+    y: *mut (dyn BTNode<'a, K, V> + 'a),
+) -> bool {
+    infix_iter(x).eq(infix_iter(y))
+}
+
+/// Lexicographic on the items, the same as `BTreeMap`
+pub(crate) unsafe fn content_partial_cmp<'a, K: CollKey + 'a, V: PartialOrd + 'a>(
+    x: *mut (dyn BTNode<'a, K, V> + 'a),
+    y: *mut (dyn BTNode<'a, K, V> + 'a),
+) -> Option<Ordering> {
+    infix_iter(x).partial_cmp(infix_iter(y))
+}
+
+pub(crate) unsafe fn content_hash<'a, K: CollKey + Hash + 'a, V: Hash + 'a, H: Hasher>(
+    x: *mut (dyn BTNode<'a, K, V> + 'a),
+    state: &mut H,
+) {
+    let mut len = 0;
+
+    for item in infix_iter(x) {
+        item.hash(state);
+        len += 1;
+    }
+
+    // Prefix-free, like the std collections
+    state.write_usize(len);
+}
+
+pub(crate) unsafe fn content_fmt<'a, K: CollKey + 'a, V: Debug + 'a>(
+    x: *mut (dyn BTNode<'a, K, V> + 'a),
+    f: &mut fmt::Formatter<'_>,
+) -> fmt::Result {
+    f.debug_map().entries(infix_iter(x)).finish()
+}
+
+
+/// Debug, PartialEq, Eq, PartialOrd, Hash, FromIterator, Extend and Index on the content,
+/// for a tree which is `BT` (and `Default` for `FromIterator`).
+///
+/// Type parameters besides `K`, `V` and `O` follow the type with their bound.
+///
+/// Equality and ordering are against any other `BT` with the same key and value.
+macro_rules! impl_std_traits {
+    ($ty:ty) => {
+        impl_std_traits!($ty,);
+    };
+    ($ty:ty, $($param:ident: $bound:path),*) => {
+        impl<'a, K: CollKey + 'a, V: core::fmt::Debug + 'a, O: Observer<K> $(, $param: $bound)*> core::fmt::Debug for $ty {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                unsafe { $crate::raw::content_fmt(self.root(), f) }
+            }
+        }
+
+        impl<'a, K: CollKey + 'a, V: PartialEq + 'a, D: BT<'a, K, V>, O: Observer<K> $(, $param: $bound)*> PartialEq<D> for $ty {
+            fn eq(&self, other: &D) -> bool {
+                unsafe { $crate::raw::content_eq(self.root(), other.root()) }
+            }
+        }
+
+        impl<'a, K: CollKey + 'a, V: Eq + 'a, O: Observer<K> $(, $param: $bound)*> Eq for $ty {}
+
+        impl<'a, K: CollKey + 'a, V: PartialOrd + 'a, D: BT<'a, K, V>, O: Observer<K> $(, $param: $bound)*> PartialOrd<D> for $ty {
+            fn partial_cmp(&self, other: &D) -> Option<core::cmp::Ordering> {
+                unsafe { $crate::raw::content_partial_cmp(self.root(), other.root()) }
+            }
+        }
+
+        impl<'a, K: CollKey + core::hash::Hash + 'a, V: core::hash::Hash + 'a, O: Observer<K> $(, $param: $bound)*> core::hash::Hash for $ty {
+            fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+                unsafe { $crate::raw::content_hash(self.root(), state) }
+            }
+        }
+
+        impl<'a, K: CollKey + 'a, V: 'a, O: Observer<K> $(, $param: $bound)*> FromIterator<(K, V)> for $ty
+        where
+            Self: Default,
+        {
+            fn from_iter<T: IntoIterator<Item = (K, V)>>(iter: T) -> Self {
+                let mut dict = Self::default();
+
+                dict.extend(iter);
+
+                dict
+            }
+        }
+
+        /// Replace the value if the key is already present, like `BTreeMap`.
+        impl<'a, K: CollKey + 'a, V: 'a, O: Observer<K> $(, $param: $bound)*> Extend<(K, V)> for $ty {
+            fn extend<T: IntoIterator<Item = (K, V)>>(&mut self, iter: T) {
+                for (key, value) in iter {
+                    if let Some(here) = self.get_mut(&key) {
+                        *here = value;
+                    } else {
+                        self.insert(key, value);
+                    }
+                }
+            }
+        }
+
+        /// Panic if the key isn't present.
+        impl<'a, K: CollKey + 'a, V: 'a, O: Observer<K> $(, $param: $bound)*> core::ops::Index<&K> for $ty {
+            type Output = V;
+
+            /// A plain search, it doesn't splay as `get` of Splay does,
+            /// as the iterators borrowed from `&self` may be in the middle of it.
+            fn index(&self, key: &K) -> &V {
+                self.basic_lookup(key).expect("no entry found for key")
+            }
+        }
+    };
+}
+pub(crate) use impl_std_traits;
+
+
+/// Clone the subtree with the same shape in O(n), by an explicit stack instead of recursion.
+///
+/// `clone_node` copies the node itself without the links.
+pub(crate) unsafe fn clone_subtree<'a, K: CollKey + 'a, V: 'a, N: BTNode<'a, K, V> + 'a>(
+    x: *mut N,
+    clone_node: fn(&N) -> *mut N,
+) -> *mut N {
+    if x.is_null() {
+        return ptr::null_mut();
+    }
+
+    let root = clone_node(&*x);
+    let mut stack = vec![(x, root)];
+
+    while let Some((src, dst)) = stack.pop() {
+        for idx in 0..=(*src).node_size() {
+            let child = (*src).child(idx) as *mut N;
+
+            if !child.is_null() {
+                let child_clone = clone_node(&*child);
+
+                (*dst).connect_child(child_clone, idx);
+                stack.push((child, child_clone));
+            }
+        }
+    }
+
+    root
+}
+
+/// Same keys, values and links node by node
+#[cfg(test)]
+pub(crate) unsafe fn same_shape<'a, K: CollKey + 'a, V: PartialEq + 'a>(
+    x: *mut (dyn BTNode<'a, K, V> + 'a),
+    y: *mut (dyn BTNode<'a, K, V> + 'a),
+) -> bool {
+    let mut stack = vec![(x, y)];
+
+    while let Some((x, y)) = stack.pop() {
+        if x.is_null() || y.is_null() {
+            if x.is_null() != y.is_null() {
+                return false;
+            }
+
+            continue;
+        }
+
+        if (*x).node_size() != (*y).node_size() {
+            return false;
+        }
+
+        for idx in 0..(*x).node_size() {
+            if (*x).key(idx) != (*y).key(idx) || (*x).value(idx) != (*y).value(idx) {
+                return false;
+            }
+        }
+
+        for idx in 0..=(*x).node_size() {
+            stack.push(((*x).child(idx), (*y).child(idx)));
+        }
+    }
+
+    true
+}
+
+
+#[derive(Clone)]
+pub struct BTItem<'a, K, V> {
+    pub(crate) node: *mut (dyn BTNode<'a, K, V> + 'a),
+    pub(crate) idx: usize
+}
+
+impl<'a, K: CollKey, V> BTItem<'a, K, V> {
+    pub fn new(node: *mut (dyn BTNode<'a, K, V> + 'a), idx: usize) -> Self {
+        Self {
+            node,
+            idx,
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        !self.node.is_null()
+    }
+
+    pub fn key(&self) -> &K {
+        unsafe {
+            (*self.node).key(self.idx).unwrap()
+        }
+    }
+
+    pub fn successor(&self) -> Self {
+        unsafe {
+            (*self.node).successor(self.key())
+        }
+    }
+
+    pub fn precessor(&self) -> Self {
+        unsafe {
+            (*self.node).precessor(self.key())
+        }
+    }
+
+    pub fn swap(x: &mut Self, y: &mut Self) {
+        unsafe {
+            ptr::swap(
+                (*x.node).key_mut(x.idx).unwrap() as *mut K,
+                (*y.node).key_mut(y.idx).unwrap() as *mut K,
+            );
+            ptr::swap(
+                (*x.node).value_mut(x.idx).unwrap() as *mut V,
+                (*y.node).value_mut(y.idx).unwrap() as *mut V,
+            );
+        }
+    }
+
+    /// Swap the item down to a leaf along the successors.
+    pub fn swap_to_leaf(self) -> Self {
+        let mut item_x = self;
+
+        while let Ok(item_nxt) = item_x.swap_with_successor_until_leaf() {
+            item_x = item_nxt;
+        }
+
+        item_x
+    }
+
+    #[allow(clippy::result_unit_err, reason = "Err stops the swapping, there is nothing to report")]
+    pub fn swap_with_successor_until_leaf(&mut self) -> Result<Self, ()> {
+        unsafe {
+            if (*self.node).is_leaf() {
+                return Err(())
+            }
+
+            let mut nxt_item = self.successor();
+
+            if nxt_item.is_valid() {
+                BTItem::swap(self, &mut nxt_item);
+
+                Ok(nxt_item)
+            } else {
+                Err(())
+            }
+
+        }
+    }
+
+    /// END COND: is_leaf || key < successor's key
+    #[allow(clippy::result_unit_err, reason = "as `swap_with_successor_until_leaf`")]
+    pub fn swap_with_successor_until_valid(&mut self) -> Result<Self, ()> {
+        unsafe {
+            if (*self.node).is_leaf() {
+                return Err(())
+            }
+
+            let mut nxt_item = self.successor();
+
+            if nxt_item.is_valid() && self.key() > nxt_item.key() {
+                BTItem::swap(self, &mut nxt_item);
+
+                Ok(nxt_item)
+            } else {
+                Err(())
+            }
+
+        }
+    }
+
+    #[allow(clippy::result_unit_err, reason = "as `swap_with_successor_until_leaf`")]
+    pub fn swap_with_precessor_until_leaf(&mut self) -> Result<Self, ()> {
+        unsafe {
+            if (*self.node).is_leaf() {
+                return Err(())
+            }
+
+            let mut nxt_item = self.precessor();
+
+            if nxt_item.is_valid() {
+                BTItem::swap(self, &mut nxt_item);
+
+                Ok(nxt_item)
+            } else {
+                Err(())
+            }
+
+        }
+    }
+
+}
@@ -0,0 +1,301 @@
+//! Set on any Dictionary with `()` value,
+//! `()` is zero-sized, so the value costs nothing.
+
+use core::{
+    marker::PhantomData,
+    ops::{BitAnd, BitOr, BitXor, Sub},
+};
+
+use itertools::{EitherOrBoth, Itertools};
+
+use crate::{
+    b3::B3,
+    b4::B4,
+    bst::{aa::AA, avl::AVL, llrb::LLRB, lsg::LSG, rawst::RawST, rb::RB, splay::Splay, treap::Treap},
+    bstar4::BStar4,
+    *,
+};
+
+
+////////////////////////////////////////////////////////////////////////////////
+//// Structs
+
+pub struct Set<K, D> {
+    dict: D,
+    len: usize,
+    _marker: PhantomData<K>,
+}
+
+pub type AVLSet<K> = Set<K, AVL<K, ()>>;
+pub type RBSet<K> = Set<K, RB<K, ()>>;
+pub type LLRBSet<K> = Set<K, LLRB<K, ()>>;
+pub type AASet<K> = Set<K, AA<K, ()>>;
+pub type TreapSet<K> = Set<K, Treap<K, ()>>;
+pub type SplaySet<K> = Set<K, Splay<K, ()>>;
+pub type LSGSet<K> = Set<K, LSG<K, ()>>;
+pub type RawSTSet<K> = Set<K, RawST<K, ()>>;
+pub type B3Set<K> = Set<K, B3<K, ()>>;
+pub type B4Set<K> = Set<K, B4<K, ()>>;
+pub type BStar4Set<K> = Set<K, BStar4<K, ()>>;
+
+
+
+////////////////////////////////////////////////////////////////////////////////
+//// Implement
+
+impl<'a, K: CollKey + 'a, D: Dictionary<K, ()> + BT<'a, K, ()>> Set<K, D> {
+    pub fn new() -> Self
+    where
+        D: Default,
+    {
+        Self {
+            dict: D::default(),
+            len: 0,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Return false if it's already present.
+    pub fn insert(&mut self, key: K) -> bool {
+        let res = self.dict.insert(key, ());
+
+        if res {
+            self.len += 1;
+        }
+
+        res
+    }
+
+    /// A plain search, it never restructures the tree, as `Map::get`
+    pub fn contains(&self, key: &K) -> bool {
+        self.dict.basic_lookup(key).is_some()
+    }
+
+    /// Return false if it isn't present.
+    pub fn remove(&mut self, key: &K) -> bool {
+        let res = self.dict.remove(key).is_some();
+
+        if res {
+            self.len -= 1;
+        }
+
+        res
+    }
+
+    /// In ascending order
+    pub fn iter(&'a self) -> impl Iterator<Item = &'a K> + 'a {
+        self.dict.keys()
+    }
+
+    pub fn is_subset(&'a self, other: &'a Self) -> bool {
+        self.len <= other.len
+            && self
+                .iter()
+                .merge_join_by(other.iter(), |a, b| a.cmp(b))
+                .all(|item| !matches!(item, EitherOrBoth::Left(_)))
+    }
+
+    pub fn is_superset(&'a self, other: &'a Self) -> bool {
+        other.is_subset(self)
+    }
+
+    pub fn is_disjoint(&'a self, other: &'a Self) -> bool {
+        self.iter()
+            .merge_join_by(other.iter(), |a, b| a.cmp(b))
+            .all(|item| !matches!(item, EitherOrBoth::Both(..)))
+    }
+
+    /// Merge the two ascending sequences, keep the keys in left only, both or right only.
+    fn combine(&'a self, other: &'a Self, lf: bool, both: bool, rh: bool) -> Self
+    where
+        K: Clone,
+        D: Default,
+    {
+        let mut res = Self::new();
+
+        for item in self.iter().merge_join_by(other.iter(), |a, b| a.cmp(b)) {
+            let key = match item {
+                EitherOrBoth::Left(key) if lf => key,
+                EitherOrBoth::Both(key, _) if both => key,
+                EitherOrBoth::Right(key) if rh => key,
+                _ => continue,
+            };
+
+            res.insert(key.clone());
+        }
+
+        res
+    }
+
+    pub fn self_validate(&self) -> Result<(), Box<dyn core::error::Error>> {
+        self.dict.self_validate()
+    }
+}
+
+
+impl<K, D> Coll for Set<K, D> {
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+
+impl<'a, K: CollKey + 'a, D: Dictionary<K, ()> + BT<'a, K, ()> + Default> Default
+    for Set<K, D>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+/// Union
+impl<'a, K, D> BitOr<&'a Set<K, D>> for &'a Set<K, D>
+where
+    K: CollKey + Clone + 'a,
+    D: Dictionary<K, ()> + BT<'a, K, ()> + Default,
+{
+    type Output = Set<K, D>;
+
+    fn bitor(self, rhs: &'a Set<K, D>) -> Self::Output {
+        self.combine(rhs, true, true, true)
+    }
+}
+
+
+/// Intersection
+impl<'a, K, D> BitAnd<&'a Set<K, D>> for &'a Set<K, D>
+where
+    K: CollKey + Clone + 'a,
+    D: Dictionary<K, ()> + BT<'a, K, ()> + Default,
+{
+    type Output = Set<K, D>;
+
+    fn bitand(self, rhs: &'a Set<K, D>) -> Self::Output {
+        self.combine(rhs, false, true, false)
+    }
+}
+
+
+/// Difference
+impl<'a, K, D> Sub<&'a Set<K, D>> for &'a Set<K, D>
+where
+    K: CollKey + Clone + 'a,
+    D: Dictionary<K, ()> + BT<'a, K, ()> + Default,
+{
+    type Output = Set<K, D>;
+
+    fn sub(self, rhs: &'a Set<K, D>) -> Self::Output {
+        self.combine(rhs, true, false, false)
+    }
+}
+
+
+/// Symmetric difference
+impl<'a, K, D> BitXor<&'a Set<K, D>> for &'a Set<K, D>
+where
+    K: CollKey + Clone + 'a,
+    D: Dictionary<K, ()> + BT<'a, K, ()> + Default,
+{
+    type Output = Set<K, D>;
+
+    fn bitxor(self, rhs: &'a Set<K, D>) -> Self::Output {
+        self.combine(rhs, true, false, true)
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::*;
+
+
+    macro_rules! test_set {
+        ($ty:ident) => {
+            for _ in 0..crate::test_size(20, 1) {
+                let mut a = $ty::new();
+                let mut b = $ty::new();
+                let mut ref_a = BTreeSet::new();
+                let mut ref_b = BTreeSet::new();
+
+                for _ in 0..200 {
+                    let k = rand::random::<u32>() % 300;
+
+                    assert_eq!(a.insert(k), ref_a.insert(k));
+
+                    let k = rand::random::<u32>() % 300;
+
+                    assert_eq!(b.insert(k), ref_b.insert(k));
+                }
+
+                for _ in 0..50 {
+                    let k = rand::random::<u32>() % 300;
+
+                    assert_eq!(a.remove(&k), ref_a.remove(&k));
+                    assert_eq!(a.contains(&k), ref_a.contains(&k));
+                }
+
+                a.self_validate().unwrap();
+                assert_eq!(a.len(), ref_a.len());
+                assert!(a.iter().eq(ref_a.iter()));
+
+                let (u, i, d, x) = (&a | &b, &a & &b, &a - &b, &a ^ &b);
+
+                assert!(u.iter().eq(ref_a.union(&ref_b)));
+                assert!(i.iter().eq(ref_a.intersection(&ref_b)));
+                assert!(d.iter().eq(ref_a.difference(&ref_b)));
+                assert!(x.iter().eq(ref_a.symmetric_difference(&ref_b)));
+
+                assert_eq!(i.len(), ref_a.intersection(&ref_b).count());
+
+                assert_eq!(a.is_subset(&b), ref_a.is_subset(&ref_b));
+                assert_eq!(a.is_disjoint(&b), ref_a.is_disjoint(&ref_b));
+
+                assert!(i.is_subset(&a));
+                assert!(a.is_superset(&i));
+                assert!(d.is_disjoint(&b));
+            }
+        };
+    }
+
+
+    #[test]
+    fn test_set_contains_in_iter() {
+        let mut set = SplaySet::new();
+
+        for k in 0..30 {
+            set.insert(k);
+        }
+
+        let mut keys = vec![];
+
+        for k in set.iter() {
+            assert!(set.contains(&(29 - k)));
+            keys.push(*k);
+        }
+
+        assert!(keys.into_iter().eq(0..30));
+    }
+
+    #[test]
+    fn test_set_bst() {
+        test_set!(AVLSet);
+        test_set!(RBSet);
+        test_set!(LLRBSet);
+        test_set!(AASet);
+        test_set!(TreapSet);
+        test_set!(SplaySet);
+        test_set!(LSGSet);
+        test_set!(RawSTSet);
+    }
+
+    #[test]
+    fn test_set_btree() {
+        test_set!(B3Set);
+        test_set!(B4Set);
+        test_set!(BStar4Set);
+    }
+}
@@ -0,0 +1,434 @@
+//! Concurrent map partitioning the key space into ordered ranges, one dictionary and one latch per range.
+//!
+//! An operation locates its shard under the read latch of the boundaries and latches the shard
+//! before letting the boundaries go (crabbing), so that it never works on a shard that's been moved away.
+//! Scans keep the boundaries read-latched and latch the shards in key order, which is also the lock order.
+//!
+//! Rebalancing moves the boundaries to the quantiles of the keys when a shard grows
+//! well over the average, and migrates the entries that change shards.
+
+use core::{
+    cell::UnsafeCell,
+    marker::PhantomData,
+    ops::{Bound, RangeBounds},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use crate::*;
+
+
+/// A shard isn't skewed until it has this many entries
+const REBALANCE_MIN: usize = 64;
+
+
+////////////////////////////////////////////////////////////////////////////////
+//// Structs
+
+/// Shared by threads, all operations take `&self`.
+pub struct ShardedMap<K, V, D> {
+    /// guards the boundaries
+    latch: Latch,
+    /// shard i has the keys in [boundaries[i-1], boundaries[i]),
+    /// fewer than `shards.len() - 1` of them leaves the tail shards empty
+    boundaries: UnsafeCell<Vec<K>>,
+    shards: Vec<Shard<D>>,
+    len: AtomicUsize,
+    rebalancing: AtomicBool,
+    _marker: PhantomData<V>,
+}
+
+struct Shard<D> {
+    latch: Latch,
+    dict: UnsafeCell<D>,
+    /// updated under the write latch, read anytime as a hint
+    len: AtomicUsize,
+}
+
+/// Holds the latch of a shard till it drops, the dictionary is borrowed from it.
+struct ShardGuard<'s, D> {
+    shard: &'s Shard<D>,
+    write: bool,
+}
+
+
+////////////////////////////////////////////////////////////////////////////////
+//// Implement
+
+impl<D: Default> Shard<D> {
+    fn new() -> Self {
+        Self {
+            latch: Latch::new(),
+            dict: UnsafeCell::new(D::default()),
+            len: AtomicUsize::new(0),
+        }
+    }
+}
+
+
+impl<'s, D> ShardGuard<'s, D> {
+    fn read(shard: &'s Shard<D>) -> Self {
+        shard.latch.read();
+
+        Self { shard, write: false }
+    }
+
+    fn write(shard: &'s Shard<D>) -> Self {
+        shard.latch.write();
+
+        Self { shard, write: true }
+    }
+
+    fn dict(&self) -> &D {
+        unsafe { &*self.shard.dict.get() }
+    }
+
+    fn dict_mut(&mut self) -> &mut D {
+        debug_assert!(self.write);
+
+        unsafe { &mut *self.shard.dict.get() }
+    }
+}
+
+
+impl<D> Drop for ShardGuard<'_, D> {
+    fn drop(&mut self) {
+        if self.write {
+            self.shard.latch.write_unlock();
+        } else {
+            self.shard.latch.read_unlock();
+        }
+    }
+}
+
+
+impl<K: CollKey + Clone, V, D: for<'a> BT<'a, K, V> + Default> ShardedMap<K, V, D> {
+    /// All keys start in the first shard until the first rebalancing
+    pub fn new(shards: usize) -> Self {
+        assert!(shards > 0);
+
+        Self {
+            latch: Latch::new(),
+            boundaries: UnsafeCell::new(vec![]),
+            shards: (0..shards).map(|_| Shard::new()).collect(),
+            len: AtomicUsize::new(0),
+            rebalancing: AtomicBool::new(false),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Number of shards, it doesn't change
+    pub fn shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Entries of each shard (a hint under concurrent updates)
+    pub fn shard_lens(&self) -> Vec<usize> {
+        self.shards.iter().map(|shard| shard.len.load(Ordering::Relaxed)).collect()
+    }
+
+    /// Under the latch of boundaries
+    unsafe fn shard_of(&self, key: &K) -> usize {
+        (*self.boundaries.get()).partition_point(|boundary| boundary <= key)
+    }
+
+    /// Write-latch the shard of the key (crabbing through the boundaries)
+    fn latch_shard_of(&self, key: &K) -> ShardGuard<'_, D> {
+        self.latch.read();
+
+        let shard = ShardGuard::write(&self.shards[unsafe { self.shard_of(key) }]);
+
+        self.latch.read_unlock();
+
+        shard
+    }
+
+    /// Read-latch the shard of the key
+    fn read_latch_shard_of(&self, key: &K) -> ShardGuard<'_, D> {
+        self.latch.read();
+
+        let shard = ShardGuard::read(&self.shards[unsafe { self.shard_of(key) }]);
+
+        self.latch.read_unlock();
+
+        shard
+    }
+
+    /// Return false if the key exists (it's left as it is), like `Dictionary::insert`.
+    ///
+    /// It may rebalance the shards after that.
+    pub fn insert(&self, key: K, value: V) -> bool {
+        let mut shard = self.latch_shard_of(&key);
+
+        let is_new = shard.dict_mut().insert(key, value);
+        let shard_len = if is_new {
+            self.len.fetch_add(1, Ordering::Relaxed);
+            shard.shard.len.fetch_add(1, Ordering::Relaxed) + 1
+        } else {
+            0
+        };
+
+        drop(shard);
+
+        if self.is_skewed(shard_len)
+            && self
+                .rebalancing
+                .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+        {
+            self.rebalance();
+            self.rebalancing.store(false, Ordering::Release);
+        }
+
+        is_new
+    }
+
+    pub fn remove(&self, key: &K) -> Option<V> {
+        let mut shard = self.latch_shard_of(key);
+
+        let popped = shard.dict_mut().remove(key);
+
+        if popped.is_some() {
+            self.len.fetch_sub(1, Ordering::Relaxed);
+            shard.shard.len.fetch_sub(1, Ordering::Relaxed);
+        }
+
+        popped
+    }
+
+    pub fn get(&self, key: &K) -> Option<V>
+    where
+        V: Clone,
+    {
+        self.read_latch_shard_of(key).dict().get(key).cloned()
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.read_latch_shard_of(key).dict().get(key).is_some()
+    }
+
+    /// Items in the range in key order, consistent across the shards (of the same moment)
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Vec<(K, V)>
+    where
+        V: Clone,
+    {
+        let range = (range.start_bound().cloned(), range.end_bound().cloned());
+        let mut items = vec![];
+
+        self.latch.read();
+
+        let (first, last) = unsafe {
+            let first = match &range.0 {
+                Bound::Included(start) | Bound::Excluded(start) => self.shard_of(start),
+                Bound::Unbounded => 0,
+            };
+            let last = match &range.1 {
+                Bound::Included(end) | Bound::Excluded(end) => self.shard_of(end),
+                Bound::Unbounded => (*self.boundaries.get()).len(),
+            };
+
+            (first, last)
+        };
+
+        // all of them before any scan (empty if the range is reversed),
+        // as a writer may go to the later one meanwhile
+        let shards = self.shards[first..=last.max(first)].iter().map(ShardGuard::read).collect::<Vec<_>>();
+
+        for shard in &shards {
+            items.extend(shard.dict().range(range.clone()).map(|(key, value)| (key.clone(), value.clone())));
+        }
+
+        drop(shards);
+
+        self.latch.read_unlock();
+
+        items
+    }
+
+    /// All items in key order
+    pub fn to_vec(&self) -> Vec<(K, V)>
+    where
+        V: Clone,
+    {
+        self.range(..)
+    }
+
+    fn is_skewed(&self, shard_len: usize) -> bool {
+        let average = self.len.load(Ordering::Relaxed) / self.shards.len();
+
+        shard_len >= REBALANCE_MIN && shard_len * 2 > average * 3
+    }
+
+    /// Move the boundaries to split the keys evenly and migrate the entries changing shards.
+    ///
+    /// All shards are write-latched meanwhile, O(n log n).
+    pub fn rebalance(&self) {
+        self.latch.write();
+
+        let mut shards = self.shards.iter().map(ShardGuard::write).collect::<Vec<_>>();
+
+        // (shard, key) in key order
+        let mut keys = vec![];
+
+        for (i, shard) in shards.iter().enumerate() {
+            keys.extend(shard.dict().keys().map(|key| (i, key.clone())));
+        }
+
+        let per_shard = keys.len().div_ceil(self.shards.len()).max(1);
+
+        unsafe {
+            *self.boundaries.get() =
+                keys.iter().skip(per_shard).step_by(per_shard).map(|(_, key)| key.clone()).collect();
+        }
+
+        // the shards are still latched
+        self.latch.write_unlock();
+
+        for (i, key) in keys {
+            let j = unsafe { self.shard_of(&key) };
+
+            if i != j {
+                let value = shards[i].dict_mut().remove(&key).unwrap();
+                shards[j].dict_mut().insert(key, value);
+
+                self.shards[i].len.fetch_sub(1, Ordering::Relaxed);
+                self.shards[j].len.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    pub fn self_validate(&mut self) -> Result<(), Box<dyn core::error::Error>> {
+        let boundaries = self.boundaries.get_mut();
+        let mut total = 0;
+
+        assert!(boundaries.len() < self.shards.len());
+        assert!(boundaries.is_sorted_by(|x, y| x < y));
+
+        for (i, shard) in self.shards.iter_mut().enumerate() {
+            let dict = shard.dict.get_mut();
+            dict.self_validate()?;
+
+            let len = dict.iter().count();
+
+            for key in dict.keys() {
+                assert!(i == 0 || boundaries.get(i - 1).is_some_and(|start| start <= key));
+                assert!(boundaries.get(i).is_none_or(|end| key < end));
+            }
+
+            assert_eq!(*shard.len.get_mut(), len);
+            total += len;
+        }
+
+        assert_eq!(*self.len.get_mut(), total);
+
+        Ok(())
+    }
+}
+
+
+impl<K, V, D> Coll for ShardedMap<K, V, D> {
+    fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+}
+
+// the boundaries are shared and cloned, and the dictionaries are read at the same time
+unsafe impl<K: Send, V: Send, D: Send> Send for ShardedMap<K, V, D> {}
+unsafe impl<K: Send + Sync, V: Send + Sync, D: Send + Sync> Sync for ShardedMap<K, V, D> {}
+
+
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, thread};
+
+    use super::*;
+    use crate::{b4::B4, bst::{avl::AVL, treap::Treap}};
+
+    macro_rules! test_sharded_map {
+        ($ty:ident) => {
+            let threads = crate::test_size(4, 2);
+            let n = crate::test_size(2000, 100);
+            let map = ShardedMap::<usize, usize, $ty<usize, usize>>::new(4);
+
+            // each thread has its own keys, interleaved across the shards
+            thread::scope(|s| {
+                for t in 0..threads {
+                    let map = &map;
+
+                    s.spawn(move || {
+                        for i in 0..n {
+                            assert!(map.insert(i * threads + t, t));
+                        }
+                        for i in (0..n).filter(|i| i % 3 == 0) {
+                            assert_eq!(map.remove(&(i * threads + t)), Some(t));
+                        }
+                        for i in 0..n {
+                            assert_eq!(map.get(&(i * threads + t)), (i % 3 != 0).then_some(t));
+                        }
+                    });
+                }
+
+                s.spawn(|| {
+                    for _ in 0..crate::test_size(20, 4) {
+                        let items = map.range(n..n * 2);
+
+                        assert!(items.is_sorted_by(|x, y| x.0 < y.0));
+                        assert!(items.iter().all(|(k, _)| (n..n * 2).contains(k)));
+                    }
+                });
+            });
+
+            map.rebalance();
+
+            let mut map = map;
+            map.self_validate().unwrap();
+
+            let expect: BTreeMap<usize, usize> = (0..n * threads)
+                .filter(|k| (k / threads) % 3 != 0)
+                .map(|k| (k, k % threads))
+                .collect();
+
+            assert_eq!(map.len(), expect.len());
+            assert!(map.to_vec().into_iter().eq(expect.into_iter()));
+        };
+    }
+
+    #[test]
+    fn test_sharded_map_treap() {
+        test_sharded_map!(Treap);
+    }
+
+    #[test]
+    fn test_sharded_map_avl() {
+        test_sharded_map!(AVL);
+    }
+
+    #[test]
+    fn test_sharded_map_b4() {
+        test_sharded_map!(B4);
+    }
+
+    #[test]
+    fn test_sharded_map_skewed() {
+        let n = crate::test_size(4000, 400);
+        let mut map = ShardedMap::<usize, usize, AVL<usize, usize>>::new(4);
+        let mut expect = BTreeMap::new();
+
+        // ascending keys always go to the last shard
+        for k in 0..n {
+            map.insert(k, k);
+            expect.insert(k, k);
+        }
+
+        map.self_validate().unwrap();
+
+        let lens = map.shard_lens();
+        assert!(lens.iter().all(|&len| len > 0), "{lens:?}");
+        assert!(*lens.iter().max().unwrap() * 2 <= n, "{lens:?}");
+
+        for range in [(Bound::Unbounded, Bound::Excluded(n / 3)), (Bound::Included(n / 5), Bound::Included(n - 7))] {
+            assert!(map.range(range).into_iter().eq(expect.range(range).map(|(k, v)| (*k, *v))));
+        }
+        assert_eq!(map.range(n..), vec![]);
+    }
+}
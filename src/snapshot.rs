@@ -0,0 +1,293 @@
+//! Map for read-mostly workloads, immutable versions of a tree are published through an atomic pointer.
+//!
+//! Readers get a consistent snapshot wait-free, a writer builds the next version aside
+//! (merging its batch into the current one by `FromSortedIter`) and swaps it in.
+//! The replaced versions are freed by the epoch scheme in `epoch` once no reader can see them.
+
+use core::{
+    cell::UnsafeCell,
+    marker::PhantomData,
+    ops::Deref,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+use itertools::{EitherOrBoth, Itertools};
+
+use crate::{
+    epoch::{Collector, Handle},
+    *,
+};
+
+
+////////////////////////////////////////////////////////////////////////////////
+//// Structs
+
+pub struct SnapshotMap<K, V, D> {
+    current: AtomicPtr<D>,
+    collector: Collector,
+    /// serializes the writers and guards the garbage
+    writer: Latch,
+    /// replaced versions with the epoch they were replaced at
+    garbage: UnsafeCell<Vec<(usize, *mut D)>>,
+    _marker: PhantomData<(K, V)>,
+}
+
+/// A reader registered to the map, it takes one snapshot at a time.
+pub struct Reader<'m, K, V, D> {
+    map: &'m SnapshotMap<K, V, D>,
+    handle: Handle<'m>,
+}
+
+/// A version of the map, it stays the same however the map changes.
+pub struct Snapshot<'r, D> {
+    dict: &'r D,
+    handle: &'r Handle<'r>,
+}
+
+
+////////////////////////////////////////////////////////////////////////////////
+//// Implement
+
+impl<'a, K: CollKey + 'a, V: 'a, D: 'a> SnapshotMap<K, V, D> {
+    pub fn new(dict: D) -> Self {
+        Self {
+            current: AtomicPtr::new(Box::into_raw(Box::new(dict))),
+            collector: Collector::new(),
+            writer: Latch::new(),
+            garbage: UnsafeCell::new(vec![]),
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn reader(&self) -> Reader<'_, K, V, D> {
+        Reader { map: self, handle: self.collector.register() }
+    }
+
+    /// Replace the whole content with a new version
+    pub fn publish(&self, dict: D) {
+        self.writer.write();
+
+        unsafe { self.publish_(dict) };
+
+        self.writer.write_unlock();
+    }
+
+    /// Under the writer latch
+    unsafe fn publish_(&self, dict: D) {
+        let old = self.current.swap(Box::into_raw(Box::new(dict)), Ordering::SeqCst);
+        let garbage = &mut *self.garbage.get();
+
+        garbage.push((self.collector.epoch(), old));
+
+        let epoch = self.collector.try_advance();
+
+        garbage.retain(|&(retired, x)| {
+            if retired + 2 <= epoch {
+                drop(Box::from_raw(x));
+                false
+            } else {
+                true
+            }
+        });
+    }
+
+    /// Number of the replaced versions waiting for the readers
+    pub fn garbage_len(&self) -> usize {
+        self.writer.write();
+
+        let len = unsafe { (*self.garbage.get()).len() };
+
+        self.writer.write_unlock();
+
+        len
+    }
+}
+
+
+impl<'a, K: CollKey + Clone + 'a, V: Clone + 'a, D: BT<'a, K, V> + FromSortedIter<K, V> + 'a>
+    SnapshotMap<K, V, D>
+{
+    /// Publish a new version with the batch applied, `Some` to insert or replace and `None` to remove,
+    /// the later one wins on the same key.
+    ///
+    /// O(n + m log m) for n pairs and m operations.
+    pub fn write<I: IntoIterator<Item = (K, Option<V>)>>(&'a self, batch: I) {
+        let mut batch = batch.into_iter().collect_vec();
+
+        // stable, keep the last of the same key
+        batch.sort_by(|x, y| x.0.cmp(&y.0));
+
+        let mut ops: Vec<(K, Option<V>)> = Vec::with_capacity(batch.len());
+
+        for (key, value) in batch {
+            if ops.last().is_some_and(|(last, _)| *last == key) {
+                ops.pop();
+            }
+
+            ops.push((key, value));
+        }
+
+        self.writer.write();
+
+        unsafe {
+            // only the writers replace it
+            let current = &*self.current.load(Ordering::SeqCst);
+
+            let merged = current
+                .iter()
+                .merge_join_by(ops, |(key, _), (op_key, _)| (*key).cmp(op_key))
+                .filter_map(|either| match either {
+                    EitherOrBoth::Left((key, value)) => Some((key.clone(), value.clone())),
+                    EitherOrBoth::Right((key, value)) | EitherOrBoth::Both(_, (key, value)) => {
+                        value.map(|value| (key, value))
+                    }
+                });
+
+            let dict = D::from_sorted_iter(merged).unwrap();
+
+            self.publish_(dict);
+        }
+
+        self.writer.write_unlock();
+    }
+}
+
+
+impl<'a, K: CollKey + 'a, V: 'a, D: Default + 'a> Default for SnapshotMap<K, V, D> {
+    fn default() -> Self {
+        Self::new(D::default())
+    }
+}
+
+unsafe impl<K, V, D: Send + Sync> Send for SnapshotMap<K, V, D> {}
+unsafe impl<K, V, D: Send + Sync> Sync for SnapshotMap<K, V, D> {}
+
+impl<K, V, D> Drop for SnapshotMap<K, V, D> {
+    fn drop(&mut self) {
+        unsafe {
+            drop(Box::from_raw(*self.current.get_mut()));
+
+            for (_, x) in self.garbage.get_mut().drain(..) {
+                drop(Box::from_raw(x));
+            }
+        }
+    }
+}
+
+
+impl<K, V, D> Reader<'_, K, V, D> {
+    /// Wait-free
+    pub fn snapshot(&mut self) -> Snapshot<'_, D> {
+        self.handle.pin(&self.map.collector);
+
+        let dict = unsafe { &*self.map.current.load(Ordering::SeqCst) };
+
+        Snapshot { dict, handle: &self.handle }
+    }
+}
+
+
+impl<D> Deref for Snapshot<'_, D> {
+    type Target = D;
+
+    fn deref(&self) -> &D {
+        self.dict
+    }
+}
+
+impl<D> Drop for Snapshot<'_, D> {
+    fn drop(&mut self) {
+        self.handle.unpin();
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicBool, Ordering},
+        thread,
+    };
+
+    use super::*;
+    use crate::{b4::B4, bst::avl::AVL};
+
+    macro_rules! test_snapshot_map {
+        ($ty:ident) => {
+            let n = crate::test_size(200u64, 20);
+            let versions = crate::test_size(100u64, 10);
+            let map = SnapshotMap::new($ty::from_sorted_iter((0..n).map(|k| (k, 0u64))).unwrap());
+            let done = AtomicBool::new(false);
+
+            thread::scope(|s| {
+                for _ in 0..crate::test_size(4, 2) {
+                    s.spawn(|| {
+                        let mut reader = map.reader();
+                        let mut last = 0;
+
+                        while !done.load(Ordering::Relaxed) {
+                            let snapshot = reader.snapshot();
+
+                            // every version writes all keys with its number, and the even keys only exist in it
+                            let version = *snapshot.get(&1).unwrap();
+
+                            assert!(version >= last);
+                            assert!(snapshot.iter().all(|(k, v)| *v == version && (k % 2 == 1 || version % 2 == 0)));
+                            assert_eq!(snapshot.iter().count() as u64, if version % 2 == 0 { n } else { n / 2 });
+
+                            last = version;
+                        }
+                    });
+                }
+
+                s.spawn(|| {
+                    for version in 1..=versions {
+                        map.write((0..n).map(|k| (k, (k % 2 == 1 || version % 2 == 0).then_some(version))));
+                    }
+
+                    done.store(true, Ordering::Relaxed);
+                });
+            });
+
+            let mut reader = map.reader();
+            let snapshot = reader.snapshot();
+
+            snapshot.self_validate().unwrap();
+            assert_eq!(snapshot.get(&1), Some(&versions));
+            drop(snapshot);
+
+            // no reader stays on the old versions
+            map.publish($ty::new());
+            map.publish($ty::new());
+            assert!(map.garbage_len() <= 2);
+        };
+    }
+
+    #[test]
+    fn test_snapshot_map_avl() {
+        test_snapshot_map!(AVL);
+    }
+
+    #[test]
+    fn test_snapshot_map_b4() {
+        test_snapshot_map!(B4);
+    }
+
+    #[test]
+    fn test_snapshot_map_write() {
+        let map = SnapshotMap::new(AVL::new());
+        let mut reader = map.reader();
+        let old = reader.snapshot();
+
+        map.write([(3, Some(30)), (1, Some(10)), (3, None), (2, Some(20)), (2, Some(21))]);
+        map.write([(1, None), (4, Some(40))]);
+
+        // the snapshot before the writes
+        assert_eq!(old.iter().count(), 0);
+        drop(old);
+
+        let new = reader.snapshot();
+        assert!(new.iter().eq([(&2, &21), (&4, &40)]));
+    }
+}
@@ -0,0 +1,254 @@
+//! Operation counts of a tree for comparing them on a workload, opt in per instance:
+//! `AVL::with_stats()` and then `stats()`.
+//!
+//! It's an `Observer` of the tree, so the trees without it pay nothing.
+
+use core::cell::Cell;
+
+use either::Either;
+
+use crate::*;
+
+
+////////////////////////////////////////////////////////////////////////////////
+//// Structs
+
+/// Counts since the tree is created or `reset_stats`
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    /// key comparisons of the searches (`BT::search_approximately`)
+    pub comparisons: usize,
+    /// nodes visited by the searches
+    pub visits: usize,
+    /// single rotations, including the two of each double rotation
+    pub rotations: usize,
+    pub double_rotations: usize,
+    /// B-Tree node splits
+    pub splits: usize,
+    /// B-Tree node merges
+    pub merges: usize,
+    /// B-Tree items borrowed from a sibling through the paren
+    pub borrows: usize,
+    /// LSG scapegoat rebuilds
+    pub rebuilds: usize,
+    /// nodes of the rebuilt subtrees
+    pub rebuilt_nodes: usize,
+    pub splays: usize,
+    /// sum of the depths splayed from
+    pub splay_depth: usize,
+    /// whole tree splits (Treap, and `remove_range` of AVL, RB and B4)
+    pub tree_splits: usize,
+    /// whole tree joins
+    pub tree_joins: usize,
+}
+
+/// The observer collecting `Stats`.
+///
+/// The counts are in a `Cell` as the searches go through `&self`,
+/// so a tree with it isn't `Sync`.
+#[derive(Default, Debug, Clone)]
+pub struct StatsObserver {
+    stats: Cell<Stats>,
+}
+
+
+////////////////////////////////////////////////////////////////////////////////
+//// Implement
+
+impl StatsObserver {
+    pub fn stats(&self) -> Stats {
+        self.stats.get()
+    }
+
+    pub fn reset(&mut self) {
+        self.stats.set(Stats::default());
+    }
+
+    fn update(&self, f: impl FnOnce(&mut Stats)) {
+        let mut stats = self.stats.get();
+        f(&mut stats);
+        self.stats.set(stats);
+    }
+}
+
+impl<K> Observer<K> for StatsObserver {
+    fn on_rotate(&mut self, _x: &K, _z: &K, _dir: Either<(), ()>) {
+        self.stats.get_mut().rotations += 1;
+    }
+
+    fn on_double_rotate(&mut self, _y: &K, _dir: Either<(), ()>) {
+        self.stats.get_mut().double_rotations += 1;
+    }
+
+    fn on_search(&self, visits: usize, comparisons: usize) {
+        self.update(|stats| {
+            stats.visits += visits;
+            stats.comparisons += comparisons;
+        });
+    }
+
+    fn on_split(&mut self, _key: &K) {
+        self.stats.get_mut().splits += 1;
+    }
+
+    fn on_merge(&mut self, _key: &K) {
+        self.stats.get_mut().merges += 1;
+    }
+
+    fn on_borrow(&mut self, _key: &K) {
+        self.stats.get_mut().borrows += 1;
+    }
+
+    fn on_tree_split(&mut self) {
+        self.stats.get_mut().tree_splits += 1;
+    }
+
+    fn on_tree_join(&mut self) {
+        self.stats.get_mut().tree_joins += 1;
+    }
+
+    fn on_rebuild(&mut self, _key: &K, size: usize) {
+        let stats = self.stats.get_mut();
+
+        stats.rebuilds += 1;
+        stats.rebuilt_nodes += size;
+    }
+
+    fn on_splay(&mut self, _key: &K, depth: usize) {
+        let stats = self.stats.get_mut();
+
+        stats.splays += 1;
+        stats.splay_depth += depth;
+    }
+}
+
+
+/// `with_stats`, `stats` and `reset_stats` for the tree observed by `StatsObserver`,
+/// which has `with_observer`, `observer` and `observer_mut`.
+macro_rules! impl_stats {
+    ($ty:ty) => {
+        impl<'a, K: CollKey + 'a, V: 'a> $ty {
+            /// Count the operations, see `Stats`
+            pub fn with_stats() -> Self {
+                Self::with_observer($crate::StatsObserver::default())
+            }
+
+            pub fn stats(&self) -> $crate::Stats {
+                self.observer().stats()
+            }
+
+            pub fn reset_stats(&mut self) {
+                self.observer_mut().reset()
+            }
+        }
+    };
+}
+pub(crate) use impl_stats;
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        b3::B3,
+        b4::B4,
+        bst::{aa::AA, avl::AVL, llrb::LLRB, lsg::LSG, rawst::RawST, rb::RB, splay::Splay, treap::Treap},
+        bstar4::BStar4,
+    };
+
+    /// Random inserts, lookups and removes, return the stats before the reset
+    macro_rules! test_stats {
+        ($ty:ident) => {{
+            let n = crate::test_size(1000u64, 60);
+            let mut dict = $ty::with_stats();
+            let mut keys = vec![];
+
+            for _ in 0..n {
+                let k = rand::random::<u64>() % (n * 4);
+
+                if dict.insert(k, k) {
+                    keys.push(k);
+                }
+            }
+
+            for k in keys.iter() {
+                assert_eq!(dict.get(k), Some(k));
+            }
+
+            for k in keys.iter().step_by(2) {
+                assert_eq!(dict.remove(k), Some(*k));
+            }
+
+            dict.self_validate().unwrap();
+
+            let stats = dict.stats();
+
+            // at least one node for each lookup
+            assert!(stats.visits >= keys.len());
+            assert!(stats.comparisons >= stats.visits);
+            assert!(stats.rotations >= stats.double_rotations * 2);
+
+            dict.reset_stats();
+            assert_eq!(dict.stats(), Stats::default());
+
+            dict.get(&keys[1]);
+            assert!(dict.stats().visits > 0);
+
+            stats
+        }};
+    }
+
+    #[test]
+    fn test_stats_bst() {
+        let stats = test_stats!(AVL);
+        assert!(stats.rotations > 0 && stats.double_rotations > 0);
+
+        let stats = test_stats!(RB);
+        assert!(stats.rotations > 0 && stats.double_rotations > 0);
+
+        let stats = test_stats!(LLRB);
+        assert!(stats.rotations > 0);
+
+        let stats = test_stats!(AA);
+        assert!(stats.rotations > 0);
+
+        let stats = test_stats!(Treap);
+        assert!(stats.tree_splits > 0 && stats.tree_joins > 0);
+        assert_eq!(stats.rotations, 0);
+
+        let stats = test_stats!(Splay);
+        assert!(stats.splays > 0);
+        assert_eq!(stats.rotations, stats.splay_depth);
+
+        let stats = test_stats!(LSG);
+        assert!(stats.rebuilds > 0 && stats.rebuilt_nodes >= stats.rebuilds);
+
+        let stats = test_stats!(RawST);
+        assert_eq!(stats.rotations, 0);
+    }
+
+    #[test]
+    fn test_stats_btree() {
+        for stats in [test_stats!(B3), test_stats!(B4), test_stats!(BStar4)] {
+            assert!(stats.splits > 0);
+            assert!(stats.merges + stats.borrows > 0);
+            assert_eq!(stats.rotations, 0);
+        }
+    }
+
+    #[test]
+    fn test_stats_remove_range() {
+        let mut avl = AVL::with_stats();
+
+        for k in 0..crate::test_size(100, 20) {
+            avl.insert(k, k);
+        }
+
+        avl.reset_stats();
+        avl.remove_range(5..10);
+
+        assert_eq!(avl.stats().tree_splits, 2);
+        assert_eq!(avl.stats().tree_joins, 1);
+    }
+}
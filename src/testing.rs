@@ -0,0 +1,491 @@
+//! The checks the trees here are tested with, for Dictionary, Heap and BT implementations
+//! of one's own, behind the `testing` feature:
+//!
+//! ```ignore
+//! #[test]
+//! fn test_my_dict() {
+//!     let conf = Config { seed: 7, ..Config::default() };
+//!
+//!     check_dictionary(MyDict::new, clustered_u64(), &conf);
+//!     check_bt(MyDict::new, uniform::<(u32, u8)>, &conf);
+//! }
+//! ```
+//!
+//! They're seeded and panic at the first failure, the message has the seed and the round.
+
+use core::fmt::{self, Debug};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use rand::{
+    distributions::{Distribution, Standard},
+    rngs::StdRng,
+    seq::SliceRandom,
+    Rng, SeedableRng,
+};
+
+pub use crate::{MaxDictHeap, MinDictHeap};
+use crate::*;
+
+
+////////////////////////////////////////////////////////////////////////////////
+//// Structs
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// each round checks a fresh one
+    pub rounds: usize,
+    /// items of each round
+    pub size: usize,
+    pub seed: u64,
+    /// `self_validate` after every so many updates besides the end of each phase, 0 for never
+    pub validate_every: usize,
+}
+
+/// Which end the heap pops
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endian {
+    Max,
+    Min,
+}
+
+/// For the failure messages
+#[derive(Clone, Copy)]
+struct At {
+    seed: u64,
+    round: usize,
+}
+
+enum RefHeap<T> {
+    Max(MaxDictHeap<T>),
+    Min(MinDictHeap<T>),
+}
+
+
+////////////////////////////////////////////////////////////////////////////////
+//// Implement
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            rounds: 20,
+            size: 1000,
+            seed: 0,
+            validate_every: 10,
+        }
+    }
+}
+
+impl fmt::Display for At {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "seed {} round {}", self.seed, self.round)
+    }
+}
+
+impl<T: Ord> RefHeap<T> {
+    fn new(endian: Endian) -> Self {
+        match endian {
+            Endian::Max => Self::Max(MaxDictHeap::new()),
+            Endian::Min => Self::Min(MinDictHeap::new()),
+        }
+    }
+
+    fn push(&mut self, v: T) {
+        match self {
+            Self::Max(heap) => heap.push((), v),
+            Self::Min(heap) => heap.push((), v),
+        }
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        match self {
+            Self::Max(heap) => heap.pop(),
+            Self::Min(heap) => heap.pop(),
+        }
+    }
+}
+
+
+////////////////////////////////////////////////////////////////////////////////
+//// Generators
+
+/// Anything `rand` draws uniformly, e.g. `uniform::<(u32, u8)>` for pairs
+pub fn uniform<T>(rng: &mut StdRng) -> T
+where
+    Standard: Distribution<T>,
+{
+    rng.gen()
+}
+
+/// Pairs of `(k, k + 1000)`, the keys in runs of 10 consecutive ones from uniform starts
+pub fn clustered_u64() -> impl FnMut(&mut StdRng) -> (u64, u64) {
+    let mut k = 0u64;
+    let mut left = 0;
+
+    move |rng| {
+        if left == 0 {
+            k = rng.gen();
+            left = 10;
+        }
+
+        left -= 1;
+        k = k.wrapping_add(1);
+
+        (k, k.wrapping_add(1000))
+    }
+}
+
+/// Distinct keys for `Heap::push`
+pub fn gen_unique() -> impl FnMut(&mut StdRng) -> usize {
+    let mut set = HashSet::new();
+
+    move |rng| loop {
+        let v = rng.gen();
+
+        if set.insert(v) {
+            return v;
+        }
+    }
+}
+
+/// `num` pairs of distinct keys from `gen`
+pub fn gen_data<K: CollKey + Clone, V>(
+    rng: &mut StdRng,
+    gen: &mut impl FnMut(&mut StdRng) -> (K, V),
+    num: usize,
+) -> Vec<(K, V)> {
+    let mut keys = BTreeSet::new();
+    let mut elems = Vec::with_capacity(num);
+    let mut tries = 0;
+
+    while elems.len() < num {
+        let (k, v) = gen(rng);
+
+        if keys.insert(k.clone()) {
+            elems.push((k, v));
+        }
+
+        tries += 1;
+        assert!(tries <= num * 100 + 1000, "the generator has less than {num} keys");
+    }
+
+    elems
+}
+
+
+////////////////////////////////////////////////////////////////////////////////
+//// Checks
+
+/// Insert all, insert them again, modify all and remove all in random order,
+/// `get` after each.
+pub fn check_dictionary<K, V, D>(
+    mut new: impl FnMut() -> D,
+    mut gen: impl FnMut(&mut StdRng) -> (K, V),
+    conf: &Config,
+) where
+    K: CollKey + Clone + Debug,
+    V: Clone + PartialEq + Debug,
+    D: Dictionary<K, V>,
+{
+    let mut rng = StdRng::seed_from_u64(conf.seed);
+
+    for round in 0..conf.rounds {
+        let at = At { seed: conf.seed, round };
+        let mut dict = new();
+        let mut elems = gen_data(&mut rng, &mut gen, conf.size);
+
+        /* Create */
+
+        for (i, (k, v)) in elems.iter().cloned().enumerate() {
+            assert!(dict.insert(k.clone(), v.clone()), "[dict insert] {at}: {k:?} is new");
+            assert_eq!(dict.get(&k), Some(&v), "[dict insert] {at}: get {k:?}");
+
+            validate(&dict, i, conf, at);
+        }
+
+        validate(&dict, 0, conf, at);
+
+        for (k, v) in elems.iter() {
+            let (_, other) = gen(&mut rng);
+
+            assert!(!dict.insert(k.clone(), other), "[dict insert] {at}: {k:?} is present");
+            assert_eq!(dict.get(k), Some(v), "[dict insert] {at}: {k:?} is replaced");
+        }
+
+        /* Update */
+
+        for (k, v) in elems.iter_mut() {
+            let (_, newv) = gen(&mut rng);
+
+            assert!(dict.modify(k, newv.clone()), "[dict modify] {at}: {k:?} is present");
+            *v = newv;
+
+            assert_eq!(dict.get(k), Some(&*v), "[dict modify] {at}: get {k:?}");
+        }
+
+        validate(&dict, 0, conf, at);
+
+        /* Remove */
+
+        elems.shuffle(&mut rng);
+
+        for (i, (k, v)) in elems.into_iter().enumerate() {
+            assert_eq!(dict.get(&k), Some(&v), "[dict remove] {at}: get {k:?}");
+            assert_eq!(dict.remove(&k), Some(v.clone()), "[dict remove] {at}: remove {k:?}");
+            assert_eq!(dict.get(&k), None, "[dict remove] {at}: get {k:?} removed");
+            assert!(dict.remove(&k).is_none(), "[dict remove] {at}: remove {k:?} removed");
+            assert!(!dict.modify(&k, v), "[dict remove] {at}: modify {k:?} removed");
+
+            validate(&dict, i, conf, at);
+        }
+
+        validate(&dict, 0, conf, at);
+    }
+}
+
+/// Pop all pushed, then interleave pushes and pops against `MaxDictHeap`/`MinDictHeap`,
+/// `top` is what the next pop gives.
+pub fn check_heap<T, H>(
+    mut new: impl FnMut() -> H,
+    endian: Endian,
+    mut gen: impl FnMut(&mut StdRng) -> T,
+    conf: &Config,
+) where
+    T: CollKey + Clone + Debug,
+    H: Heap<usize, T>,
+{
+    let mut rng = StdRng::seed_from_u64(conf.seed);
+
+    let pop = |heap: &mut H, at: At| {
+        let top = heap.top().cloned();
+        let res = heap.pop();
+
+        assert_eq!(res, top, "[heap pop] {at}: top isn't popped");
+
+        res
+    };
+
+    for round in 0..conf.rounds {
+        let at = At { seed: conf.seed, round };
+
+        /* Basic */
+
+        let mut heap = new();
+        let mut unique = gen_unique();
+
+        for _ in 0..conf.size {
+            let i = unique(&mut rng);
+            heap.push(i, gen(&mut rng));
+        }
+
+        let mut res = vec![];
+
+        for _ in 0..conf.size {
+            res.push(pop(&mut heap, at).expect("[heap pop] not empty"));
+        }
+
+        if endian == Endian::Max {
+            res.reverse();
+        }
+
+        assert!(res.is_sorted(), "[heap pop] {at}: {endian:?} out of order");
+        assert_eq!(pop(&mut heap, at), None, "[heap pop] {at}: empty");
+
+        /* Accompany */
+
+        let mut refheap = RefHeap::new(endian);
+        let mut heap = new();
+        let mut unique = gen_unique();
+        let mut rems = 0;
+
+        for i in 0..conf.size {
+            // push the first quarter
+            if i < conf.size / 4 || rems == 0 || rng.gen::<bool>() {
+                let e = gen(&mut rng);
+
+                refheap.push(e.clone());
+                heap.push(unique(&mut rng), e);
+                rems += 1;
+            } else {
+                assert_eq!(pop(&mut heap, at), refheap.pop(), "[heap pop] {at}");
+                rems -= 1;
+            }
+        }
+    }
+}
+
+/// Random inserts and removes, checking the structure of the tree at each phase:
+/// `self_validate`, the infix order, ranges and the height against a `BTreeMap`.
+pub fn check_bt<K, V, D>(mut new: impl FnMut() -> D, mut gen: impl FnMut(&mut StdRng) -> (K, V), conf: &Config)
+where
+    K: CollKey + Clone + Debug,
+    V: Clone + PartialEq + Debug,
+    D: Dictionary<K, V> + for<'a> BT<'a, K, V>,
+{
+    let mut rng = StdRng::seed_from_u64(conf.seed);
+
+    for round in 0..conf.rounds {
+        let at = At { seed: conf.seed, round };
+        let mut dict = new();
+        let mut model = BTreeMap::new();
+        let mut elems = gen_data(&mut rng, &mut gen, conf.size);
+
+        check_structure(&dict, &model, &mut rng, at);
+
+        for (i, (k, v)) in elems.iter().cloned().enumerate() {
+            dict.insert(k.clone(), v.clone());
+            model.insert(k, v);
+
+            if conf.validate_every > 0 && i.is_multiple_of(conf.validate_every) {
+                check_structure(&dict, &model, &mut rng, at);
+            }
+        }
+
+        check_structure(&dict, &model, &mut rng, at);
+
+        elems.shuffle(&mut rng);
+
+        for (i, (k, _)) in elems.iter().enumerate() {
+            dict.remove(k);
+            model.remove(k);
+
+            if i == elems.len() / 2 || conf.validate_every > 0 && i.is_multiple_of(conf.validate_every) {
+                check_structure(&dict, &model, &mut rng, at);
+            }
+        }
+
+        check_structure(&dict, &model, &mut rng, at);
+        assert_eq!(dict.height(), -1, "[bt] {at}: empty height");
+    }
+}
+
+
+fn validate<K: CollKey, V, D: Dictionary<K, V>>(dict: &D, i: usize, conf: &Config, at: At) {
+    if i == 0 || conf.validate_every > 0 && i.is_multiple_of(conf.validate_every) {
+        if let Err(err) = dict.self_validate() {
+            panic!("[dict validate] {at}: {err}");
+        }
+    }
+}
+
+fn check_structure<K, V, D>(dict: &D, model: &BTreeMap<K, V>, rng: &mut StdRng, at: At)
+where
+    K: CollKey + Clone + Debug,
+    V: PartialEq + Debug,
+    D: Dictionary<K, V> + for<'a> BT<'a, K, V>,
+{
+    if let Err(err) = dict.basic_self_validate().and_then(|_| dict.self_validate()) {
+        panic!("[bt validate] {at}: {err}");
+    }
+
+    assert!(dict.iter().eq(model.iter()), "[bt iter] {at}: not the infix order");
+    assert_eq!(dict.height(), dict.calc_height(), "[bt height] {at}");
+
+    let keys: Vec<&K> = model.keys().collect();
+
+    for _ in 0..keys.len().min(10) {
+        let mut lo = keys[rng.gen_range(0, keys.len())];
+        let mut hi = keys[rng.gen_range(0, keys.len())];
+
+        if lo > hi {
+            (lo, hi) = (hi, lo);
+        }
+
+        assert!(
+            dict.range(lo.clone()..hi.clone()).eq(model.range(lo..hi)),
+            "[bt range] {at}: {lo:?}..{hi:?}"
+        );
+        assert!(
+            dict.range(lo.clone()..).eq(model.range(lo..)),
+            "[bt range] {at}: {lo:?}.."
+        );
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        b3::B3,
+        b4::B4,
+        bst::{aa::AA, avl::AVL, llrb::LLRB, lsg::LSG, rawst::RawST, rb::RB, splay::Splay, treap},
+        bstar4::BStar4,
+    };
+
+    /// Priorities from the key hash, so that the seed in a failure builds the same shapes
+    type Treap<K, V> = treap::Treap<K, V, usize, (), KeyHash>;
+
+    /// Forget a key on every 50th insert
+    #[derive(Default)]
+    struct Forgetful(AVL<u64, u64>, usize);
+
+    impl Dictionary<u64, u64> for Forgetful {
+        fn insert(&mut self, key: u64, value: u64) -> bool {
+            self.1 += 1;
+
+            self.1.is_multiple_of(50) || self.0.insert(key, value)
+        }
+
+        fn remove(&mut self, key: &u64) -> Option<u64> {
+            self.0.remove(key)
+        }
+
+        fn modify(&mut self, key: &u64, value: u64) -> bool {
+            self.0.modify(key, value)
+        }
+
+        fn get(&self, key: &u64) -> Option<&u64> {
+            self.0.get(key)
+        }
+
+        fn get_mut(&mut self, key: &u64) -> Option<&mut u64> {
+            self.0.get_mut(key)
+        }
+
+        fn self_validate(&self) -> Result<(), Box<dyn core::error::Error>> {
+            self.0.self_validate()
+        }
+    }
+
+    #[test]
+    fn test_check_bt() {
+        // the structure checks walk the whole tree
+        let conf = Config {
+            rounds: crate::test_size(4, 1),
+            validate_every: crate::test_size(50, 20),
+            ..crate::test_config()
+        };
+
+        macro_rules! check_bt {
+            ($($ty:ident),*) => {
+                $(
+                    check_bt(<$ty<_, _>>::default, clustered_u64(), &conf);
+                    check_bt(<$ty<_, _>>::default, uniform::<(u16, u8)>, &Config { size: 100, ..conf.clone() });
+                )*
+            };
+        }
+
+        check_bt!(AVL, RB, LLRB, AA, Treap, Splay, LSG, RawST, B3, B4, BStar4);
+    }
+
+    #[test]
+    #[should_panic(expected = "seed 7 round 0")]
+    fn test_check_dictionary_catch() {
+        check_dictionary(Forgetful::default, clustered_u64(), &Config { seed: 7, ..Config::default() });
+    }
+
+    #[test]
+    fn test_gen() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let data = gen_data(&mut rng, &mut clustered_u64(), 100);
+
+        assert_eq!(data.len(), 100);
+        assert!(data.iter().all(|(k, v)| *v == k.wrapping_add(1000)));
+        assert_eq!(data, gen_data(&mut StdRng::seed_from_u64(1), &mut clustered_u64(), 100));
+
+        // only 256 keys
+        let data = gen_data(&mut rng, &mut uniform::<(u8, ())>, 256);
+        assert_eq!(data.iter().map(|(k, _)| *k).collect::<BTreeSet<_>>().len(), 256);
+    }
+}
@@ -0,0 +1,979 @@
+//! Operation traces of a Dictionary, to catch a slow path or a broken tree and run it again.
+//!
+//! `Recorder` wraps any Dictionary and writes each `insert`, `remove`, `modify` and `get`
+//! with its keys, values and outcome, `Trace::replay` drives any tree with them and stops at
+//! the first answer differing from the recorded one or the first failed `self_validate`.
+//! An op the dictionary panicked in is kept as `panicked`, so the trace of a crash reads as well.
+//!
+//! The binary form is `MAGIC`, the `VERSION` byte and the ops, each a tag byte, the key,
+//! the value for `insert`/`modify` and the outcome (byte 2 for `panicked`). Integers are
+//! LEB128 varints (zigzag for the signed), strings are length-prefixed.
+//! The text form is a line for each op:
+//!
+//! ```text
+//! coll-bt trace 1
+//! insert 3 "three" -> true
+//! modify 3 "3" -> true
+//! get 4 -> none
+//! remove 3 -> some "3"
+//! insert 4 "four" -> panicked
+//! ```
+
+use core::{
+    cell::{Ref, RefCell},
+    fmt::{self, Write},
+    marker::PhantomData,
+};
+
+use crate::*;
+
+
+pub const MAGIC: &[u8; 4] = b"BTTR";
+pub const VERSION: u8 = 1;
+
+const TEXT_HEADER: &str = "coll-bt trace";
+
+const INSERT: u8 = 0;
+const REMOVE: u8 = 1;
+const MODIFY: u8 = 2;
+const GET: u8 = 3;
+
+/// In place of the outcome
+const PANICKED: u8 = 2;
+
+
+////////////////////////////////////////////////////////////////////////////////
+//// Structs
+
+/// An operation and what the dictionary answered
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Op<K, V> {
+    Insert(K, V, bool),
+    Remove(K, Option<V>),
+    Modify(K, V, bool),
+    /// `get_mut` is recorded as it too
+    Get(K, Option<V>),
+    /// The dictionary panicked in it, there is no outcome
+    Panicked(Call<K, V>),
+}
+
+/// An operation without the outcome
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Call<K, V> {
+    Insert(K, V),
+    Remove(K),
+    Modify(K, V),
+    Get(K),
+}
+
+/// The ops encoded in the binary form
+pub struct Trace<K, V> {
+    bytes: Vec<u8>,
+    len: usize,
+    _marker: PhantomData<(K, V)>,
+}
+
+/// Record the ops to the wrapped dictionary.
+///
+/// The writes through `get_mut` aren't recorded (it's recorded as a `get`).
+/// Each op is written ahead of the call, `panicked` till the outcome takes its place.
+pub struct Recorder<K, V, D> {
+    dict: D,
+    // `get` takes `&self`
+    trace: RefCell<Trace<K, V>>,
+}
+
+/// Where `Trace::replay` stopped, `step` is the index of the op
+#[derive(Debug)]
+pub enum ReplayError<K, V> {
+    /// the dictionary answered `found` instead of the recorded
+    Diverged { step: usize, expected: Op<K, V>, found: Op<K, V> },
+    /// `self_validate` failed right after the op
+    Invalid { step: usize, op: Op<K, V>, error: Box<dyn core::error::Error> },
+}
+
+
+////////////////////////////////////////////////////////////////////////////////
+//// Traits
+
+/// Keys and values a trace can hold
+pub trait TraceItem: Sized {
+    fn encode(&self, buf: &mut Vec<u8>);
+
+    /// Take it from the front of `buf`
+    #[allow(clippy::result_unit_err, reason = "a trace is either whole or rejected, see `Trace::from_bytes`")]
+    fn decode(buf: &mut &[u8]) -> Result<Self, ()>;
+
+    /// A single token, whitespace only in quotes
+    fn write_text(&self, out: &mut String);
+
+    #[allow(clippy::result_unit_err, reason = "as `decode`")]
+    fn parse_text(token: &str) -> Result<Self, ()>;
+}
+
+
+////////////////////////////////////////////////////////////////////////////////
+//// Implement
+
+macro_rules! impl_trace_item_unsigned {
+    ($($ty:ty),*) => {
+        $(
+            impl TraceItem for $ty {
+                fn encode(&self, buf: &mut Vec<u8>) {
+                    put_varint(buf, *self as u64)
+                }
+
+                fn decode(buf: &mut &[u8]) -> Result<Self, ()> {
+                    Self::try_from(take_varint(buf)?).map_err(|_| ())
+                }
+
+                fn write_text(&self, out: &mut String) {
+                    write!(out, "{self}").unwrap()
+                }
+
+                fn parse_text(token: &str) -> Result<Self, ()> {
+                    token.parse().map_err(|_| ())
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! impl_trace_item_signed {
+    ($($ty:ty),*) => {
+        $(
+            impl TraceItem for $ty {
+                fn encode(&self, buf: &mut Vec<u8>) {
+                    let x = *self as i64;
+
+                    put_varint(buf, ((x << 1) ^ (x >> 63)) as u64)
+                }
+
+                fn decode(buf: &mut &[u8]) -> Result<Self, ()> {
+                    let x = take_varint(buf)?;
+
+                    Self::try_from((x >> 1) as i64 ^ -((x & 1) as i64)).map_err(|_| ())
+                }
+
+                fn write_text(&self, out: &mut String) {
+                    write!(out, "{self}").unwrap()
+                }
+
+                fn parse_text(token: &str) -> Result<Self, ()> {
+                    token.parse().map_err(|_| ())
+                }
+            }
+        )*
+    };
+}
+
+impl_trace_item_unsigned!(u8, u16, u32, u64, usize);
+impl_trace_item_signed!(i8, i16, i32, i64, isize);
+
+
+impl TraceItem for () {
+    fn encode(&self, _buf: &mut Vec<u8>) {}
+
+    fn decode(_buf: &mut &[u8]) -> Result<Self, ()> {
+        Ok(())
+    }
+
+    fn write_text(&self, out: &mut String) {
+        out.push_str("()")
+    }
+
+    fn parse_text(token: &str) -> Result<Self, ()> {
+        if token == "()" { Ok(()) } else { Err(()) }
+    }
+}
+
+impl TraceItem for bool {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.push(*self as u8)
+    }
+
+    fn decode(buf: &mut &[u8]) -> Result<Self, ()> {
+        match take_byte(buf)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(()),
+        }
+    }
+
+    fn write_text(&self, out: &mut String) {
+        write!(out, "{self}").unwrap()
+    }
+
+    fn parse_text(token: &str) -> Result<Self, ()> {
+        token.parse().map_err(|_| ())
+    }
+}
+
+impl TraceItem for String {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        put_varint(buf, self.len() as u64);
+        buf.extend_from_slice(self.as_bytes());
+    }
+
+    fn decode(buf: &mut &[u8]) -> Result<Self, ()> {
+        let len = take_varint(buf)? as usize;
+
+        if buf.len() < len {
+            return Err(());
+        }
+
+        let (bytes, rest) = buf.split_at(len);
+        *buf = rest;
+
+        String::from_utf8(bytes.to_vec()).map_err(|_| ())
+    }
+
+    fn write_text(&self, out: &mut String) {
+        out.push('"');
+
+        for c in self.chars() {
+            match c {
+                '"' => out.push_str("\\\""),
+                '\\' => out.push_str("\\\\"),
+                '\n' => out.push_str("\\n"),
+                '\r' => out.push_str("\\r"),
+                '\t' => out.push_str("\\t"),
+                _ => out.push(c),
+            }
+        }
+
+        out.push('"');
+    }
+
+    fn parse_text(token: &str) -> Result<Self, ()> {
+        let inner = token
+            .strip_prefix('"')
+            .and_then(|s| s.strip_suffix('"'))
+            .ok_or(())?;
+
+        let mut res = String::with_capacity(inner.len());
+        let mut chars = inner.chars();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => return Err(()),
+                '\\' => res.push(match chars.next().ok_or(())? {
+                    '"' => '"',
+                    '\\' => '\\',
+                    'n' => '\n',
+                    'r' => '\r',
+                    't' => '\t',
+                    _ => return Err(()),
+                }),
+                _ => res.push(c),
+            }
+        }
+
+        Ok(res)
+    }
+}
+
+
+impl<K, V> Op<K, V> {
+    /// The tag, the name, the key and the value
+    fn head(&self) -> (u8, &'static str, &K, Option<&V>) {
+        match self {
+            Self::Insert(key, value, _) | Self::Panicked(Call::Insert(key, value)) => (INSERT, "insert", key, Some(value)),
+            Self::Remove(key, _) | Self::Panicked(Call::Remove(key)) => (REMOVE, "remove", key, None),
+            Self::Modify(key, value, _) | Self::Panicked(Call::Modify(key, value)) => (MODIFY, "modify", key, Some(value)),
+            Self::Get(key, _) | Self::Panicked(Call::Get(key)) => (GET, "get", key, None),
+        }
+    }
+
+    /// The op without the outcome
+    pub fn call(&self) -> Call<K, V>
+    where
+        K: Clone,
+        V: Clone,
+    {
+        match self {
+            Self::Insert(key, value, _) => Call::Insert(key.clone(), value.clone()),
+            Self::Remove(key, _) => Call::Remove(key.clone()),
+            Self::Modify(key, value, _) => Call::Modify(key.clone(), value.clone()),
+            Self::Get(key, _) => Call::Get(key.clone()),
+            Self::Panicked(call) => call.clone(),
+        }
+    }
+}
+
+impl<K: TraceItem, V: TraceItem> Op<K, V> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        let (tag, _, key, value) = self.head();
+
+        buf.push(tag);
+        key.encode(buf);
+
+        if let Some(value) = value {
+            value.encode(buf);
+        }
+
+        match self {
+            Self::Insert(.., res) | Self::Modify(.., res) => res.encode(buf),
+            Self::Remove(_, res) | Self::Get(_, res) => put_option(buf, res.as_ref()),
+            Self::Panicked(_) => buf.push(PANICKED),
+        }
+    }
+
+    fn decode(buf: &mut &[u8]) -> Result<Self, ()> {
+        let tag = take_byte(buf)?;
+        let key = K::decode(buf)?;
+
+        let call = match tag {
+            INSERT => Call::Insert(key, V::decode(buf)?),
+            MODIFY => Call::Modify(key, V::decode(buf)?),
+            REMOVE => Call::Remove(key),
+            GET => Call::Get(key),
+            _ => return Err(()),
+        };
+
+        if buf.first() == Some(&PANICKED) {
+            take_byte(buf)?;
+
+            return Ok(Self::Panicked(call));
+        }
+
+        Ok(match call {
+            Call::Insert(key, value) => Self::Insert(key, value, bool::decode(buf)?),
+            Call::Modify(key, value) => Self::Modify(key, value, bool::decode(buf)?),
+            Call::Remove(key) => Self::Remove(key, take_option(buf)?),
+            Call::Get(key) => Self::Get(key, take_option(buf)?),
+        })
+    }
+
+    fn parse_text(line: &str) -> Result<Self, ()> {
+        let tokens = tokens(line)?;
+        let key = K::parse_text(tokens.get(1).ok_or(())?)?;
+
+        let (call, rest) = match (tokens[0], &tokens[2..]) {
+            ("insert", [value, rest @ ..]) => (Call::Insert(key, V::parse_text(value)?), rest),
+            ("modify", [value, rest @ ..]) => (Call::Modify(key, V::parse_text(value)?), rest),
+            ("remove", rest) => (Call::Remove(key), rest),
+            ("get", rest) => (Call::Get(key), rest),
+            _ => return Err(()),
+        };
+
+        let option = |rest: &[&str]| match rest {
+            ["->", "none"] => Ok(None),
+            ["->", "some", value] => V::parse_text(value).map(Some),
+            _ => Err(()),
+        };
+
+        match (call, rest) {
+            (call, ["->", "panicked"]) => Ok(Self::Panicked(call)),
+            (Call::Insert(key, value), ["->", res]) => Ok(Self::Insert(key, value, bool::parse_text(res)?)),
+            (Call::Modify(key, value), ["->", res]) => Ok(Self::Modify(key, value, bool::parse_text(res)?)),
+            (Call::Remove(key), rest) => Ok(Self::Remove(key, option(rest)?)),
+            (Call::Get(key), rest) => Ok(Self::Get(key, option(rest)?)),
+            _ => Err(()),
+        }
+    }
+}
+
+impl<K: TraceItem, V: TraceItem> fmt::Display for Op<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut line = String::new();
+        let (_, name, key, value) = self.head();
+
+        line.push_str(name);
+        line.push(' ');
+        key.write_text(&mut line);
+
+        if let Some(value) = value {
+            line.push(' ');
+            value.write_text(&mut line);
+        }
+
+        match self {
+            Self::Insert(.., res) | Self::Modify(.., res) => write!(line, " -> {res}")?,
+            Self::Remove(_, Some(value)) | Self::Get(_, Some(value)) => {
+                line.push_str(" -> some ");
+                value.write_text(&mut line);
+            }
+            Self::Remove(_, None) | Self::Get(_, None) => line.push_str(" -> none"),
+            Self::Panicked(_) => line.push_str(" -> panicked"),
+        }
+
+        f.write_str(&line)
+    }
+}
+
+
+impl<K: CollKey + Clone, V: Clone> Call<K, V> {
+    /// Call it on `dict`, the op with the outcome
+    pub fn run<D: Dictionary<K, V>>(self, dict: &mut D) -> Op<K, V> {
+        match self {
+            Self::Insert(key, value) => {
+                let res = dict.insert(key.clone(), value.clone());
+                Op::Insert(key, value, res)
+            }
+            Self::Remove(key) => {
+                let res = dict.remove(&key);
+                Op::Remove(key, res)
+            }
+            Self::Modify(key, value) => {
+                let res = dict.modify(&key, value.clone());
+                Op::Modify(key, value, res)
+            }
+            Self::Get(key) => {
+                let res = dict.get(&key).cloned();
+                Op::Get(key, res)
+            }
+        }
+    }
+}
+
+
+impl<K: TraceItem, V: TraceItem> Trace<K, V> {
+    pub fn new() -> Self {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+
+        Self { bytes, len: 0, _marker: PhantomData }
+    }
+
+    /// Number of the ops
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn push(&mut self, op: &Op<K, V>) {
+        op.encode(&mut self.bytes);
+        self.len += 1;
+    }
+
+    /// Write the op ahead of the call as `panicked`, return where the outcome goes
+    fn begin(&mut self, tag: u8, key: &K, value: Option<&V>) -> usize {
+        self.bytes.push(tag);
+        key.encode(&mut self.bytes);
+
+        if let Some(value) = value {
+            value.encode(&mut self.bytes);
+        }
+
+        self.bytes.push(PANICKED);
+        self.len += 1;
+
+        self.bytes.len() - 1
+    }
+
+    /// The call returned, it's still the last op
+    fn end(&mut self, at: usize, res: bool) {
+        self.bytes.truncate(at);
+        res.encode(&mut self.bytes);
+    }
+
+    fn end_option(&mut self, at: usize, res: Option<&V>) {
+        self.bytes.truncate(at);
+        put_option(&mut self.bytes, res);
+    }
+
+    /// It stops at a broken op, though there's none unless an `encode` panicked
+    pub fn iter(&self) -> impl Iterator<Item = Op<K, V>> + '_ {
+        let mut rest = &self.bytes[MAGIC.len() + 1..];
+
+        core::iter::from_fn(move || if rest.is_empty() { None } else { Op::decode(&mut rest).ok() })
+    }
+
+    /// The binary form
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Err if it isn't a whole trace of this version, a cut-off op at the end included
+    #[allow(clippy::result_unit_err, reason = "a damaged trace has no use in part, there is nothing more to tell")]
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ()> {
+        let mut rest = bytes.strip_prefix(&MAGIC[..]).ok_or(())?;
+
+        if take_byte(&mut rest)? != VERSION {
+            return Err(());
+        }
+
+        let mut len = 0;
+
+        while !rest.is_empty() {
+            Op::<K, V>::decode(&mut rest)?;
+            len += 1;
+        }
+
+        Ok(Self { bytes: bytes.to_vec(), len, _marker: PhantomData })
+    }
+
+    /// Parse the text form (`Display` writes it), blank lines and `#` comments are skipped
+    #[allow(clippy::result_unit_err, reason = "as `from_bytes`")]
+    pub fn from_text(text: &str) -> Result<Self, ()> {
+        let mut lines = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'));
+
+        let version = lines.next().and_then(|line| line.strip_prefix(TEXT_HEADER)).ok_or(())?;
+
+        if version.trim().parse::<u8>().map_err(|_| ())? != VERSION {
+            return Err(());
+        }
+
+        let mut trace = Self::new();
+
+        for line in lines {
+            trace.push(&Op::parse_text(line)?);
+        }
+
+        Ok(trace)
+    }
+
+    /// Run the ops on `dict`, `validate` it after each one or not.
+    ///
+    /// A `panicked` op is expected to panic again, the panic goes on if it does.
+    pub fn replay<D: Dictionary<K, V>>(&self, dict: &mut D, validate: bool) -> Result<(), ReplayError<K, V>>
+    where
+        K: CollKey + Clone,
+        V: Clone + PartialEq,
+    {
+        for (step, op) in self.iter().enumerate() {
+            let found = op.call().run(dict);
+
+            if found != op {
+                return Err(ReplayError::Diverged { step, expected: op, found });
+            }
+
+            if validate {
+                if let Err(error) = dict.self_validate() {
+                    return Err(ReplayError::Invalid { step, op, error });
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<K: TraceItem, V: TraceItem> Default for Trace<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> Clone for Trace<K, V> {
+    fn clone(&self) -> Self {
+        Self { bytes: self.bytes.clone(), len: self.len, _marker: PhantomData }
+    }
+}
+
+impl<K: TraceItem, V: TraceItem> fmt::Display for Trace<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{TEXT_HEADER} {VERSION}")?;
+
+        for op in self.iter() {
+            writeln!(f, "{op}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<K: TraceItem, V: TraceItem> fmt::Debug for Trace<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+
+impl<K: TraceItem, V: TraceItem, D> Recorder<K, V, D> {
+    pub fn new(dict: D) -> Self {
+        Self { dict, trace: RefCell::new(Trace::new()) }
+    }
+
+    pub fn dict(&self) -> &D {
+        &self.dict
+    }
+
+    pub fn trace(&self) -> Ref<'_, Trace<K, V>> {
+        self.trace.borrow()
+    }
+
+    /// Take the trace so far and start a new one
+    pub fn take_trace(&mut self) -> Trace<K, V> {
+        core::mem::take(self.trace.get_mut())
+    }
+
+    pub fn into_inner(self) -> (D, Trace<K, V>) {
+        (self.dict, self.trace.into_inner())
+    }
+}
+
+impl<K: CollKey + TraceItem, V: TraceItem, D: Dictionary<K, V>> Dictionary<K, V> for Recorder<K, V, D> {
+    fn insert(&mut self, key: K, value: V) -> bool {
+        let trace = self.trace.get_mut();
+
+        let at = trace.begin(INSERT, &key, Some(&value));
+        let res = self.dict.insert(key, value);
+        trace.end(at, res);
+
+        res
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        let trace = self.trace.get_mut();
+
+        let at = trace.begin(REMOVE, key, None);
+        let res = self.dict.remove(key);
+        trace.end_option(at, res.as_ref());
+
+        res
+    }
+
+    fn modify(&mut self, key: &K, value: V) -> bool {
+        let trace = self.trace.get_mut();
+
+        let at = trace.begin(MODIFY, key, Some(&value));
+        let res = self.dict.modify(key, value);
+        trace.end(at, res);
+
+        res
+    }
+
+    fn get(&self, key: &K) -> Option<&V> {
+        let at = self.trace.borrow_mut().begin(GET, key, None);
+        let res = self.dict.get(key);
+        self.trace.borrow_mut().end_option(at, res);
+
+        res
+    }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let trace = self.trace.get_mut();
+
+        let at = trace.begin(GET, key, None);
+        let res = self.dict.get_mut(key);
+        trace.end_option(at, res.as_deref());
+
+        res
+    }
+
+    fn self_validate(&self) -> Result<(), Box<dyn core::error::Error>> {
+        self.dict.self_validate()
+    }
+}
+
+
+////////////////////////////////////////////////////////////////////////////////
+//// Functions
+
+fn put_varint(buf: &mut Vec<u8>, mut x: u64) {
+    while x >= 0x80 {
+        buf.push(x as u8 | 0x80);
+        x >>= 7;
+    }
+
+    buf.push(x as u8);
+}
+
+fn take_varint(buf: &mut &[u8]) -> Result<u64, ()> {
+    let mut x = 0;
+
+    for shift in (0..64).step_by(7) {
+        let b = take_byte(buf)?;
+
+        x |= ((b & 0x7f) as u64) << shift;
+
+        if b < 0x80 {
+            return Ok(x);
+        }
+    }
+
+    Err(())
+}
+
+fn take_byte(buf: &mut &[u8]) -> Result<u8, ()> {
+    let (&b, rest) = buf.split_first().ok_or(())?;
+    *buf = rest;
+
+    Ok(b)
+}
+
+fn put_option<V: TraceItem>(buf: &mut Vec<u8>, x: Option<&V>) {
+    match x {
+        Some(x) => {
+            buf.push(1);
+            x.encode(buf);
+        }
+        None => buf.push(0),
+    }
+}
+
+fn take_option<V: TraceItem>(buf: &mut &[u8]) -> Result<Option<V>, ()> {
+    match take_byte(buf)? {
+        0 => Ok(None),
+        1 => V::decode(buf).map(Some),
+        _ => Err(()),
+    }
+}
+
+/// Split the line by whitespace out of the quotes
+fn tokens(line: &str) -> Result<Vec<&str>, ()> {
+    let mut res = vec![];
+    let mut rest = line.trim_start();
+
+    while !rest.is_empty() {
+        let end = if rest.starts_with('"') {
+            let mut escaped = false;
+
+            let (i, _) = rest
+                .char_indices()
+                .skip(1)
+                .find(|&(_, c)| {
+                    let close = c == '"' && !escaped;
+                    escaped = c == '\\' && !escaped;
+                    close
+                })
+                .ok_or(())?;
+
+            i + 1
+        } else {
+            rest.find(char::is_whitespace).unwrap_or(rest.len())
+        };
+
+        res.push(&rest[..end]);
+        rest = rest[end..].trim_start();
+    }
+
+    if res.is_empty() {
+        return Err(());
+    }
+
+    Ok(res)
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::ToString;
+    use std::panic::{self, AssertUnwindSafe};
+
+    use super::*;
+    use crate::{
+        b3::B3,
+        b4::B4,
+        bst::{aa::AA, avl::AVL, llrb::LLRB, lsg::LSG, rawst::RawST, rb::RB, splay::Splay, treap::Treap},
+        bstar4::BStar4,
+    };
+
+    /// Random ops of all kinds on a recorded AVL
+    fn record(n: u64) -> Trace<u64, i32> {
+        let mut dict = Recorder::new(AVL::new());
+
+        for _ in 0..n {
+            let k = rand::random::<u64>() % (n / 2);
+            let v = rand::random::<i32>();
+
+            match rand::random::<u8>() % 4 {
+                0 | 1 => {
+                    dict.insert(k, v);
+                }
+                2 => {
+                    dict.remove(&k);
+                }
+                _ => {
+                    dict.modify(&k, v);
+                    dict.get(&(k + 1));
+                }
+            }
+        }
+
+        dict.self_validate().unwrap();
+        dict.into_inner().1
+    }
+
+    /// Fail `self_validate` once 13 is in it, panic removing 7
+    #[derive(Default)]
+    struct Unlucky(AVL<u64, i32>);
+
+    impl Dictionary<u64, i32> for Unlucky {
+        fn insert(&mut self, key: u64, value: i32) -> bool {
+            self.0.insert(key, value)
+        }
+
+        fn remove(&mut self, key: &u64) -> Option<i32> {
+            assert_ne!(*key, 7);
+
+            self.0.remove(key)
+        }
+
+        fn modify(&mut self, key: &u64, value: i32) -> bool {
+            self.0.modify(key, value)
+        }
+
+        fn get(&self, key: &u64) -> Option<&i32> {
+            self.0.get(key)
+        }
+
+        fn get_mut(&mut self, key: &u64) -> Option<&mut i32> {
+            self.0.get_mut(key)
+        }
+
+        fn self_validate(&self) -> Result<(), Box<dyn core::error::Error>> {
+            if self.0.get(&13).is_some() {
+                return Err("13".into());
+            }
+
+            self.0.self_validate()
+        }
+    }
+
+    #[test]
+    fn test_trace_replay() {
+        let trace = record(crate::test_size(1000, 100));
+
+        macro_rules! replay {
+            ($($ty:ident),*) => {
+                $(
+                    trace.replay(&mut $ty::new(), true).unwrap();
+                )*
+            };
+        }
+
+        replay!(AVL, RB, LLRB, AA, Treap, Splay, LSG, RawST, B3, B4, BStar4);
+
+        // a step answered differently
+        let mut ops: Vec<_> = trace.iter().collect();
+        let step = ops.iter().position(|op| matches!(op, Op::Remove(_, Some(_)))).unwrap();
+
+        if let Op::Remove(_, res) = &mut ops[step] {
+            *res = None;
+        }
+
+        let mut broken = Trace::new();
+
+        for op in ops.iter() {
+            broken.push(op);
+        }
+
+        match broken.replay(&mut RB::new(), false) {
+            Err(ReplayError::Diverged { step: found, expected, found: Op::Remove(_, Some(_)) }) => {
+                assert_eq!(found, step);
+                assert_eq!(expected, ops[step]);
+            }
+            res => panic!("{res:?}"),
+        }
+
+        // the tree goes bad
+        let mut trace = Trace::new();
+
+        trace.push(&Op::Insert(12, 0, true));
+        trace.push(&Op::Get(12, Some(0)));
+        trace.push(&Op::Insert(13, 0, true));
+        trace.push(&Op::Remove(13, Some(0)));
+
+        trace.replay(&mut Unlucky::default(), false).unwrap();
+
+        match trace.replay(&mut Unlucky::default(), true) {
+            Err(ReplayError::Invalid { step: 2, op: Op::Insert(13, ..), .. }) => (),
+            res => panic!("{res:?}"),
+        }
+    }
+
+    #[test]
+    fn test_trace_panic() {
+        let mut dict = Recorder::new(Unlucky::default());
+
+        for k in 0..10 {
+            dict.insert(k, k as i32);
+        }
+
+        assert!(panic::catch_unwind(AssertUnwindSafe(|| dict.remove(&7))).is_err());
+        assert_eq!(dict.get(&7), Some(&7));
+
+        let trace = dict.take_trace();
+
+        assert_eq!(trace.len(), 12);
+        assert_eq!(trace.iter().nth(10), Some(Op::Panicked(Call::Remove(7))));
+        assert!(trace.to_string().contains("\nremove 7 -> panicked\nget 7 -> some 7\n"));
+
+        let bytes = Trace::<u64, i32>::from_bytes(trace.as_bytes()).unwrap();
+        let text = Trace::<u64, i32>::from_text(&trace.to_string()).unwrap();
+
+        assert!(bytes.iter().eq(trace.iter()));
+        assert_eq!(text.as_bytes(), trace.as_bytes());
+
+        // a sound tree doesn't panic there
+        match trace.replay(&mut AVL::new(), true) {
+            Err(ReplayError::Diverged { step: 10, expected: Op::Panicked(_), found: Op::Remove(7, Some(7)) }) => (),
+            res => panic!("{res:?}"),
+        }
+
+        // the same one does again
+        assert!(panic::catch_unwind(AssertUnwindSafe(|| trace.replay(&mut Unlucky::default(), true))).is_err());
+
+        // ends with the op in
+        let mut dict = Recorder::new(Unlucky::default());
+
+        dict.insert(7, 0);
+        assert!(panic::catch_unwind(AssertUnwindSafe(|| dict.remove(&7))).is_err());
+        assert_eq!(dict.trace().iter().last(), Some(Op::Panicked(Call::Remove(7))));
+    }
+
+    #[test]
+    fn test_trace_format() {
+        let trace = record(crate::test_size(1000, 100));
+
+        let bytes = Trace::<u64, i32>::from_bytes(trace.as_bytes()).unwrap();
+        assert_eq!(bytes.len(), trace.len());
+        assert!(bytes.iter().eq(trace.iter()));
+
+        let text = Trace::<u64, i32>::from_text(&trace.to_string()).unwrap();
+        assert_eq!(text.as_bytes(), trace.as_bytes());
+
+        // versioned and whole
+        let mut other = trace.as_bytes().to_vec();
+        other[MAGIC.len()] = VERSION + 1;
+        assert!(Trace::<u64, i32>::from_bytes(&other).is_err());
+        assert!(Trace::<u64, i32>::from_bytes(&trace.as_bytes()[1..]).is_err());
+        assert!(Trace::<u64, i32>::from_bytes(&trace.as_bytes()[..trace.as_bytes().len() - 1]).is_err());
+        assert!(Trace::<u64, i32>::from_text("coll-bt trace 2\n").is_err());
+        assert!(Trace::<u64, i32>::from_text("coll-bt trace 1\nget 1 -> 2\n").is_err());
+
+        // quoting
+        let text = "
+            # strings
+            coll-bt trace 1
+            insert \"a b\" \"\\\"c\\\"\\n\" -> true
+            get \"a b\" -> some \"\\\"c\\\"\\n\"
+
+            remove \"\" -> none
+            modify \"\\\\\" \"\" -> false
+        ";
+
+        let trace = Trace::<String, String>::from_text(text).unwrap();
+        let ops: Vec<_> = trace.iter().collect();
+
+        assert_eq!(ops[0], Op::Insert("a b".into(), "\"c\"\n".into(), true));
+        assert_eq!(ops[1], Op::Get("a b".into(), Some("\"c\"\n".into())));
+        assert_eq!(ops[2], Op::Remove("".into(), None));
+        assert_eq!(ops[3], Op::Modify("\\".into(), "".into(), false));
+
+        assert_eq!(Trace::<String, String>::from_text(&trace.to_string()).unwrap().as_bytes(), trace.as_bytes());
+        trace.replay(&mut B4::new(), true).unwrap();
+
+        assert!(Trace::<String, String>::from_text("coll-bt trace 1\nremove \"a\" b\" -> none").is_err());
+
+        // signed and unit
+        let mut trace = Trace::<i64, ()>::new();
+        trace.push(&Op::Insert(i64::MIN, (), true));
+        trace.push(&Op::Insert(-1, (), true));
+        trace.push(&Op::Get(i64::MAX, None));
+
+        assert_eq!(trace.as_bytes().len(), MAGIC.len() + 1 + (1 + 10 + 1) + (1 + 1 + 1) + (1 + 10 + 1));
+        assert!(Trace::<i64, ()>::from_bytes(trace.as_bytes()).unwrap().iter().eq(trace.iter()));
+        assert!(Trace::<i64, ()>::from_text(&trace.to_string()).unwrap().iter().eq(trace.iter()));
+    }
+}
@@ -0,0 +1,213 @@
+//! All-or-nothing batch of updates on a Dictionary, see `Dictionary::transaction`.
+//!
+//! The updates go to the dictionary right away and an undo log keeps what they overwrote,
+//! the log is replayed backwards on rollback, so it restores the same pairs (not the same shape).
+
+use core::mem;
+
+use crate::*;
+
+
+////////////////////////////////////////////////////////////////////////////////
+//// Structs
+
+/// Rolled back on drop unless committed, an early return or a panic of the caller rolls it back too.
+pub struct Transaction<'d, K: CollKey, V, D: Dictionary<K, V>> {
+    dict: &'d mut D,
+    undo: Vec<Undo<K, V>>,
+}
+
+enum Undo<K, V> {
+    Inserted(K),
+    Removed(K, V),
+    Modified(K, V),
+}
+
+
+////////////////////////////////////////////////////////////////////////////////
+//// Implement
+
+impl<'d, K: CollKey + Clone, V: Clone, D: Dictionary<K, V>> Transaction<'d, K, V, D> {
+    pub fn new(dict: &'d mut D) -> Self {
+        Self { dict, undo: vec![] }
+    }
+
+    pub fn insert(&mut self, key: K, value: V) -> bool {
+        let undo_key = key.clone();
+
+        if self.dict.insert(key, value) {
+            self.undo.push(Undo::Inserted(undo_key));
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let value = self.dict.remove(key)?;
+
+        self.undo.push(Undo::Removed(key.clone(), value.clone()));
+
+        Some(value)
+    }
+
+    pub fn modify(&mut self, key: &K, value: V) -> bool {
+        match self.dict.get_mut(key) {
+            Some(here) => {
+                let old = mem::replace(here, value);
+                self.undo.push(Undo::Modified(key.clone(), old));
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.dict.get(key)
+    }
+
+    /// Number of the updates so far
+    pub fn undo_len(&self) -> usize {
+        self.undo.len()
+    }
+
+    /// Keep the updates
+    pub fn commit(mut self) {
+        self.undo.clear();
+    }
+
+    /// Undo the updates, the same as dropping it
+    pub fn rollback(self) {}
+}
+
+
+impl<K: CollKey, V, D: Dictionary<K, V>> Drop for Transaction<'_, K, V, D> {
+    fn drop(&mut self) {
+        while let Some(undo) = self.undo.pop() {
+            match undo {
+                Undo::Inserted(key) => {
+                    self.dict.remove(&key);
+                }
+                Undo::Removed(key, value) => {
+                    self.dict.insert(key, value);
+                }
+                Undo::Modified(key, value) => {
+                    self.dict.modify(&key, value);
+                }
+            }
+        }
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, panic};
+
+    use super::*;
+    use crate::{
+        b3::B3,
+        b4::B4,
+        bst::{aa::AA, avl::AVL, llrb::LLRB, lsg::LSG, rawst::RawST, rb::RB, splay::Splay, treap::Treap},
+        bstar4::BStar4,
+    };
+
+    macro_rules! test_transaction {
+        ($ty:ident) => {
+            let n = crate::test_size(200u32, 30);
+            let mut dict = $ty::new();
+            let mut expect = BTreeMap::new();
+
+            for k in 0..n {
+                dict.insert(k * 2, k);
+                expect.insert(k * 2, k);
+            }
+
+            for round in 0..crate::test_size(20, 3) {
+                let before = dict.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>();
+
+                let res: Result<(), &str> = dict.transaction(|txn| {
+                    for _ in 0..n {
+                        let k = rand::random::<u32>() % (n * 3);
+
+                        match rand::random::<u8>() % 3 {
+                            0 => {
+                                txn.insert(k, k + 1);
+                            }
+                            1 => {
+                                txn.remove(&k);
+                            }
+                            _ => {
+                                txn.modify(&k, k + 2);
+                            }
+                        }
+                    }
+
+                    assert!(txn.undo_len() <= n as usize);
+
+                    if round % 2 == 0 { Err("abort") } else { Ok(()) }
+                });
+
+                if round % 2 == 0 {
+                    assert_eq!(res, Err("abort"));
+
+                    dict.self_validate().unwrap();
+                    assert!(dict.iter().map(|(k, v)| (*k, *v)).eq(before.iter().cloned()));
+                } else {
+                    dict.self_validate().unwrap();
+
+                    expect = dict.iter().map(|(k, v)| (*k, *v)).collect();
+                }
+            }
+
+            // a panic in the middle rolls back on unwinding
+            let before = dict.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>();
+
+            let res = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+                let _ = dict.transaction(|txn| -> Result<(), ()> {
+                    for k in 0..n * 3 {
+                        txn.remove(&k);
+                        txn.insert(k, 0);
+                    }
+
+                    panic!("abort");
+                });
+            }));
+
+            assert!(res.is_err());
+            dict.self_validate().unwrap();
+            assert!(dict.iter().map(|(k, v)| (*k, *v)).eq(before.into_iter()));
+            assert!(dict.iter().map(|(k, v)| (*k, *v)).eq(expect.into_iter()));
+
+            // committed explicitly
+            let mut txn = Transaction::new(&mut dict);
+            assert!(txn.insert(n * 3, 0));
+            assert!(txn.modify(&(n * 3), 100));
+            assert_eq!(txn.remove(&(n * 3)), Some(100));
+            assert!(txn.insert(n * 3, 1));
+            txn.commit();
+
+            assert_eq!(dict.get(&(n * 3)), Some(&1));
+        };
+    }
+
+    #[test]
+    fn test_transaction_bst() {
+        test_transaction!(AVL);
+        test_transaction!(RB);
+        test_transaction!(LLRB);
+        test_transaction!(AA);
+        test_transaction!(Treap);
+        test_transaction!(Splay);
+        test_transaction!(LSG);
+        test_transaction!(RawST);
+    }
+
+    #[test]
+    fn test_transaction_btree() {
+        test_transaction!(B3);
+        test_transaction!(B4);
+        test_transaction!(BStar4);
+    }
+}